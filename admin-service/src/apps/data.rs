@@ -1,5 +1,7 @@
 pub use drogue_cloud_database_common::models::app::Role;
+pub use drogue_cloud_service_api::history::HistoryEntry;
 
+use chrono::{DateTime, Utc};
 use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 
@@ -23,3 +25,45 @@ pub struct Members {
 pub struct MemberEntry {
     pub role: Role,
 }
+
+/// An application in the trash.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeletedApplication {
+    pub name: String,
+    pub uid: String,
+    pub generation: u64,
+    pub deletion_timestamp: DateTime<Utc>,
+    pub expiration_timestamp: DateTime<Utc>,
+}
+
+/// A device in the trash.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeletedDevice {
+    pub name: String,
+    pub uid: String,
+    pub generation: u64,
+    /// The device got deleted as part of deleting its application, and will be restored with it.
+    pub cascaded: bool,
+    pub deletion_timestamp: DateTime<Utc>,
+    pub expiration_timestamp: DateTime<Utc>,
+}
+
+/// Options for retrieving the history of a resource.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct HistoryParams {
+    /// The maximum number of entries, the most recent first.
+    pub limit: Option<usize>,
+    /// Continue a previous page, using the token of its response.
+    #[serde(default, rename = "continue")]
+    pub continue_token: Option<String>,
+}
+
+/// A page of the history of a resource.
+#[derive(Clone, Debug, Default)]
+pub struct HistoryPage {
+    pub entries: Vec<HistoryEntry>,
+    /// The token to continue with, in case there are more entries.
+    pub continue_token: Option<String>,
+}
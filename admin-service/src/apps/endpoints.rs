@@ -1,8 +1,13 @@
-use crate::apps::{data::TransferOwnership, service::AdminService, Members};
+use crate::apps::{
+    data::TransferOwnership, service::AdminService, HistoryPage, HistoryParams, Members,
+};
 use actix_web::{web, HttpResponse};
 use drogue_cloud_service_api::auth::user::UserInformation;
 use std::ops::Deref;
 
+/// The header carrying the continuation token of the history, in case there are more entries.
+pub const HEADER_CONTINUE: &str = "X-Continue";
+
 pub struct WebData<S: AdminService> {
     pub service: S,
 }
@@ -107,3 +112,128 @@ where
 
     result
 }
+
+/// List applications in the trash
+pub async fn list_deleted_apps<S>(
+    user: UserInformation,
+    service: web::Data<WebData<S>>,
+) -> Result<HttpResponse, actix_web::Error>
+where
+    S: AdminService + 'static,
+{
+    let result = match service.list_deleted_apps(&user).await {
+        Ok(apps) => Ok(HttpResponse::Ok().json(apps)),
+        Err(e) => Err(e.into()),
+    };
+
+    result
+}
+
+/// Restore an application from the trash
+pub async fn restore_app<S>(
+    user: UserInformation,
+    service: web::Data<WebData<S>>,
+    app_id: web::Path<String>,
+) -> Result<HttpResponse, actix_web::Error>
+where
+    S: AdminService + 'static,
+{
+    let result = match service.restore_app(&user, app_id.into_inner()).await {
+        Ok(_) => Ok(HttpResponse::NoContent().finish()),
+        Err(e) => Err(e.into()),
+    };
+
+    result
+}
+
+/// List devices of an application in the trash
+pub async fn list_deleted_devices<S>(
+    user: UserInformation,
+    service: web::Data<WebData<S>>,
+    app_id: web::Path<String>,
+) -> Result<HttpResponse, actix_web::Error>
+where
+    S: AdminService + 'static,
+{
+    let result = match service
+        .list_deleted_devices(&user, app_id.into_inner())
+        .await
+    {
+        Ok(devices) => Ok(HttpResponse::Ok().json(devices)),
+        Err(e) => Err(e.into()),
+    };
+
+    result
+}
+
+/// Restore a device from the trash
+pub async fn restore_device<S>(
+    user: UserInformation,
+    service: web::Data<WebData<S>>,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, actix_web::Error>
+where
+    S: AdminService + 'static,
+{
+    let (app_id, device_id) = path.into_inner();
+
+    let result = match service.restore_device(&user, app_id, device_id).await {
+        Ok(_) => Ok(HttpResponse::NoContent().finish()),
+        Err(e) => Err(e.into()),
+    };
+
+    result
+}
+
+/// Create the response for a page of the history.
+fn history_response(page: HistoryPage) -> HttpResponse {
+    let mut response = HttpResponse::Ok();
+    if let Some(continue_token) = page.continue_token {
+        response.append_header((HEADER_CONTINUE, continue_token));
+    }
+    response.json(page.entries)
+}
+
+/// Get the history of an application
+pub async fn get_app_history<S>(
+    user: UserInformation,
+    service: web::Data<WebData<S>>,
+    app_id: web::Path<String>,
+    params: web::Query<HistoryParams>,
+) -> Result<HttpResponse, actix_web::Error>
+where
+    S: AdminService + 'static,
+{
+    let result = match service
+        .get_app_history(&user, app_id.into_inner(), params.into_inner())
+        .await
+    {
+        Ok(page) => Ok(history_response(page)),
+        Err(e) => Err(e.into()),
+    };
+
+    result
+}
+
+/// Get the history of a device
+pub async fn get_device_history<S>(
+    user: UserInformation,
+    service: web::Data<WebData<S>>,
+    path: web::Path<(String, String)>,
+    params: web::Query<HistoryParams>,
+) -> Result<HttpResponse, actix_web::Error>
+where
+    S: AdminService + 'static,
+{
+    let (app_id, device_id) = path.into_inner();
+
+    let result = match service
+        .get_device_history(&user, app_id, device_id, params.into_inner())
+        .await
+    {
+        Ok(page) => Ok(history_response(page)),
+        Err(e) => Err(e.into()),
+    };
+
+    result
+}
@@ -1,4 +1,6 @@
-use crate::apps::{Members, TransferOwnership};
+use crate::apps::{
+    DeletedApplication, DeletedDevice, HistoryPage, HistoryParams, Members, TransferOwnership,
+};
 use actix_http::ResponseError;
 use async_trait::async_trait;
 use drogue_cloud_service_api::auth::user::UserInformation;
@@ -28,4 +30,46 @@ pub trait AdminService: Clone {
         app_id: String,
         members: Members,
     ) -> Result<(), Self::Error>;
+
+    /// List the applications in the trash, which the user may restore.
+    async fn list_deleted_apps(
+        &self,
+        identity: &UserInformation,
+    ) -> Result<Vec<DeletedApplication>, Self::Error>;
+    /// Restore the most recently deleted application from the trash.
+    async fn restore_app(
+        &self,
+        identity: &UserInformation,
+        app_id: String,
+    ) -> Result<(), Self::Error>;
+
+    /// List the devices of an application in the trash.
+    async fn list_deleted_devices(
+        &self,
+        identity: &UserInformation,
+        app_id: String,
+    ) -> Result<Vec<DeletedDevice>, Self::Error>;
+    /// Restore the most recently deleted device from the trash.
+    async fn restore_device(
+        &self,
+        identity: &UserInformation,
+        app_id: String,
+        device_id: String,
+    ) -> Result<(), Self::Error>;
+
+    /// Get the history of all resources of an application, the most recent change first.
+    async fn get_app_history(
+        &self,
+        identity: &UserInformation,
+        app_id: String,
+        params: HistoryParams,
+    ) -> Result<HistoryPage, Self::Error>;
+    /// Get the history of a device, the most recent change first.
+    async fn get_device_history(
+        &self,
+        identity: &UserInformation,
+        app_id: String,
+        device_id: String,
+        params: HistoryParams,
+    ) -> Result<HistoryPage, Self::Error>;
 }
@@ -6,7 +6,7 @@ use drogue_client::registry::v1::Password;
 use drogue_client::{registry, Dialect, Translator};
 use drogue_cloud_database_common::{
     error::ServiceError,
-    models::{app::*, device::*},
+    models::{app::*, device::*, device_type::*, gateway::*},
     Client, DatabaseService,
 };
 use drogue_cloud_service_api::{
@@ -73,6 +73,8 @@ impl PostgresAuthenticationService {
         device_id: String,
         as_id: String,
         accessor: PostgresDeviceAccessor<'c, C>,
+        device_types: PostgresDeviceTypeAccessor<'c, C>,
+        gateways: PostgresGatewayAccessor<'c, C>,
         application: registry::v1::Application,
         device: registry::v1::Device,
     ) -> Result<Outcome, ServiceError>
@@ -82,33 +84,28 @@ impl PostgresAuthenticationService {
         Ok(
             match accessor.lookup(&application.metadata.name, &as_id).await? {
                 Some(as_device) if as_device.deletion_timestamp.is_none() => {
-                    let as_manage: registry::v1::Device = as_device.into();
-                    match as_manage.section::<registry::v1::DeviceSpecGatewaySelector>() {
-                        Some(Ok(gateway_selector)) => {
-                            if gateway_selector.match_names.contains(&device_id) {
-                                log::debug!(
-                                    "Device {:?} allowed to publish as {:?}",
-                                    device_id,
-                                    as_id
-                                );
-                                pass!(application, device, Some(as_manage))
-                            } else {
-                                log::debug!(
-                                "Device {:?} not allowed to publish as {:?}, gateway not listed",
-                                device_id,
-                                as_id
-                            );
-                                Outcome::Fail
-                            }
-                        }
-                        _ => {
-                            log::debug!(
+                    let as_manage = device_types.apply(as_device.into()).await?;
+                    let gateways = gateways.gateways(&as_manage).await?;
+                    if gateways.is_empty() {
+                        log::debug!(
                             "Device {:?} not allowed to publish as {:?}, no gateways configured",
                             device_id,
                             as_id
                         );
-                            Outcome::Fail
-                        }
+                        Outcome::Fail
+                    } else if gateways
+                        .iter()
+                        .any(|gateway| gateway.metadata.name == device.metadata.name)
+                    {
+                        log::debug!("Device {:?} allowed to publish as {:?}", device_id, as_id);
+                        pass!(application, device, Some(as_manage))
+                    } else {
+                        log::debug!(
+                            "Device {:?} not allowed to publish as {:?}, gateway not selected",
+                            device_id,
+                            as_id
+                        );
+                        Outcome::Fail
                     }
                 }
                 Some(_) => {
@@ -170,6 +167,11 @@ impl AuthenticationService for PostgresAuthenticationService {
             }
         };
 
+        // merge in the device type
+
+        let device_types = PostgresDeviceTypeAccessor::new(&c);
+        let device = device_types.apply(device).await?;
+
         log::debug!("Found device: {:?}", device);
 
         // validate credential
@@ -184,6 +186,8 @@ impl AuthenticationService for PostgresAuthenticationService {
                                 request.device,
                                 as_id,
                                 accessor,
+                                device_types,
+                                PostgresGatewayAccessor::new(&c),
                                 application,
                                 device,
                             )
@@ -0,0 +1,72 @@
+mod common;
+
+use actix_web::{test, web, App};
+use drogue_cloud_authentication_service::{endpoints, service, WebData};
+use drogue_cloud_service_api::auth::device::authn::{AuthenticationRequest, Credential};
+use drogue_cloud_test_common::{client, db};
+use serde_json::{json, Value};
+use serial_test::serial;
+
+fn device_typed_json(name: &str, uid: &str) -> Value {
+    json!({"pass":{
+        "application": {
+            "metadata": {
+                "name": "app1",
+                "uid": "4e185ea6-7c26-11eb-a319-d45d6455d210",
+                "creationTimestamp": "2020-01-01T00:00:00Z",
+                "resourceVersion": "a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11",
+                "generation": 0,
+            },
+        },
+        "device": {
+            "metadata": {
+                "application": "app1",
+                "name": name,
+                "uid": uid,
+                "creationTimestamp": "2020-01-01T00:00:00Z",
+                "resourceVersion": "a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11",
+                "generation": 0,
+            },
+            "spec": {
+                "deviceType": "sensor",
+                "foo": "bar",
+            },
+        }
+    }})
+}
+
+/// Authorize a device using the password of its device type.
+#[actix_rt::test]
+#[serial]
+async fn test_auth_passes_password_of_type() {
+    test_auth!(AuthenticationRequest{
+        application: "app1".into(),
+        device: "device-typed1".into(),
+        credential: Credential::Password("typed".into()),
+        r#as: None,
+    } => device_typed_json("device-typed1", "4e185ea6-7c26-11eb-a319-d45d6455d251"));
+}
+
+/// Authorize a device using its own password, overriding the one of its device type.
+#[actix_rt::test]
+#[serial]
+async fn test_auth_passes_password_overriding_type() {
+    test_auth!(AuthenticationRequest{
+        application: "app1".into(),
+        device: "device-typed2".into(),
+        credential: Credential::Password("own".into()),
+        r#as: None,
+    } => device_typed_json("device-typed2", "4e185ea6-7c26-11eb-a319-d45d6455d252"));
+}
+
+/// The password of the device type must not work, when the device overrides it.
+#[actix_rt::test]
+#[serial]
+async fn test_auth_fails_password_of_overridden_type() {
+    test_auth!(AuthenticationRequest{
+        application: "app1".into(),
+        device: "device-typed2".into(),
+        credential: Credential::Password("typed".into()),
+        r#as: None,
+    } => json!("fail"));
+}
@@ -0,0 +1,49 @@
+mod common;
+
+use actix_web::{test, web, App};
+use drogue_cloud_authentication_service::{endpoints, service, WebData};
+use drogue_cloud_service_api::auth::device::authn::{AuthenticationRequest, Credential};
+use drogue_cloud_test_common::{client, db};
+use rstest::rstest;
+use serde_json::{json, Value};
+use serial_test::serial;
+
+/// Authorize a gateway to publish as another device, following chains of gateways.
+#[rstest]
+// direct, by name
+#[case("sub1", "sub1", "behind1", true)]
+// direct, by label
+#[case("gw1", "gw1", "sub1", true)]
+// chained: gw1 -> sub1 -> behind1
+#[case("gw1", "gw1", "behind1", true)]
+// gw1 doesn't select any gateways
+#[case("sub1", "sub1", "gw1", false)]
+// not selected as gateway
+#[case("device1", "foo", "behind1", false)]
+#[actix_rt::test]
+#[serial]
+async fn test_auth_gateway(
+    #[case] device: &str,
+    #[case] password: &str,
+    #[case] r#as: &str,
+    #[case] pass: bool,
+) {
+    test!(app => {
+        let resp = test::TestRequest::post().uri("/api/v1/auth").set_json(&AuthenticationRequest {
+            application: "app1".into(),
+            device: device.to_string(),
+            credential: Credential::Password(password.to_string()),
+            r#as: Some(r#as.to_string()),
+        }).send_request(&app).await;
+        assert!(resp.status().is_success());
+
+        let result: Value = test::read_body_json(resp).await;
+
+        if pass {
+            assert_eq!(result["outcome"]["pass"]["device"]["metadata"]["name"], device);
+            assert_eq!(result["outcome"]["pass"]["as"]["metadata"]["name"], r#as);
+        } else {
+            assert_eq!(result, json!({"outcome": "fail"}));
+        }
+    });
+}
@@ -0,0 +1,111 @@
+--
+-- type "sensor" -> pass: typed
+--
+
+INSERT INTO DEVICE_TYPES (
+    APP,
+    NAME,
+    UID,
+    CREATION_TIMESTAMP,
+    RESOURCE_VERSION,
+    GENERATION,
+    DATA
+) VALUES (
+    'app1',
+    'sensor',
+    '4e185ea6-7c26-11eb-a319-d45d6455d250',
+    '2020-01-01 00:00:00',
+    'A0EEBC99-9C0B-4EF8-BB6D-6BB9BD380A11',
+    0,
+    '{
+      "spec": {
+        "credentials": {
+          "credentials": [
+            { "pass": "typed"}
+          ]
+        },
+        "foo": "bar"
+      }
+    }'::JSONB
+);
+
+--
+-- device-typed1 -> type: sensor
+--
+
+INSERT INTO DEVICES (
+    APP,
+    NAME,
+    UID,
+    CREATION_TIMESTAMP,
+    RESOURCE_VERSION,
+    GENERATION,
+    DATA
+) VALUES (
+    'app1',
+    'device-typed1',
+    '4e185ea6-7c26-11eb-a319-d45d6455d251',
+    '2020-01-01 00:00:00',
+    'A0EEBC99-9C0B-4EF8-BB6D-6BB9BD380A11',
+    0,
+    '{
+      "spec": {
+        "deviceType": "sensor"
+      }
+    }'::JSONB
+);
+
+INSERT INTO DEVICE_ALIASES(
+    APP,
+    DEVICE,
+    TYPE,
+    ALIAS
+) VALUES (
+    'app1',
+    'device-typed1',
+    'id',
+    'device-typed1'
+);
+
+--
+-- device-typed2 -> type: sensor, overriding the credentials with pass: own
+--
+
+INSERT INTO DEVICES (
+    APP,
+    NAME,
+    UID,
+    CREATION_TIMESTAMP,
+    RESOURCE_VERSION,
+    GENERATION,
+    DATA
+) VALUES (
+    'app1',
+    'device-typed2',
+    '4e185ea6-7c26-11eb-a319-d45d6455d252',
+    '2020-01-01 00:00:00',
+    'A0EEBC99-9C0B-4EF8-BB6D-6BB9BD380A11',
+    0,
+    '{
+      "spec": {
+        "deviceType": "sensor",
+        "credentials": {
+          "credentials": [
+            { "pass": "own"}
+          ]
+        }
+      }
+    }'::JSONB
+);
+
+INSERT INTO DEVICE_ALIASES(
+    APP,
+    DEVICE,
+    TYPE,
+    ALIAS
+) VALUES (
+    'app1',
+    'device-typed2',
+    'id',
+    'device-typed2'
+);
@@ -0,0 +1,128 @@
+--
+-- gw1 (role: gateway) -> pass: gw1
+--
+
+INSERT INTO DEVICES (
+    APP,
+    NAME,
+    UID,
+    LABELS,
+    CREATION_TIMESTAMP,
+    RESOURCE_VERSION,
+    GENERATION,
+    DATA
+) VALUES (
+    'app1',
+    'gw1',
+    '4e185ea6-7c26-11eb-a319-d45d6455d260',
+    '{"role": "gateway"}'::JSONB,
+    '2020-01-01 00:00:00',
+    'A0EEBC99-9C0B-4EF8-BB6D-6BB9BD380A11',
+    0,
+    '{
+      "spec": {
+        "credentials": {
+          "credentials": [
+            { "pass": "gw1"}
+          ]
+        }
+      }
+    }'::JSONB
+);
+
+INSERT INTO DEVICE_ALIASES(
+    APP,
+    DEVICE,
+    TYPE,
+    ALIAS
+) VALUES (
+    'app1',
+    'gw1',
+    'id',
+    'gw1'
+);
+
+--
+-- sub1 -> pass: sub1, gateways: role=gateway
+--
+
+INSERT INTO DEVICES (
+    APP,
+    NAME,
+    UID,
+    CREATION_TIMESTAMP,
+    RESOURCE_VERSION,
+    GENERATION,
+    DATA
+) VALUES (
+    'app1',
+    'sub1',
+    '4e185ea6-7c26-11eb-a319-d45d6455d261',
+    '2020-01-01 00:00:00',
+    'A0EEBC99-9C0B-4EF8-BB6D-6BB9BD380A11',
+    0,
+    '{
+      "spec": {
+        "credentials": {
+          "credentials": [
+            { "pass": "sub1"}
+          ]
+        },
+        "gatewaySelector": {
+          "matchLabels": { "role": "gateway" }
+        }
+      }
+    }'::JSONB
+);
+
+INSERT INTO DEVICE_ALIASES(
+    APP,
+    DEVICE,
+    TYPE,
+    ALIAS
+) VALUES (
+    'app1',
+    'sub1',
+    'id',
+    'sub1'
+);
+
+--
+-- behind1 -> gateways: sub1
+--
+
+INSERT INTO DEVICES (
+    APP,
+    NAME,
+    UID,
+    CREATION_TIMESTAMP,
+    RESOURCE_VERSION,
+    GENERATION,
+    DATA
+) VALUES (
+    'app1',
+    'behind1',
+    '4e185ea6-7c26-11eb-a319-d45d6455d262',
+    '2020-01-01 00:00:00',
+    'A0EEBC99-9C0B-4EF8-BB6D-6BB9BD380A11',
+    0,
+    '{
+      "spec": {
+        "gatewaySelector": {
+          "matchNames": [ "sub1" ]
+        }
+      }
+    }'::JSONB
+);
+
+INSERT INTO DEVICE_ALIASES(
+    APP,
+    DEVICE,
+    TYPE,
+    ALIAS
+) VALUES (
+    'app1',
+    'behind1',
+    'id',
+    'behind1'
+);
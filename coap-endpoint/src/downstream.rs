@@ -61,6 +61,12 @@ where
                 Some(v)
             })),
 
+            // ok, but quota exceeded
+            Ok(PublishOutcome::QuotaExceeded) => Ok(req.response.and_then(|mut v| {
+                v.set_status(ResponseType::TooManyRequests);
+                Some(v)
+            })),
+
             // internal error
             Err(err) => Err(CoapEndpointError(EndpointError::ConfigurationError {
                 details: err.to_string(),
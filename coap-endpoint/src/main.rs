@@ -12,7 +12,7 @@ use dotenv::dotenv;
 use drogue_cloud_endpoint_common::{
     command_endpoint::{CommandServer, CommandServerConfig},
     commands::Commands,
-    downstream::{DownstreamSender, DownstreamSink, KafkaSink},
+    downstream::{ConfiguredSink, DownstreamSender, DownstreamSink},
     error::EndpointError,
 };
 use drogue_cloud_service_common::{
@@ -38,6 +38,10 @@ const HEADER_AUTH: CoapOption = CoapOption::Unknown(4209);
 // Option Number 4210 correspons to the command header,
 // which is meant for commands to be sent back to the device
 const HEADER_COMMAND: CoapOption = CoapOption::Unknown(4210);
+//
+// Option Number 4211 corresponds to the time header, which contains
+// the time of the event (RFC 3339), as provided by the device
+const HEADER_TIME: CoapOption = CoapOption::Unknown(4211);
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct Config {
@@ -199,7 +203,7 @@ async fn main() -> anyhow::Result<()> {
     let coap_server_commands = commands.clone();
 
     let app = App {
-        downstream: DownstreamSender::new(KafkaSink::new("DOWNSTREAM_KAFKA_SINK")?)?,
+        downstream: DownstreamSender::new(ConfiguredSink::new("DOWNSTREAM").await?)?,
         authenticator: DeviceAuthenticator(
             drogue_cloud_endpoint_common::auth::DeviceAuthenticator::new().await?,
         ),
@@ -1,6 +1,8 @@
 use crate::auth::DeviceAuthenticator;
 use crate::downstream::CoapCommandSender;
 use crate::error::CoapEndpointError;
+use crate::HEADER_TIME;
+use chrono::{DateTime, Utc};
 use coap_lite::{CoapOption, CoapRequest, CoapResponse};
 use drogue_cloud_endpoint_common::{
     commands::Commands,
@@ -145,6 +147,23 @@ where
         None => device.metadata.name,
     };
 
+    let time = req
+        .message
+        .get_option(HEADER_TIME)
+        .and_then(|v| v.front())
+        .map(|v| {
+            std::str::from_utf8(v)
+                .ok()
+                .and_then(|v| DateTime::parse_from_rfc3339(v).ok())
+                .map(|time| time.with_timezone(&Utc))
+                .ok_or_else(|| {
+                    CoapEndpointError(EndpointError::InvalidRequest {
+                        details: "Invalid value of time option".into(),
+                    })
+                })
+        })
+        .transpose()?;
+
     // publish
 
     let publish = downstream::Publish {
@@ -152,6 +171,7 @@ where
         app_id: application.metadata.name.clone(),
         device_id: device_id.clone(),
         options: downstream::PublishOptions {
+            time,
             data_schema: opts.common.data_schema,
             topic: suffix,
             content_type: req
@@ -159,6 +179,7 @@ where
                 .get_option(CoapOption::ContentFormat)
                 .and_then(|v| std::str::from_utf8(v.front().unwrap()).ok())
                 .map(|s| s.to_string()),
+            quotas: downstream::QuotaSpec::from_application(&application),
             ..Default::default()
         },
     };
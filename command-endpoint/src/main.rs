@@ -7,9 +7,9 @@ use actix_web::{
     web, App, HttpResponse, HttpServer, Responder,
 };
 use dotenv::dotenv;
-use drogue_client::registry;
-use drogue_cloud_endpoint_common::downstream::{DownstreamSender, KafkaSink};
+use drogue_cloud_endpoint_common::downstream::{ConfiguredSink, DownstreamSender};
 use drogue_cloud_service_common::{
+    client::RegistryGatewayClient,
     config::ConfigFromEnv,
     defaults,
     health::{HealthServer, HealthServerConfig},
@@ -69,7 +69,7 @@ async fn main() -> anyhow::Result<()> {
 
     log::info!("Starting Command service endpoint");
 
-    let sender = DownstreamSender::new(KafkaSink::new("COMMAND_KAFKA_SINK")?)?;
+    let sender = DownstreamSender::new(ConfiguredSink::new("COMMAND").await?)?;
 
     let config = Config::from_env()?;
     let max_json_payload_size = config.max_json_payload_size;
@@ -86,7 +86,7 @@ async fn main() -> anyhow::Result<()> {
 
     let client = reqwest::Client::new();
 
-    let registry = registry::v1::Client::new(
+    let registry = RegistryGatewayClient::new(
         client.clone(),
         config.registry.url,
         Some(
@@ -95,7 +95,7 @@ async fn main() -> anyhow::Result<()> {
                 .discover_from(client.clone())
                 .await?,
         ),
-    );
+    )?;
 
     // health server
 
@@ -124,7 +124,7 @@ async fn main() -> anyhow::Result<()> {
                     .wrap(Cors::permissive())
                     .service(
                         web::resource("/apps/{appId}/devices/{deviceId}")
-                            .route(web::post().to(v1alpha1::command::<KafkaSink>)),
+                            .route(web::post().to(v1alpha1::command::<ConfiguredSink>)),
                     ),
             )
     })
@@ -1,11 +1,12 @@
 use actix_web::{http::header, web, HttpResponse};
 use actix_web_httpauth::extractors::bearer::BearerAuth;
-use drogue_client::{registry, Context};
+use drogue_client::Context;
 use drogue_cloud_endpoint_common::{
     downstream::{DownstreamSender, DownstreamSink},
     error::HttpEndpointError,
 };
 use drogue_cloud_integration_common::{self, commands::CommandOptions};
+use drogue_cloud_service_common::client::RegistryGatewayClient;
 use serde::Deserialize;
 
 #[derive(Clone, Debug, Deserialize)]
@@ -20,7 +21,7 @@ pub async fn command<S>(
     web::Query(opts): web::Query<CommandQuery>,
     req: web::HttpRequest,
     body: web::Bytes,
-    registry: web::Data<registry::v1::Client>,
+    registry: web::Data<RegistryGatewayClient>,
     token: BearerAuth,
 ) -> Result<HttpResponse, HttpEndpointError>
 where
@@ -0,0 +1,108 @@
+mod tracker;
+
+use dotenv::dotenv;
+use drogue_client::registry;
+use drogue_cloud_integration_common::stream::{EventSource, EventStreamConfig};
+use drogue_cloud_service_common::{
+    client::RegistryStatusClient,
+    config::ConfigFromEnv,
+    defaults,
+    health::{HealthServer, HealthServerConfig},
+    openid::TokenConfig,
+};
+use serde::Deserialize;
+use std::{collections::HashMap, time::Duration};
+use url::Url;
+
+#[derive(Clone, Debug, Deserialize)]
+struct Config {
+    #[serde(default)]
+    pub event_source: EventSource,
+    #[serde(default = "defaults::kafka_bootstrap_servers")]
+    pub kafka_bootstrap_servers: String,
+    #[serde(default = "defaults::kafka_events_topic")]
+    pub kafka_topic: String,
+    #[serde(default)]
+    pub kafka_properties: HashMap<String, String>,
+
+    /// The interval in which the last-seen time of devices gets stored.
+    #[serde(default = "default_update_interval", with = "humantime_serde")]
+    pub update_interval: Duration,
+
+    #[serde(default)]
+    pub registry: RegistryConfig,
+
+    #[serde(default)]
+    pub health: HealthServerConfig,
+}
+
+#[inline]
+fn default_update_interval() -> Duration {
+    Duration::from_secs(60)
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct RegistryConfig {
+    #[serde(default = "defaults::registry_url")]
+    pub url: Url,
+}
+
+impl Default for RegistryConfig {
+    fn default() -> Self {
+        Self {
+            url: defaults::registry_url(),
+        }
+    }
+}
+
+#[actix_web::main]
+async fn main() -> anyhow::Result<()> {
+    env_logger::init();
+    dotenv().ok();
+
+    let config = Config::from_env()?;
+
+    log::info!("Event source: {:?}", config.event_source);
+    log::info!("Kafka servers: {}", config.kafka_bootstrap_servers);
+    log::info!("Kafka topic: {}", config.kafka_topic);
+
+    let client = reqwest::Client::new();
+    let token_provider = TokenConfig::from_env_prefix("REGISTRY")?
+        .amend_with_env()
+        .discover_from(client.clone())
+        .await?;
+    let registry = registry::v1::Client::new(
+        client.clone(),
+        config.registry.url.clone(),
+        Some(token_provider.clone()),
+    );
+    let status =
+        RegistryStatusClient::new(client.clone(), config.registry.url, Some(token_provider))?;
+
+    let tracker = tracker::Tracker::new(registry, status, config.update_interval);
+
+    // track the events of all applications
+
+    let stream = EventStreamConfig {
+        source: config.event_source,
+        bootstrap_servers: config.kafka_bootstrap_servers,
+        properties: config.kafka_properties,
+        topic: config.kafka_topic,
+        app: None,
+        consumer_group: Some("connection-tracker".into()),
+        manual_commit: false,
+        since: None,
+    };
+
+    // health server
+
+    let health = HealthServer::new(config.health, vec![]);
+
+    // run
+
+    futures::try_join!(health.run(), tracker.run(stream))?;
+
+    // exiting
+
+    Ok(())
+}
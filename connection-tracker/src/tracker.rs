@@ -0,0 +1,340 @@
+use chrono::{DateTime, Utc};
+use cloudevents::{event::ExtensionValue, AttributesReader, Data, Event};
+use drogue_client::registry;
+use drogue_cloud_endpoint_common::downstream::EXT_RECEIVE_TIME;
+use drogue_cloud_integration_common::stream::{EventStream, EventStreamConfig};
+use drogue_cloud_service_api::{
+    connection::{ConnectionEvent, DeviceConnectionStatus, EVENT_TYPE_CONNECTION},
+    EXT_APPLICATION, EXT_DEVICE,
+};
+use drogue_cloud_service_common::client::RegistryStatusClient;
+use futures::{stream, StreamExt};
+use std::{collections::HashMap, time::Duration};
+use tokio::sync::mpsc;
+
+/// The number of devices stored concurrently.
+const STORE_CONCURRENCY: usize = 10;
+
+/// A change of the connection state, not yet stored.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Connection {
+    connected: bool,
+    protocol: String,
+    since: DateTime<Utc>,
+}
+
+/// The changes of a device, not yet stored.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct Pending {
+    last_seen: Option<DateTime<Utc>>,
+    connection: Option<Connection>,
+}
+
+impl Pending {
+    /// Record an event, returning `true` if it changed the connection state.
+    fn apply(&mut self, event: &Event) -> bool {
+        let time = received(event);
+
+        if self.last_seen.map_or(true, |last_seen| last_seen < time) {
+            self.last_seen = Some(time);
+        }
+
+        if event.ty() != EVENT_TYPE_CONNECTION {
+            return false;
+        }
+
+        let connection = match connection(event) {
+            Some(connection) => connection,
+            None => return false,
+        };
+
+        // ignore events older than the state we already have
+        if let Some(current) = &self.connection {
+            if current.since > time {
+                return false;
+            }
+        }
+
+        self.connection = Some(Connection {
+            connected: connection.connected,
+            protocol: connection.protocol,
+            since: time,
+        });
+
+        true
+    }
+
+    /// Merge the changes into the stored status.
+    fn merge_into(&self, status: &mut DeviceConnectionStatus) {
+        if let Some(last_seen) = self.last_seen {
+            if status.last_seen.map_or(true, |current| current < last_seen) {
+                status.last_seen = Some(last_seen);
+            }
+        }
+
+        if let Some(connection) = &self.connection {
+            if status.since.map_or(true, |since| since <= connection.since) {
+                status.connected = connection.connected;
+                status.protocol = Some(connection.protocol.clone());
+                status.since = Some(connection.since);
+            }
+        }
+    }
+}
+
+/// Get a string extension of the event.
+fn extension<'e>(event: &'e Event, name: &str) -> Option<&'e str> {
+    match event.extension(name) {
+        Some(ExtensionValue::String(value)) => Some(value.as_str()),
+        _ => None,
+    }
+}
+
+/// The time the event was received by the endpoint.
+fn received(event: &Event) -> DateTime<Utc> {
+    extension(event, EXT_RECEIVE_TIME)
+        .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+        .map(|time| time.with_timezone(&Utc))
+        .or_else(|| event.time().cloned())
+        .unwrap_or_else(Utc::now)
+}
+
+fn connection(event: &Event) -> Option<ConnectionEvent> {
+    match event.data() {
+        Some(Data::Json(value)) => serde_json::from_value(value.clone()).ok(),
+        Some(Data::Binary(value)) => serde_json::from_slice(value).ok(),
+        Some(Data::String(value)) => serde_json::from_str(value).ok(),
+        None => None,
+    }
+}
+
+type Key = (String, String);
+
+/// Storing batches of changes in the status section of devices.
+#[derive(Clone)]
+struct Writer {
+    registry: registry::v1::Client,
+    status: RegistryStatusClient,
+}
+
+impl Writer {
+    /// Store batches, returning the changes which failed to be stored.
+    async fn run(
+        self,
+        mut batches: mpsc::UnboundedReceiver<Vec<(Key, Pending)>>,
+        failed: mpsc::UnboundedSender<(Key, Pending)>,
+    ) {
+        // batches are stored one after the other, so that changes of a device don't overtake
+        while let Some(batch) = batches.recv().await {
+            log::debug!("Storing {} pending changes", batch.len());
+
+            let writer = &self;
+            let mut results = stream::iter(batch)
+                .map(|(key, pending)| async move {
+                    let result = writer.store(&key, &pending).await;
+                    (key, pending, result)
+                })
+                .buffer_unordered(STORE_CONCURRENCY);
+
+            while let Some((key, pending, result)) = results.next().await {
+                if let Err(err) = result {
+                    log::info!(
+                        "Failed to store connection status of '{}/{}': {}",
+                        key.0,
+                        key.1,
+                        err
+                    );
+                    let _ = failed.send((key, pending));
+                }
+            }
+        }
+    }
+
+    /// Store the pending changes of a device.
+    async fn store(&self, (app, device): &Key, pending: &Pending) -> anyhow::Result<()> {
+        if let Some(mut device) = self
+            .registry
+            .get_device(app, device, Default::default())
+            .await?
+        {
+            device.update_section(|mut status: DeviceConnectionStatus| {
+                pending.merge_into(&mut status);
+                status
+            })?;
+            self.status
+                .update_device_status(&device, Default::default())
+                .await?;
+        }
+        // if the device is gone, there is nothing to store
+        Ok(())
+    }
+}
+
+/// Tracking the connection state and last-seen time of devices, and storing it in the status
+/// section of the device.
+///
+/// Changes of the connection state are stored right away, updates of the last-seen time are
+/// batched and stored once per update interval. Storing is performed by a separate task, so that
+/// the event stream doesn't have to wait for the registry.
+pub struct Tracker {
+    writer: Writer,
+    update_interval: Duration,
+    pending: HashMap<Key, Pending>,
+}
+
+impl Tracker {
+    pub fn new(
+        registry: registry::v1::Client,
+        status: RegistryStatusClient,
+        update_interval: Duration,
+    ) -> Self {
+        Self {
+            writer: Writer { registry, status },
+            update_interval,
+            pending: HashMap::new(),
+        }
+    }
+
+    pub async fn run(mut self, config: EventStreamConfig) -> anyhow::Result<()> {
+        let mut stream = EventStream::new(config)?;
+        let mut interval = tokio::time::interval(self.update_interval);
+
+        let (batches, batches_rx) = mpsc::unbounded_channel();
+        let (failed_tx, mut failed) = mpsc::unbounded_channel();
+        let writer = actix_web::rt::spawn(self.writer.clone().run(batches_rx, failed_tx));
+
+        let result = loop {
+            tokio::select! {
+                event = stream.next() => match event {
+                    Some(Ok(event)) => self.handle_event(event, &batches),
+                    Some(Err(err)) => break Err(anyhow::anyhow!("Failed to receive event: {}", err)),
+                    None => break Err(anyhow::anyhow!("Event stream closed")),
+                },
+                _ = interval.tick() => self.flush(&batches),
+                Some((key, pending)) = failed.recv() => {
+                    // try again with the next update, unless newer changes are already pending
+                    self.pending.entry(key).or_insert(pending);
+                }
+            }
+        };
+
+        writer.abort();
+        result
+    }
+
+    fn handle_event(&mut self, event: Event, batches: &mpsc::UnboundedSender<Vec<(Key, Pending)>>) {
+        let key = match (
+            extension(&event, EXT_APPLICATION),
+            extension(&event, EXT_DEVICE),
+        ) {
+            (Some(app), Some(device)) => (app.to_string(), device.to_string()),
+            _ => return,
+        };
+
+        let changed = self.pending.entry(key.clone()).or_default().apply(&event);
+
+        if changed {
+            if let Some(pending) = self.pending.remove(&key) {
+                let _ = batches.send(vec![(key, pending)]);
+            }
+        }
+    }
+
+    /// Hand over all pending changes for storing.
+    fn flush(&mut self, batches: &mpsc::UnboundedSender<Vec<(Key, Pending)>>) {
+        if !self.pending.is_empty() {
+            let _ = batches.send(self.pending.drain().collect());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+    use cloudevents::{EventBuilder, EventBuilderV10};
+    use serde_json::json;
+
+    fn event(ty: &str, time: DateTime<Utc>, data: serde_json::Value) -> Event {
+        EventBuilderV10::new()
+            .id("1")
+            .ty(ty)
+            .source("drogue://app/device")
+            .extension(EXT_RECEIVE_TIME, time.to_rfc3339())
+            .data("application/json", data)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_apply() {
+        let t1 = Utc.ymd(2021, 1, 1).and_hms(0, 0, 0);
+        let t2 = Utc.ymd(2021, 1, 1).and_hms(0, 1, 0);
+
+        let mut pending = Pending::default();
+
+        assert!(!pending.apply(&event("io.drogue.event.v1", t1, json!({}))));
+        assert_eq!(pending.last_seen, Some(t1));
+        assert_eq!(pending.connection, None);
+
+        assert!(pending.apply(&event(
+            EVENT_TYPE_CONNECTION,
+            t2,
+            json!({"connected": true, "protocol": "mqtt"})
+        )));
+        assert_eq!(pending.last_seen, Some(t2));
+        assert_eq!(
+            pending.connection,
+            Some(Connection {
+                connected: true,
+                protocol: "mqtt".into(),
+                since: t2
+            })
+        );
+
+        // outdated events don't change the state
+        assert!(!pending.apply(&event(
+            EVENT_TYPE_CONNECTION,
+            t1,
+            json!({"connected": false, "protocol": "mqtt"})
+        )));
+        assert_eq!(pending.last_seen, Some(t2));
+    }
+
+    #[test]
+    fn test_merge() {
+        let t1 = Utc.ymd(2021, 1, 1).and_hms(0, 0, 0);
+        let t2 = Utc.ymd(2021, 1, 1).and_hms(0, 1, 0);
+
+        let mut status = DeviceConnectionStatus {
+            connected: true,
+            protocol: Some("mqtt".into()),
+            since: Some(t1),
+            last_seen: Some(t1),
+        };
+
+        // only a new last-seen time keeps the connection state
+        Pending {
+            last_seen: Some(t2),
+            connection: None,
+        }
+        .merge_into(&mut status);
+
+        assert!(status.connected);
+        assert_eq!(status.last_seen, Some(t2));
+
+        Pending {
+            last_seen: Some(t1),
+            connection: Some(Connection {
+                connected: false,
+                protocol: "mqtt".into(),
+                since: t2,
+            }),
+        }
+        .merge_into(&mut status);
+
+        assert!(!status.connected);
+        assert_eq!(status.since, Some(t2));
+        assert_eq!(status.last_seen, Some(t2));
+    }
+}
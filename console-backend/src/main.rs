@@ -19,6 +19,7 @@ use drogue_cloud_api_key_service::{
     endpoints as keys,
     service::{KeycloakApiKeyService, KeycloakApiKeyServiceConfig},
 };
+use drogue_cloud_integration_common::stream::EventSource;
 use drogue_cloud_service_api::endpoints::Endpoints;
 use drogue_cloud_service_common::{
     client::{UserAuthClient, UserAuthClientConfig},
@@ -56,6 +57,8 @@ pub struct Config {
     pub health_bind_addr: String,
     #[serde(default = "defaults::enable_auth")]
     pub enable_auth: bool,
+    #[serde(default)]
+    pub event_source: EventSource,
     #[serde(default = "defaults::kafka_bootstrap_servers")]
     pub kafka_bootstrap_servers: String,
     #[serde(default = "defaults::kafka_events_topic")]
@@ -2,7 +2,9 @@ use crate::Config;
 use actix::clock::{interval_at, Instant};
 use actix_web::http::header::ContentType;
 use actix_web::{get, web, web::Bytes, HttpResponse};
-use drogue_cloud_integration_common::stream::{EventStream, EventStreamConfig, IntoSseStream};
+use drogue_cloud_integration_common::stream::{
+    parse_since, EventStream, EventStreamConfig, IntoSseStream,
+};
 use drogue_cloud_service_api::auth::user::{
     authz::{AuthorizationRequest, Permission},
     UserInformation,
@@ -20,6 +22,9 @@ use tokio_stream::wrappers::IntervalStream;
 pub struct SpyQuery {
     token: String,
     app: String,
+    /// Replay events, starting at this time.
+    #[serde(default)]
+    since: Option<String>,
 }
 
 #[get("/spy")]
@@ -60,12 +65,22 @@ pub async fn stream_events(
             .ensure(|| ServiceError::AuthenticationError)?
     }
 
+    let since = query
+        .since
+        .as_deref()
+        .map(parse_since)
+        .transpose()
+        .map_err(ServiceError::InvalidRequest)?;
+
     let cfg = EventStreamConfig {
+        source: config.event_source,
         bootstrap_servers: config.kafka_bootstrap_servers.clone(),
         properties: config.kafka_properties.clone(),
         topic: config.kafka_topic.clone(),
-        app: query.app.clone(),
+        app: Some(query.app.clone()),
         consumer_group: None,
+        manual_commit: false,
+        since,
     };
 
     log::debug!("Config: {:?}", cfg);
@@ -0,0 +1,195 @@
+use crate::{backend::Backend, error::error};
+use drogue_cloud_service_api::history::HistoryEntry;
+use patternfly_yew::*;
+use serde_json::Value;
+use yew::{format::*, prelude::*, services::fetch::*};
+
+/// The number of history entries loaded at once.
+const HISTORY_LIMIT: usize = 100;
+/// The header carrying the token for loading the next page.
+const HEADER_CONTINUE: &str = "X-Continue";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct HistoryRow {
+    pub entry: HistoryEntry,
+}
+
+impl HistoryRow {
+    /// Summarize the JSON patch of a change, by listing the changed paths.
+    fn changes(&self) -> String {
+        match &self.entry.diff {
+            Value::Array(ops) => ops
+                .iter()
+                .map(|op| match (op["op"].as_str(), op["path"].as_str()) {
+                    (Some(op), Some("")) => format!("{} /", op),
+                    (Some(op), Some(path)) => format!("{} {}", op, path),
+                    _ => "?".into(),
+                })
+                .collect::<Vec<_>>()
+                .join(", "),
+            _ => String::new(),
+        }
+    }
+}
+
+impl TableRenderer for HistoryRow {
+    fn render(&self, column: ColumnIndex) -> Html {
+        match column.index {
+            0 => self.entry.timestamp.format("%e %b %Y, %k:%M:%S").into(),
+            1 => format!("{} / {}", self.entry.kind, self.entry.name).into(),
+            2 => self.entry.operation.to_string().into(),
+            3 => self
+                .entry
+                .user_id
+                .clone()
+                .unwrap_or_else(|| "<anonymous>".into())
+                .into(),
+            4 => self.entry.generation.to_string().into(),
+            5 => html! { <code>{self.changes()}</code> },
+            _ => html! {},
+        }
+    }
+}
+
+#[derive(Clone, Debug, Properties, PartialEq)]
+pub struct Props {
+    pub backend: Backend,
+    /// The URL of the history to show.
+    pub url: String,
+}
+
+pub enum Msg {
+    Load,
+    LoadMore,
+    /// Entries of a page, with the token for loading the next page.
+    AddData(Vec<HistoryEntry>, Option<String>),
+    Error(String),
+}
+
+/// Show the history of changes of a resource.
+pub struct History {
+    props: Props,
+    link: ComponentLink<Self>,
+
+    fetch_task: Option<FetchTask>,
+
+    entries: Vec<HistoryRow>,
+    /// The token for loading the next page, if there is one.
+    next: Option<String>,
+}
+
+impl Component for History {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        link.send_message(Msg::Load);
+
+        Self {
+            props,
+            link,
+            fetch_task: None,
+            entries: Vec::new(),
+            next: None,
+        }
+    }
+
+    fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        match msg {
+            Msg::Load => {
+                self.entries.clear();
+                self.next = None;
+                match self.load(None) {
+                    Ok(task) => self.fetch_task = Some(task),
+                    Err(err) => error("Failed to load", err),
+                }
+            }
+            Msg::LoadMore => match self.load(self.next.as_deref()) {
+                Ok(task) => self.fetch_task = Some(task),
+                Err(err) => error("Failed to load", err),
+            },
+            Msg::AddData(entries, next) => {
+                self.entries
+                    .extend(entries.into_iter().map(|entry| HistoryRow { entry }));
+                self.next = next;
+                self.fetch_task = None;
+            }
+            Msg::Error(msg) => {
+                self.fetch_task = None;
+                error("Error", msg);
+            }
+        }
+        true
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        if self.props != props {
+            self.props = props;
+            self.link.send_message(Msg::Load);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn view(&self) -> Html {
+        return html! {
+            <>
+            <Table<SimpleTableModel<HistoryRow>>
+                entries=SimpleTableModel::from(self.entries.clone())
+                header={html_nested!{
+                    <TableHeader>
+                        <TableColumn label="Time"/>
+                        <TableColumn label="Resource"/>
+                        <TableColumn label="Operation"/>
+                        <TableColumn label="User"/>
+                        <TableColumn label="Generation"/>
+                        <TableColumn label="Changes"/>
+                    </TableHeader>
+                }}
+                >
+            </Table<SimpleTableModel<HistoryRow>>>
+            {
+                if self.next.is_some() {
+                    html! {
+                        <Button disabled=self.fetch_task.is_some() label="Load more" variant=Variant::Link onclick=self.link.callback(|_|Msg::LoadMore)/>
+                    }
+                } else {
+                    html! {}
+                }
+            }
+            </>
+        };
+    }
+}
+
+impl History {
+    /// Load a page of the history, continuing after a previous page if a token is provided.
+    fn load(&self, next: Option<&str>) -> Result<FetchTask, anyhow::Error> {
+        let mut url = format!("{}?limit={}", self.props.url, HISTORY_LIMIT);
+        if let Some(next) = next {
+            url.push_str(&format!("&continue={}", next));
+        }
+
+        self.props.backend.info.request(
+            Method::GET,
+            url,
+            Nothing,
+            vec![],
+            self.link.callback(
+                move |response: Response<Json<Result<Vec<HistoryEntry>, anyhow::Error>>>| {
+                    let (meta, Json(body)) = response.into_parts();
+                    let next = meta
+                        .headers
+                        .get(HEADER_CONTINUE)
+                        .and_then(|value| value.to_str().ok())
+                        .map(ToString::to_string);
+                    match body {
+                        Ok(entries) => Msg::AddData(entries, next),
+                        Err(err) => Msg::Error(err.to_string()),
+                    }
+                },
+            ),
+        )
+    }
+}
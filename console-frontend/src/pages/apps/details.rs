@@ -1,6 +1,7 @@
 use super::{ApplicationTabs, Pages};
 use crate::{
-    backend::Backend, error::error, page::AppRoute, pages::apps::DetailsSection, utils::url_encode,
+    backend::Backend, components::history::History, error::error, page::AppRoute,
+    pages::apps::DetailsSection, utils::url_encode,
 };
 use drogue_client::registry::v1::Application;
 use monaco::{api::*, sys::editor::BuiltinTheme, yew::CodeEditor};
@@ -188,6 +189,7 @@ impl Details {
                         >
                         <TabRouterItem<DetailsSection> to=DetailsSection::Overview label="Overview"/>
                         <TabRouterItem<DetailsSection> to=DetailsSection::Yaml label="YAML"/>
+                        <TabRouterItem<DetailsSection> to=DetailsSection::History label="History"/>
                     </ApplicationTabs>
                 </PageSection>
                 <PageSection>
@@ -195,6 +197,12 @@ impl Details {
                     match self.props.details {
                         DetailsSection::Overview => self.render_overview(app),
                         DetailsSection::Yaml => self.render_editor(),
+                        DetailsSection::History => html!{
+                            <History
+                                backend=self.props.backend.clone()
+                                url=format!("/api/admin/v1alpha1/apps/{}/history", url_encode(&self.props.name))
+                                />
+                        },
                     }
                 }
                 </PageSection>
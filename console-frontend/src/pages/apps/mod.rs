@@ -25,6 +25,8 @@ pub enum Pages {
 pub enum DetailsSection {
     #[to = "yaml"]
     Yaml,
+    #[to = "history"]
+    History,
     #[end]
     Overview,
 }
@@ -1,12 +1,14 @@
 use super::{DevicesTabs, Pages};
 use crate::{
     backend::Backend,
+    components::history::History,
     error::error,
     page::AppRoute,
     pages::{apps::ApplicationContext, devices::DetailsSection},
     utils::url_encode,
 };
-use drogue_client::registry::v1::Device;
+use drogue_client::{registry::v1::Device, Translator};
+use drogue_cloud_service_api::connection::DeviceConnectionStatus;
 use monaco::{api::*, sys::editor::BuiltinTheme, yew::CodeEditor};
 use patternfly_yew::*;
 use std::rc::Rc;
@@ -194,6 +196,7 @@ impl Details {
                         >
                         <TabRouterItem<DetailsSection> to=DetailsSection::Overview label="Overview"/>
                         <TabRouterItem<DetailsSection> to=DetailsSection::Yaml label="YAML"/>
+                        <TabRouterItem<DetailsSection> to=DetailsSection::History label="History"/>
                     </DevicesTabs>
                 </PageSection>
                 <PageSection>
@@ -201,6 +204,16 @@ impl Details {
                     match self.props.details {
                         DetailsSection::Overview => self.render_overview(device),
                         DetailsSection::Yaml => self.render_editor(),
+                        DetailsSection::History => html!{
+                            <History
+                                backend=self.props.backend.clone()
+                                url=format!(
+                                    "/api/admin/v1alpha1/apps/{}/devices/{}/history",
+                                    url_encode(&self.props.app),
+                                    url_encode(&self.props.name)
+                                )
+                                />
+                        },
                     }
                 }
                 </PageSection>
@@ -234,10 +247,49 @@ impl Details {
                     </DescriptionList>
                     </Card>
                 </GridItem>
+                <GridItem cols=[3]>
+                    { self.render_connection(device) }
+                </GridItem>
             </Grid>
         };
     }
 
+    fn render_connection(&self, device: &Device) -> Html {
+        let status = device
+            .section::<DeviceConnectionStatus>()
+            .and_then(|status| status.ok())
+            .unwrap_or_default();
+
+        let state = match (status.connected, &status.protocol) {
+            (true, Some(protocol)) => format!("Connected ({})", protocol),
+            (true, None) => "Connected".into(),
+            (false, _) => "Disconnected".into(),
+        };
+
+        let time = |time: Option<chrono::DateTime<chrono::Utc>>| {
+            time.map(|time| time.to_rfc2822())
+                .unwrap_or_else(|| "Never".into())
+        };
+
+        return html! {
+            <Card
+                title={html_nested!{<>{"Connection"}</>}}
+            >
+            <DescriptionList>
+                <DescriptionGroup term="State">
+                    {state}
+                </DescriptionGroup>
+                <DescriptionGroup term="Since">
+                    {time(status.since)}
+                </DescriptionGroup>
+                <DescriptionGroup term="Last seen">
+                    {time(status.last_seen)}
+                </DescriptionGroup>
+            </DescriptionList>
+            </Card>
+        };
+    }
+
     fn render_editor(&self) -> Html {
         let options = CodeEditorOptions::default()
             .with_scroll_beyond_last_line(false)
@@ -0,0 +1,17 @@
+-- the twin state of devices
+
+CREATE TABLE device_twins (
+    APP VARCHAR(64) NOT NULL,
+    DEVICE VARCHAR(256) NOT NULL,
+
+    -- the state reported by the device
+    REPORTED JSONB NOT NULL DEFAULT '{}',
+    REPORTED_TIMESTAMP TIMESTAMP WITH TIME ZONE,
+
+    -- the state desired by the application
+    DESIRED JSONB NOT NULL DEFAULT '{}',
+    DESIRED_TIMESTAMP TIMESTAMP WITH TIME ZONE,
+
+    PRIMARY KEY (APP, DEVICE),
+    FOREIGN KEY (DEVICE, APP) REFERENCES devices (NAME, APP) ON DELETE CASCADE
+);
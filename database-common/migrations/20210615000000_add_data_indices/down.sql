@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS DEVICES_BY_DATA_IDX;
+DROP INDEX IF EXISTS APPLICATIONS_BY_DATA_IDX;
@@ -0,0 +1,3 @@
+-- creating indices for efficiently selecting resources by the content of their spec and status
+CREATE INDEX APPLICATIONS_BY_DATA_IDX ON APPLICATIONS USING GIN (DATA jsonb_path_ops);
+CREATE INDEX DEVICES_BY_DATA_IDX ON DEVICES USING GIN (DATA jsonb_path_ops);
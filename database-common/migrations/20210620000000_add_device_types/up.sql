@@ -0,0 +1,19 @@
+-- types of devices, which devices inherit their spec from
+
+CREATE TABLE device_types (
+    APP VARCHAR(64) NOT NULL,
+    NAME VARCHAR(256) NOT NULL,
+    UID UUID NOT NULL,
+
+    LABELS JSONB,
+    ANNOTATIONS JSONB,
+
+    CREATION_TIMESTAMP TIMESTAMP WITH TIME ZONE NOT NULL,
+    RESOURCE_VERSION UUID NOT NULL,
+    GENERATION BIGINT NOT NULL,
+
+    DATA JSONB,
+
+    PRIMARY KEY (APP, NAME),
+    FOREIGN KEY (APP) REFERENCES applications (NAME) ON DELETE CASCADE
+);
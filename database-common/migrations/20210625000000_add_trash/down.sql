@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS deleted_devices;
+DROP TABLE IF EXISTS deleted_applications;
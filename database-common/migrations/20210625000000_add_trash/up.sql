@@ -0,0 +1,51 @@
+-- the trash, keeping deleted resources until they expire
+
+CREATE TABLE deleted_applications (
+    UID UUID NOT NULL,
+    NAME VARCHAR(64) NOT NULL,
+
+    OWNER VARCHAR(256),
+    MEMBERS JSONB,
+    GENERATION BIGINT NOT NULL,
+
+    DELETION_TIMESTAMP TIMESTAMP WITH TIME ZONE NOT NULL,
+    EXPIRATION_TIMESTAMP TIMESTAMP WITH TIME ZONE NOT NULL,
+
+    -- the application record, as it was stored in the "applications" table
+    RESOURCE JSONB NOT NULL,
+    -- the records of the "application_aliases" table
+    ALIASES JSONB NOT NULL DEFAULT '[]',
+    -- the records of the "device_types" table
+    DEVICE_TYPES JSONB NOT NULL DEFAULT '[]',
+
+    PRIMARY KEY (UID)
+);
+
+CREATE INDEX deleted_applications_name ON deleted_applications (NAME);
+CREATE INDEX deleted_applications_expiration ON deleted_applications (EXPIRATION_TIMESTAMP);
+
+CREATE TABLE deleted_devices (
+    UID UUID NOT NULL,
+    APP VARCHAR(64) NOT NULL,
+    APP_UID UUID NOT NULL,
+    NAME VARCHAR(256) NOT NULL,
+
+    GENERATION BIGINT NOT NULL,
+    -- the device got deleted as part of deleting its application
+    CASCADED BOOLEAN NOT NULL,
+
+    DELETION_TIMESTAMP TIMESTAMP WITH TIME ZONE NOT NULL,
+    EXPIRATION_TIMESTAMP TIMESTAMP WITH TIME ZONE NOT NULL,
+
+    -- the device record, as it was stored in the "devices" table
+    RESOURCE JSONB NOT NULL,
+    -- the records of the "device_aliases" table
+    ALIASES JSONB NOT NULL DEFAULT '[]',
+    -- the record of the "device_twins" table, if the device had a twin
+    TWIN JSONB,
+
+    PRIMARY KEY (UID)
+);
+
+CREATE INDEX deleted_devices_app ON deleted_devices (APP_UID, NAME);
+CREATE INDEX deleted_devices_expiration ON deleted_devices (EXPIRATION_TIMESTAMP);
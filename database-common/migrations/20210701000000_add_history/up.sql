@@ -0,0 +1,26 @@
+-- the history of changes to registry resources
+
+CREATE TABLE resource_history (
+    ID BIGSERIAL NOT NULL,
+
+    APP VARCHAR(64) NOT NULL,
+    -- the instance of the application, as names may be re-used
+    APP_UID UUID NOT NULL,
+
+    KIND VARCHAR(32) NOT NULL,
+    NAME VARCHAR(256) NOT NULL,
+    UID UUID NOT NULL,
+
+    OPERATION VARCHAR(16) NOT NULL,
+    TIMESTAMP TIMESTAMP WITH TIME ZONE NOT NULL,
+    GENERATION BIGINT NOT NULL,
+    USER_ID VARCHAR(256),
+
+    -- the change, as JSON patch
+    DIFF JSONB NOT NULL,
+
+    PRIMARY KEY (ID)
+);
+
+CREATE INDEX resource_history_app ON resource_history (APP_UID, KIND, NAME);
+CREATE INDEX resource_history_timestamp ON resource_history (TIMESTAMP);
@@ -0,0 +1,18 @@
+-- the log of changes, used for resuming watches
+
+CREATE TABLE change_log (
+    SEQ BIGSERIAL NOT NULL,
+
+    -- NULL for the initial entry
+    APP VARCHAR(64),
+    DEVICE VARCHAR(256),
+
+    TS TIMESTAMP WITH TIME ZONE NOT NULL,
+
+    PRIMARY KEY (SEQ)
+);
+
+CREATE INDEX change_log_ts ON change_log (TS);
+
+-- the log is never empty, so that there always is a version to start from
+INSERT INTO change_log (TS) VALUES (now());
@@ -0,0 +1,6 @@
+CREATE INDEX resource_history_app ON resource_history (APP_UID, KIND, NAME);
+DROP INDEX IF EXISTS RESOURCE_HISTORY_BY_RESOURCE_IDX;
+DROP INDEX IF EXISTS RESOURCE_HISTORY_BY_APP_IDX;
+DROP INDEX IF EXISTS DEVICES_BY_CREATION_IDX;
+DROP INDEX IF EXISTS DEVICES_BY_NAME_IDX;
+DROP INDEX IF EXISTS APPLICATIONS_BY_CREATION_IDX;
@@ -0,0 +1,9 @@
+-- creating indices for efficiently paging through sorted lists
+CREATE INDEX APPLICATIONS_BY_CREATION_IDX ON APPLICATIONS (CREATION_TIMESTAMP, NAME, UID);
+CREATE INDEX DEVICES_BY_NAME_IDX ON DEVICES (APP, NAME, UID);
+CREATE INDEX DEVICES_BY_CREATION_IDX ON DEVICES (APP, CREATION_TIMESTAMP, NAME, UID);
+CREATE INDEX RESOURCE_HISTORY_BY_APP_IDX ON RESOURCE_HISTORY (APP_UID, ID);
+CREATE INDEX RESOURCE_HISTORY_BY_RESOURCE_IDX ON RESOURCE_HISTORY (APP_UID, KIND, NAME, ID);
+
+-- superseded by RESOURCE_HISTORY_BY_RESOURCE_IDX
+DROP INDEX IF EXISTS resource_history_app;
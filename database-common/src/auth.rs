@@ -99,6 +99,18 @@ where
     }
 }
 
+/// Ensure the status of a resource may be written.
+///
+/// The status is owned by controllers, so only they (and admins) may write it. It will return
+/// [`ServiceError::NotAuthorized`] otherwise.
+pub fn ensure_status(identity: &UserInformation) -> Result<(), ServiceError> {
+    if identity.is_admin() || identity.is_controller() {
+        Ok(())
+    } else {
+        Err(ServiceError::NotAuthorized)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -250,4 +262,12 @@ mod test {
             ]
         )
     }
+
+    #[test]
+    fn test_ensure_status() {
+        assert!(ensure_status(&user("foo", &["drogue-admin"])).is_ok());
+        assert!(ensure_status(&user("foo", &["drogue-controller"])).is_ok());
+        assert!(ensure_status(&user("foo", &[])).is_err());
+        assert!(ensure_status(&UserInformation::Anonymous).is_err());
+    }
 }
@@ -25,6 +25,8 @@ pub enum ServiceError {
     BadRequest(String),
     #[error("Lock failed")]
     OptimisticLockFailed,
+    #[error("Gone: {0}")]
+    Gone(String),
 }
 
 impl From<GenerationError> for ServiceError {
@@ -51,44 +53,44 @@ impl ServiceError {
 impl ResponseError for ServiceError {
     fn error_response(&self) -> HttpResponse {
         match self {
+            ServiceError::Internal(_) => HttpResponse::InternalServerError(),
+            ServiceError::Pool(..) => HttpResponse::ServiceUnavailable(),
+            ServiceError::Database(..) => HttpResponse::ServiceUnavailable(),
+            ServiceError::NotAuthorized => HttpResponse::Forbidden(),
+            ServiceError::NotFound => HttpResponse::NotFound(),
+            ServiceError::Conflict(_) => HttpResponse::Conflict(),
+            ServiceError::ReferenceNotFound => HttpResponse::NotFound(),
+            ServiceError::BadRequest(_) => HttpResponse::BadRequest(),
+            ServiceError::OptimisticLockFailed => HttpResponse::Conflict(),
+            ServiceError::Gone(_) => HttpResponse::Gone(),
+        }
+        .json(ErrorResponse::from(self))
+    }
+}
+
+impl From<&ServiceError> for ErrorResponse {
+    fn from(err: &ServiceError) -> Self {
+        let error = match err {
             ServiceError::Internal(message) => {
-                HttpResponse::InternalServerError().json(ErrorResponse {
+                return ErrorResponse {
                     error: "InternalError".into(),
                     message: message.clone(),
-                })
+                }
             }
-            ServiceError::Pool(..) => HttpResponse::ServiceUnavailable().json(ErrorResponse {
-                error: "PoolError".into(),
-                message: self.to_string(),
-            }),
-            ServiceError::Database(..) => HttpResponse::ServiceUnavailable().json(ErrorResponse {
-                error: "DatabaseError".into(),
-                message: self.to_string(),
-            }),
-            ServiceError::NotAuthorized => HttpResponse::Forbidden().json(ErrorResponse {
-                error: "AuthenticationError".into(),
-                message: self.to_string(),
-            }),
-            ServiceError::NotFound => HttpResponse::NotFound().json(ErrorResponse {
-                error: "NotFound".into(),
-                message: self.to_string(),
-            }),
-            ServiceError::Conflict(_) => HttpResponse::Conflict().json(ErrorResponse {
-                error: "Conflict".into(),
-                message: self.to_string(),
-            }),
-            ServiceError::ReferenceNotFound => HttpResponse::NotFound().json(ErrorResponse {
-                error: "ReferenceNotFound".into(),
-                message: self.to_string(),
-            }),
-            ServiceError::BadRequest(_) => HttpResponse::BadRequest().json(ErrorResponse {
-                error: "BadRequest".into(),
-                message: self.to_string(),
-            }),
-            ServiceError::OptimisticLockFailed => HttpResponse::Conflict().json(ErrorResponse {
-                error: "OptimisticLockFailed".into(),
-                message: self.to_string(),
-            }),
+            ServiceError::Pool(..) => "PoolError",
+            ServiceError::Database(..) => "DatabaseError",
+            ServiceError::NotAuthorized => "AuthenticationError",
+            ServiceError::NotFound => "NotFound",
+            ServiceError::Conflict(_) => "Conflict",
+            ServiceError::ReferenceNotFound => "ReferenceNotFound",
+            ServiceError::BadRequest(_) => "BadRequest",
+            ServiceError::OptimisticLockFailed => "OptimisticLockFailed",
+            ServiceError::Gone(_) => "Gone",
+        };
+
+        ErrorResponse {
+            error: error.into(),
+            message: err.to_string(),
         }
     }
 }
@@ -5,6 +5,7 @@ use crate::{
     generation,
     models::{
         fix_null_default,
+        paging::{Cursor, Sort},
         sql::{slice_iter, SelectBuilder},
         Lock, TypedAlias,
     },
@@ -14,7 +15,9 @@ use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use core::pin::Pin;
 use drogue_client::{meta, registry};
-use drogue_cloud_service_api::{auth::user::UserInformation, labels::LabelSelector};
+use drogue_cloud_service_api::{
+    auth::user::UserInformation, fields::FieldSelector, labels::LabelSelector,
+};
 use futures::{future, Stream, TryStreamExt};
 use indexmap::map::IndexMap;
 use serde::{Deserialize, Serialize};
@@ -130,11 +133,13 @@ pub trait ApplicationAccessor {
             .list(
                 Some(app),
                 LabelSelector::default(),
+                FieldSelector::default(),
                 Some(1),
                 None,
                 None,
                 lock,
-                &[],
+                &Sort::default(),
+                None,
             )
             .await?
             .try_next()
@@ -146,13 +151,23 @@ pub trait ApplicationAccessor {
         &self,
         name: Option<&str>,
         labels: LabelSelector,
+        fields: FieldSelector,
         limit: Option<usize>,
         offset: Option<usize>,
         id: Option<&UserInformation>,
         lock: Lock,
-        sort: &[&str],
+        sort: &Sort,
+        after: Option<&Cursor>,
     ) -> Result<Pin<Box<dyn Stream<Item = Result<Application, ServiceError>> + Send>>, ServiceError>;
 
+    /// Count the applications matching the selector
+    async fn count(
+        &self,
+        labels: LabelSelector,
+        fields: FieldSelector,
+        id: Option<&UserInformation>,
+    ) -> Result<u64, ServiceError>;
+
     /// Create a new application
     async fn create(
         &self,
@@ -293,11 +308,13 @@ FROM
         &self,
         name: Option<&str>,
         labels: LabelSelector,
+        fields: FieldSelector,
         limit: Option<usize>,
         offset: Option<usize>,
         id: Option<&UserInformation>,
         lock: Lock,
-        sort: &[&str],
+        sort: &Sort,
+        after: Option<&Cursor>,
     ) -> Result<Pin<Box<dyn Stream<Item = Result<Application, ServiceError>> + Send>>, ServiceError>
     {
         let select = format!(
@@ -323,9 +340,11 @@ FROM APPLICATIONS
         let builder = SelectBuilder::new(select, Vec::new())
             .name(&name)
             .labels(&labels.0)
+            .fields(&fields.0)
             .auth_read(&id)
+            .after(sort, after)
             .lock(lock)
-            .sort(sort)
+            .order(sort)
             .limit(limit)
             .offset(offset);
 
@@ -345,6 +364,31 @@ FROM APPLICATIONS
         Ok(Box::pin(stream))
     }
 
+    async fn count(
+        &self,
+        labels: LabelSelector,
+        fields: FieldSelector,
+        id: Option<&UserInformation>,
+    ) -> Result<u64, ServiceError> {
+        let builder =
+            SelectBuilder::new("SELECT COUNT(NAME) AS COUNT FROM APPLICATIONS", Vec::new())
+                .labels(&labels.0)
+                .fields(&fields.0)
+                .auth_read(&id);
+
+        let (select, params) = builder.build();
+
+        let count = self
+            .client
+            .query_opt(select.as_str(), &params[..])
+            .await?
+            .ok_or_else(|| {
+                ServiceError::Internal("Unable to retrieve number of applications".into())
+            })?;
+
+        Ok(count.try_get::<_, i64>("COUNT")? as u64)
+    }
+
     async fn create(
         &self,
         application: Application,
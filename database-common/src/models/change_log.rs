@@ -0,0 +1,189 @@
+use crate::{error::ServiceError, Client};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::{future, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
+
+/// The key of the advisory lock, serializing appends to the change log.
+const APPEND_LOCK: i64 = 0x6368_616e_6765_6c6f;
+
+/// A position in the change log.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Position {
+    pub seq: u64,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl Position {
+    fn from_row(row: &Row) -> Result<Self, tokio_postgres::Error> {
+        Ok(Self {
+            seq: row.try_get::<_, i64>("SEQ")? as u64,
+            timestamp: row.try_get("TS")?,
+        })
+    }
+}
+
+/// An entry of the change log.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChangeEntry {
+    pub seq: u64,
+    pub timestamp: DateTime<Utc>,
+
+    pub app: String,
+    pub device: Option<String>,
+}
+
+impl ChangeEntry {
+    pub fn position(&self) -> Position {
+        Position {
+            seq: self.seq,
+            timestamp: self.timestamp,
+        }
+    }
+
+    fn from_row(row: &Row) -> Result<Self, tokio_postgres::Error> {
+        Ok(Self {
+            seq: row.try_get::<_, i64>("SEQ")? as u64,
+            timestamp: row.try_get("TS")?,
+            app: row.try_get("APP")?,
+            device: row.try_get("DEVICE")?,
+        })
+    }
+}
+
+/// The log of changed objects.
+///
+/// Entries are numbered by an increasing sequence number, which is used as the version for
+/// resuming watches. The log only records which object changed, not the change itself.
+#[async_trait]
+pub trait ChangeLogAccessor {
+    /// Append a change to the log.
+    ///
+    /// This must be called in a transaction. It locks the log for appending until the transaction
+    /// ends, so that the sequence numbers are committed in order. Otherwise, readers could see an
+    /// entry before a pending one with a lower sequence number, and miss the latter when resuming
+    /// from their position.
+    async fn append(&self, app: &str, device: Option<&str>) -> Result<ChangeEntry, ServiceError>;
+
+    /// Get the latest position of the log.
+    async fn current(&self) -> Result<Position, ServiceError>;
+
+    /// Get a position of the log, if it is still known.
+    async fn position(&self, seq: u64) -> Result<Option<Position>, ServiceError>;
+
+    /// Get all entries after a sequence number, ordered by their sequence number.
+    async fn since(&self, seq: u64) -> Result<Vec<ChangeEntry>, ServiceError>;
+
+    /// Delete all entries older than `before`.
+    ///
+    /// This always keeps the latest entry.
+    async fn purge(&self, before: DateTime<Utc>) -> Result<u64, ServiceError>;
+}
+
+pub struct PostgresChangeLogAccessor<'c, C: Client> {
+    client: &'c C,
+}
+
+impl<'c, C: Client> PostgresChangeLogAccessor<'c, C> {
+    pub fn new(client: &'c C) -> Self {
+        Self { client }
+    }
+}
+
+#[async_trait]
+impl<'c, C: Client> ChangeLogAccessor for PostgresChangeLogAccessor<'c, C> {
+    async fn append(&self, app: &str, device: Option<&str>) -> Result<ChangeEntry, ServiceError> {
+        // the sequence number gets assigned on insert, so lock before that
+        self.client
+            .execute("SELECT pg_advisory_xact_lock($1)", &[&APPEND_LOCK])
+            .await?;
+
+        let row = self
+            .client
+            .query_opt(
+                r#"
+INSERT INTO change_log (
+    APP,
+    DEVICE,
+    TS
+) VALUES (
+    $1,
+    $2,
+    $3
+)
+RETURNING SEQ, APP, DEVICE, TS
+"#,
+                &[&app, &device, &Utc::now()],
+            )
+            .await?
+            .ok_or_else(|| ServiceError::Internal("Missing change log entry".into()))?;
+
+        Ok(ChangeEntry::from_row(&row)?)
+    }
+
+    async fn current(&self) -> Result<Position, ServiceError> {
+        let row = self
+            .client
+            .query_opt(
+                "SELECT SEQ, TS FROM change_log ORDER BY SEQ DESC LIMIT 1",
+                &[],
+            )
+            .await?
+            .ok_or_else(|| ServiceError::Internal("Empty change log".into()))?;
+
+        Ok(Position::from_row(&row)?)
+    }
+
+    async fn position(&self, seq: u64) -> Result<Option<Position>, ServiceError> {
+        let row = self
+            .client
+            .query_opt(
+                "SELECT SEQ, TS FROM change_log WHERE SEQ = $1",
+                &[&(seq as i64)],
+            )
+            .await?;
+
+        Ok(row.as_ref().map(Position::from_row).transpose()?)
+    }
+
+    async fn since(&self, seq: u64) -> Result<Vec<ChangeEntry>, ServiceError> {
+        self.client
+            .query_raw(
+                r#"
+SELECT
+    SEQ, APP, DEVICE, TS
+FROM
+    change_log
+WHERE
+        SEQ > $1
+    AND
+        APP IS NOT NULL
+ORDER BY
+    SEQ ASC
+"#,
+                &[seq as i64],
+            )
+            .await?
+            .and_then(|row| future::ready(ChangeEntry::from_row(&row)))
+            .err_into()
+            .try_collect()
+            .await
+    }
+
+    async fn purge(&self, before: DateTime<Utc>) -> Result<u64, ServiceError> {
+        Ok(self
+            .client
+            .execute(
+                r#"
+DELETE FROM change_log
+WHERE
+        TS < $1
+    AND
+        SEQ < (SELECT MAX(SEQ) FROM change_log)
+"#,
+                &[&before],
+            )
+            .await?)
+    }
+}
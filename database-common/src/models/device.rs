@@ -3,6 +3,7 @@ use crate::{
     error::ServiceError,
     generation,
     models::{
+        paging::{Cursor, Sort},
         sql::{slice_iter, SelectBuilder},
         Lock, TypedAlias,
     },
@@ -11,7 +12,7 @@ use crate::{
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use drogue_client::{meta, registry};
-use drogue_cloud_service_api::labels::LabelSelector;
+use drogue_cloud_service_api::{fields::FieldSelector, labels::LabelSelector};
 use futures::{future, Stream, TryStreamExt};
 use serde_json::Value;
 use std::collections::{hash_map::RandomState, HashMap, HashSet};
@@ -84,9 +85,12 @@ pub trait DeviceAccessor {
                 app,
                 Some(device),
                 LabelSelector::default(),
+                FieldSelector::default(),
                 Some(1),
                 None,
                 lock,
+                &Sort::default(),
+                None,
             )
             .await?
             .try_next()
@@ -119,10 +123,21 @@ pub trait DeviceAccessor {
         app: &str,
         name: Option<&str>,
         labels: LabelSelector,
+        fields: FieldSelector,
         limit: Option<usize>,
         offset: Option<usize>,
         lock: Lock,
+        sort: &Sort,
+        after: Option<&Cursor>,
     ) -> Result<Pin<Box<dyn Stream<Item = Result<Device, ServiceError>> + Send>>, ServiceError>;
+
+    /// Count the devices of an application matching the selector
+    async fn count(
+        &self,
+        app: &str,
+        labels: LabelSelector,
+        fields: FieldSelector,
+    ) -> Result<u64, ServiceError>;
 }
 
 pub struct PostgresDeviceAccessor<'c, C: Client> {
@@ -237,9 +252,12 @@ WHERE
         app: &str,
         name: Option<&str>,
         labels: LabelSelector,
+        fields: FieldSelector,
         limit: Option<usize>,
         offset: Option<usize>,
         lock: Lock,
+        sort: &Sort,
+        after: Option<&Cursor>,
     ) -> Result<Pin<Box<dyn Stream<Item = Result<Device, ServiceError>> + Send>>, ServiceError>
     {
         let select = format!(
@@ -268,8 +286,10 @@ WHERE APP=$1
             .has_where()
             .name(&name)
             .labels(&labels.0)
+            .fields(&fields.0)
+            .after(sort, after)
             .lock(lock)
-            .sort(&["NAME"])
+            .order(sort)
             .limit(limit)
             .offset(offset);
 
@@ -427,6 +447,34 @@ AND
         Ok(count)
     }
 
+    async fn count(
+        &self,
+        app: &str,
+        labels: LabelSelector,
+        fields: FieldSelector,
+    ) -> Result<u64, ServiceError> {
+        let mut params: Vec<&(dyn ToSql + Sync)> = Vec::new();
+        params.push(&app);
+
+        let builder = SelectBuilder::new(
+            "SELECT COUNT(NAME) AS COUNT FROM DEVICES WHERE APP=$1",
+            params,
+        )
+        .has_where()
+        .labels(&labels.0)
+        .fields(&fields.0);
+
+        let (select, params) = builder.build();
+
+        let count = self
+            .client
+            .query_opt(select.as_str(), &params[..])
+            .await?
+            .ok_or_else(|| ServiceError::Internal("Unable to retrieve number of devices".into()))?;
+
+        Ok(count.try_get::<_, i64>("COUNT")? as u64)
+    }
+
     async fn count_devices(&self, app_id: &str) -> Result<u64, ServiceError> {
         let count = self
             .client
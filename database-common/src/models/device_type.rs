@@ -0,0 +1,308 @@
+use crate::{
+    default_resource,
+    error::ServiceError,
+    generation,
+    models::{sql::slice_iter, Lock},
+    Client,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use drogue_client::{meta, registry};
+use drogue_cloud_service_api::device_type::{self, device_type_of};
+use futures::{future, TryStreamExt};
+use serde_json::Value;
+use std::collections::HashMap;
+use tokio_postgres::{
+    types::{Json, ToSql},
+    Row,
+};
+use uuid::Uuid;
+
+/// A device type entity record.
+#[derive(Clone, Debug)]
+pub struct DeviceType {
+    pub application: String,
+    pub uid: Uuid,
+    pub name: String,
+    pub labels: HashMap<String, String>,
+    pub annotations: HashMap<String, String>,
+    pub creation_timestamp: DateTime<Utc>,
+    pub resource_version: Uuid,
+    pub generation: u64,
+
+    pub data: Value,
+}
+
+generation!(DeviceType => generation);
+default_resource!(DeviceType);
+
+impl From<DeviceType> for device_type::DeviceType {
+    fn from(device_type: DeviceType) -> Self {
+        device_type::DeviceType {
+            metadata: meta::v1::ScopedMetadata {
+                uid: device_type.uid.to_string(),
+                name: device_type.name,
+                application: device_type.application,
+                labels: device_type.labels,
+                annotations: device_type.annotations,
+                creation_timestamp: device_type.creation_timestamp,
+                generation: device_type.generation,
+                resource_version: device_type.resource_version.to_string(),
+                deletion_timestamp: None,
+                finalizers: vec![],
+            },
+            spec: device_type.data["spec"]
+                .as_object()
+                .cloned()
+                .unwrap_or_default(),
+        }
+    }
+}
+
+#[async_trait]
+pub trait DeviceTypeAccessor {
+    /// Get a device type.
+    async fn get(
+        &self,
+        app: &str,
+        name: &str,
+        lock: Lock,
+    ) -> Result<Option<DeviceType>, ServiceError>;
+
+    /// Get all device types of an application.
+    async fn list(&self, app: &str) -> Result<Vec<DeviceType>, ServiceError>;
+
+    /// Create a new device type.
+    async fn create(&self, device_type: DeviceType) -> Result<(), ServiceError>;
+
+    /// Update an existing device type.
+    async fn update(&self, device_type: DeviceType) -> Result<(), ServiceError>;
+
+    /// Delete a device type.
+    async fn delete(&self, app: &str, name: &str) -> Result<(), ServiceError>;
+
+    /// Evaluate the effective device, by merging in the spec of the device type it references.
+    ///
+    /// A device referencing a non-existing type is returned as is.
+    async fn apply(
+        &self,
+        device: registry::v1::Device,
+    ) -> Result<registry::v1::Device, ServiceError>;
+}
+
+pub struct PostgresDeviceTypeAccessor<'c, C: Client> {
+    client: &'c C,
+}
+
+impl<'c, C: Client> PostgresDeviceTypeAccessor<'c, C> {
+    pub fn new(client: &'c C) -> Self {
+        Self { client }
+    }
+
+    pub fn from_row(row: Row) -> Result<DeviceType, tokio_postgres::Error> {
+        Ok(DeviceType {
+            application: row.try_get("APP")?,
+            uid: row.try_get("UID")?,
+            name: row.try_get("NAME")?,
+
+            creation_timestamp: row.try_get("CREATION_TIMESTAMP")?,
+            generation: row.try_get::<_, i64>("GENERATION")? as u64,
+            resource_version: row.try_get("RESOURCE_VERSION")?,
+            labels: super::row_to_map(&row, "LABELS")?,
+            annotations: super::row_to_map(&row, "ANNOTATIONS")?,
+
+            data: row.try_get::<_, Json<_>>("DATA")?.0,
+        })
+    }
+}
+
+#[async_trait]
+impl<'c, C: Client> DeviceTypeAccessor for PostgresDeviceTypeAccessor<'c, C> {
+    async fn get(
+        &self,
+        app: &str,
+        name: &str,
+        lock: Lock,
+    ) -> Result<Option<DeviceType>, ServiceError> {
+        let sql = format!(
+            r#"
+SELECT
+    APP,
+    NAME,
+    UID,
+    LABELS,
+    ANNOTATIONS,
+    CREATION_TIMESTAMP,
+    GENERATION,
+    RESOURCE_VERSION,
+    DATA
+FROM
+    DEVICE_TYPES
+WHERE
+        APP = $1
+    AND
+        NAME = $2
+{}
+"#,
+            lock.as_ref()
+        );
+
+        let result = self.client.query_opt(sql.as_str(), &[&app, &name]).await?;
+
+        Ok(result.map(Self::from_row).transpose()?)
+    }
+
+    async fn list(&self, app: &str) -> Result<Vec<DeviceType>, ServiceError> {
+        let params: Vec<&(dyn ToSql + Sync)> = vec![&app];
+
+        let result = self
+            .client
+            .query_raw(
+                r#"
+SELECT
+    APP,
+    NAME,
+    UID,
+    LABELS,
+    ANNOTATIONS,
+    CREATION_TIMESTAMP,
+    GENERATION,
+    RESOURCE_VERSION,
+    DATA
+FROM
+    DEVICE_TYPES
+WHERE
+    APP = $1
+ORDER BY
+    NAME ASC
+"#,
+                slice_iter(&params[..]),
+            )
+            .await?
+            .and_then(|row| future::ready(Self::from_row(row)))
+            .try_collect()
+            .await?;
+
+        Ok(result)
+    }
+
+    async fn create(&self, device_type: DeviceType) -> Result<(), ServiceError> {
+        self.client
+            .execute(
+                r#"
+INSERT INTO DEVICE_TYPES (
+    APP,
+    NAME,
+    UID,
+    LABELS,
+    ANNOTATIONS,
+    CREATION_TIMESTAMP,
+    GENERATION,
+    RESOURCE_VERSION,
+    DATA
+) VALUES (
+    $1,
+    $2,
+    $3,
+    $4,
+    $5,
+    $6,
+    $7,
+    $8,
+    $9
+)"#,
+                &[
+                    &device_type.application,
+                    &device_type.name,
+                    &device_type.uid,
+                    &Json(&device_type.labels),
+                    &Json(&device_type.annotations),
+                    &Utc::now(),
+                    &(device_type.generation as i64),
+                    &Uuid::new_v4(),
+                    &Json(&device_type.data),
+                ],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn update(&self, device_type: DeviceType) -> Result<(), ServiceError> {
+        let count = self
+            .client
+            .execute(
+                r#"
+UPDATE
+    DEVICE_TYPES
+SET
+    LABELS = $3,
+    ANNOTATIONS = $4,
+    GENERATION = $5,
+    RESOURCE_VERSION = $6,
+    DATA = $7
+WHERE
+    APP = $1 AND NAME = $2
+"#,
+                &[
+                    &device_type.application,
+                    &device_type.name,
+                    &Json(device_type.labels),
+                    &Json(device_type.annotations),
+                    &(device_type.generation as i64),
+                    &Uuid::new_v4(),
+                    &Json(device_type.data),
+                ],
+            )
+            .await?;
+
+        if count > 0 {
+            Ok(())
+        } else {
+            Err(ServiceError::NotFound)
+        }
+    }
+
+    async fn delete(&self, app: &str, name: &str) -> Result<(), ServiceError> {
+        let count = self
+            .client
+            .execute(
+                "DELETE FROM DEVICE_TYPES WHERE APP = $1 AND NAME = $2",
+                &[&app, &name],
+            )
+            .await?;
+
+        if count > 0 {
+            Ok(())
+        } else {
+            Err(ServiceError::NotFound)
+        }
+    }
+
+    async fn apply(
+        &self,
+        device: registry::v1::Device,
+    ) -> Result<registry::v1::Device, ServiceError> {
+        let name = match device_type_of(&device.spec) {
+            Some(name) => name.to_string(),
+            None => return Ok(device),
+        };
+
+        Ok(
+            match self
+                .get(&device.metadata.application, &name, Lock::None)
+                .await?
+            {
+                Some(device_type) => device_type::apply(&device_type.into(), device),
+                None => {
+                    log::debug!(
+                        "Device {:?} references missing device type {:?}",
+                        device.metadata.name,
+                        name
+                    );
+                    device
+                }
+            },
+        )
+    }
+}
@@ -0,0 +1,247 @@
+use crate::{
+    error::ServiceError,
+    models::{
+        device::{Device, DeviceAccessor, PostgresDeviceAccessor},
+        device_type::{DeviceTypeAccessor, PostgresDeviceTypeAccessor},
+        paging::Sort,
+        sql::slice_iter,
+        Lock,
+    },
+    Client,
+};
+use async_trait::async_trait;
+use drogue_client::{registry, Translator};
+use drogue_cloud_service_api::{
+    device_type::{self, device_type_of},
+    fields::FieldSelector,
+    gateway::{GatewaySelector, MAX_GATEWAY_DEPTH},
+    labels::{LabelSelector, Operation},
+};
+use futures::{future, TryStreamExt};
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use tokio_postgres::types::{Json, ToSql};
+
+#[async_trait]
+pub trait GatewayAccessor {
+    /// Get the effective gateways of a device.
+    ///
+    /// This follows chains of gateways, up to [`MAX_GATEWAY_DEPTH`] levels. The gateways are
+    /// ordered by their distance to the device, the nearest first. The device is expected to
+    /// already be the effective device, with its device type applied.
+    async fn gateways(
+        &self,
+        device: &registry::v1::Device,
+    ) -> Result<Vec<registry::v1::Device>, ServiceError>;
+
+    /// Get the devices directly selecting a device as their gateway.
+    async fn children(
+        &self,
+        gateway: &registry::v1::Device,
+    ) -> Result<Vec<registry::v1::Device>, ServiceError>;
+}
+
+pub struct PostgresGatewayAccessor<'c, C: Client> {
+    client: &'c C,
+}
+
+impl<'c, C: Client> PostgresGatewayAccessor<'c, C> {
+    pub fn new(client: &'c C) -> Self {
+        Self { client }
+    }
+
+    /// Get the gateways a device selects, without following chains.
+    async fn direct_gateways(
+        &self,
+        device: &registry::v1::Device,
+    ) -> Result<Vec<registry::v1::Device>, ServiceError> {
+        let selector = match device.section::<GatewaySelector>() {
+            Some(Ok(selector)) if !selector.is_empty() => selector,
+            _ => return Ok(vec![]),
+        };
+
+        let app = &device.metadata.application;
+        let devices = PostgresDeviceAccessor::new(self.client);
+        let device_types = PostgresDeviceTypeAccessor::new(self.client);
+
+        let mut gateways = Vec::new();
+
+        // gateways by name, which may also be an alias
+
+        for name in &selector.match_names {
+            if let Some(gateway) = devices.lookup(app, name).await? {
+                gateways.push(gateway);
+            }
+        }
+
+        // gateways by label
+
+        if !selector.match_labels.is_empty() {
+            let labels = LabelSelector(
+                selector
+                    .match_labels
+                    .into_iter()
+                    .map(|(key, value)| Operation::Eq(key, value))
+                    .collect(),
+            );
+            let mut found: Vec<_> = devices
+                .list(
+                    app,
+                    None,
+                    labels,
+                    FieldSelector::default(),
+                    None,
+                    None,
+                    Lock::None,
+                    &Sort::default(),
+                    None,
+                )
+                .await?
+                .try_collect()
+                .await?;
+            gateways.append(&mut found);
+        }
+
+        let mut result = Vec::with_capacity(gateways.len());
+        for gateway in gateways {
+            // devices being deleted can't act as gateway
+            if gateway.deletion_timestamp.is_some() {
+                continue;
+            }
+            // the selector of a gateway may be inherited from its type
+            result.push(device_types.apply(gateway.into()).await?);
+        }
+
+        Ok(result)
+    }
+}
+
+#[async_trait]
+impl<'c, C: Client> GatewayAccessor for PostgresGatewayAccessor<'c, C> {
+    async fn gateways(
+        &self,
+        device: &registry::v1::Device,
+    ) -> Result<Vec<registry::v1::Device>, ServiceError> {
+        let mut seen = HashSet::new();
+        seen.insert(device.metadata.name.clone());
+
+        let mut result = Vec::new();
+        let mut level = vec![device.clone()];
+
+        for _ in 0..MAX_GATEWAY_DEPTH {
+            let mut next = Vec::new();
+            for device in &level {
+                for gateway in self.direct_gateways(device).await? {
+                    // skip duplicates, and break cycles
+                    if seen.insert(gateway.metadata.name.clone()) {
+                        next.push(gateway);
+                    }
+                }
+            }
+
+            if next.is_empty() {
+                break;
+            }
+
+            result.extend(next.iter().cloned());
+            level = next;
+        }
+
+        Ok(result)
+    }
+
+    async fn children(
+        &self,
+        gateway: &registry::v1::Device,
+    ) -> Result<Vec<registry::v1::Device>, ServiceError> {
+        let app = &gateway.metadata.application;
+        let name = &gateway.metadata.name;
+
+        // the gateway selector may be inherited from the device type, so the candidates are the
+        // devices whose own selector may match, and the devices of a type whose selector may match
+
+        let device_types: HashMap<_, device_type::DeviceType> =
+            PostgresDeviceTypeAccessor::new(self.client)
+                .list(app)
+                .await?
+                .into_iter()
+                .map(|device_type| (device_type.name.clone(), device_type.into()))
+                .collect();
+
+        let candidate_types: Vec<String> = device_types
+            .iter()
+            .filter(|(_, device_type)| {
+                device_type
+                    .spec
+                    .get("gatewaySelector")
+                    .cloned()
+                    .and_then(|selector| serde_json::from_value::<GatewaySelector>(selector).ok())
+                    .map(|selector| {
+                        selector.match_names.contains(name) || !selector.match_labels.is_empty()
+                    })
+                    .unwrap_or_default()
+            })
+            .map(|(type_name, _)| type_name.clone())
+            .collect();
+
+        let match_name = Json(json!({"spec": {"gatewaySelector": {"matchNames": [name]}}}));
+        let labels = Json(&gateway.metadata.labels);
+        let params: [&(dyn ToSql + Sync); 5] = [app, name, &match_name, &labels, &candidate_types];
+
+        let devices: Vec<Device> = self
+            .client
+            .query_raw(
+                r#"
+SELECT
+    UID,
+    NAME,
+    APP,
+    LABELS,
+    ANNOTATIONS,
+    CREATION_TIMESTAMP,
+    GENERATION,
+    RESOURCE_VERSION,
+    DELETION_TIMESTAMP,
+    FINALIZERS,
+    DATA
+FROM
+    DEVICES
+WHERE
+        APP = $1
+    AND
+        NAME <> $2
+    AND (
+            DATA @> $3
+        OR
+            jsonb_strip_nulls(DATA -> 'spec' -> 'gatewaySelector' -> 'matchLabels') <@ $4
+        OR
+            DATA -> 'spec' ->> 'deviceType' = ANY($5)
+    )
+"#,
+                slice_iter(&params[..]),
+            )
+            .await?
+            .and_then(|row| future::ready(PostgresDeviceAccessor::<C>::from_row(row)))
+            .try_collect()
+            .await?;
+
+        // evaluate the candidates, using the effective device
+
+        Ok(devices
+            .into_iter()
+            .map(registry::v1::Device::from)
+            .map(|device| {
+                let device_type =
+                    device_type_of(&device.spec).and_then(|name| device_types.get(name));
+                match device_type {
+                    Some(device_type) => device_type::apply(device_type, device),
+                    None => device,
+                }
+            })
+            .filter(|device| match device.section::<GatewaySelector>() {
+                Some(Ok(selector)) => selector.matches(name, &gateway.metadata.labels),
+                _ => false,
+            })
+            .collect())
+    }
+}
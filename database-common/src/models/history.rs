@@ -0,0 +1,282 @@
+use crate::{error::ServiceError, models::sql::slice_iter, Client};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use drogue_cloud_service_api::history::{HistoryEntry, Operation, ResourceKind};
+use futures::{future, TryStreamExt};
+use serde_json::{json, Value};
+use tokio_postgres::{
+    types::{Json, ToSql},
+    Row,
+};
+use uuid::Uuid;
+
+#[async_trait]
+pub trait HistoryAccessor {
+    /// Record a change of a resource.
+    ///
+    /// The application of the resource must exist.
+    async fn record(&self, entry: &HistoryEntry) -> Result<(), ServiceError>;
+
+    /// Record the deletion of all devices of an application, which don't have any finalizers.
+    ///
+    /// This must be called right before the devices get deleted, as part of deleting the
+    /// application.
+    async fn record_app_devices_deleted(
+        &self,
+        app: &str,
+        user_id: Option<&str>,
+        timestamp: DateTime<Utc>,
+    ) -> Result<u64, ServiceError>;
+
+    /// Get the history of an application, the most recent change first.
+    ///
+    /// If a resource is provided, only the changes of this resource are returned. If a position
+    /// is provided, only changes before that position are returned.
+    ///
+    /// Returns the entries, and the position to continue with, in case there are more entries.
+    async fn list(
+        &self,
+        app_uid: Uuid,
+        resource: Option<(ResourceKind, &str)>,
+        limit: Option<usize>,
+        before: Option<u64>,
+    ) -> Result<(Vec<HistoryEntry>, Option<u64>), ServiceError>;
+
+    /// Delete all entries older than `before`.
+    async fn purge(&self, before: DateTime<Utc>) -> Result<u64, ServiceError>;
+}
+
+pub struct PostgresHistoryAccessor<'c, C: Client> {
+    client: &'c C,
+}
+
+impl<'c, C: Client> PostgresHistoryAccessor<'c, C> {
+    pub fn new(client: &'c C) -> Self {
+        Self { client }
+    }
+
+    pub fn from_row(row: Row) -> Result<HistoryEntry, ServiceError> {
+        Ok(HistoryEntry {
+            kind: row
+                .try_get::<_, String>("KIND")?
+                .parse()
+                .map_err(ServiceError::Internal)?,
+            application: row.try_get("APP")?,
+            name: row.try_get("NAME")?,
+            uid: row.try_get::<_, Uuid>("UID")?.to_string(),
+            operation: row
+                .try_get::<_, String>("OPERATION")?
+                .parse()
+                .map_err(ServiceError::Internal)?,
+            timestamp: row.try_get("TIMESTAMP")?,
+            generation: row.try_get::<_, i64>("GENERATION")? as u64,
+            user_id: row.try_get("USER_ID")?,
+            diff: row.try_get::<_, Json<Value>>("DIFF")?.0,
+        })
+    }
+}
+
+#[async_trait]
+impl<'c, C: Client> HistoryAccessor for PostgresHistoryAccessor<'c, C> {
+    async fn record(&self, entry: &HistoryEntry) -> Result<(), ServiceError> {
+        let uid = Uuid::parse_str(&entry.uid).map_err(|err| {
+            ServiceError::Internal(format!("Invalid UID '{}': {}", entry.uid, err))
+        })?;
+
+        self.client
+            .execute(
+                r#"
+INSERT INTO RESOURCE_HISTORY (
+    APP,
+    APP_UID,
+    KIND,
+    NAME,
+    UID,
+    OPERATION,
+    TIMESTAMP,
+    GENERATION,
+    USER_ID,
+    DIFF
+)
+SELECT
+    A.NAME,
+    A.UID,
+    $2,
+    $3,
+    $4,
+    $5,
+    $6,
+    $7,
+    $8,
+    $9
+FROM
+    APPLICATIONS A
+WHERE
+    A.NAME = $1
+"#,
+                &[
+                    &entry.application,
+                    &entry.kind.as_ref(),
+                    &entry.name,
+                    &uid,
+                    &entry.operation.as_ref(),
+                    &entry.timestamp,
+                    &(entry.generation as i64),
+                    &entry.user_id,
+                    &Json(&entry.diff),
+                ],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn record_app_devices_deleted(
+        &self,
+        app: &str,
+        user_id: Option<&str>,
+        timestamp: DateTime<Utc>,
+    ) -> Result<u64, ServiceError> {
+        // the same patch, as the one of deleting a single resource
+        let diff = json!([{"op": "replace", "path": "", "value": null}]);
+
+        let count = self
+            .client
+            .execute(
+                r#"
+INSERT INTO RESOURCE_HISTORY (
+    APP,
+    APP_UID,
+    KIND,
+    NAME,
+    UID,
+    OPERATION,
+    TIMESTAMP,
+    GENERATION,
+    USER_ID,
+    DIFF
+)
+SELECT
+    D.APP,
+    A.UID,
+    $2,
+    D.NAME,
+    D.UID,
+    $3,
+    $4,
+    D.GENERATION,
+    $5,
+    $6
+FROM
+    DEVICES D INNER JOIN APPLICATIONS A ON D.APP = A.NAME
+WHERE
+        D.APP = $1
+    AND
+        cardinality ( D.FINALIZERS ) = 0
+"#,
+                &[
+                    &app,
+                    &ResourceKind::Device.as_ref(),
+                    &Operation::Delete.as_ref(),
+                    &timestamp,
+                    &user_id,
+                    &Json(&diff),
+                ],
+            )
+            .await?;
+
+        Ok(count)
+    }
+
+    async fn list(
+        &self,
+        app_uid: Uuid,
+        resource: Option<(ResourceKind, &str)>,
+        limit: Option<usize>,
+        before: Option<u64>,
+    ) -> Result<(Vec<HistoryEntry>, Option<u64>), ServiceError> {
+        let mut sql = r#"
+SELECT
+    ID,
+    APP,
+    KIND,
+    NAME,
+    UID,
+    OPERATION,
+    TIMESTAMP,
+    GENERATION,
+    USER_ID,
+    DIFF
+FROM
+    RESOURCE_HISTORY
+WHERE
+    APP_UID = $1
+"#
+        .to_string();
+
+        // fetch one more, to find out if there is a next page
+        let fetch = limit.map(|limit| limit as i64 + 1);
+        let before = before.map(|before| before as i64);
+        let kind = resource.map(|(kind, _)| kind.as_ref().to_string());
+        let name = resource.map(|(_, name)| name.to_string());
+
+        let mut params: Vec<&(dyn ToSql + Sync)> = vec![&app_uid];
+
+        if let (Some(kind), Some(name)) = (&kind, &name) {
+            params.push(kind);
+            params.push(name);
+            sql.push_str(&format!(
+                "AND KIND = ${} AND NAME = ${}\n",
+                params.len() - 1,
+                params.len()
+            ));
+        }
+
+        if let Some(before) = &before {
+            params.push(before);
+            sql.push_str(&format!("AND ID < ${}\n", params.len()));
+        }
+
+        sql.push_str("ORDER BY ID DESC\n");
+
+        if let Some(fetch) = &fetch {
+            params.push(fetch);
+            sql.push_str(&format!("LIMIT ${}\n", params.len()));
+        }
+
+        let mut rows: Vec<(i64, HistoryEntry)> = self
+            .client
+            .query_raw(sql.as_str(), slice_iter(&params[..]))
+            .await?
+            .map_err(ServiceError::from)
+            .and_then(|row| {
+                future::ready(
+                    row.try_get::<_, i64>("ID")
+                        .map_err(ServiceError::from)
+                        .and_then(|id| Ok((id, Self::from_row(row)?))),
+                )
+            })
+            .try_collect()
+            .await?;
+
+        let next = match limit {
+            Some(limit) if rows.len() > limit => {
+                rows.truncate(limit);
+                rows.last().map(|(id, _)| *id as u64)
+            }
+            _ => None,
+        };
+
+        Ok((rows.into_iter().map(|(_, entry)| entry).collect(), next))
+    }
+
+    async fn purge(&self, before: DateTime<Utc>) -> Result<u64, ServiceError> {
+        Ok(self
+            .client
+            .execute(
+                "DELETE FROM RESOURCE_HISTORY WHERE TIMESTAMP < $1",
+                &[&before],
+            )
+            .await?)
+    }
+}
@@ -1,9 +1,16 @@
 pub mod app;
+pub mod change_log;
 pub mod device;
+pub mod device_type;
 pub mod diff;
+pub mod gateway;
 mod gen;
+pub mod history;
 pub mod outbox;
+pub mod paging;
 pub mod sql;
+pub mod trash;
+pub mod twin;
 
 pub use gen::*;
 
@@ -1,12 +1,23 @@
-use crate::{error::ServiceError, Client};
+use crate::{
+    error::ServiceError,
+    models::change_log::{ChangeLogAccessor, PostgresChangeLogAccessor},
+    Client,
+};
 use async_trait::async_trait;
 use chrono::{Duration, Utc};
 use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
 use std::convert::{TryFrom, TryInto};
 use std::pin::Pin;
 use tokio_postgres::Row;
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+/// The channel used to notify listeners about new outbox entries.
+///
+/// The payload of the notification is the JSON encoded
+/// [`ChangeEntry`](crate::models::change_log::ChangeEntry) of the change log.
+pub const OUTBOX_CHANNEL: &str = "outbox";
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct OutboxEntry {
     pub instance: String,
 
@@ -43,6 +54,9 @@ impl TryFrom<Row> for OutboxEntry {
 #[async_trait]
 pub trait OutboxAccessor {
     /// Create a new outbox entry.
+    ///
+    /// This also appends the change to the change log, and notifies listeners of the
+    /// [`OUTBOX_CHANNEL`], once the transaction is committed.
     async fn create(&self, entry: OutboxEntry) -> Result<(), ServiceError>;
     /// Mark the outbox entry as seen.
     async fn mark_seen(&self, entry: OutboxEntry) -> Result<bool, ServiceError>;
@@ -69,6 +83,12 @@ impl<'c, C: Client> PostgresOutboxAccessor<'c, C> {
 #[async_trait]
 impl<'c, C: Client> OutboxAccessor for PostgresOutboxAccessor<'c, C> {
     async fn create(&self, entry: OutboxEntry) -> Result<(), ServiceError> {
+        let change = PostgresChangeLogAccessor::new(self.client)
+            .append(&entry.app, entry.device.as_deref())
+            .await?;
+        let payload = serde_json::to_string(&change)
+            .map_err(|err| ServiceError::Internal(err.to_string()))?;
+
         let num = self
             .client
             .execute(
@@ -114,6 +134,10 @@ DO
 
         log::debug!("Rows changed by create: {}", num);
 
+        self.client
+            .execute("SELECT pg_notify($1, $2)", &[&OUTBOX_CHANNEL, &payload])
+            .await?;
+
         Ok(())
     }
 
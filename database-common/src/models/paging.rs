@@ -0,0 +1,186 @@
+use crate::error::ServiceError;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{Display, Formatter},
+    str::FromStr,
+};
+use uuid::Uuid;
+
+/// A field to sort a list of resources by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortField {
+    Name,
+    CreationTimestamp,
+}
+
+/// The sort order of a list of resources.
+///
+/// Resources with an equal sort field are ordered by name and UID, so that the order is stable,
+/// which is required for continuing a list.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sort {
+    pub field: SortField,
+    pub descending: bool,
+}
+
+impl Default for Sort {
+    fn default() -> Self {
+        Self {
+            field: SortField::Name,
+            descending: false,
+        }
+    }
+}
+
+impl FromStr for Sort {
+    type Err = ServiceError;
+
+    /// Parse a sort order, like `name` or `-creationTimestamp` for a descending order.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (field, descending) = match s.strip_prefix('-') {
+            Some(field) => (field, true),
+            None => (s, false),
+        };
+
+        let field = match field {
+            "name" => SortField::Name,
+            "creationTimestamp" => SortField::CreationTimestamp,
+            _ => {
+                return Err(ServiceError::BadRequest(format!(
+                    "Unknown sort field: {}",
+                    s
+                )))
+            }
+        };
+
+        Ok(Self { field, descending })
+    }
+}
+
+impl Display for Sort {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.descending {
+            write!(f, "-")?;
+        }
+        match self.field {
+            SortField::Name => write!(f, "name"),
+            SortField::CreationTimestamp => write!(f, "creationTimestamp"),
+        }
+    }
+}
+
+impl Sort {
+    /// The columns making up the sort key.
+    pub fn columns(&self) -> &'static [&'static str] {
+        match self.field {
+            SortField::Name => &["NAME", "UID"],
+            SortField::CreationTimestamp => &["CREATION_TIMESTAMP", "NAME", "UID"],
+        }
+    }
+
+    /// The `ORDER BY` expressions.
+    pub fn order_by(&self) -> Vec<String> {
+        let direction = if self.descending { "DESC" } else { "ASC" };
+        self.columns()
+            .iter()
+            .map(|column| format!("{} {}", column, direction))
+            .collect()
+    }
+}
+
+/// The position of the last item of a page, after which a list continues.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Cursor {
+    #[serde(rename = "s")]
+    pub sort: String,
+    #[serde(rename = "t")]
+    pub creation_timestamp: DateTime<Utc>,
+    #[serde(rename = "n")]
+    pub name: String,
+    #[serde(rename = "u")]
+    pub uid: Uuid,
+}
+
+impl Cursor {
+    pub fn new<S: Into<String>>(
+        sort: &Sort,
+        creation_timestamp: DateTime<Utc>,
+        name: S,
+        uid: Uuid,
+    ) -> Self {
+        Self {
+            sort: sort.to_string(),
+            creation_timestamp,
+            name: name.into(),
+            uid,
+        }
+    }
+
+    /// Encode the cursor into an opaque continuation token.
+    pub fn encode(&self) -> String {
+        base64::encode_config(
+            serde_json::to_vec(self).unwrap_or_default(),
+            base64::URL_SAFE_NO_PAD,
+        )
+    }
+
+    /// Decode a continuation token, which must have been created for the same sort order.
+    pub fn decode(token: &str, sort: &Sort) -> Result<Self, ServiceError> {
+        let cursor: Cursor = base64::decode_config(token, base64::URL_SAFE_NO_PAD)
+            .ok()
+            .and_then(|json| serde_json::from_slice(&json).ok())
+            .ok_or_else(|| ServiceError::BadRequest("Invalid continuation token".into()))?;
+
+        if cursor.sort != sort.to_string() {
+            return Err(ServiceError::BadRequest(
+                "Continuation token doesn't match the sort order".into(),
+            ));
+        }
+
+        Ok(cursor)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_sort() {
+        assert_eq!("name".parse::<Sort>().unwrap(), Sort::default());
+        let sort: Sort = "-creationTimestamp".parse().unwrap();
+        assert_eq!(
+            sort,
+            Sort {
+                field: SortField::CreationTimestamp,
+                descending: true
+            }
+        );
+        assert_eq!(sort.to_string(), "-creationTimestamp");
+        assert_eq!(
+            sort.order_by(),
+            vec!["CREATION_TIMESTAMP DESC", "NAME DESC", "UID DESC"]
+        );
+        assert!("foo".parse::<Sort>().is_err());
+    }
+
+    #[test]
+    fn test_cursor() {
+        let sort = Sort::default();
+        let cursor = Cursor::new(
+            &sort,
+            Utc.ymd(2021, 1, 1).and_hms(0, 0, 0),
+            "foo",
+            Uuid::new_v4(),
+        );
+
+        let token = cursor.encode();
+        assert_eq!(Cursor::decode(&token, &sort).unwrap(), cursor);
+
+        // the token must only be used with the same sort order
+        assert!(Cursor::decode(&token, &"-name".parse().unwrap()).is_err());
+        assert!(Cursor::decode("foo", &sort).is_err());
+    }
+}
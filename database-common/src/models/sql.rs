@@ -1,5 +1,9 @@
-use crate::models::Lock;
+use crate::models::{
+    paging::{Cursor, Sort, SortField},
+    Lock,
+};
 use drogue_cloud_service_api::{auth::user::UserInformation, labels::Operation};
+use serde_json::Value;
 use tokio_postgres::types::ToSql;
 
 pub fn slice_iter<'a>(
@@ -8,6 +12,60 @@ pub fn slice_iter<'a>(
     s.iter().map(|s| *s as _)
 }
 
+/// The text array path of a dotted field name, provided by the parameter `idx`.
+fn field_path(idx: usize) -> String {
+    format!("string_to_array(${}, '.')", idx)
+}
+
+/// The JSON path of a dotted field name, provided by the parameter `idx`, matching if the field
+/// is present and not `null`.
+fn json_path(idx: usize) -> String {
+    format!(
+        r#"CAST('$."' || replace(${}, '.', '"."') || '" ? (@ != null)' AS jsonpath)"#,
+        idx
+    )
+}
+
+/// A JSON document, holding the JSON value `value` at the dotted field name `field`, which is
+/// provided by the parameter `idx`.
+///
+/// The document is built from the parameters, so that the query can use it with the containment
+/// operator.
+fn json_document(field: &str, idx: usize, value: String) -> String {
+    (1..=field.split('.').count())
+        .rev()
+        .fold(value, |document, segment| {
+            format!(
+                "jsonb_build_object(split_part(${}, '.', {}), {})",
+                idx, segment, document
+            )
+        })
+}
+
+/// Check if the field `field`, provided by the parameter `idx`, contains the value `value`,
+/// provided by the expression `expr`.
+///
+/// As values are compared by their text representation, values which are valid JSON numbers or
+/// booleans are also checked in their JSON form.
+fn json_contains(field: &str, idx: usize, value: &str, expr: &str) -> Vec<String> {
+    let mut documents = vec![json_document(
+        field,
+        idx,
+        format!("to_jsonb({}::text)", expr),
+    )];
+    if matches!(
+        serde_json::from_str(value),
+        Ok(Value::Number(_)) | Ok(Value::Bool(_))
+    ) {
+        documents.push(json_document(field, idx, format!("{}::jsonb", expr)));
+    }
+
+    documents
+        .into_iter()
+        .map(|document| format!("DATA @> {}", document))
+        .collect()
+}
+
 pub struct SelectBuilder<'a> {
     select: String,
     params: Vec<&'a (dyn ToSql + Sync + 'a)>,
@@ -72,10 +130,55 @@ impl<'a> SelectBuilder<'a> {
         self
     }
 
+    /// Sort by the provided sort order.
+    pub fn order(self, sort: &Sort) -> Self {
+        self.sort(sort.order_by())
+    }
+
+    /// Only select items after the cursor, in the provided sort order.
+    pub fn after(mut self, sort: &Sort, cursor: Option<&'a Cursor>) -> Self {
+        if let Some(cursor) = cursor {
+            self.ensure_where_or_and();
+
+            if sort.field == SortField::CreationTimestamp {
+                self.params.push(&cursor.creation_timestamp);
+            }
+            self.params.push(&cursor.name);
+            self.params.push(&cursor.uid);
+
+            let columns = sort.columns();
+            let values: Vec<String> = (self.params.len() - columns.len() + 1..=self.params.len())
+                .map(|idx| format!("${}", idx))
+                .collect();
+
+            self.select.push_str(&format!(
+                " ({}) {} ({})",
+                columns.join(", "),
+                if sort.descending { "<" } else { ">" },
+                values.join(", ")
+            ));
+        }
+        self
+    }
+
     /// Add restrictions to the select so that no unauthorized items get returned for the read permission.
     ///
     /// NOTE: This must be aligned with [`crate::auth::authorize`].
-    pub fn auth_read(mut self, user: &'a Option<&'a UserInformation>) -> Self {
+    pub fn auth_read(self, user: &'a Option<&'a UserInformation>) -> Self {
+        self.auth(user, "'reader', 'manager', 'admin'")
+    }
+
+    /// Add restrictions to the select so that no unauthorized items get returned for the admin
+    /// permission.
+    ///
+    /// NOTE: This must be aligned with [`crate::auth::authorize`].
+    pub fn auth_admin(self, user: &'a Option<&'a UserInformation>) -> Self {
+        self.auth(user, "'admin'")
+    }
+
+    /// Restrict the select to items owned by the user, or having the user as member with one of
+    /// the provided roles.
+    fn auth(mut self, user: &'a Option<&'a UserInformation>, roles: &str) -> Self {
         // check if we have authentication enabled
         let user = match user {
             Some(user) => user,
@@ -103,17 +206,18 @@ impl<'a> SelectBuilder<'a> {
         let idx = self.params.len();
 
         // must be equal to the owner (which may be empty)
-        // or contain a member with one of the roles eligible for reading
+        // or contain a member with one of the eligible roles
 
         self.select.push_str(&format!(
             r#"
     (
         OWNER=${idx}
     OR
-        MEMBERS->${idx}->>'role' IN ('reader', 'manager', 'admin')
+        MEMBERS->${idx}->>'role' IN ({roles})
     )
 "#,
-            idx = idx
+            idx = idx,
+            roles = roles
         ));
 
         // done
@@ -188,6 +292,88 @@ impl<'a> SelectBuilder<'a> {
         self
     }
 
+    /// Add a filter on the fields of the DATA column.
+    ///
+    /// The existence of a field is checked using a JSON path, and the value of a field using the
+    /// containment operator, both of which can make use of the GIN index on the column. Values are
+    /// compared by their text representation.
+    pub fn fields(mut self, fields: &'a Vec<Operation>) -> Self {
+        for op in fields {
+            self.ensure_where_or_and();
+            match op {
+                Operation::Exists(field) => {
+                    self.params.push(field);
+                    self.select
+                        .push_str(&format!(" DATA @? {}", json_path(self.params.len())));
+                }
+                Operation::NotExists(field) => {
+                    self.params.push(field);
+                    self.select
+                        .push_str(&format!(" (NOT DATA @? {})", json_path(self.params.len())));
+                }
+                Operation::Eq(field, value) => {
+                    self.params.push(field);
+                    self.params.push(value);
+                    let idx = self.params.len();
+                    let contains =
+                        json_contains(field, idx - 1, value, &format!("${}", idx)).join(" OR ");
+                    // the containment check can use the index, the text comparison is exact
+                    self.select.push_str(&format!(
+                        " ({}) AND DATA #>> {} = ${}",
+                        contains,
+                        field_path(idx - 1),
+                        idx
+                    ));
+                }
+                Operation::NotEq(field, value) => {
+                    self.params.push(field);
+                    self.params.push(value);
+                    self.select.push_str(&format!(
+                        " (DATA #>> {}) IS DISTINCT FROM ${}",
+                        field_path(self.params.len() - 1),
+                        self.params.len()
+                    ));
+                }
+                Operation::In(_, values) if values.is_empty() => {
+                    self.select.push_str(" false");
+                }
+                Operation::In(field, values) => {
+                    self.params.push(field);
+                    self.params.push(values);
+                    let idx = self.params.len();
+                    let contains: Vec<String> = values
+                        .iter()
+                        .enumerate()
+                        .flat_map(|(i, value)| {
+                            json_contains(
+                                field,
+                                idx - 1,
+                                value,
+                                &format!("(${}::text[])[{}]", idx, i + 1),
+                            )
+                        })
+                        .collect();
+                    self.select.push_str(&format!(
+                        " ({}) AND DATA #>> {} = ANY (${})",
+                        contains.join(" OR "),
+                        field_path(idx - 1),
+                        idx
+                    ));
+                }
+                Operation::NotIn(field, values) => {
+                    self.params.push(field);
+                    self.params.push(values);
+                    self.select.push_str(&format!(
+                        " NOT COALESCE(DATA #>> {} = ANY (${}), false)",
+                        field_path(self.params.len() - 1),
+                        self.params.len()
+                    ));
+                }
+            }
+        }
+        self
+    }
+
     pub fn build(self) -> (String, Vec<&'a (dyn ToSql + Sync)>) {
         let mut select = self.select;
 
@@ -216,7 +402,7 @@ impl<'a> SelectBuilder<'a> {
 #[cfg(test)]
 mod test {
     use super::*;
-    use drogue_cloud_service_api::labels::LabelSelector;
+    use drogue_cloud_service_api::{fields::FieldSelector, labels::LabelSelector};
     use std::convert::TryInto;
     use std::fmt::Debug;
 
@@ -305,6 +491,73 @@ AND LABELS ->> $4 <> $5
         );
     }
 
+    #[test]
+    fn test_to_fields() {
+        let mut builder = SelectBuilder::new("SELECT * FROM TABLE", Vec::new());
+
+        let selector: FieldSelector = r#"spec.foo,status.bar.baz!=1.2"#.try_into().unwrap();
+        builder = builder.fields(&selector.0);
+
+        let (sql, params) = builder.build();
+
+        assert_eq!(
+            sql,
+            r#"SELECT * FROM TABLE
+WHERE DATA @? CAST('$."' || replace($1, '.', '"."') || '" ? (@ != null)' AS jsonpath)
+AND (DATA #>> string_to_array($2, '.')) IS DISTINCT FROM $3
+"#
+        );
+        assert_eq!(
+            params
+                .into_iter()
+                .map(|p| format!("{:?}", p))
+                .collect::<Vec<String>>(),
+            to_debug(&[&"spec.foo", &"status.bar.baz", &"1.2"])
+        );
+    }
+
+    #[test]
+    fn test_to_fields_contains() {
+        let mut builder = SelectBuilder::new("SELECT * FROM TABLE", Vec::new());
+
+        let selector: FieldSelector = r#"spec.foo=bar,status.bar in (1, baz)"#.try_into().unwrap();
+        builder = builder.fields(&selector.0);
+
+        let (sql, params) = builder.build();
+
+        assert_eq!(
+            sql,
+            r#"SELECT * FROM TABLE
+WHERE (DATA @> jsonb_build_object(split_part($1, '.', 1), jsonb_build_object(split_part($1, '.', 2), to_jsonb($2::text)))) AND DATA #>> string_to_array($1, '.') = $2
+AND (DATA @> jsonb_build_object(split_part($3, '.', 1), jsonb_build_object(split_part($3, '.', 2), to_jsonb(($4::text[])[1]::text))) OR DATA @> jsonb_build_object(split_part($3, '.', 1), jsonb_build_object(split_part($3, '.', 2), ($4::text[])[1]::jsonb)) OR DATA @> jsonb_build_object(split_part($3, '.', 1), jsonb_build_object(split_part($3, '.', 2), to_jsonb(($4::text[])[2]::text)))) AND DATA #>> string_to_array($3, '.') = ANY ($4)
+"#
+        );
+        assert_eq!(params.len(), 4);
+    }
+
+    #[test]
+    fn test_to_sql_after() {
+        let sort: Sort = "-creationTimestamp".parse().unwrap();
+        let cursor = Cursor::new(&sort, chrono::Utc::now(), "foo", uuid::Uuid::nil());
+
+        let builder = SelectBuilder::new("SELECT * FROM TABLE", Vec::new())
+            .name(&Some("bar"))
+            .after(&sort, Some(&cursor))
+            .order(&sort);
+
+        let (sql, params) = builder.build();
+
+        assert_eq!(
+            sql,
+            r#"SELECT * FROM TABLE
+WHERE NAME=$1
+AND (CREATION_TIMESTAMP, NAME, UID) < ($2, $3, $4)
+ORDER BY CREATION_TIMESTAMP DESC,NAME DESC,UID DESC
+"#
+        );
+        assert_eq!(params.len(), 4);
+    }
+
     fn to_debug(list: &[&dyn Debug]) -> Vec<String> {
         list.iter().map(|s| format!("{:?}", s)).collect()
     }
@@ -0,0 +1,632 @@
+use crate::{
+    auth::Resource,
+    error::ServiceError,
+    models::{
+        app::MemberEntry,
+        fix_null_default,
+        sql::{slice_iter, SelectBuilder},
+        Lock,
+    },
+    Client,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use drogue_cloud_service_api::auth::user::UserInformation;
+use futures::{future, TryStreamExt};
+use indexmap::map::IndexMap;
+use serde_json::Value;
+use tokio_postgres::{
+    types::{Json, ToSql},
+    Row,
+};
+use uuid::Uuid;
+
+/// The changes applied to a record, when it gets restored from the trash.
+const RESTORE_PATCH: &str = "jsonb_build_object('deletion_timestamp', NULL, 'finalizers', '{}'::text[], 'resource_version', gen_random_uuid())";
+
+/// A deleted application, kept in the trash.
+#[derive(Clone, Debug)]
+pub struct DeletedApplication {
+    pub uid: Uuid,
+    pub name: String,
+    pub owner: Option<String>,
+    pub members: IndexMap<String, MemberEntry>,
+    pub generation: u64,
+    pub deletion_timestamp: DateTime<Utc>,
+    pub expiration_timestamp: DateTime<Utc>,
+}
+
+impl Resource for DeletedApplication {
+    fn owner(&self) -> Option<&str> {
+        self.owner.as_deref()
+    }
+
+    fn members(&self) -> &IndexMap<String, MemberEntry> {
+        &self.members
+    }
+}
+
+/// A deleted device, kept in the trash.
+#[derive(Clone, Debug)]
+pub struct DeletedDevice {
+    pub uid: Uuid,
+    pub application: String,
+    pub application_uid: Uuid,
+    pub name: String,
+    pub generation: u64,
+    /// The device got deleted as part of deleting its application.
+    pub cascaded: bool,
+    pub deletion_timestamp: DateTime<Utc>,
+    pub expiration_timestamp: DateTime<Utc>,
+}
+
+#[async_trait]
+pub trait TrashAccessor {
+    /// Move an application, including its aliases and device types, to the trash.
+    ///
+    /// This must be called right before the application gets deleted.
+    async fn archive_app(&self, app: &str, expires: DateTime<Utc>) -> Result<(), ServiceError>;
+
+    /// Move a device, including its aliases and twin, to the trash.
+    ///
+    /// This must be called right before the device gets deleted.
+    async fn archive_device(
+        &self,
+        app: &str,
+        device: &str,
+        expires: DateTime<Utc>,
+    ) -> Result<(), ServiceError>;
+
+    /// Move all devices of an application, which don't have any finalizers, to the trash.
+    ///
+    /// This must be called right before the devices get deleted, as part of deleting the
+    /// application.
+    async fn archive_app_devices(
+        &self,
+        app: &str,
+        expires: DateTime<Utc>,
+    ) -> Result<u64, ServiceError>;
+
+    /// Get all deleted applications the user may restore, the most recently deleted first.
+    async fn list_apps(
+        &self,
+        user: Option<&UserInformation>,
+    ) -> Result<Vec<DeletedApplication>, ServiceError>;
+
+    /// Get the most recently deleted application with the provided name.
+    async fn get_app(
+        &self,
+        app: &str,
+        lock: Lock,
+    ) -> Result<Option<DeletedApplication>, ServiceError>;
+
+    /// Get all deleted devices of an application, the most recently deleted first.
+    async fn list_devices(&self, app_uid: Uuid) -> Result<Vec<DeletedDevice>, ServiceError>;
+
+    /// Get the most recently deleted device with the provided name.
+    async fn get_device(
+        &self,
+        app_uid: Uuid,
+        device: &str,
+        lock: Lock,
+    ) -> Result<Option<DeletedDevice>, ServiceError>;
+
+    /// Restore an application from the trash.
+    ///
+    /// This also restores all devices which got deleted as part of deleting the application, and
+    /// returns them.
+    async fn restore_app(&self, uid: Uuid) -> Result<Vec<DeletedDevice>, ServiceError>;
+
+    /// Restore a device from the trash.
+    async fn restore_device(&self, uid: Uuid) -> Result<(), ServiceError>;
+
+    /// Purge all resources from the trash, which expired at the provided timestamp.
+    async fn purge(&self, now: DateTime<Utc>) -> Result<u64, ServiceError>;
+}
+
+/// A deleted device, with its archived record, aliases, and twin.
+type ArchivedDevice = (DeletedDevice, Json<Value>, Json<Value>, Option<Json<Value>>);
+
+pub struct PostgresTrashAccessor<'c, C: Client> {
+    client: &'c C,
+}
+
+impl<'c, C: Client> PostgresTrashAccessor<'c, C> {
+    pub fn new(client: &'c C) -> Self {
+        Self { client }
+    }
+
+    pub fn app_from_row(row: Row) -> Result<DeletedApplication, tokio_postgres::Error> {
+        Ok(DeletedApplication {
+            uid: row.try_get("UID")?,
+            name: row.try_get("NAME")?,
+            owner: row.try_get("OWNER")?,
+            members: row
+                .try_get::<_, Json<IndexMap<String, MemberEntry>>>("MEMBERS")
+                .map(|json| json.0)
+                .or_else(fix_null_default)?,
+            generation: row.try_get::<_, i64>("GENERATION")? as u64,
+            deletion_timestamp: row.try_get("DELETION_TIMESTAMP")?,
+            expiration_timestamp: row.try_get("EXPIRATION_TIMESTAMP")?,
+        })
+    }
+
+    pub fn device_from_row(row: Row) -> Result<DeletedDevice, tokio_postgres::Error> {
+        Ok(DeletedDevice {
+            uid: row.try_get("UID")?,
+            application: row.try_get("APP")?,
+            application_uid: row.try_get("APP_UID")?,
+            name: row.try_get("NAME")?,
+            generation: row.try_get::<_, i64>("GENERATION")? as u64,
+            cascaded: row.try_get("CASCADED")?,
+            deletion_timestamp: row.try_get("DELETION_TIMESTAMP")?,
+            expiration_timestamp: row.try_get("EXPIRATION_TIMESTAMP")?,
+        })
+    }
+
+    /// Convert a row, including the archived records, of the deleted devices table.
+    fn archived_device_from_row(row: Row) -> Result<ArchivedDevice, tokio_postgres::Error> {
+        let resource = row.try_get("RESOURCE")?;
+        let aliases = row.try_get("ALIASES")?;
+        let twin = row.try_get("TWIN")?;
+        Ok((Self::device_from_row(row)?, resource, aliases, twin))
+    }
+
+    /// Restore the devices, and their aliases and twins, from the trash which match the condition.
+    ///
+    /// The condition is applied on the `DELETED_DEVICES` table, aliased as `D`.
+    async fn restore_devices(
+        &self,
+        condition: &str,
+        uid: &Uuid,
+    ) -> Result<Vec<DeletedDevice>, ServiceError> {
+        let params: Vec<&(dyn ToSql + Sync)> = vec![uid];
+
+        let devices = self
+            .client
+            .query_raw(
+                format!(
+                    r#"
+DELETE FROM
+    DELETED_DEVICES D
+WHERE
+    {}
+RETURNING
+    D.UID,
+    D.APP,
+    D.APP_UID,
+    D.NAME,
+    D.GENERATION,
+    D.CASCADED,
+    D.DELETION_TIMESTAMP,
+    D.EXPIRATION_TIMESTAMP,
+    D.RESOURCE,
+    D.ALIASES,
+    D.TWIN
+"#,
+                    condition
+                )
+                .as_str(),
+                slice_iter(&params[..]),
+            )
+            .await?
+            .and_then(|row| future::ready(Self::archived_device_from_row(row)))
+            .try_collect::<Vec<_>>()
+            .await?;
+
+        let mut result = Vec::with_capacity(devices.len());
+
+        for (device, resource, aliases, twin) in devices {
+            self.client
+                .execute(
+                    format!(
+                        r#"
+INSERT INTO DEVICES
+SELECT * FROM jsonb_populate_record(NULL::DEVICES, $1::JSONB || {})
+"#,
+                        RESTORE_PATCH
+                    )
+                    .as_str(),
+                    &[&resource],
+                )
+                .await?;
+
+            self.client
+                .execute(
+                    r#"
+INSERT INTO DEVICE_ALIASES
+SELECT * FROM jsonb_populate_recordset(NULL::DEVICE_ALIASES, $1::JSONB)
+"#,
+                    &[&aliases],
+                )
+                .await?;
+
+            if let Some(twin) = twin {
+                self.client
+                    .execute(
+                        r#"
+INSERT INTO DEVICE_TWINS
+SELECT * FROM jsonb_populate_record(NULL::DEVICE_TWINS, $1::JSONB)
+"#,
+                        &[&twin],
+                    )
+                    .await?;
+            }
+
+            result.push(device);
+        }
+
+        Ok(result)
+    }
+}
+
+#[async_trait]
+impl<'c, C: Client> TrashAccessor for PostgresTrashAccessor<'c, C> {
+    async fn archive_app(&self, app: &str, expires: DateTime<Utc>) -> Result<(), ServiceError> {
+        self.client
+            .execute(
+                r#"
+INSERT INTO DELETED_APPLICATIONS (
+    UID,
+    NAME,
+    OWNER,
+    MEMBERS,
+    GENERATION,
+    DELETION_TIMESTAMP,
+    EXPIRATION_TIMESTAMP,
+    RESOURCE,
+    ALIASES,
+    DEVICE_TYPES
+)
+SELECT
+    A.UID,
+    A.NAME,
+    A.OWNER,
+    A.MEMBERS,
+    A.GENERATION,
+    $2,
+    $3,
+    to_jsonb(A),
+    (SELECT COALESCE(jsonb_agg(to_jsonb(AA)), '[]') FROM APPLICATION_ALIASES AA WHERE AA.APP = A.NAME),
+    (SELECT COALESCE(jsonb_agg(to_jsonb(T)), '[]') FROM DEVICE_TYPES T WHERE T.APP = A.NAME)
+FROM
+    APPLICATIONS A
+WHERE
+    A.NAME = $1
+"#,
+                &[&app, &Utc::now(), &expires],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn archive_device(
+        &self,
+        app: &str,
+        device: &str,
+        expires: DateTime<Utc>,
+    ) -> Result<(), ServiceError> {
+        // a device deleted while its application is being deleted, is part of the application
+        self.client
+            .execute(
+                r#"
+INSERT INTO DELETED_DEVICES (
+    UID,
+    APP,
+    APP_UID,
+    NAME,
+    GENERATION,
+    CASCADED,
+    DELETION_TIMESTAMP,
+    EXPIRATION_TIMESTAMP,
+    RESOURCE,
+    ALIASES,
+    TWIN
+)
+SELECT
+    D.UID,
+    D.APP,
+    A.UID,
+    D.NAME,
+    D.GENERATION,
+    A.DELETION_TIMESTAMP IS NOT NULL,
+    $3,
+    $4,
+    to_jsonb(D),
+    (SELECT COALESCE(jsonb_agg(to_jsonb(DA)), '[]') FROM DEVICE_ALIASES DA WHERE DA.APP = D.APP AND DA.DEVICE = D.NAME),
+    (SELECT to_jsonb(DT) FROM DEVICE_TWINS DT WHERE DT.APP = D.APP AND DT.DEVICE = D.NAME)
+FROM
+    DEVICES D INNER JOIN APPLICATIONS A ON D.APP = A.NAME
+WHERE
+        D.APP = $1
+    AND
+        D.NAME = $2
+"#,
+                &[&app, &device, &Utc::now(), &expires],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn archive_app_devices(
+        &self,
+        app: &str,
+        expires: DateTime<Utc>,
+    ) -> Result<u64, ServiceError> {
+        let count = self
+            .client
+            .execute(
+                r#"
+INSERT INTO DELETED_DEVICES (
+    UID,
+    APP,
+    APP_UID,
+    NAME,
+    GENERATION,
+    CASCADED,
+    DELETION_TIMESTAMP,
+    EXPIRATION_TIMESTAMP,
+    RESOURCE,
+    ALIASES,
+    TWIN
+)
+SELECT
+    D.UID,
+    D.APP,
+    A.UID,
+    D.NAME,
+    D.GENERATION,
+    TRUE,
+    $2,
+    $3,
+    to_jsonb(D),
+    (SELECT COALESCE(jsonb_agg(to_jsonb(DA)), '[]') FROM DEVICE_ALIASES DA WHERE DA.APP = D.APP AND DA.DEVICE = D.NAME),
+    (SELECT to_jsonb(DT) FROM DEVICE_TWINS DT WHERE DT.APP = D.APP AND DT.DEVICE = D.NAME)
+FROM
+    DEVICES D INNER JOIN APPLICATIONS A ON D.APP = A.NAME
+WHERE
+        D.APP = $1
+    AND
+        cardinality ( D.FINALIZERS ) = 0
+"#,
+                &[&app, &Utc::now(), &expires],
+            )
+            .await?;
+
+        Ok(count)
+    }
+
+    async fn list_apps(
+        &self,
+        user: Option<&UserInformation>,
+    ) -> Result<Vec<DeletedApplication>, ServiceError> {
+        let select = r#"
+SELECT
+    UID,
+    NAME,
+    OWNER,
+    MEMBERS,
+    GENERATION,
+    DELETION_TIMESTAMP,
+    EXPIRATION_TIMESTAMP
+FROM
+    DELETED_APPLICATIONS
+"#;
+
+        // only the applications the user would be permitted to restore
+
+        let (mut select, params) = SelectBuilder::new(select, Vec::new())
+            .auth_admin(&user)
+            .build();
+        select.push_str("\nORDER BY DELETION_TIMESTAMP DESC");
+
+        let result = self
+            .client
+            .query_raw(select.as_str(), slice_iter(&params[..]))
+            .await?
+            .and_then(|row| future::ready(Self::app_from_row(row)))
+            .try_collect()
+            .await?;
+
+        Ok(result)
+    }
+
+    async fn get_app(
+        &self,
+        app: &str,
+        lock: Lock,
+    ) -> Result<Option<DeletedApplication>, ServiceError> {
+        let sql = format!(
+            r#"
+SELECT
+    UID,
+    NAME,
+    OWNER,
+    MEMBERS,
+    GENERATION,
+    DELETION_TIMESTAMP,
+    EXPIRATION_TIMESTAMP
+FROM
+    DELETED_APPLICATIONS
+WHERE
+    NAME = $1
+ORDER BY
+    DELETION_TIMESTAMP DESC
+LIMIT 1
+{}
+"#,
+            lock.as_ref()
+        );
+
+        let result = self.client.query_opt(sql.as_str(), &[&app]).await?;
+
+        Ok(result.map(Self::app_from_row).transpose()?)
+    }
+
+    async fn list_devices(&self, app_uid: Uuid) -> Result<Vec<DeletedDevice>, ServiceError> {
+        let params: Vec<&(dyn ToSql + Sync)> = vec![&app_uid];
+
+        let result = self
+            .client
+            .query_raw(
+                r#"
+SELECT
+    UID,
+    APP,
+    APP_UID,
+    NAME,
+    GENERATION,
+    CASCADED,
+    DELETION_TIMESTAMP,
+    EXPIRATION_TIMESTAMP
+FROM
+    DELETED_DEVICES
+WHERE
+    APP_UID = $1
+ORDER BY
+    DELETION_TIMESTAMP DESC
+"#,
+                slice_iter(&params[..]),
+            )
+            .await?
+            .and_then(|row| future::ready(Self::device_from_row(row)))
+            .try_collect()
+            .await?;
+
+        Ok(result)
+    }
+
+    async fn get_device(
+        &self,
+        app_uid: Uuid,
+        device: &str,
+        lock: Lock,
+    ) -> Result<Option<DeletedDevice>, ServiceError> {
+        let sql = format!(
+            r#"
+SELECT
+    UID,
+    APP,
+    APP_UID,
+    NAME,
+    GENERATION,
+    CASCADED,
+    DELETION_TIMESTAMP,
+    EXPIRATION_TIMESTAMP
+FROM
+    DELETED_DEVICES
+WHERE
+        APP_UID = $1
+    AND
+        NAME = $2
+ORDER BY
+    DELETION_TIMESTAMP DESC
+LIMIT 1
+{}
+"#,
+            lock.as_ref()
+        );
+
+        let result = self
+            .client
+            .query_opt(sql.as_str(), &[&app_uid, &device])
+            .await?;
+
+        Ok(result.map(Self::device_from_row).transpose()?)
+    }
+
+    async fn restore_app(&self, uid: Uuid) -> Result<Vec<DeletedDevice>, ServiceError> {
+        let row = self
+            .client
+            .query_opt(
+                r#"
+DELETE FROM
+    DELETED_APPLICATIONS
+WHERE
+    UID = $1
+RETURNING
+    RESOURCE,
+    ALIASES,
+    DEVICE_TYPES
+"#,
+                &[&uid],
+            )
+            .await?
+            .ok_or(ServiceError::NotFound)?;
+
+        let resource: Json<Value> = row.try_get("RESOURCE")?;
+        let aliases: Json<Value> = row.try_get("ALIASES")?;
+        let device_types: Json<Value> = row.try_get("DEVICE_TYPES")?;
+
+        self.client
+            .execute(
+                format!(
+                    r#"
+INSERT INTO APPLICATIONS
+SELECT * FROM jsonb_populate_record(NULL::APPLICATIONS, $1::JSONB || {})
+"#,
+                    RESTORE_PATCH
+                )
+                .as_str(),
+                &[&resource],
+            )
+            .await?;
+
+        self.client
+            .execute(
+                r#"
+INSERT INTO APPLICATION_ALIASES
+SELECT * FROM jsonb_populate_recordset(NULL::APPLICATION_ALIASES, $1::JSONB)
+"#,
+                &[&aliases],
+            )
+            .await?;
+
+        self.client
+            .execute(
+                r#"
+INSERT INTO DEVICE_TYPES
+SELECT * FROM jsonb_populate_recordset(NULL::DEVICE_TYPES, $1::JSONB)
+"#,
+                &[&device_types],
+            )
+            .await?;
+
+        // restore the devices which got deleted together with the application
+
+        self.restore_devices("D.APP_UID = $1 AND D.CASCADED", &uid)
+            .await
+    }
+
+    async fn restore_device(&self, uid: Uuid) -> Result<(), ServiceError> {
+        let devices = self.restore_devices("D.UID = $1", &uid).await?;
+
+        if devices.is_empty() {
+            Err(ServiceError::NotFound)
+        } else {
+            Ok(())
+        }
+    }
+
+    async fn purge(&self, now: DateTime<Utc>) -> Result<u64, ServiceError> {
+        let devices = self
+            .client
+            .execute(
+                "DELETE FROM DELETED_DEVICES WHERE EXPIRATION_TIMESTAMP <= $1",
+                &[&now],
+            )
+            .await?;
+
+        let apps = self
+            .client
+            .execute(
+                "DELETE FROM DELETED_APPLICATIONS WHERE EXPIRATION_TIMESTAMP <= $1",
+                &[&now],
+            )
+            .await?;
+
+        Ok(devices + apps)
+    }
+}
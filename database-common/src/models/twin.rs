@@ -0,0 +1,198 @@
+use crate::{error::ServiceError, models::Lock, Client};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use drogue_cloud_service_api::twin::{self, DeviceTwin};
+use serde_json::Value;
+use tokio_postgres::{types::Json, Row};
+
+/// A device twin entity record.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Twin {
+    pub application: String,
+    pub device: String,
+
+    pub reported: Value,
+    pub reported_timestamp: Option<DateTime<Utc>>,
+
+    pub desired: Value,
+    pub desired_timestamp: Option<DateTime<Utc>>,
+}
+
+impl Twin {
+    /// An empty twin, used when nothing was stored for a device so far.
+    pub fn new<A: Into<String>, D: Into<String>>(application: A, device: D) -> Self {
+        Self {
+            application: application.into(),
+            device: device.into(),
+            reported: Value::Object(Default::default()),
+            reported_timestamp: None,
+            desired: Value::Object(Default::default()),
+            desired_timestamp: None,
+        }
+    }
+
+    /// Evaluate the part of the desired state, which doesn't match the reported state.
+    pub fn delta(&self) -> Option<Value> {
+        twin::delta(&self.desired, &self.reported)
+    }
+}
+
+impl From<Twin> for DeviceTwin {
+    fn from(twin: Twin) -> Self {
+        let delta = twin.delta();
+        DeviceTwin {
+            reported: twin.reported,
+            reported_timestamp: twin.reported_timestamp,
+            desired: twin.desired,
+            desired_timestamp: twin.desired_timestamp,
+            delta,
+        }
+    }
+}
+
+#[async_trait]
+pub trait TwinAccessor {
+    /// Get the twin of a device.
+    async fn get(&self, app: &str, device: &str, lock: Lock) -> Result<Option<Twin>, ServiceError>;
+
+    /// Replace the reported state of a device, creating the twin if necessary.
+    async fn set_reported(
+        &self,
+        app: &str,
+        device: &str,
+        reported: Value,
+        timestamp: DateTime<Utc>,
+    ) -> Result<(), ServiceError>;
+
+    /// Replace the desired state of a device, creating the twin if necessary.
+    async fn set_desired(
+        &self,
+        app: &str,
+        device: &str,
+        desired: Value,
+        timestamp: DateTime<Utc>,
+    ) -> Result<(), ServiceError>;
+}
+
+pub struct PostgresTwinAccessor<'c, C: Client> {
+    client: &'c C,
+}
+
+impl<'c, C: Client> PostgresTwinAccessor<'c, C> {
+    pub fn new(client: &'c C) -> Self {
+        Self { client }
+    }
+
+    pub fn from_row(row: Row) -> Result<Twin, tokio_postgres::Error> {
+        Ok(Twin {
+            application: row.try_get("APP")?,
+            device: row.try_get("DEVICE")?,
+
+            reported: row.try_get::<_, Json<_>>("REPORTED")?.0,
+            reported_timestamp: row.try_get("REPORTED_TIMESTAMP")?,
+
+            desired: row.try_get::<_, Json<_>>("DESIRED")?.0,
+            desired_timestamp: row.try_get("DESIRED_TIMESTAMP")?,
+        })
+    }
+}
+
+#[async_trait]
+impl<'c, C: Client> TwinAccessor for PostgresTwinAccessor<'c, C> {
+    async fn get(&self, app: &str, device: &str, lock: Lock) -> Result<Option<Twin>, ServiceError> {
+        let sql = format!(
+            r#"
+SELECT
+    APP,
+    DEVICE,
+    REPORTED,
+    REPORTED_TIMESTAMP,
+    DESIRED,
+    DESIRED_TIMESTAMP
+FROM
+    DEVICE_TWINS
+WHERE
+        APP = $1
+    AND
+        DEVICE = $2
+{}
+"#,
+            lock.as_ref()
+        );
+
+        let result = self
+            .client
+            .query_opt(sql.as_str(), &[&app, &device])
+            .await?;
+
+        Ok(result.map(Self::from_row).transpose()?)
+    }
+
+    async fn set_reported(
+        &self,
+        app: &str,
+        device: &str,
+        reported: Value,
+        timestamp: DateTime<Utc>,
+    ) -> Result<(), ServiceError> {
+        self.client
+            .execute(
+                r#"
+INSERT INTO DEVICE_TWINS (
+    APP,
+    DEVICE,
+    REPORTED,
+    REPORTED_TIMESTAMP
+) VALUES (
+    $1,
+    $2,
+    $3,
+    $4
+)
+ON CONFLICT (APP, DEVICE)
+DO
+    UPDATE SET
+        REPORTED = EXCLUDED.REPORTED,
+        REPORTED_TIMESTAMP = EXCLUDED.REPORTED_TIMESTAMP
+"#,
+                &[&app, &device, &Json(reported), &timestamp],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn set_desired(
+        &self,
+        app: &str,
+        device: &str,
+        desired: Value,
+        timestamp: DateTime<Utc>,
+    ) -> Result<(), ServiceError> {
+        self.client
+            .execute(
+                r#"
+INSERT INTO DEVICE_TWINS (
+    APP,
+    DEVICE,
+    DESIRED,
+    DESIRED_TIMESTAMP
+) VALUES (
+    $1,
+    $2,
+    $3,
+    $4
+)
+ON CONFLICT (APP, DEVICE)
+DO
+    UPDATE SET
+        DESIRED = EXCLUDED.DESIRED,
+        DESIRED_TIMESTAMP = EXCLUDED.DESIRED_TIMESTAMP
+"#,
+                &[&app, &device, &Json(desired), &timestamp],
+            )
+            .await?;
+
+        Ok(())
+    }
+}
@@ -1,15 +1,14 @@
-use super::streamer::ArrayStreamer;
+use super::streamer::{json_lines, ArrayStreamer};
 use crate::{
-    endpoints::params::{DeleteParams, ListParams},
-    service::{management::ManagementService, PostgresManagementService},
+    endpoints::params::{DeleteParams, ListParams, HEADER_CONTINUE, HEADER_TOTAL_COUNT},
+    service::{management::ManagementService, patch::Patch, PostgresManagementService},
     WebData,
 };
 use actix_web::{http::header, web, web::Json, HttpRequest, HttpResponse};
 use drogue_client::registry;
 use drogue_cloud_registry_events::EventSender;
-use drogue_cloud_service_api::{auth::user::UserInformation, labels::ParserError};
+use drogue_cloud_service_api::auth::user::UserInformation;
 use drogue_cloud_service_common::error::ServiceError;
-use std::convert::TryInto;
 
 pub async fn create<S>(
     data: web::Data<WebData<PostgresManagementService<S>>>,
@@ -63,6 +62,66 @@ where
     Ok(HttpResponse::NoContent().finish())
 }
 
+pub async fn update_status<S>(
+    data: web::Data<WebData<PostgresManagementService<S>>>,
+    path: web::Path<String>,
+    app: Json<registry::v1::Application>,
+    user: UserInformation,
+) -> Result<HttpResponse, actix_web::Error>
+where
+    S: EventSender + Clone,
+{
+    let app_id = path.into_inner();
+
+    log::debug!("Updating app status: '{:?}'", app);
+
+    if app_id.is_empty() {
+        return Ok(HttpResponse::BadRequest().finish());
+    }
+
+    if app_id != app.metadata.name {
+        return Ok(HttpResponse::BadRequest().finish());
+    }
+
+    data.service.update_app_status(&user, app.0).await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub async fn patch<S>(
+    data: web::Data<WebData<PostgresManagementService<S>>>,
+    path: web::Path<String>,
+    body: web::Bytes,
+    user: UserInformation,
+    req: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error>
+where
+    S: EventSender + Clone,
+{
+    let app_id = path.into_inner();
+
+    log::debug!("Patching app: '{}'", app_id);
+
+    if app_id.is_empty() {
+        return Ok(HttpResponse::BadRequest().finish());
+    }
+
+    let content_type = req
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+
+    let patch = match Patch::parse(content_type, &body) {
+        Some(patch) => patch.map_err(|err| ServiceError::InvalidRequest(err.to_string()))?,
+        None => return Ok(HttpResponse::UnsupportedMediaType().finish()),
+    };
+
+    data.service.patch_app(&user, &app_id, patch).await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
 pub async fn delete<S>(
     data: web::Data<WebData<PostgresManagementService<S>>>,
     path: web::Path<String>,
@@ -120,18 +179,35 @@ where
 {
     log::debug!("Listing apps");
 
-    let selector = params
-        .0
-        .labels
-        .try_into()
-        .map_err(|err: ParserError| ServiceError::InvalidRequest(err.to_string()))?;
+    let watch = params.0.watch;
+    let options = params.0.into_options()?;
+
+    if watch {
+        let events = data
+            .service
+            .watch_apps(
+                user,
+                options.labels,
+                options.fields,
+                options.resource_version,
+            )
+            .await?;
+
+        return Ok(HttpResponse::Ok()
+            .content_type("application/json")
+            .streaming(json_lines(events)));
+    }
 
-    let apps = data
-        .service
-        .list_apps(user, selector, params.0.limit, params.0.offset)
-        .await?;
+    let page = data.service.list_apps(user, options).await?;
+
+    let mut response = HttpResponse::Ok();
+    response.content_type("application/json");
+    if let Some(continue_token) = page.continue_token {
+        response.append_header((HEADER_CONTINUE, continue_token));
+    }
+    if let Some(total) = page.total {
+        response.append_header((HEADER_TOTAL_COUNT, total.to_string()));
+    }
 
-    Ok(HttpResponse::Ok()
-        .content_type("application/json")
-        .streaming(ArrayStreamer::new(apps)))
+    Ok(response.streaming(ArrayStreamer::new(page.items)))
 }
@@ -0,0 +1,139 @@
+use crate::{
+    service::{management::ManagementService, PostgresManagementService},
+    WebData,
+};
+use actix_web::{http::header, web, web::Json, HttpRequest, HttpResponse};
+use drogue_cloud_registry_events::EventSender;
+use drogue_cloud_service_api::{auth::user::UserInformation, device_type::DeviceType};
+
+pub async fn create<S>(
+    data: web::Data<WebData<PostgresManagementService<S>>>,
+    path: web::Path<String>,
+    device_type: Json<DeviceType>,
+    user: UserInformation,
+    req: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error>
+where
+    S: EventSender + Clone,
+{
+    let app_id = path.into_inner();
+    log::debug!("Creating device type: '{}' / '{:?}'", app_id, device_type);
+
+    if device_type.metadata.name.is_empty() || device_type.metadata.application != app_id {
+        return Ok(HttpResponse::BadRequest().finish());
+    }
+
+    let location = req.url_for("deviceType", &[&app_id, &device_type.metadata.name])?;
+
+    data.service
+        .create_device_type(&user, device_type.0)
+        .await?;
+
+    let response = HttpResponse::Created()
+        .append_header((header::LOCATION, location.as_str()))
+        .finish();
+
+    Ok(response)
+}
+
+pub async fn list<S>(
+    data: web::Data<WebData<PostgresManagementService<S>>>,
+    path: web::Path<String>,
+    user: UserInformation,
+) -> Result<HttpResponse, actix_web::Error>
+where
+    S: EventSender + Clone,
+{
+    let app_id = path.into_inner();
+
+    log::debug!("Listing device types: '{}'", app_id);
+
+    if app_id.is_empty() {
+        return Ok(HttpResponse::BadRequest().finish());
+    }
+
+    let device_types = data.service.list_device_types(&user, &app_id).await?;
+
+    Ok(HttpResponse::Ok().json(device_types))
+}
+
+pub async fn read<S>(
+    data: web::Data<WebData<PostgresManagementService<S>>>,
+    path: web::Path<(String, String)>,
+    user: UserInformation,
+) -> Result<HttpResponse, actix_web::Error>
+where
+    S: EventSender + Clone,
+{
+    let (app_id, name) = path.into_inner();
+
+    log::debug!("Reading device type: '{}' / '{}'", app_id, name);
+
+    if app_id.is_empty() || name.is_empty() {
+        return Ok(HttpResponse::BadRequest().finish());
+    }
+
+    let device_type = data.service.get_device_type(&user, &app_id, &name).await?;
+
+    let result = match device_type {
+        None => HttpResponse::NotFound().finish(),
+        Some(device_type) => HttpResponse::Ok().json(device_type),
+    };
+
+    Ok(result)
+}
+
+pub async fn update<S>(
+    data: web::Data<WebData<PostgresManagementService<S>>>,
+    path: web::Path<(String, String)>,
+    user: UserInformation,
+    device_type: Json<DeviceType>,
+) -> Result<HttpResponse, actix_web::Error>
+where
+    S: EventSender + Clone,
+{
+    let (app_id, name) = path.into_inner();
+
+    log::debug!(
+        "Updating device type: '{}' / '{}' / '{:?}'",
+        app_id,
+        name,
+        device_type
+    );
+
+    if app_id.is_empty() || name.is_empty() {
+        return Ok(HttpResponse::BadRequest().finish());
+    }
+    if app_id != device_type.metadata.application || name != device_type.metadata.name {
+        return Ok(HttpResponse::BadRequest().finish());
+    }
+
+    data.service
+        .update_device_type(&user, device_type.0)
+        .await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub async fn delete<S>(
+    data: web::Data<WebData<PostgresManagementService<S>>>,
+    path: web::Path<(String, String)>,
+    user: UserInformation,
+) -> Result<HttpResponse, actix_web::Error>
+where
+    S: EventSender + Clone,
+{
+    let (app_id, name) = path.into_inner();
+
+    log::debug!("Deleting device type: '{}' / '{}'", app_id, name);
+
+    if app_id.is_empty() || name.is_empty() {
+        return Ok(HttpResponse::BadRequest().finish());
+    }
+
+    data.service
+        .delete_device_type(&user, &app_id, &name)
+        .await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -1,17 +1,24 @@
 use crate::{
     endpoints::{
-        params::{DeleteParams, ListParams},
-        streamer::ArrayStreamer,
+        params::{
+            DeleteParams, ExportParams, ImportParams, ListParams, ReadParams, HEADER_CONTINUE,
+            HEADER_TOTAL_COUNT,
+        },
+        streamer::{json_lines, ArrayStreamer},
+    },
+    service::{
+        bulk::{self, CONTENT_TYPE_NDJSON},
+        management::ManagementService,
+        patch::Patch,
+        PostgresManagementService,
     },
-    service::{management::ManagementService, PostgresManagementService},
     WebData,
 };
 use actix_web::{http::header, web, web::Json, HttpRequest, HttpResponse};
 use drogue_client::registry;
 use drogue_cloud_registry_events::EventSender;
-use drogue_cloud_service_api::{auth::user::UserInformation, labels::ParserError};
+use drogue_cloud_service_api::auth::user::UserInformation;
 use drogue_cloud_service_common::error::ServiceError;
-use std::convert::TryInto;
 
 pub async fn create<S>(
     data: web::Data<WebData<PostgresManagementService<S>>>,
@@ -71,6 +78,72 @@ where
     Ok(HttpResponse::NoContent().finish())
 }
 
+pub async fn update_status<S>(
+    data: web::Data<WebData<PostgresManagementService<S>>>,
+    path: web::Path<(String, String)>,
+    user: UserInformation,
+    device: Json<registry::v1::Device>,
+) -> Result<HttpResponse, actix_web::Error>
+where
+    S: EventSender + Clone,
+{
+    let (app_id, device_id) = path.into_inner();
+
+    log::debug!(
+        "Updating device status: '{}' / '{}' / '{:?}'",
+        app_id,
+        device_id,
+        device
+    );
+
+    if app_id.is_empty() || device_id.is_empty() {
+        return Ok(HttpResponse::BadRequest().finish());
+    }
+    if app_id != device.metadata.application || device_id != device.metadata.name {
+        return Ok(HttpResponse::BadRequest().finish());
+    }
+
+    data.service.update_device_status(&user, device.0).await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub async fn patch<S>(
+    data: web::Data<WebData<PostgresManagementService<S>>>,
+    path: web::Path<(String, String)>,
+    body: web::Bytes,
+    user: UserInformation,
+    req: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error>
+where
+    S: EventSender + Clone,
+{
+    let (app_id, device_id) = path.into_inner();
+
+    log::debug!("Patching device: '{}' / '{}'", app_id, device_id);
+
+    if app_id.is_empty() || device_id.is_empty() {
+        return Ok(HttpResponse::BadRequest().finish());
+    }
+
+    let content_type = req
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+
+    let patch = match Patch::parse(content_type, &body) {
+        Some(patch) => patch.map_err(|err| ServiceError::InvalidRequest(err.to_string()))?,
+        None => return Ok(HttpResponse::UnsupportedMediaType().finish()),
+    };
+
+    data.service
+        .patch_device(&user, &app_id, &device_id, patch)
+        .await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
 pub async fn delete<S>(
     data: web::Data<WebData<PostgresManagementService<S>>>,
     path: web::Path<(String, String)>,
@@ -103,6 +176,7 @@ where
 pub async fn read<S>(
     data: web::Data<WebData<PostgresManagementService<S>>>,
     path: web::Path<(String, String)>,
+    params: web::Query<ReadParams>,
     user: UserInformation,
 ) -> Result<HttpResponse, actix_web::Error>
 where
@@ -116,7 +190,14 @@ where
         return Ok(HttpResponse::BadRequest().finish());
     }
 
-    let device = data.service.get_device(&user, &app_id, &device_id).await?;
+    let device = match params.effective {
+        true => {
+            data.service
+                .get_effective_device(&user, &app_id, &device_id)
+                .await?
+        }
+        false => data.service.get_device(&user, &app_id, &device_id).await?,
+    };
 
     let result = match device {
         None => HttpResponse::NotFound().finish(),
@@ -126,6 +207,64 @@ where
     Ok(result)
 }
 
+pub async fn gateways<S>(
+    data: web::Data<WebData<PostgresManagementService<S>>>,
+    path: web::Path<(String, String)>,
+    user: UserInformation,
+) -> Result<HttpResponse, actix_web::Error>
+where
+    S: EventSender + Clone,
+{
+    let (app_id, device_id) = path.into_inner();
+
+    log::debug!("Reading device gateways: '{}' / '{}'", app_id, device_id);
+
+    if app_id.is_empty() || device_id.is_empty() {
+        return Ok(HttpResponse::BadRequest().finish());
+    }
+
+    let gateways = data
+        .service
+        .get_device_gateways(&user, &app_id, &device_id)
+        .await?;
+
+    let result = match gateways {
+        None => HttpResponse::NotFound().finish(),
+        Some(gateways) => HttpResponse::Ok().json(gateways),
+    };
+
+    Ok(result)
+}
+
+pub async fn children<S>(
+    data: web::Data<WebData<PostgresManagementService<S>>>,
+    path: web::Path<(String, String)>,
+    user: UserInformation,
+) -> Result<HttpResponse, actix_web::Error>
+where
+    S: EventSender + Clone,
+{
+    let (app_id, device_id) = path.into_inner();
+
+    log::debug!("Reading gateway children: '{}' / '{}'", app_id, device_id);
+
+    if app_id.is_empty() || device_id.is_empty() {
+        return Ok(HttpResponse::BadRequest().finish());
+    }
+
+    let children = data
+        .service
+        .get_device_children(&user, &app_id, &device_id)
+        .await?;
+
+    let result = match children {
+        None => HttpResponse::NotFound().finish(),
+        Some(children) => HttpResponse::Ok().json(children),
+    };
+
+    Ok(result)
+}
+
 pub async fn list<S>(
     data: web::Data<WebData<PostgresManagementService<S>>>,
     path: web::Path<String>,
@@ -143,18 +282,103 @@ where
         return Ok(HttpResponse::BadRequest().finish());
     }
 
-    let selector = params
-        .0
-        .labels
-        .try_into()
-        .map_err(|err: ParserError| ServiceError::InvalidRequest(err.to_string()))?;
+    let watch = params.0.watch;
+    let options = params.0.into_options()?;
+
+    if watch {
+        let events = data
+            .service
+            .watch_devices(
+                user,
+                &app_id,
+                options.labels,
+                options.fields,
+                options.resource_version,
+            )
+            .await?;
+
+        return Ok(HttpResponse::Ok()
+            .content_type("application/json")
+            .streaming(json_lines(events)));
+    }
+
+    let page = data.service.list_devices(user, &app_id, options).await?;
+
+    let mut response = HttpResponse::Ok();
+    response.content_type("application/json");
+    if let Some(continue_token) = page.continue_token {
+        response.append_header((HEADER_CONTINUE, continue_token));
+    }
+    if let Some(total) = page.total {
+        response.append_header((HEADER_TOTAL_COUNT, total.to_string()));
+    }
+
+    Ok(response.streaming(ArrayStreamer::new(page.items)))
+}
+
+pub async fn import<S>(
+    data: web::Data<WebData<PostgresManagementService<S>>>,
+    path: web::Path<String>,
+    params: web::Query<ImportParams>,
+    body: web::Bytes,
+    user: UserInformation,
+    req: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error>
+where
+    S: EventSender + Clone,
+{
+    let app_id = path.into_inner();
+
+    log::debug!("Importing devices: '{}'", app_id);
+
+    if app_id.is_empty() {
+        return Ok(HttpResponse::BadRequest().finish());
+    }
+
+    let options = params.0.into_options()?;
+
+    let content_type = req
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+
+    let devices = match bulk::parse(content_type, &body, &options) {
+        Some(devices) => devices.map_err(|err| ServiceError::InvalidRequest(err.to_string()))?,
+        None => return Ok(HttpResponse::UnsupportedMediaType().finish()),
+    };
+
+    let results = data
+        .service
+        .import_devices(&user, &app_id, devices, options)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(results))
+}
+
+pub async fn export<S>(
+    data: web::Data<WebData<PostgresManagementService<S>>>,
+    path: web::Path<String>,
+    params: web::Query<ExportParams>,
+    user: UserInformation,
+) -> Result<HttpResponse, actix_web::Error>
+where
+    S: EventSender + Clone,
+{
+    let app_id = path.into_inner();
+
+    log::debug!("Exporting devices: '{}'", app_id);
+
+    if app_id.is_empty() {
+        return Ok(HttpResponse::BadRequest().finish());
+    }
 
-    let apps = data
+    let devices = data
         .service
-        .list_devices(user, &app_id, selector, params.0.limit, params.0.offset)
+        .export_devices(user, &app_id, params.0.credentials)
         .await?;
 
     Ok(HttpResponse::Ok()
-        .content_type("application/json")
-        .streaming(ArrayStreamer::new(apps)))
+        .content_type(CONTENT_TYPE_NDJSON)
+        .streaming(json_lines(devices)))
 }
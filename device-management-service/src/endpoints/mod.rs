@@ -1,4 +1,6 @@
 pub mod apps;
+pub mod device_types;
 pub mod devices;
 pub mod params;
 pub mod streamer;
+pub mod twin;
@@ -1,4 +1,17 @@
+use crate::service::{bulk::ImportOptions, management::ListOptions};
+use drogue_cloud_database_common::{
+    error::ServiceError as DatabaseServiceError,
+    models::paging::{Cursor, Sort},
+};
+use drogue_cloud_service_api::labels::ParserError;
+use drogue_cloud_service_common::error::ServiceError;
 use serde::{Deserialize, Serialize};
+use std::convert::TryInto;
+
+/// The header carrying the continuation token of a list, in case there are more items.
+pub const HEADER_CONTINUE: &str = "X-Continue";
+/// The header carrying the total number of matching items of a list, if requested.
+pub const HEADER_TOTAL_COUNT: &str = "X-Total-Count";
 
 #[derive(Clone, Default, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -19,16 +32,139 @@ pub struct Preconditions {
     pub resource_version: String,
 }
 
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadParams {
+    /// Merge in the spec of the device type.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub effective: bool,
+}
+
 #[derive(Clone, Default, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ListParams {
     #[serde(default)]
     #[serde(skip_serializing_if = "String::is_empty")]
     pub labels: String,
+    /// Select by the content of the spec and status, like `spec.foo,status.bar!=baz`.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub fields: String,
     #[serde(default)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub limit: Option<usize>,
     #[serde(default)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub offset: Option<usize>,
+    /// Watch for changes, instead of listing.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub watch: bool,
+    /// Resume a watch after this version, taken from a previous watch event.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resource_version: Option<String>,
+    /// The sort order, like `name` or `-creationTimestamp`.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sort: Option<String>,
+    /// Continue a previous list, using the token of its response.
+    #[serde(default, rename = "continue")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub continue_token: Option<String>,
+    /// Report the total number of matching items.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub count: bool,
+}
+
+impl ListParams {
+    /// Convert into the options of a list operation.
+    pub fn into_options(self) -> Result<ListOptions, ServiceError> {
+        let labels = self
+            .labels
+            .try_into()
+            .map_err(|err: ParserError| ServiceError::InvalidRequest(err.to_string()))?;
+        let fields = self
+            .fields
+            .try_into()
+            .map_err(|err: ParserError| ServiceError::InvalidRequest(err.to_string()))?;
+
+        let sort = match self.sort {
+            Some(sort) => sort.parse().map_err(|err: DatabaseServiceError| {
+                ServiceError::InvalidRequest(err.to_string())
+            })?,
+            None => Sort::default(),
+        };
+
+        let after = match self.continue_token {
+            Some(_) if self.offset.is_some() => {
+                return Err(ServiceError::InvalidRequest(
+                    "Offset and continuation token must not be combined".into(),
+                ))
+            }
+            Some(token) => Some(
+                Cursor::decode(&token, &sort)
+                    .map_err(|err| ServiceError::InvalidRequest(err.to_string()))?,
+            ),
+            None => None,
+        };
+
+        let resource_version = match self.resource_version {
+            Some(_) if !self.watch => {
+                return Err(ServiceError::InvalidRequest(
+                    "A resource version can only be used with a watch".into(),
+                ))
+            }
+            Some(version) => Some(version.parse().map_err(|_| {
+                ServiceError::InvalidRequest(format!("Invalid resource version: {}", version))
+            })?),
+            None => None,
+        };
+
+        Ok(ListOptions {
+            labels,
+            fields,
+            limit: self.limit,
+            offset: self.offset,
+            sort,
+            after,
+            count: self.count,
+            resource_version,
+        })
+    }
+}
+
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportParams {
+    /// Commit in chunks of this size, instead of a single transaction.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chunk_size: Option<usize>,
+}
+
+impl ImportParams {
+    /// Convert into the options of an import operation.
+    pub fn into_options(self) -> Result<ImportOptions, ServiceError> {
+        if self.chunk_size == Some(0) {
+            return Err(ServiceError::InvalidRequest(
+                "Chunk size must be greater than zero".into(),
+            ));
+        }
+
+        Ok(ImportOptions {
+            chunk_size: self.chunk_size,
+        })
+    }
+}
+
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportParams {
+    /// Include the credentials of the devices.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub credentials: bool,
 }
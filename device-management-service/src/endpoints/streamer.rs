@@ -3,7 +3,7 @@ use bytes::{BufMut, Bytes, BytesMut};
 use core::fmt::Debug;
 use futures::{
     task::{Context, Poll},
-    {ready, Stream},
+    {ready, Stream, StreamExt},
 };
 use pin_project::pin_project;
 use serde::Serialize;
@@ -147,6 +147,28 @@ where
     }
 }
 
+/// Stream items as JSON, one item per line.
+///
+/// This is used for long running streams, like watches, where the client can't wait for the end
+/// of an array.
+pub fn json_lines<S, T, E>(stream: S) -> impl Stream<Item = Result<Bytes, ArrayStreamerError<E>>>
+where
+    S: Stream<Item = Result<T, E>>,
+    T: Serialize,
+    E: Debug + Display,
+{
+    stream.map(|item| match item {
+        Ok(item) => match serde_json::to_vec(&item) {
+            Ok(mut buffer) => {
+                buffer.push(b'\n');
+                Ok(Bytes::from(buffer))
+            }
+            Err(err) => Err(ArrayStreamerError::Serializer(err)),
+        },
+        Err(err) => Err(ArrayStreamerError::Source(err)),
+    })
+}
+
 #[cfg(test)]
 mod test {
 
@@ -176,4 +198,15 @@ mod test {
             .collect();
         assert_eq!(outcome, r#"[]"#);
     }
+
+    #[tokio::test]
+    async fn test_json_lines() {
+        let data: Vec<Result<_, String>> = vec![Ok("foo"), Ok("bar")];
+        let outcome: Vec<Bytes> = json_lines(stream::iter(data)).try_collect().await.unwrap();
+        let outcome: String = outcome
+            .into_iter()
+            .map(|b| String::from_utf8(b.to_vec()).unwrap_or_default())
+            .collect();
+        assert_eq!(outcome, "\"foo\"\n\"bar\"\n");
+    }
 }
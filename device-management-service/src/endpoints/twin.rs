@@ -0,0 +1,66 @@
+use crate::{
+    service::{management::ManagementService, PostgresManagementService},
+    WebData,
+};
+use actix_web::{web, web::Json, HttpResponse};
+use drogue_cloud_registry_events::EventSender;
+use drogue_cloud_service_api::auth::user::UserInformation;
+use serde_json::Value;
+
+pub async fn read<S>(
+    data: web::Data<WebData<PostgresManagementService<S>>>,
+    path: web::Path<(String, String)>,
+    user: UserInformation,
+) -> Result<HttpResponse, actix_web::Error>
+where
+    S: EventSender + Clone,
+{
+    let (app_id, device_id) = path.into_inner();
+
+    log::debug!("Reading device twin: '{}' / '{}'", app_id, device_id);
+
+    if app_id.is_empty() || device_id.is_empty() {
+        return Ok(HttpResponse::BadRequest().finish());
+    }
+
+    let twin = data
+        .service
+        .get_device_twin(&user, &app_id, &device_id)
+        .await?;
+
+    let result = match twin {
+        None => HttpResponse::NotFound().finish(),
+        Some(twin) => HttpResponse::Ok().json(twin),
+    };
+
+    Ok(result)
+}
+
+pub async fn update_desired<S>(
+    data: web::Data<WebData<PostgresManagementService<S>>>,
+    path: web::Path<(String, String)>,
+    user: UserInformation,
+    desired: Json<Value>,
+) -> Result<HttpResponse, actix_web::Error>
+where
+    S: EventSender + Clone,
+{
+    let (app_id, device_id) = path.into_inner();
+
+    log::debug!(
+        "Updating desired state: '{}' / '{}' / '{:?}'",
+        app_id,
+        device_id,
+        desired
+    );
+
+    if app_id.is_empty() || device_id.is_empty() {
+        return Ok(HttpResponse::BadRequest().finish());
+    }
+
+    data.service
+        .update_device_twin_desired(&user, &app_id, &device_id, desired.0)
+        .await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -57,11 +57,32 @@ macro_rules! crud {
                         use $module as m;
                         m::update::<$sender>
                     }))
+                    .route(web::patch().to({
+                        use $module as m;
+                        m::patch::<$sender>
+                    }))
                     .route(web::delete().to({
                         use $module as m;
                         m::delete::<$sender>
                     }))
             })
+            .service({
+                let resource = concat!(
+                    $base,
+                    stringify!($name),
+                    "s/{",
+                    stringify!($name),
+                    "}/status"
+                );
+                log::debug!("{}", resource);
+
+                web::resource(resource)
+                    // update the status subresource
+                    .route(web::put().to({
+                        use $module as m;
+                        m::update_status::<$sender>
+                    }))
+            })
     }};
 }
 
@@ -93,6 +114,75 @@ macro_rules! app {
                 device
             );
 
+            let scope = scope
+                .service(
+                    web::resource("apps/{app}/devices/{device}/twin").route(web::get().to({
+                        use endpoints::twin as m;
+                        m::read::<$sender>
+                    })),
+                )
+                .service(
+                    web::resource("apps/{app}/devices/{device}/twin/desired").route(web::put().to(
+                        {
+                            use endpoints::twin as m;
+                            m::update_desired::<$sender>
+                        },
+                    )),
+                )
+                .service(web::resource("apps/{app}/devices/{device}/gateways").route(
+                    web::get().to({
+                        use endpoints::devices as m;
+                        m::gateways::<$sender>
+                    }),
+                ))
+                .service(web::resource("apps/{app}/devices/{device}/children").route(
+                    web::get().to({
+                        use endpoints::devices as m;
+                        m::children::<$sender>
+                    }),
+                ))
+                .service(
+                    web::resource("apps/{app}/import")
+                        .app_data(web::PayloadConfig::new(
+                            $crate::service::bulk::MAX_IMPORT_PAYLOAD_SIZE,
+                        ))
+                        .route(web::post().to({
+                            use endpoints::devices as m;
+                            m::import::<$sender>
+                        })),
+                )
+                .service(web::resource("apps/{app}/export").route(web::get().to({
+                    use endpoints::devices as m;
+                    m::export::<$sender>
+                })))
+                .service(
+                    web::resource("apps/{app}/deviceTypes")
+                        .route(web::post().to({
+                            use endpoints::device_types as m;
+                            m::create::<$sender>
+                        }))
+                        .route(web::get().to({
+                            use endpoints::device_types as m;
+                            m::list::<$sender>
+                        })),
+                )
+                .service(
+                    web::resource("apps/{app}/deviceTypes/{deviceType}")
+                        .name("deviceType")
+                        .route(web::get().to({
+                            use endpoints::device_types as m;
+                            m::read::<$sender>
+                        }))
+                        .route(web::put().to({
+                            use endpoints::device_types as m;
+                            m::update::<$sender>
+                        }))
+                        .route(web::delete().to({
+                            use endpoints::device_types as m;
+                            m::delete::<$sender>
+                        })),
+                );
+
             app.service(scope)
         };
 
@@ -129,6 +219,42 @@ macro_rules! app {
                             >)),
                     );
 
+                let scope =
+                    scope
+                        .service(web::resource("/trash/apps").route(web::get().to(
+                            apps::list_deleted_apps::<service::PostgresManagementService<$sender>>,
+                        )))
+                        .service(web::resource("/trash/apps/{appId}/restore").route(
+                            web::put().to(apps::restore_app::<
+                                service::PostgresManagementService<$sender>,
+                            >),
+                        ))
+                        .service(web::resource("/trash/apps/{appId}/devices").route(
+                            web::get().to(apps::list_deleted_devices::<
+                                service::PostgresManagementService<$sender>,
+                            >),
+                        ))
+                        .service(
+                            web::resource("/trash/apps/{appId}/devices/{deviceId}/restore").route(
+                                web::put().to(apps::restore_device::<
+                                    service::PostgresManagementService<$sender>,
+                                >),
+                            ),
+                        );
+
+                let scope =
+                    scope
+                        .service(web::resource("/apps/{appId}/history").route(web::get().to(
+                            apps::get_app_history::<service::PostgresManagementService<$sender>>,
+                        )))
+                        .service(
+                            web::resource("/apps/{appId}/devices/{deviceId}/history").route(
+                                web::get().to(apps::get_device_history::<
+                                    service::PostgresManagementService<$sender>,
+                                >),
+                            ),
+                        );
+
                 app.service(scope)
             };
 
@@ -49,6 +49,10 @@ async fn main() -> anyhow::Result<()> {
 
     let health = HealthServer::new(config.health, vec![Box::new(service.clone())]);
 
+    // trash and change log purger
+
+    let purger = service.clone().run_purger();
+
     // main server
 
     let main = HttpServer::new(move || {
@@ -71,7 +75,7 @@ async fn main() -> anyhow::Result<()> {
 
     // run
 
-    futures::try_join!(health.run(), main.err_into())?;
+    futures::try_join!(health.run(), main.err_into(), purger)?;
 
     // exiting
 
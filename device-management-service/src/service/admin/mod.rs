@@ -1,16 +1,179 @@
-use crate::service::{error::PostgresManagementServiceError, PostgresManagementService};
+use crate::service::{
+    error::PostgresManagementServiceError,
+    history::{Change, HistoryView},
+    PostgresManagementService,
+};
 use async_trait::async_trait;
-use drogue_cloud_admin_service::apps::{AdminService, MemberEntry, Members, TransferOwnership};
+use deadpool_postgres::Transaction;
+use drogue_cloud_admin_service::apps::{
+    AdminService, DeletedApplication, DeletedDevice, HistoryEntry, HistoryPage, HistoryParams,
+    MemberEntry, Members, TransferOwnership,
+};
 use drogue_cloud_database_common::{
     auth::ensure_with,
     error::ServiceError,
     models::{
         app::{self, ApplicationAccessor, PostgresApplicationAccessor},
+        device::{DeviceAccessor, PostgresDeviceAccessor},
+        history::{HistoryAccessor, PostgresHistoryAccessor},
+        trash::{self, PostgresTrashAccessor, TrashAccessor},
         Lock,
     },
+    Client,
+};
+use drogue_cloud_registry_events::{Event, EventSender, SendEvent};
+use drogue_cloud_service_api::{
+    auth::user::{authz::Permission, UserInformation},
+    history::{Operation, ResourceKind},
 };
-use drogue_cloud_registry_events::EventSender;
-use drogue_cloud_service_api::auth::user::{authz::Permission, UserInformation};
+use tokio_postgres::error::SqlState;
+use uuid::Uuid;
+
+impl From<trash::DeletedApplication> for DeletedApplication {
+    fn from(app: trash::DeletedApplication) -> Self {
+        Self {
+            name: app.name,
+            uid: app.uid.to_string(),
+            generation: app.generation,
+            deletion_timestamp: app.deletion_timestamp,
+            expiration_timestamp: app.expiration_timestamp,
+        }
+    }
+}
+
+impl From<trash::DeletedDevice> for DeletedDevice {
+    fn from(device: trash::DeletedDevice) -> Self {
+        Self {
+            name: device.name,
+            uid: device.uid.to_string(),
+            generation: device.generation,
+            cascaded: device.cascaded,
+            deletion_timestamp: device.deletion_timestamp,
+            expiration_timestamp: device.expiration_timestamp,
+        }
+    }
+}
+
+/// The position to continue the history with, taken from the continuation token.
+fn history_position(params: &HistoryParams) -> Result<Option<u64>, ServiceError> {
+    params
+        .continue_token
+        .as_ref()
+        .map(|token| {
+            token
+                .parse()
+                .map_err(|_| ServiceError::BadRequest("Invalid continuation token".into()))
+        })
+        .transpose()
+}
+
+fn history_page(entries: Vec<HistoryEntry>, next: Option<u64>) -> HistoryPage {
+    HistoryPage {
+        entries,
+        continue_token: next.map(|next| next.to_string()),
+    }
+}
+
+/// Find the application to get the history for, and ensure the user may read its history.
+///
+/// The history is kept after an application got deleted. As long as the application is in the
+/// trash, its history can be retrieved using the deleted application.
+async fn history_app<C: Client>(
+    c: &C,
+    identity: &UserInformation,
+    app_id: &str,
+) -> Result<Uuid, ServiceError> {
+    if let Some(app) = PostgresApplicationAccessor::new(c)
+        .get(app_id, Lock::None)
+        .await?
+    {
+        ensure_with(&app, identity, Permission::Admin, || ServiceError::NotFound)?;
+        return Ok(app.uid);
+    }
+
+    let app = PostgresTrashAccessor::new(c)
+        .get_app(app_id, Lock::None)
+        .await?
+        .ok_or(ServiceError::NotFound)?;
+
+    ensure_with(&app, identity, Permission::Admin, || ServiceError::NotFound)?;
+
+    Ok(app.uid)
+}
+
+/// Map a unique key violation to a conflict, as the resource to restore already exists.
+fn restore_conflict(err: ServiceError) -> ServiceError {
+    match err.sql_state() {
+        Some(state) if state == &SqlState::UNIQUE_VIOLATION => {
+            ServiceError::Conflict("Resource already exists".to_string())
+        }
+        _ => err,
+    }
+}
+
+impl<S> PostgresManagementService<S>
+where
+    S: EventSender + Clone,
+{
+    /// Record the restore of an application, which was already restored in the transaction.
+    async fn record_restored_app(
+        &self,
+        t: &Transaction<'_>,
+        identity: &UserInformation,
+        app: &str,
+    ) -> Result<(), PostgresManagementServiceError<S::Error>> {
+        let app = PostgresApplicationAccessor::new(t)
+            .get(app, Lock::None)
+            .await?
+            .ok_or(ServiceError::NotFound)?;
+
+        self.record_history(
+            t,
+            identity,
+            Change {
+                kind: ResourceKind::Application,
+                application: &app.name,
+                name: &app.name,
+                uid: app.uid,
+                operation: Operation::Create,
+                generation: app.generation,
+                before: None,
+                after: Some(app.history_view()),
+            },
+        )
+        .await
+    }
+
+    /// Record the restore of a device, which was already restored in the transaction.
+    async fn record_restored_device(
+        &self,
+        t: &Transaction<'_>,
+        identity: &UserInformation,
+        app: &str,
+        device: &str,
+    ) -> Result<(), PostgresManagementServiceError<S::Error>> {
+        let device = PostgresDeviceAccessor::new(t)
+            .get(app, device, Lock::None)
+            .await?
+            .ok_or(ServiceError::NotFound)?;
+
+        self.record_history(
+            t,
+            identity,
+            Change {
+                kind: ResourceKind::Device,
+                application: app,
+                name: &device.name,
+                uid: device.uid,
+                operation: Operation::Create,
+                generation: device.generation,
+                before: None,
+                after: Some(device.history_view()),
+            },
+        )
+        .await
+    }
+}
 
 #[async_trait]
 impl<S> AdminService for PostgresManagementService<S>
@@ -49,12 +212,15 @@ where
 
         accessor
             .update_transfer(
-                app.name,
+                app.name.clone(),
                 identity.user_id().map(Into::into),
                 Some(transfer.new_user),
             )
             .await?;
 
+        self.record_app_updated(&t, identity, &app.name, app.history_view())
+            .await?;
+
         // commit
 
         t.commit().await?;
@@ -82,7 +248,10 @@ where
         // make the change
 
         accessor
-            .update_transfer(app.name, identity.user_id().map(Into::into), None)
+            .update_transfer(app.name.clone(), identity.user_id().map(Into::into), None)
+            .await?;
+
+        self.record_app_updated(&t, identity, &app.name, app.history_view())
             .await?;
 
         // commit
@@ -115,7 +284,10 @@ where
 
         if app.transfer_owner.as_deref() == identity.user_id() {
             accessor
-                .update_transfer(app.name, identity.user_id().map(Into::into), None)
+                .update_transfer(app.name.clone(), identity.user_id().map(Into::into), None)
+                .await?;
+
+            self.record_app_updated(&t, identity, &app.name, app.history_view())
                 .await?;
 
             // commit
@@ -199,10 +371,242 @@ where
             .await
             .map(|_| ())?;
 
+        self.record_app_updated(&t, identity, &app.name, app.history_view())
+            .await?;
+
+        // commit
+
+        t.commit().await?;
+
+        Ok(())
+    }
+
+    async fn list_deleted_apps(
+        &self,
+        identity: &UserInformation,
+    ) -> Result<Vec<DeletedApplication>, Self::Error> {
+        let c = self.pool.get().await?;
+
+        Ok(PostgresTrashAccessor::new(&c)
+            .list_apps(Some(identity))
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    async fn restore_app(
+        &self,
+        identity: &UserInformation,
+        app_id: String,
+    ) -> Result<(), Self::Error> {
+        let mut c = self.pool.get().await?;
+        let t = c.build_transaction().start().await?;
+
+        let trash = PostgresTrashAccessor::new(&t);
+
+        // retrieve the deleted app
+
+        let app = trash.get_app(&app_id, Lock::ForUpdate).await?;
+        let app = app.ok_or_else(|| ServiceError::NotFound)?;
+
+        // ensure we are permitted to perform the operation
+
+        ensure_with(&app, identity, Permission::Admin, || ServiceError::NotFound)?;
+
+        // restore, fails if the name is taken in the meantime
+
+        let devices = trash.restore_app(app.uid).await.map_err(restore_conflict)?;
+
+        // record the history
+
+        self.record_restored_app(&t, identity, &app.name).await?;
+        for device in &devices {
+            self.record_restored_device(&t, identity, &app.name, &device.name)
+                .await?;
+        }
+
+        // create events
+
+        let mut events = Event::new_app(
+            self.instance.clone(),
+            &app.name,
+            app.uid,
+            app.generation,
+            vec![],
+        );
+        for device in devices {
+            events.extend(Event::new_device(
+                self.instance.clone(),
+                &app.name,
+                device.name,
+                device.uid,
+                device.generation,
+                vec![],
+            ));
+        }
+
+        // send events to outbox
+
+        Self::send_to_outbox(&t, &events).await?;
+
         // commit
 
         t.commit().await?;
 
+        // send events
+
+        events.send_with(&self.sender).await?;
+
         Ok(())
     }
+
+    async fn list_deleted_devices(
+        &self,
+        identity: &UserInformation,
+        app_id: String,
+    ) -> Result<Vec<DeletedDevice>, Self::Error> {
+        let c = self.pool.get().await?;
+
+        // retrieve app
+
+        let app = PostgresApplicationAccessor::new(&c)
+            .get(&app_id, Lock::None)
+            .await?;
+        let app = app.ok_or_else(|| ServiceError::NotFound)?;
+
+        // ensure we are permitted to perform the operation
+
+        ensure_with(&app, identity, Permission::Read, || ServiceError::NotFound)?;
+
+        // list operation, only devices of this instance of the application
+
+        Ok(PostgresTrashAccessor::new(&c)
+            .list_devices(app.uid)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    async fn restore_device(
+        &self,
+        identity: &UserInformation,
+        app_id: String,
+        device_id: String,
+    ) -> Result<(), Self::Error> {
+        let mut c = self.pool.get().await?;
+        let t = c.build_transaction().start().await?;
+
+        // retrieve app
+
+        let app = PostgresApplicationAccessor::new(&t)
+            .get(&app_id, Lock::ForShare)
+            .await?;
+        let app = app.ok_or_else(|| ServiceError::NotFound)?;
+
+        // ensure we are permitted to perform the operation
+
+        ensure_with(&app, identity, Permission::Write, || ServiceError::NotFound)?;
+
+        if app.deletion_timestamp.is_some() {
+            return Err(ServiceError::Conflict("Application is being deleted".into()).into());
+        }
+
+        // retrieve the deleted device
+
+        let trash = PostgresTrashAccessor::new(&t);
+
+        let device = trash
+            .get_device(app.uid, &device_id, Lock::ForUpdate)
+            .await?;
+        let device = device.ok_or_else(|| ServiceError::NotFound)?;
+
+        // restore, fails if the name is taken in the meantime
+
+        trash
+            .restore_device(device.uid)
+            .await
+            .map_err(restore_conflict)?;
+
+        // record the history
+
+        self.record_restored_device(&t, identity, &app.name, &device.name)
+            .await?;
+
+        // create events
+
+        let events = Event::new_device(
+            self.instance.clone(),
+            &app.name,
+            &device.name,
+            device.uid,
+            device.generation,
+            vec![],
+        );
+
+        // send events to outbox
+
+        Self::send_to_outbox(&t, &events).await?;
+
+        // commit
+
+        t.commit().await?;
+
+        // send events
+
+        events.send_with(&self.sender).await?;
+
+        Ok(())
+    }
+
+    async fn get_app_history(
+        &self,
+        identity: &UserInformation,
+        app_id: String,
+        params: HistoryParams,
+    ) -> Result<HistoryPage, Self::Error> {
+        let c = self.pool.get().await?;
+
+        // retrieve app, which may already be deleted, and ensure we are permitted to perform the
+        // operation
+
+        let app_uid = history_app(&c, identity, &app_id).await?;
+
+        // get history, only for this instance of the application
+
+        let (entries, next) = PostgresHistoryAccessor::new(&c)
+            .list(app_uid, None, params.limit, history_position(&params)?)
+            .await?;
+
+        Ok(history_page(entries, next))
+    }
+
+    async fn get_device_history(
+        &self,
+        identity: &UserInformation,
+        app_id: String,
+        device_id: String,
+        params: HistoryParams,
+    ) -> Result<HistoryPage, Self::Error> {
+        let c = self.pool.get().await?;
+
+        // retrieve app, which may already be deleted, and ensure we are permitted to perform the
+        // operation
+
+        let app_uid = history_app(&c, identity, &app_id).await?;
+
+        // get history, also for devices which are already deleted
+
+        let (entries, next) = PostgresHistoryAccessor::new(&c)
+            .list(
+                app_uid,
+                Some((ResourceKind::Device, &device_id)),
+                params.limit,
+                history_position(&params)?,
+            )
+            .await?;
+
+        Ok(history_page(entries, next))
+    }
 }
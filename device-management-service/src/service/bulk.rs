@@ -0,0 +1,350 @@
+use drogue_client::registry;
+use drogue_cloud_database_common::error::{ErrorResponse, ServiceError};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// The content type of newline delimited JSON, one device per line.
+pub const CONTENT_TYPE_NDJSON: &str = "application/x-ndjson";
+/// The content type of CSV, one device per row.
+pub const CONTENT_TYPE_CSV: &str = "text/csv";
+
+/// The maximum size of an import payload.
+pub const MAX_IMPORT_PAYLOAD_SIZE: usize = 16 * 1024 * 1024;
+
+/// Options for importing devices.
+#[derive(Clone, Debug, Default)]
+pub struct ImportOptions {
+    /// Commit every `chunk_size` devices, skipping failed devices.
+    ///
+    /// If not set, all devices are imported in a single transaction, which is rolled back in case
+    /// any device fails.
+    pub chunk_size: Option<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ImportOutcome {
+    /// The device was created.
+    Created,
+    /// The device failed to be created.
+    Failed,
+    /// The device could have been created, but was rolled back due to another failed device.
+    RolledBack,
+}
+
+/// The result of importing a single device.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportResult {
+    pub name: String,
+    pub outcome: ImportOutcome,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorResponse>,
+}
+
+impl ImportResult {
+    pub fn created<S: Into<String>>(name: S) -> Self {
+        Self {
+            name: name.into(),
+            outcome: ImportOutcome::Created,
+            error: None,
+        }
+    }
+
+    pub fn failed<S: Into<String>>(name: S, err: &ServiceError) -> Self {
+        Self {
+            name: name.into(),
+            outcome: ImportOutcome::Failed,
+            error: Some(err.into()),
+        }
+    }
+}
+
+/// A device to import, or the result of a row which failed to parse.
+pub type ImportEntry = Result<registry::v1::Device, ImportResult>;
+
+/// A row which failed to parse, with the name of the device, if known.
+type RowError = (String, ServiceError);
+
+/// Parse the devices to import, based on the content type.
+///
+/// Without a chunk size, a row which fails to parse fails the whole import, as nothing would be
+/// imported anyway. Otherwise, it is reported as a failed entry, and the other rows are imported.
+///
+/// Returns `None` if the content type isn't supported.
+pub fn parse(
+    content_type: &str,
+    body: &[u8],
+    options: &ImportOptions,
+) -> Option<Result<Vec<ImportEntry>, ServiceError>> {
+    let essence = content_type.split(';').next().unwrap_or_default().trim();
+    let rows = match essence {
+        CONTENT_TYPE_NDJSON => parse_ndjson(body),
+        CONTENT_TYPE_CSV => match parse_csv(body) {
+            Ok(rows) => rows,
+            Err(err) => return Some(Err(err)),
+        },
+        _ => return None,
+    };
+
+    Some(
+        rows.into_iter()
+            .map(|row| match row {
+                Ok(device) => Ok(Ok(device)),
+                Err((_, err)) if options.chunk_size.is_none() => Err(err),
+                Err((name, err)) => Ok(Err(ImportResult::failed(name, &err))),
+            })
+            .collect(),
+    )
+}
+
+fn parse_ndjson(body: &[u8]) -> Vec<Result<registry::v1::Device, RowError>> {
+    body.split(|b| *b == b'\n')
+        .enumerate()
+        .filter(|(_, line)| !line.iter().all(u8::is_ascii_whitespace))
+        .map(|(idx, line)| {
+            serde_json::from_slice(line).map_err(|err| {
+                // report the name, if the line is at least valid JSON
+                let name = serde_json::from_slice::<Value>(line)
+                    .ok()
+                    .and_then(|value| value["metadata"]["name"].as_str().map(Into::into))
+                    .unwrap_or_default();
+                (
+                    name,
+                    ServiceError::BadRequest(format!(
+                        "Invalid device in line {}: {}",
+                        idx + 1,
+                        err
+                    )),
+                )
+            })
+        })
+        .collect()
+}
+
+/// Parse a CSV file, with a header row.
+///
+/// The column `name` is required. The columns `labels.<label>` and `annotations.<annotation>`
+/// set the label or annotation, if the value is not empty. The column `spec` provides the spec
+/// as JSON object.
+///
+/// An invalid header fails the whole file, an invalid row only that row.
+fn parse_csv(body: &[u8]) -> Result<Vec<Result<registry::v1::Device, RowError>>, ServiceError> {
+    let mut reader = csv::Reader::from_reader(body);
+
+    let headers = reader
+        .headers()
+        .map_err(|err| ServiceError::BadRequest(format!("Invalid CSV header: {}", err)))?
+        .clone();
+
+    let name_idx = headers
+        .iter()
+        .position(|header| header == "name")
+        .ok_or_else(|| ServiceError::BadRequest("Missing column in CSV header: 'name'".into()))?;
+
+    if let Some(header) = headers.iter().find(|header| {
+        !header.starts_with("labels.")
+            && !header.starts_with("annotations.")
+            && !matches!(*header, "name" | "spec")
+    }) {
+        return Err(ServiceError::BadRequest(format!(
+            "Unknown column in CSV header: '{}'",
+            header
+        )));
+    }
+
+    Ok(reader
+        .records()
+        .enumerate()
+        .map(|(idx, record)| {
+            // the header is the first line
+            let line = idx + 2;
+            let record = record.map_err(|err| {
+                (
+                    String::new(),
+                    ServiceError::BadRequest(format!("Invalid CSV in line {}: {}", line, err)),
+                )
+            })?;
+            let name = record.get(name_idx).unwrap_or_default();
+
+            let mut device = registry::v1::Device {
+                metadata: Default::default(),
+                spec: Default::default(),
+                status: Default::default(),
+            };
+            device.metadata.name = name.into();
+
+            for (header, value) in headers.iter().zip(record.iter()) {
+                if value.is_empty() {
+                    continue;
+                }
+                if let Some(label) = header.strip_prefix("labels.") {
+                    device.metadata.labels.insert(label.into(), value.into());
+                } else if let Some(annotation) = header.strip_prefix("annotations.") {
+                    device
+                        .metadata
+                        .annotations
+                        .insert(annotation.into(), value.into());
+                } else if header == "spec" {
+                    device.spec = serde_json::from_str(value).map_err(|err| {
+                        (
+                            name.to_string(),
+                            ServiceError::BadRequest(format!(
+                                "Invalid spec in line {}: {}",
+                                line, err
+                            )),
+                        )
+                    })?;
+                }
+            }
+
+            Ok(device)
+        })
+        .collect())
+}
+
+/// Remove the credentials from a device, so that they don't leak into an export.
+pub fn redact_credentials(mut device: registry::v1::Device) -> registry::v1::Device {
+    device
+        .spec
+        .remove(registry::v1::DeviceSpecCredentials::key());
+    device
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn devices(entries: Vec<ImportEntry>) -> Vec<registry::v1::Device> {
+        entries.into_iter().map(Result::unwrap).collect()
+    }
+
+    #[test]
+    fn test_parse_ndjson() {
+        let devices = devices(
+            parse(
+                CONTENT_TYPE_NDJSON,
+                br#"{"metadata": {"name": "device1"}}
+
+{"metadata": {"name": "device2"}, "spec": {"foo": "bar"}}
+"#,
+                &Default::default(),
+            )
+            .unwrap()
+            .unwrap(),
+        );
+
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices[0].metadata.name, "device1");
+        assert_eq!(devices[1].metadata.name, "device2");
+        assert_eq!(devices[1].spec["foo"], "bar");
+
+        assert!(matches!(
+            parse(
+                CONTENT_TYPE_NDJSON,
+                b"{\"metadata\": {}}\nfoo",
+                &Default::default()
+            ),
+            Some(Err(ServiceError::BadRequest(_)))
+        ));
+        assert!(parse("application/json", b"", &Default::default()).is_none());
+    }
+
+    #[test]
+    fn test_parse_ndjson_chunked() {
+        let entries = parse(
+            CONTENT_TYPE_NDJSON,
+            b"{\"metadata\": {\"name\": \"device1\"}}\nfoo\n{\"metadata\": {\"name\": \"device3\"}, \"spec\": 1}\n",
+            &ImportOptions {
+                chunk_size: Some(10),
+            },
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].as_ref().unwrap().metadata.name, "device1");
+
+        let failed = entries[1].as_ref().unwrap_err();
+        assert_eq!(failed.name, "");
+        assert_eq!(failed.outcome, ImportOutcome::Failed);
+
+        let failed = entries[2].as_ref().unwrap_err();
+        assert_eq!(failed.name, "device3");
+        assert_eq!(failed.outcome, ImportOutcome::Failed);
+    }
+
+    #[test]
+    fn test_parse_csv() {
+        let devices = devices(
+            parse(
+                "text/csv; charset=utf-8",
+                br#"name,labels.floor,spec
+device1,1,
+device2,,"{""foo"": ""bar""}"
+"#,
+                &Default::default(),
+            )
+            .unwrap()
+            .unwrap(),
+        );
+
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices[0].metadata.name, "device1");
+        assert_eq!(devices[0].metadata.labels["floor"], "1");
+        assert!(devices[0].spec.is_empty());
+        assert_eq!(devices[1].metadata.name, "device2");
+        assert!(devices[1].metadata.labels.is_empty());
+        assert_eq!(devices[1].spec["foo"], "bar");
+
+        assert!(
+            parse(CONTENT_TYPE_CSV, b"labels.floor\n1\n", &Default::default())
+                .unwrap()
+                .is_err()
+        );
+        assert!(parse(
+            CONTENT_TYPE_CSV,
+            b"name,foo\ndevice1,bar\n",
+            &Default::default()
+        )
+        .unwrap()
+        .is_err());
+        assert!(parse(
+            CONTENT_TYPE_CSV,
+            b"name,spec\ndevice1,foo\n",
+            &Default::default()
+        )
+        .unwrap()
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_csv_chunked() {
+        let options = ImportOptions {
+            chunk_size: Some(10),
+        };
+
+        let entries = parse(
+            CONTENT_TYPE_CSV,
+            b"name,spec\ndevice1,\ndevice2,foo\ndevice3\n",
+            &options,
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].as_ref().unwrap().metadata.name, "device1");
+        assert_eq!(entries[1].as_ref().unwrap_err().name, "device2");
+        assert_eq!(
+            entries[2].as_ref().unwrap_err().outcome,
+            ImportOutcome::Failed
+        );
+
+        // an invalid header still fails the whole import
+        assert!(
+            parse(CONTENT_TYPE_CSV, b"name,foo\ndevice1,bar\n", &options)
+                .unwrap()
+                .is_err()
+        );
+    }
+}
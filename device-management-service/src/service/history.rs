@@ -0,0 +1,247 @@
+use super::{
+    error::PostgresManagementServiceError,
+    secrets::{digest, digest_app_secrets},
+    PostgresManagementService,
+};
+use chrono::Utc;
+use deadpool_postgres::Transaction;
+use drogue_client::registry;
+use drogue_cloud_database_common::{
+    error::ServiceError,
+    models::{
+        self,
+        app::{ApplicationAccessor, PostgresApplicationAccessor},
+        history::{HistoryAccessor, PostgresHistoryAccessor},
+        Lock,
+    },
+};
+use drogue_cloud_registry_events::EventSender;
+use drogue_cloud_service_api::{
+    auth::user::UserInformation,
+    history::{HistoryEntry, Operation, ResourceKind},
+};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+/// The parts of a resource, which are tracked by the history.
+///
+/// This leaves out the metadata which changes with every update, like the resource version, and
+/// the status, which is owned by controllers and frequently updated (e.g. the connection status).
+pub(crate) trait HistoryView {
+    fn history_view(&self) -> Value;
+}
+
+/// The spec of a device, or device type, with the digest of its credentials.
+///
+/// The history is kept for a long time, and shown in the console, so it must not contain any
+/// secrets. Still, it should show when the credentials got changed.
+fn digested_spec(data: &Value) -> Value {
+    let mut spec = data["spec"].clone();
+    if let Some(credentials) = spec.get_mut(registry::v1::DeviceSpecCredentials::key()) {
+        *credentials = Value::String(digest(credentials));
+    }
+    spec
+}
+
+/// The spec of an application, with the digest of its secrets.
+fn digested_app_spec(data: &Value) -> Value {
+    let mut spec = data["spec"].clone();
+    if let Value::Object(spec) = &mut spec {
+        digest_app_secrets(spec);
+    }
+    spec
+}
+
+impl HistoryView for models::app::Application {
+    fn history_view(&self) -> Value {
+        json!({
+            "metadata": {
+                "labels": self.labels,
+                "annotations": self.annotations,
+                "deletionTimestamp": self.deletion_timestamp,
+                "finalizers": self.finalizers,
+            },
+            "owner": self.owner,
+            "transferOwner": self.transfer_owner,
+            "members": self.members,
+            "spec": digested_app_spec(&self.data),
+        })
+    }
+}
+
+impl HistoryView for models::device::Device {
+    fn history_view(&self) -> Value {
+        json!({
+            "metadata": {
+                "labels": self.labels,
+                "annotations": self.annotations,
+                "deletionTimestamp": self.deletion_timestamp,
+                "finalizers": self.finalizers,
+            },
+            "spec": digested_spec(&self.data),
+        })
+    }
+}
+
+impl HistoryView for models::device_type::DeviceType {
+    fn history_view(&self) -> Value {
+        json!({
+            "metadata": {
+                "labels": self.labels,
+                "annotations": self.annotations,
+            },
+            "spec": digested_spec(&self.data),
+        })
+    }
+}
+
+/// A change of a resource, to be recorded in the history.
+pub(crate) struct Change<'a> {
+    pub kind: ResourceKind,
+    pub application: &'a str,
+    pub name: &'a str,
+    pub uid: Uuid,
+    pub operation: Operation,
+    pub generation: u64,
+    /// The state before the change, `None` if the resource didn't exist.
+    pub before: Option<Value>,
+    /// The state after the change, `None` if the resource doesn't exist anymore.
+    pub after: Option<Value>,
+}
+
+impl<S> PostgresManagementService<S>
+where
+    S: EventSender + Clone,
+{
+    /// Record a change of a resource in the history.
+    pub(crate) async fn record_history(
+        &self,
+        t: &Transaction<'_>,
+        identity: &UserInformation,
+        change: Change<'_>,
+    ) -> Result<(), PostgresManagementServiceError<S::Error>> {
+        let before = change.before.unwrap_or(Value::Null);
+        let after = change.after.unwrap_or(Value::Null);
+        let diff = serde_json::to_value(json_patch::diff(&before, &after)).unwrap_or_default();
+
+        PostgresHistoryAccessor::new(t)
+            .record(&HistoryEntry {
+                kind: change.kind,
+                application: change.application.into(),
+                name: change.name.into(),
+                uid: change.uid.to_string(),
+                operation: change.operation,
+                timestamp: Utc::now(),
+                generation: change.generation,
+                user_id: identity.user_id().map(Into::into),
+                diff,
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    /// Record an update of an application, which was already applied in the transaction.
+    ///
+    /// This is used for changes which don't touch the resource itself, like changing the owner,
+    /// or the members.
+    pub(crate) async fn record_app_updated(
+        &self,
+        t: &Transaction<'_>,
+        identity: &UserInformation,
+        app: &str,
+        before: Value,
+    ) -> Result<(), PostgresManagementServiceError<S::Error>> {
+        let after = PostgresApplicationAccessor::new(t)
+            .get(app, Lock::None)
+            .await?
+            .ok_or(ServiceError::NotFound)?;
+
+        self.record_history(
+            t,
+            identity,
+            Change {
+                kind: ResourceKind::Application,
+                application: &after.name,
+                name: &after.name,
+                uid: after.uid,
+                operation: Operation::Update,
+                generation: after.generation,
+                before: Some(before),
+                after: Some(after.history_view()),
+            },
+        )
+        .await
+    }
+
+    /// Record the deletion of all devices of an application, which can be deleted directly.
+    pub(crate) async fn record_app_devices_deleted(
+        &self,
+        t: &Transaction<'_>,
+        identity: &UserInformation,
+        app: &str,
+    ) -> Result<(), PostgresManagementServiceError<S::Error>> {
+        PostgresHistoryAccessor::new(t)
+            .record_app_devices_deleted(app, identity.user_id(), Utc::now())
+            .await?;
+
+        Ok(())
+    }
+
+    /// Purge all expired entries from the history, if the history expires.
+    pub async fn purge_history(&self) -> Result<u64, PostgresManagementServiceError<S::Error>> {
+        match self.history_retention {
+            Some(retention) => {
+                let c = self.pool.get().await?;
+
+                Ok(PostgresHistoryAccessor::new(&c)
+                    .purge(Utc::now() - retention)
+                    .await?)
+            }
+            None => Ok(0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_diff() {
+        let diff = json_patch::diff(
+            &json!({"spec": {"foo": "bar"}}),
+            &json!({"spec": {"foo": "baz"}}),
+        );
+        assert_eq!(
+            serde_json::to_value(diff).unwrap(),
+            json!([{"op": "replace", "path": "/spec/foo", "value": "baz"}])
+        );
+
+        // a deleted resource
+        let diff = json_patch::diff(&json!({"spec": {}}), &json!(null));
+        assert_eq!(
+            serde_json::to_value(diff).unwrap(),
+            json!([{"op": "replace", "path": "", "value": null}])
+        );
+    }
+
+    #[test]
+    fn test_digested_spec() {
+        let spec = digested_spec(&json!({
+            "spec": {
+                "credentials": {"credentials": [{"pass": "foo"}]},
+                "foo": "bar",
+            }
+        }));
+        assert_eq!(spec["foo"], "bar");
+
+        let credentials = spec["credentials"].as_str().unwrap();
+        assert!(credentials.starts_with("sha256:"));
+        assert!(!credentials.contains("foo"));
+
+        // a resource without a spec
+        assert_eq!(digested_spec(&json!({})), Value::Null);
+    }
+}
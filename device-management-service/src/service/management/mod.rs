@@ -1,32 +1,136 @@
 use super::utils;
 use crate::{
     endpoints::params::DeleteParams,
-    service::{error::PostgresManagementServiceError, PostgresManagementService},
+    service::{
+        bulk::{redact_credentials, ImportEntry, ImportOptions, ImportOutcome, ImportResult},
+        error::PostgresManagementServiceError,
+        history::{Change, HistoryView},
+        patch::Patch,
+        secrets::{redact_app_secrets, retain_app_secrets},
+        subresource::Subresource,
+        watch::{self, ApplicationSource, DeviceSource, PostgresChangeLog},
+        PostgresManagementService,
+    },
 };
 use actix_web::ResponseError;
 use async_trait::async_trait;
 use chrono::Utc;
 use core::pin::Pin;
+use deadpool_postgres::Transaction;
 use drogue_client::registry;
 use drogue_cloud_database_common::{
-    auth::{ensure, ensure_with},
+    auth::{ensure, ensure_status, ensure_with},
     error::ServiceError,
     models::{
         app::{ApplicationAccessor, PostgresApplicationAccessor},
         device::{DeviceAccessor, PostgresDeviceAccessor},
-        diff::diff_paths,
+        device_type::{DeviceTypeAccessor, PostgresDeviceTypeAccessor},
+        gateway::{GatewayAccessor, PostgresGatewayAccessor},
+        paging::{Cursor, Sort},
+        twin::{PostgresTwinAccessor, Twin, TwinAccessor},
         Generation, Lock,
     },
 };
 use drogue_cloud_registry_events::{Event, EventSender, SendEvent};
 use drogue_cloud_service_api::{
     auth::user::{authz::Permission, UserInformation},
-    labels::LabelSelector,
+    device_type::{DeviceType, SPEC_DEVICE_TYPE},
+    fields::FieldSelector,
+    history::{self, ResourceKind},
+    labels::{LabelSelector, Operation},
+    twin::DeviceTwin,
+    watch::WatchEvent,
 };
-use futures::{future, Stream, TryStreamExt};
+use futures::{future, stream, Stream, StreamExt, TryStreamExt};
+use serde_json::Value;
 use tokio_postgres::error::SqlState;
 use uuid::Uuid;
 
+/// Options for listing resources.
+#[derive(Clone, Debug, Default)]
+pub struct ListOptions {
+    pub labels: LabelSelector,
+    /// Select by the content of the spec and status.
+    pub fields: FieldSelector,
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+    pub sort: Sort,
+    /// Continue after this position.
+    pub after: Option<Cursor>,
+    /// Evaluate the total number of matching resources.
+    pub count: bool,
+    /// Resume a watch after this version.
+    pub resource_version: Option<u64>,
+}
+
+/// A page of a list of resources.
+pub struct Page<S> {
+    pub items: S,
+    /// The token to continue the list with, in case there are more items.
+    pub continue_token: Option<String>,
+    /// The total number of matching resources, if requested.
+    pub total: Option<u64>,
+}
+
+/// Cut a page, which was fetched with one additional item, down to its limit.
+///
+/// Returns the continuation token, in case there are more items.
+fn cut_page<T, F>(items: &mut Vec<T>, limit: usize, cursor: F) -> Option<String>
+where
+    F: FnOnce(&T) -> Cursor,
+{
+    if items.len() > limit {
+        items.truncate(limit);
+        items.last().map(|item| cursor(item).encode())
+    } else {
+        None
+    }
+}
+
+impl<S> PostgresManagementService<S>
+where
+    S: EventSender + Clone,
+{
+    /// Create the events of all devices referencing a device type.
+    ///
+    /// The effective spec of those devices changes with the device type.
+    async fn device_type_events(
+        &self,
+        t: &Transaction<'_>,
+        app: &str,
+        name: &str,
+    ) -> Result<Vec<Event>, ServiceError> {
+        PostgresDeviceAccessor::new(t)
+            .list(
+                app,
+                None,
+                LabelSelector::default(),
+                FieldSelector(vec![Operation::Eq(
+                    format!("spec.{}", SPEC_DEVICE_TYPE),
+                    name.to_string(),
+                )]),
+                None,
+                None,
+                Lock::None,
+                &Sort::default(),
+                None,
+            )
+            .await?
+            .map_ok(|device| {
+                Event::new_device(
+                    self.instance.clone(),
+                    app,
+                    &device.name,
+                    device.uid,
+                    device.generation,
+                    vec![".spec".into()],
+                )
+            })
+            .try_concat()
+            .await
+    }
+}
+
 #[async_trait]
 pub trait ManagementService: Clone {
     type Error: ResponseError;
@@ -44,11 +148,9 @@ pub trait ManagementService: Clone {
     async fn list_apps(
         &self,
         identity: UserInformation,
-        labels: LabelSelector,
-        limit: Option<usize>,
-        offset: Option<usize>,
+        options: ListOptions,
     ) -> Result<
-        Pin<Box<dyn Stream<Item = Result<registry::v1::Application, Self::Error>> + Send>>,
+        Page<Pin<Box<dyn Stream<Item = Result<registry::v1::Application, Self::Error>> + Send>>>,
         Self::Error,
     >;
     async fn update_app(
@@ -56,6 +158,17 @@ pub trait ManagementService: Clone {
         identity: &UserInformation,
         data: registry::v1::Application,
     ) -> Result<(), Self::Error>;
+    async fn patch_app(
+        &self,
+        identity: &UserInformation,
+        name: &str,
+        patch: Patch,
+    ) -> Result<(), Self::Error>;
+    async fn update_app_status(
+        &self,
+        identity: &UserInformation,
+        data: registry::v1::Application,
+    ) -> Result<(), Self::Error>;
     async fn delete_app(
         &self,
         identity: &UserInformation,
@@ -74,15 +187,34 @@ pub trait ManagementService: Clone {
         app: &str,
         name: &str,
     ) -> Result<Option<registry::v1::Device>, Self::Error>;
+    /// Get a device, with the spec of its device type merged in.
+    async fn get_effective_device(
+        &self,
+        identity: &UserInformation,
+        app: &str,
+        name: &str,
+    ) -> Result<Option<registry::v1::Device>, Self::Error>;
+    /// Get the effective gateways of a device, nearest first.
+    async fn get_device_gateways(
+        &self,
+        identity: &UserInformation,
+        app: &str,
+        name: &str,
+    ) -> Result<Option<Vec<registry::v1::Device>>, Self::Error>;
+    /// Get the devices directly selecting a device as their gateway.
+    async fn get_device_children(
+        &self,
+        identity: &UserInformation,
+        app: &str,
+        name: &str,
+    ) -> Result<Option<Vec<registry::v1::Device>>, Self::Error>;
     async fn list_devices(
         &self,
         identity: UserInformation,
         app: &str,
-        labels: LabelSelector,
-        limit: Option<usize>,
-        offset: Option<usize>,
+        options: ListOptions,
     ) -> Result<
-        Pin<Box<dyn Stream<Item = Result<registry::v1::Device, Self::Error>> + Send>>,
+        Page<Pin<Box<dyn Stream<Item = Result<registry::v1::Device, Self::Error>> + Send>>>,
         Self::Error,
     >;
     async fn update_device(
@@ -90,6 +222,18 @@ pub trait ManagementService: Clone {
         identity: &UserInformation,
         device: registry::v1::Device,
     ) -> Result<(), Self::Error>;
+    async fn patch_device(
+        &self,
+        identity: &UserInformation,
+        app: &str,
+        name: &str,
+        patch: Patch,
+    ) -> Result<(), Self::Error>;
+    async fn update_device_status(
+        &self,
+        identity: &UserInformation,
+        device: registry::v1::Device,
+    ) -> Result<(), Self::Error>;
     async fn delete_device(
         &self,
         identity: &UserInformation,
@@ -97,6 +241,96 @@ pub trait ManagementService: Clone {
         name: &str,
         params: DeleteParams,
     ) -> Result<(), Self::Error>;
+    /// Create devices in bulk, reporting the result for each device.
+    ///
+    /// Entries which already failed, e.g. during parsing, are reported as they are.
+    async fn import_devices(
+        &self,
+        identity: &UserInformation,
+        app: &str,
+        devices: Vec<ImportEntry>,
+        options: ImportOptions,
+    ) -> Result<Vec<ImportResult>, Self::Error>;
+    /// Stream all devices of an application, optionally including their credentials.
+    async fn export_devices(
+        &self,
+        identity: UserInformation,
+        app: &str,
+        credentials: bool,
+    ) -> Result<
+        Pin<Box<dyn Stream<Item = Result<registry::v1::Device, Self::Error>> + Send>>,
+        Self::Error,
+    >;
+
+    async fn watch_apps(
+        &self,
+        identity: UserInformation,
+        labels: LabelSelector,
+        fields: FieldSelector,
+        resource_version: Option<u64>,
+    ) -> Result<
+        Pin<
+            Box<
+                dyn Stream<Item = Result<WatchEvent<registry::v1::Application>, Self::Error>>
+                    + Send,
+            >,
+        >,
+        Self::Error,
+    >;
+    async fn watch_devices(
+        &self,
+        identity: UserInformation,
+        app: &str,
+        labels: LabelSelector,
+        fields: FieldSelector,
+        resource_version: Option<u64>,
+    ) -> Result<
+        Pin<Box<dyn Stream<Item = Result<WatchEvent<registry::v1::Device>, Self::Error>> + Send>>,
+        Self::Error,
+    >;
+
+    async fn get_device_twin(
+        &self,
+        identity: &UserInformation,
+        app: &str,
+        name: &str,
+    ) -> Result<Option<DeviceTwin>, Self::Error>;
+    async fn update_device_twin_desired(
+        &self,
+        identity: &UserInformation,
+        app: &str,
+        name: &str,
+        desired: Value,
+    ) -> Result<(), Self::Error>;
+
+    async fn create_device_type(
+        &self,
+        identity: &UserInformation,
+        device_type: DeviceType,
+    ) -> Result<(), Self::Error>;
+    async fn get_device_type(
+        &self,
+        identity: &UserInformation,
+        app: &str,
+        name: &str,
+    ) -> Result<Option<DeviceType>, Self::Error>;
+    async fn list_device_types(
+        &self,
+        identity: &UserInformation,
+        app: &str,
+    ) -> Result<Vec<DeviceType>, Self::Error>;
+    async fn update_device_type(
+        &self,
+        identity: &UserInformation,
+        device_type: DeviceType,
+    ) -> Result<(), Self::Error>;
+    /// Delete a device type, which must not be referenced by any device.
+    async fn delete_device_type(
+        &self,
+        identity: &UserInformation,
+        app: &str,
+        name: &str,
+    ) -> Result<(), Self::Error>;
 }
 
 #[async_trait]
@@ -109,9 +343,14 @@ where
     async fn create_app(
         &self,
         identity: &UserInformation,
-        application: registry::v1::Application,
+        mut application: registry::v1::Application,
     ) -> Result<(), Self::Error> {
+        // the status can only be set through the status subresource
+        application.status.clear();
+
         let (mut app, aliases) = Self::app_to_entity(application)?;
+        // there are no secrets to keep
+        retain_app_secrets(&Value::Null, &mut app.data);
 
         let generation = app.generation;
         let name = app.name.clone();
@@ -120,6 +359,8 @@ where
         app.uid = uid;
         app.owner = identity.user_id().map(Into::into);
 
+        let after = app.history_view();
+
         let mut c = self.pool.get().await?;
         let t = c.build_transaction().start().await?;
 
@@ -133,6 +374,22 @@ where
                 _ => err,
             })?;
 
+        self.record_history(
+            &t,
+            identity,
+            Change {
+                kind: ResourceKind::Application,
+                application: &name,
+                name: &name,
+                uid,
+                operation: history::Operation::Create,
+                generation,
+                before: None,
+                after: Some(after),
+            },
+        )
+        .await?;
+
         let events = Event::new_app(self.instance.clone(), name, uid, generation, vec![]);
 
         // send events to outbox
@@ -167,46 +424,85 @@ where
             ensure(app, identity, Permission::Read)?;
         }
 
-        Ok(app.map(Into::into))
+        Ok(app.map(|app| redact_app_secrets(identity, app.into())))
     }
 
     async fn list_apps(
         &self,
         identity: UserInformation,
-        labels: LabelSelector,
-        limit: Option<usize>,
-        offset: Option<usize>,
+        options: ListOptions,
     ) -> Result<
-        Pin<Box<dyn Stream<Item = Result<registry::v1::Application, Self::Error>> + Send>>,
+        Page<Pin<Box<dyn Stream<Item = Result<registry::v1::Application, Self::Error>> + Send>>>,
         Self::Error,
     > {
         let c = self.pool.get().await?;
+        let accessor = PostgresApplicationAccessor::new(&c);
+
+        let total = match options.count {
+            true => Some(
+                accessor
+                    .count(
+                        options.labels.clone(),
+                        options.fields.clone(),
+                        Some(&identity),
+                    )
+                    .await?,
+            ),
+            false => None,
+        };
 
-        Ok(Box::pin(
-            PostgresApplicationAccessor::new(&c)
-                .list(
-                    None,
-                    labels,
-                    limit,
-                    offset,
-                    Some(&identity),
-                    Lock::None,
-                    &["NAME"],
+        let sort = options.sort;
+        let reader = identity.clone();
+
+        let apps = accessor
+            .list(
+                None,
+                options.labels,
+                options.fields,
+                // fetch one more, to find out if there is a next page
+                options.limit.map(|limit| limit + 1),
+                options.offset,
+                Some(&identity),
+                Lock::None,
+                &sort,
+                options.after.as_ref(),
+            )
+            .await?
+            .try_filter(move |app| {
+                // Using ensure call here is just a safeguard! The list operation must only return
+                // entries the user has access to. Otherwise the limit/offset functionality
+                // won't work
+                future::ready(ensure(app, &identity, Permission::Read).is_ok())
+            });
+
+        let (items, continue_token) = match options.limit {
+            Some(limit) => {
+                let mut apps: Vec<_> = apps.try_collect().await?;
+                let continue_token = cut_page(&mut apps, limit, |app| {
+                    Cursor::new(&sort, app.creation_timestamp, &app.name, app.uid)
+                });
+                (
+                    stream::iter(
+                        apps.into_iter()
+                            .map(move |app| Ok(redact_app_secrets(&reader, app.into()))),
+                    )
+                    .boxed(),
+                    continue_token,
                 )
-                .await?
-                .try_filter_map(move |app| {
-                    // Using ensure call here is just a safeguard! The list operation must only return
-                    // entries the user has access to. Otherwise the limit/offset functionality
-                    // won't work
-                    let result = match ensure(&app, &identity, Permission::Read) {
-                        Ok(_) => Some(app.into()),
-                        Err(_) => None,
-                    };
-                    future::ready(Ok(result))
-                })
-                .map_err(|err| PostgresManagementServiceError::Service(err))
-                .into_stream(),
-        ))
+            }
+            None => (
+                apps.map_ok(move |app| redact_app_secrets(&reader, app.into()))
+                    .map_err(|err| PostgresManagementServiceError::Service(err))
+                    .boxed(),
+                None,
+            ),
+        };
+
+        Ok(Page {
+            items,
+            continue_token,
+            total,
+        })
     }
 
     async fn update_app(
@@ -225,7 +521,62 @@ where
         let events = self
             .perform_update_app(
                 &t,
+                identity,
+                Some(identity),
+                Subresource::Main,
+                app,
+                Some(aliases),
+                expected_uid,
+                expected_resource_version,
+            )
+            .await?;
+
+        Self::send_to_outbox(&t, &events).await?;
+
+        t.commit().await?;
+
+        // send events
+
+        events.send_with(&self.sender).await?;
+
+        Ok(())
+    }
+
+    async fn patch_app(
+        &self,
+        identity: &UserInformation,
+        name: &str,
+        patch: Patch,
+    ) -> Result<(), Self::Error> {
+        let mut c = self.pool.get().await?;
+        let t = c.build_transaction().start().await?;
+
+        // lock the current state, so that the patch is applied to the most recent version
+
+        let current = PostgresApplicationAccessor::new(&t)
+            .get(name, Lock::ForUpdate)
+            .await?
+            .ok_or(ServiceError::NotFound)?;
+
+        ensure(&current, identity, Permission::Write)?;
+
+        let application: registry::v1::Application = patch.apply(current.into())?;
+
+        if application.metadata.name != name {
+            return Err(ServiceError::BadRequest("Changing the name is not allowed".into()).into());
+        }
+
+        let expected_uid = application.metadata.uid.clone();
+        let expected_resource_version = application.metadata.resource_version.clone();
+
+        let (app, aliases) = Self::app_to_entity(application)?;
+
+        let events = self
+            .perform_update_app(
+                &t,
+                identity,
                 Some(identity),
+                Subresource::Main,
                 app,
                 Some(aliases),
                 expected_uid,
@@ -244,6 +595,47 @@ where
         Ok(())
     }
 
+    async fn update_app_status(
+        &self,
+        identity: &UserInformation,
+        application: registry::v1::Application,
+    ) -> Result<(), Self::Error> {
+        ensure_status(identity)?;
+
+        let expected_uid = application.metadata.uid.clone();
+        let expected_resource_version = application.metadata.resource_version.clone();
+
+        let (app, _) = Self::app_to_entity(application)?;
+
+        let mut c = self.pool.get().await?;
+        let t = c.build_transaction().start().await?;
+
+        // the aliases are derived from the spec, so we keep them
+
+        let events = self
+            .perform_update_app(
+                &t,
+                identity,
+                None,
+                Subresource::Status,
+                app,
+                None,
+                expected_uid,
+                expected_resource_version,
+            )
+            .await?;
+
+        Self::send_to_outbox(&t, &events).await?;
+
+        t.commit().await?;
+
+        // send events
+
+        events.send_with(&self.sender).await?;
+
+        Ok(())
+    }
+
     async fn delete_app(
         &self,
         identity: &UserInformation,
@@ -270,9 +662,13 @@ where
         utils::check_preconditions(&params.preconditions, &current)?;
         // there is no need to use the provided constraints, we as locked the entry "for update"
 
+        let before = current.history_view();
+
         // next, we need to delete the application
 
-        // first, delete all devices ...
+        // first, delete all devices, keeping them in the trash ...
+        self.record_app_devices_deleted(&t, identity, &id).await?;
+        self.archive_app_devices(&t, &id).await?;
         let remaining_devices = PostgresDeviceAccessor::new(&t).delete_app(&id).await?;
 
         // ...and count the once we can only soft-delete
@@ -285,9 +681,22 @@ where
         let generation = current.increment_generation()?;
         let uid = current.uid;
 
+        let change = |after| Change {
+            kind: ResourceKind::Application,
+            application: id,
+            name: id,
+            uid,
+            operation: history::Operation::Delete,
+            generation,
+            before: Some(before),
+            after,
+        };
+
         // if there are no finalizers ...
         let paths = if current.finalizers.is_empty() {
-            // ... delete the application
+            // ... delete the application, keeping it in the trash
+            self.record_history(&t, identity, change(None)).await?;
+            self.archive_app(&t, id).await?;
             accessor.delete(id).await?;
 
             // notify an object change
@@ -299,6 +708,9 @@ where
             // update deleted timestamp
             current.deletion_timestamp = Some(Utc::now());
 
+            self.record_history(&t, identity, change(Some(current.history_view())))
+                .await?;
+
             // update the record
             accessor.update_data(current, None).await?;
 
@@ -332,17 +744,11 @@ where
         identity: &UserInformation,
         device: registry::v1::Device,
     ) -> Result<(), Self::Error> {
-        let (mut device, aliases) = Self::device_to_entity(device)?;
-
-        let generation = device.generation;
-
-        let application = device.application.clone();
-
         let mut c = self.pool.get().await?;
         let t = c.build_transaction().start().await?;
 
         let app = PostgresApplicationAccessor::new(&t)
-            .get(&application, Lock::ForShare)
+            .get(&device.metadata.application, Lock::ForShare)
             .await?;
 
         // if there is no entry, or it is marked for deletion, we don't allow adding a new device
@@ -357,36 +763,9 @@ where
             ServiceError::ReferenceNotFound
         })?;
 
-        let name = device.name.clone();
-        // assign a new UID
-        let uid = Uuid::new_v4();
-        device.uid = uid;
-
-        // create the device
-
-        PostgresDeviceAccessor::new(&t)
-            .create(device, aliases)
-            .await
-            .map_err(|err| match err.sql_state() {
-                Some(state) if state == &SqlState::UNIQUE_VIOLATION => {
-                    ServiceError::Conflict("Unique key violation".to_string())
-                }
-                Some(state) if state == &SqlState::FOREIGN_KEY_VIOLATION => {
-                    ServiceError::ReferenceNotFound
-                }
-                _ => err,
-            })?;
+        // create the device and its events
 
-        // create and persist events
-
-        let events = Event::new_device(
-            self.instance.clone(),
-            application,
-            name,
-            uid,
-            generation,
-            vec![],
-        );
+        let events = self.perform_create_device(&t, identity, device).await?;
 
         // send events to outbox
 
@@ -426,38 +805,138 @@ where
         Ok(device.map(Into::into))
     }
 
-    async fn list_devices(
+    async fn get_effective_device(
         &self,
-        identity: UserInformation,
+        identity: &UserInformation,
         app_id: &str,
-        labels: LabelSelector,
-        limit: Option<usize>,
-        offset: Option<usize>,
-    ) -> Result<
-        Pin<Box<dyn Stream<Item = Result<registry::v1::Device, Self::Error>> + Send>>,
-        Self::Error,
-    > {
+        device_id: &str,
+    ) -> Result<Option<registry::v1::Device>, Self::Error> {
+        let device = match self.get_device(identity, app_id, device_id).await? {
+            Some(device) => device,
+            None => return Ok(None),
+        };
+
         let c = self.pool.get().await?;
 
-        let app = PostgresApplicationAccessor::new(&c)
-            .get(app_id, Lock::None)
+        Ok(Some(
+            PostgresDeviceTypeAccessor::new(&c).apply(device).await?,
+        ))
+    }
+
+    async fn get_device_gateways(
+        &self,
+        identity: &UserInformation,
+        app_id: &str,
+        device_id: &str,
+    ) -> Result<Option<Vec<registry::v1::Device>>, Self::Error> {
+        let device = match self
+            .get_effective_device(identity, app_id, device_id)
             .await?
-            .ok_or(ServiceError::NotFound)?;
+        {
+            Some(device) => device,
+            None => return Ok(None),
+        };
 
-        // ensure we have access, but don't confirm the device if we don't
-        ensure_with(&app, &identity, Permission::Read, || ServiceError::NotFound)?;
+        let c = self.pool.get().await?;
 
-        Ok(Box::pin(
-            PostgresDeviceAccessor::new(&c)
-                .list(app_id, None, labels, limit, offset, Lock::None)
-                .await?
-                .map_ok(|device| device.into())
-                .map_err(|err| PostgresManagementServiceError::Service(err))
-                .into_stream(),
+        Ok(Some(
+            PostgresGatewayAccessor::new(&c).gateways(&device).await?,
         ))
     }
 
-    async fn update_device(
+    async fn get_device_children(
+        &self,
+        identity: &UserInformation,
+        app_id: &str,
+        device_id: &str,
+    ) -> Result<Option<Vec<registry::v1::Device>>, Self::Error> {
+        let device = match self.get_device(identity, app_id, device_id).await? {
+            Some(device) => device,
+            None => return Ok(None),
+        };
+
+        let c = self.pool.get().await?;
+
+        Ok(Some(
+            PostgresGatewayAccessor::new(&c).children(&device).await?,
+        ))
+    }
+
+    async fn list_devices(
+        &self,
+        identity: UserInformation,
+        app_id: &str,
+        options: ListOptions,
+    ) -> Result<
+        Page<Pin<Box<dyn Stream<Item = Result<registry::v1::Device, Self::Error>> + Send>>>,
+        Self::Error,
+    > {
+        let c = self.pool.get().await?;
+
+        let app = PostgresApplicationAccessor::new(&c)
+            .get(app_id, Lock::None)
+            .await?
+            .ok_or(ServiceError::NotFound)?;
+
+        // ensure we have access, but don't confirm the device if we don't
+        ensure_with(&app, &identity, Permission::Read, || ServiceError::NotFound)?;
+
+        let accessor = PostgresDeviceAccessor::new(&c);
+
+        let total = match options.count {
+            true => Some(
+                accessor
+                    .count(app_id, options.labels.clone(), options.fields.clone())
+                    .await?,
+            ),
+            false => None,
+        };
+
+        let sort = options.sort;
+
+        let devices = accessor
+            .list(
+                app_id,
+                None,
+                options.labels,
+                options.fields,
+                // fetch one more, to find out if there is a next page
+                options.limit.map(|limit| limit + 1),
+                options.offset,
+                Lock::None,
+                &sort,
+                options.after.as_ref(),
+            )
+            .await?;
+
+        let (items, continue_token) = match options.limit {
+            Some(limit) => {
+                let mut devices: Vec<_> = devices.try_collect().await?;
+                let continue_token = cut_page(&mut devices, limit, |device| {
+                    Cursor::new(&sort, device.creation_timestamp, &device.name, device.uid)
+                });
+                (
+                    stream::iter(devices.into_iter().map(|device| Ok(device.into()))).boxed(),
+                    continue_token,
+                )
+            }
+            None => (
+                devices
+                    .map_ok(Into::into)
+                    .map_err(|err| PostgresManagementServiceError::Service(err))
+                    .boxed(),
+                None,
+            ),
+        };
+
+        Ok(Page {
+            items,
+            continue_token,
+            total,
+        })
+    }
+
+    async fn update_device(
         &self,
         identity: &UserInformation,
         device: registry::v1::Device,
@@ -465,17 +944,14 @@ where
         let expected_resource_version = device.metadata.resource_version.clone();
         let expected_uid = device.metadata.uid.clone();
 
-        let (mut device, aliases) = Self::device_to_entity(device)?;
-
-        let application = device.application.clone();
-        let name = device.name.clone();
+        let (device, aliases) = Self::device_to_entity(device)?;
 
         let mut c = self.pool.get().await?;
         let t = c.build_transaction().start().await?;
 
         let accessor = PostgresApplicationAccessor::new(&t);
 
-        let current = match accessor.get(&application, Lock::None).await? {
+        let current = match accessor.get(&device.application, Lock::None).await? {
             Some(device) => Ok(device),
             None => Err(ServiceError::NotFound),
         }?;
@@ -485,73 +961,144 @@ where
             ServiceError::NotFound
         })?;
 
-        let accessor = PostgresDeviceAccessor::new(&t);
-
-        // get current state for diffing
-        let current = match accessor.get(&application, &name, Lock::ForUpdate).await? {
-            Some(device) => Ok(device),
-            None => Err(ServiceError::NotFound),
-        }?;
+        let events = self
+            .perform_update_device(
+                &t,
+                identity,
+                Subresource::Main,
+                device,
+                Some(aliases),
+                expected_uid,
+                expected_resource_version,
+            )
+            .await?;
 
-        // pre-check versions
-        utils::check_versions(expected_uid, expected_resource_version, &current)?;
+        // send events to outbox
 
-        // we simply copy over the deletion timestamp
-        device.deletion_timestamp = current.deletion_timestamp;
+        Self::send_to_outbox(&t, &events).await?;
 
-        if device.deletion_timestamp.is_some() && device.finalizers.is_empty() {
-            // delete, but don't send any event
-            accessor.delete(&application, &name).await?;
+        // commit
 
-            // check with the application
-            self.check_clean_app(&t, &application).await?;
+        t.commit().await?;
 
-            t.commit().await?;
-        } else {
-            // check which paths changed
-            let paths = diff_paths(&current, &device);
-            if paths.is_empty() {
-                // there was no change
-                return Ok(());
-            }
+        // send change event
 
-            let generation = device.set_incremented_generation(&current)?;
-            let uid = current.uid;
+        events.send_with(&self.sender).await?;
 
-            accessor
-                .update(device, Some(aliases))
-                .await
-                .map_err(|err| match err.sql_state() {
-                    Some(state) if state == &SqlState::UNIQUE_VIOLATION => {
-                        ServiceError::Conflict("Unique key violation".to_string())
-                    }
-                    _ => err,
-                })?;
+        // done
 
-            // create events
+        Ok(())
+    }
 
-            let events = Event::new_device(
-                self.instance.clone(),
-                application,
-                name,
-                uid,
-                generation,
-                paths,
-            );
+    async fn patch_device(
+        &self,
+        identity: &UserInformation,
+        application: &str,
+        name: &str,
+        patch: Patch,
+    ) -> Result<(), Self::Error> {
+        let mut c = self.pool.get().await?;
+        let t = c.build_transaction().start().await?;
 
-            // send events to outbox
+        let app = PostgresApplicationAccessor::new(&t)
+            .get(application, Lock::None)
+            .await?
+            .ok_or(ServiceError::NotFound)?;
 
-            Self::send_to_outbox(&t, &events).await?;
+        // ensure we have access, but don't confirm the device if we don't
+        ensure_with(&app, identity, Permission::Write, || ServiceError::NotFound)?;
 
-            // commit
+        // lock the current state, so that the patch is applied to the most recent version
 
-            t.commit().await?;
+        let current = PostgresDeviceAccessor::new(&t)
+            .get(application, name, Lock::ForUpdate)
+            .await?
+            .ok_or(ServiceError::NotFound)?;
 
-            // send change event
+        let device: registry::v1::Device = patch.apply(current.into())?;
 
-            events.send_with(&self.sender).await?;
+        if device.metadata.application != application || device.metadata.name != name {
+            return Err(ServiceError::BadRequest(
+                "Changing the application or name is not allowed".into(),
+            )
+            .into());
         }
 
+        let expected_uid = device.metadata.uid.clone();
+        let expected_resource_version = device.metadata.resource_version.clone();
+
+        let (device, aliases) = Self::device_to_entity(device)?;
+
+        let events = self
+            .perform_update_device(
+                &t,
+                identity,
+                Subresource::Main,
+                device,
+                Some(aliases),
+                expected_uid,
+                expected_resource_version,
+            )
+            .await?;
+
+        // send events to outbox
+
+        Self::send_to_outbox(&t, &events).await?;
+
+        // commit
+
+        t.commit().await?;
+
+        // send change event
+
+        events.send_with(&self.sender).await?;
+
+        // done
+
+        Ok(())
+    }
+
+    async fn update_device_status(
+        &self,
+        identity: &UserInformation,
+        device: registry::v1::Device,
+    ) -> Result<(), Self::Error> {
+        ensure_status(identity)?;
+
+        let expected_resource_version = device.metadata.resource_version.clone();
+        let expected_uid = device.metadata.uid.clone();
+
+        let (device, _) = Self::device_to_entity(device)?;
+
+        let mut c = self.pool.get().await?;
+        let t = c.build_transaction().start().await?;
+
+        // the aliases are derived from the spec, so we keep them
+
+        let events = self
+            .perform_update_device(
+                &t,
+                identity,
+                Subresource::Status,
+                device,
+                None,
+                expected_uid,
+                expected_resource_version,
+            )
+            .await?;
+
+        // send events to outbox
+
+        Self::send_to_outbox(&t, &events).await?;
+
+        // commit
+
+        t.commit().await?;
+
+        // send change event
+
+        events.send_with(&self.sender).await?;
+
         // done
 
         Ok(())
@@ -595,13 +1142,28 @@ where
         utils::check_preconditions(&params.preconditions, &current)?;
         // there is no need to use the provided constraints, we as locked the entry "for update"
 
+        let before = current.history_view();
+
         // next generation
         let generation = current.increment_generation()?;
         let uid = current.uid;
 
+        let change = |after| Change {
+            kind: ResourceKind::Device,
+            application,
+            name: device,
+            uid,
+            operation: history::Operation::Delete,
+            generation,
+            before: Some(before),
+            after,
+        };
+
         // if there are no finalizers ...
         let path = if current.finalizers.is_empty() {
-            // ... we can directly delete
+            // ... we can directly delete, keeping it in the trash
+            self.record_history(&t, identity, change(None)).await?;
+            self.archive_device(&t, application, device).await?;
             accessor.delete(application, device).await?;
 
             vec![]
@@ -612,6 +1174,9 @@ where
             // update deleted timestamp
             current.deletion_timestamp = Some(Utc::now());
 
+            self.record_history(&t, identity, change(Some(current.history_view())))
+                .await?;
+
             // update the record
             accessor.update(current, None).await?;
 
@@ -645,4 +1210,626 @@ where
 
         Ok(())
     }
+
+    async fn import_devices(
+        &self,
+        identity: &UserInformation,
+        app_id: &str,
+        devices: Vec<ImportEntry>,
+        options: ImportOptions,
+    ) -> Result<Vec<ImportResult>, Self::Error> {
+        // without a chunk size, all devices are imported in a single transaction
+        let atomic = options.chunk_size.is_none();
+        let chunk_size = options.chunk_size.unwrap_or_else(|| devices.len()).max(1);
+
+        let mut results = Vec::with_capacity(devices.len());
+        let mut devices = devices.into_iter().peekable();
+
+        let mut c = self.pool.get().await?;
+
+        while devices.peek().is_some() {
+            let t = c.build_transaction().start().await?;
+
+            let app = PostgresApplicationAccessor::new(&t)
+                .get(app_id, Lock::ForShare)
+                .await?;
+
+            // if there is no entry, or it is marked for deletion, we don't allow adding new devices
+
+            let app = match app {
+                Some(app) if app.deletion_timestamp.is_none() => app,
+                _ => return Err(ServiceError::NotFound.into()),
+            };
+
+            // ensure we have access, but don't confirm the application if we don't
+            ensure_with(&app, identity, Permission::Write, || ServiceError::NotFound)?;
+
+            let mut events = Vec::new();
+            let mut chunk = Vec::with_capacity(chunk_size);
+
+            for device in devices.by_ref().take(chunk_size) {
+                let mut device = match device {
+                    Ok(device) => device,
+                    Err(result) => {
+                        chunk.push(result);
+                        continue;
+                    }
+                };
+
+                if device.metadata.application.is_empty() {
+                    device.metadata.application = app_id.to_string();
+                }
+
+                let name = device.metadata.name.clone();
+
+                if name.is_empty() {
+                    chunk.push(ImportResult::failed(
+                        name,
+                        &ServiceError::BadRequest("Missing device name".into()),
+                    ));
+                    continue;
+                }
+                if device.metadata.application != app_id {
+                    chunk.push(ImportResult::failed(
+                        name,
+                        &ServiceError::BadRequest(
+                            "Device belongs to a different application".into(),
+                        ),
+                    ));
+                    continue;
+                }
+
+                // a failed device aborts the transaction, so roll back only that device
+
+                t.batch_execute("SAVEPOINT IMPORT_DEVICE").await?;
+
+                match self.perform_create_device(&t, identity, device).await {
+                    Ok(device_events) => {
+                        t.batch_execute("RELEASE SAVEPOINT IMPORT_DEVICE").await?;
+                        events.extend(device_events);
+                        chunk.push(ImportResult::created(name));
+                    }
+                    Err(PostgresManagementServiceError::Service(err)) => {
+                        t.batch_execute("ROLLBACK TO SAVEPOINT IMPORT_DEVICE")
+                            .await?;
+                        chunk.push(ImportResult::failed(name, &err));
+                    }
+                    Err(err) => return Err(err),
+                }
+            }
+
+            if atomic
+                && chunk
+                    .iter()
+                    .any(|result| result.outcome == ImportOutcome::Failed)
+            {
+                // roll back everything
+
+                t.rollback().await?;
+
+                for result in &mut chunk {
+                    if result.outcome == ImportOutcome::Created {
+                        result.outcome = ImportOutcome::RolledBack;
+                    }
+                }
+                results.extend(chunk);
+
+                continue;
+            }
+
+            // send events to outbox
+
+            Self::send_to_outbox(&t, &events).await?;
+
+            t.commit().await?;
+
+            // send change events, batched for the chunk
+
+            events.send_with(&self.sender).await?;
+
+            results.extend(chunk);
+        }
+
+        // done
+
+        Ok(results)
+    }
+
+    async fn export_devices(
+        &self,
+        identity: UserInformation,
+        app_id: &str,
+        credentials: bool,
+    ) -> Result<
+        Pin<Box<dyn Stream<Item = Result<registry::v1::Device, Self::Error>> + Send>>,
+        Self::Error,
+    > {
+        let devices = self
+            .list_devices(identity, app_id, ListOptions::default())
+            .await?
+            .items;
+
+        Ok(match credentials {
+            true => devices,
+            false => devices.map_ok(redact_credentials).boxed(),
+        })
+    }
+
+    async fn watch_apps(
+        &self,
+        identity: UserInformation,
+        labels: LabelSelector,
+        fields: FieldSelector,
+        resource_version: Option<u64>,
+    ) -> Result<
+        Pin<
+            Box<
+                dyn Stream<Item = Result<WatchEvent<registry::v1::Application>, Self::Error>>
+                    + Send,
+            >,
+        >,
+        Self::Error,
+    > {
+        // subscribe before reading the initial state, so that we don't miss any changes
+        let changes = self.listener.subscribe();
+
+        let source = ApplicationSource {
+            pool: self.pool.clone(),
+            identity,
+            labels,
+            fields,
+        };
+
+        let log = PostgresChangeLog {
+            pool: self.pool.clone(),
+        };
+
+        Ok(watch::watch(source, log, changes, resource_version)
+            .await?
+            .map_err(PostgresManagementServiceError::Service)
+            .boxed())
+    }
+
+    async fn watch_devices(
+        &self,
+        identity: UserInformation,
+        app_id: &str,
+        labels: LabelSelector,
+        fields: FieldSelector,
+        resource_version: Option<u64>,
+    ) -> Result<
+        Pin<Box<dyn Stream<Item = Result<WatchEvent<registry::v1::Device>, Self::Error>> + Send>>,
+        Self::Error,
+    > {
+        let c = self.pool.get().await?;
+
+        let app = PostgresApplicationAccessor::new(&c)
+            .get(app_id, Lock::None)
+            .await?
+            .ok_or(ServiceError::NotFound)?;
+
+        // ensure we have access, but don't confirm the device if we don't
+        ensure_with(&app, &identity, Permission::Read, || ServiceError::NotFound)?;
+
+        // subscribe before reading the initial state, so that we don't miss any changes
+        let changes = self.listener.subscribe();
+
+        let source = DeviceSource {
+            pool: self.pool.clone(),
+            identity,
+            app: app_id.to_string(),
+            labels,
+            fields,
+        };
+
+        let log = PostgresChangeLog {
+            pool: self.pool.clone(),
+        };
+
+        Ok(watch::watch(source, log, changes, resource_version)
+            .await?
+            .map_err(PostgresManagementServiceError::Service)
+            .boxed())
+    }
+
+    async fn get_device_twin(
+        &self,
+        identity: &UserInformation,
+        app_id: &str,
+        device_id: &str,
+    ) -> Result<Option<DeviceTwin>, Self::Error> {
+        let c = self.pool.get().await?;
+
+        let app = PostgresApplicationAccessor::new(&c)
+            .get(app_id, Lock::None)
+            .await?
+            .ok_or(ServiceError::NotFound)?;
+
+        // ensure we have access, but don't confirm the device if we don't
+        ensure_with(&app, identity, Permission::Read, || ServiceError::NotFound)?;
+
+        if PostgresDeviceAccessor::new(&c)
+            .get(app_id, device_id, Lock::None)
+            .await?
+            .is_none()
+        {
+            return Ok(None);
+        }
+
+        // a device without a stored twin has an empty one
+
+        let twin = PostgresTwinAccessor::new(&c)
+            .get(app_id, device_id, Lock::None)
+            .await?
+            .unwrap_or_else(|| Twin::new(app_id, device_id));
+
+        Ok(Some(twin.into()))
+    }
+
+    async fn update_device_twin_desired(
+        &self,
+        identity: &UserInformation,
+        app_id: &str,
+        device_id: &str,
+        desired: Value,
+    ) -> Result<(), Self::Error> {
+        if !desired.is_object() {
+            return Err(
+                ServiceError::BadRequest("Desired state must be a JSON object".into()).into(),
+            );
+        }
+
+        let mut c = self.pool.get().await?;
+        let t = c.build_transaction().start().await?;
+
+        let app = PostgresApplicationAccessor::new(&t)
+            .get(app_id, Lock::None)
+            .await?
+            .ok_or(ServiceError::NotFound)?;
+
+        // ensure we have access, but don't confirm the device if we don't
+        ensure_with(&app, identity, Permission::Write, || ServiceError::NotFound)?;
+
+        let device = PostgresDeviceAccessor::new(&t)
+            .get(app_id, device_id, Lock::ForShare)
+            .await?
+            .ok_or(ServiceError::NotFound)?;
+
+        let accessor = PostgresTwinAccessor::new(&t);
+
+        let current = accessor.get(app_id, device_id, Lock::ForUpdate).await?;
+        if current.map(|twin| twin.desired == desired).unwrap_or(false) {
+            // there was no change
+            return Ok(());
+        }
+
+        accessor
+            .set_desired(app_id, device_id, desired, Utc::now())
+            .await?;
+
+        // create events, the twin doesn't change the generation of the device
+
+        let events = Event::new_device(
+            self.instance.clone(),
+            app_id,
+            device_id,
+            device.uid,
+            device.generation,
+            vec![".twin".into()],
+        );
+
+        // send events to outbox
+
+        Self::send_to_outbox(&t, &events).await?;
+
+        // commit
+
+        t.commit().await?;
+
+        // send change events
+
+        events.send_with(&self.sender).await?;
+
+        // done
+
+        Ok(())
+    }
+
+    async fn create_device_type(
+        &self,
+        identity: &UserInformation,
+        device_type: DeviceType,
+    ) -> Result<(), Self::Error> {
+        let mut c = self.pool.get().await?;
+        let t = c.build_transaction().start().await?;
+
+        let app = PostgresApplicationAccessor::new(&t)
+            .get(&device_type.metadata.application, Lock::ForShare)
+            .await?;
+
+        // if there is no entry, or it is marked for deletion, we don't allow adding a new type
+
+        let app = match app {
+            Some(app) if app.deletion_timestamp.is_none() => app,
+            _ => return Err(ServiceError::ReferenceNotFound.into()),
+        };
+
+        // ensure we have access to the application, but don't confirm the type if we don't
+        ensure_with(&app, identity, Permission::Write, || {
+            ServiceError::ReferenceNotFound
+        })?;
+
+        let mut device_type = Self::device_type_to_entity(device_type);
+        // assign a new UID
+        device_type.uid = Uuid::new_v4();
+
+        let name = device_type.name.clone();
+        let uid = device_type.uid;
+        let generation = device_type.generation;
+        let after = device_type.history_view();
+
+        PostgresDeviceTypeAccessor::new(&t)
+            .create(device_type)
+            .await
+            .map_err(|err| match err.sql_state() {
+                Some(state) if state == &SqlState::UNIQUE_VIOLATION => {
+                    ServiceError::Conflict("Unique key violation".to_string())
+                }
+                Some(state) if state == &SqlState::FOREIGN_KEY_VIOLATION => {
+                    ServiceError::ReferenceNotFound
+                }
+                _ => err,
+            })?;
+
+        self.record_history(
+            &t,
+            identity,
+            Change {
+                kind: ResourceKind::DeviceType,
+                application: &app.name,
+                name: &name,
+                uid,
+                operation: history::Operation::Create,
+                generation,
+                before: None,
+                after: Some(after),
+            },
+        )
+        .await?;
+
+        // create events, the devices of the type change, but the application doesn't
+
+        let events = Event::new_app(
+            self.instance.clone(),
+            &app.name,
+            app.uid,
+            app.generation,
+            vec![format!(".deviceTypes.{}", name)],
+        );
+
+        // send events to outbox
+
+        Self::send_to_outbox(&t, &events).await?;
+
+        t.commit().await?;
+
+        // send change events
+
+        events.send_with(&self.sender).await?;
+
+        // done
+
+        Ok(())
+    }
+
+    async fn get_device_type(
+        &self,
+        identity: &UserInformation,
+        app_id: &str,
+        name: &str,
+    ) -> Result<Option<DeviceType>, Self::Error> {
+        let c = self.pool.get().await?;
+
+        let app = PostgresApplicationAccessor::new(&c)
+            .get(app_id, Lock::None)
+            .await?
+            .ok_or(ServiceError::NotFound)?;
+
+        // ensure we have access, but don't confirm the type if we don't
+        ensure_with(&app, identity, Permission::Read, || ServiceError::NotFound)?;
+
+        let device_type = PostgresDeviceTypeAccessor::new(&c)
+            .get(app_id, name, Lock::None)
+            .await?;
+
+        Ok(device_type.map(Into::into))
+    }
+
+    async fn list_device_types(
+        &self,
+        identity: &UserInformation,
+        app_id: &str,
+    ) -> Result<Vec<DeviceType>, Self::Error> {
+        let c = self.pool.get().await?;
+
+        let app = PostgresApplicationAccessor::new(&c)
+            .get(app_id, Lock::None)
+            .await?
+            .ok_or(ServiceError::NotFound)?;
+
+        // ensure we have access, but don't confirm the types if we don't
+        ensure_with(&app, identity, Permission::Read, || ServiceError::NotFound)?;
+
+        let device_types = PostgresDeviceTypeAccessor::new(&c).list(app_id).await?;
+
+        Ok(device_types.into_iter().map(Into::into).collect())
+    }
+
+    async fn update_device_type(
+        &self,
+        identity: &UserInformation,
+        device_type: DeviceType,
+    ) -> Result<(), Self::Error> {
+        let expected_uid = device_type.metadata.uid.clone();
+        let expected_resource_version = device_type.metadata.resource_version.clone();
+
+        let mut device_type = Self::device_type_to_entity(device_type);
+
+        let mut c = self.pool.get().await?;
+        let t = c.build_transaction().start().await?;
+
+        let app = PostgresApplicationAccessor::new(&t)
+            .get(&device_type.application, Lock::None)
+            .await?
+            .ok_or(ServiceError::NotFound)?;
+
+        // ensure we have access, but don't confirm the type if we don't
+        ensure_with(&app, identity, Permission::Write, || ServiceError::NotFound)?;
+
+        let accessor = PostgresDeviceTypeAccessor::new(&t);
+
+        // get current state for diffing
+        let current = accessor
+            .get(&device_type.application, &device_type.name, Lock::ForUpdate)
+            .await?
+            .ok_or(ServiceError::NotFound)?;
+
+        utils::check_versions(expected_uid, expected_resource_version, &current)?;
+
+        if current.labels == device_type.labels
+            && current.annotations == device_type.annotations
+            && current.data == device_type.data
+        {
+            // there was no change
+            return Ok(());
+        }
+
+        device_type.uid = current.uid;
+        let generation = device_type.set_incremented_generation(&current)?;
+
+        let name = device_type.name.clone();
+        let after = device_type.history_view();
+
+        accessor.update(device_type).await?;
+
+        self.record_history(
+            &t,
+            identity,
+            Change {
+                kind: ResourceKind::DeviceType,
+                application: &app.name,
+                name: &name,
+                uid: current.uid,
+                operation: history::Operation::Update,
+                generation,
+                before: Some(current.history_view()),
+                after: Some(after),
+            },
+        )
+        .await?;
+
+        // create events, for the application and the devices using the type
+
+        let mut events = Event::new_app(
+            self.instance.clone(),
+            &app.name,
+            app.uid,
+            app.generation,
+            vec![format!(".deviceTypes.{}", name)],
+        );
+        events.extend(self.device_type_events(&t, &app.name, &name).await?);
+
+        // send events to outbox
+
+        Self::send_to_outbox(&t, &events).await?;
+
+        t.commit().await?;
+
+        // send change events
+
+        events.send_with(&self.sender).await?;
+
+        // done
+
+        Ok(())
+    }
+
+    async fn delete_device_type(
+        &self,
+        identity: &UserInformation,
+        app_id: &str,
+        name: &str,
+    ) -> Result<(), Self::Error> {
+        let mut c = self.pool.get().await?;
+        let t = c.build_transaction().start().await?;
+
+        let app = PostgresApplicationAccessor::new(&t)
+            .get(app_id, Lock::None)
+            .await?
+            .ok_or(ServiceError::NotFound)?;
+
+        // ensure we have access, but don't confirm the type if we don't
+        ensure_with(&app, identity, Permission::Write, || ServiceError::NotFound)?;
+
+        let accessor = PostgresDeviceTypeAccessor::new(&t);
+
+        // lock the type, so that no device can start referencing it
+        let current = accessor
+            .get(app_id, name, Lock::ForUpdate)
+            .await?
+            .ok_or(ServiceError::NotFound)?;
+
+        // the type must not be in use by any device
+
+        let device_events = self.device_type_events(&t, app_id, name).await?;
+        if !device_events.is_empty() {
+            return Err(ServiceError::Conflict(format!(
+                "Device type is still used by {} device(s)",
+                device_events.len()
+            ))
+            .into());
+        }
+
+        accessor.delete(app_id, name).await?;
+
+        self.record_history(
+            &t,
+            identity,
+            Change {
+                kind: ResourceKind::DeviceType,
+                application: app_id,
+                name,
+                uid: current.uid,
+                operation: history::Operation::Delete,
+                generation: current.generation,
+                before: Some(current.history_view()),
+                after: None,
+            },
+        )
+        .await?;
+
+        // create events, there are no devices using the type
+
+        let events = Event::new_app(
+            self.instance.clone(),
+            &app.name,
+            app.uid,
+            app.generation,
+            vec![format!(".deviceTypes.{}", name)],
+        );
+
+        // send events to outbox
+
+        Self::send_to_outbox(&t, &events).await?;
+
+        t.commit().await?;
+
+        // send change events
+
+        events.send_with(&self.sender).await?;
+
+        // done
+
+        Ok(())
+    }
 }
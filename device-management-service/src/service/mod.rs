@@ -1,10 +1,24 @@
 pub mod admin;
+pub mod bulk;
 mod error;
+mod history;
 pub mod management;
+pub mod patch;
+mod secrets;
+mod subresource;
+mod trash;
 mod utils;
+pub mod watch;
 mod x509;
 
-use crate::{service::error::PostgresManagementServiceError, utils::epoch};
+use crate::{
+    service::{
+        error::PostgresManagementServiceError,
+        history::{Change, HistoryView},
+        subresource::{Subresource, APP_DERIVED_STATUS},
+    },
+    utils::epoch,
+};
 use deadpool_postgres::{Pool, Transaction};
 use drogue_client::{registry, Translator};
 use drogue_cloud_database_common::{
@@ -14,6 +28,7 @@ use drogue_cloud_database_common::{
         self,
         app::{ApplicationAccessor, PostgresApplicationAccessor},
         device::{DeviceAccessor, PostgresDeviceAccessor},
+        device_type::{DeviceTypeAccessor, PostgresDeviceTypeAccessor},
         diff::diff_paths,
         outbox::PostgresOutboxAccessor,
         Generation, Lock, TypedAlias,
@@ -23,11 +38,13 @@ use drogue_cloud_database_common::{
 use drogue_cloud_registry_events::{Event, EventSender, EventSenderError, SendEvent};
 use drogue_cloud_service_api::{
     auth::user::{authz::Permission, UserInformation},
+    device_type::{device_type_of, DeviceType},
     health::{HealthCheckError, HealthChecked},
+    history::{Operation, ResourceKind},
 };
 use serde::Deserialize;
 use serde_json::json;
-use std::collections::HashSet;
+use std::{collections::HashSet, time::Duration};
 use tokio_postgres::{error::SqlState, NoTls};
 use uuid::Uuid;
 
@@ -35,6 +52,35 @@ use uuid::Uuid;
 pub struct PostgresManagementServiceConfig {
     pub pg: deadpool_postgres::Config,
     pub instance: String,
+    /// The time deleted resources are kept in the trash. Zero disables the trash.
+    #[serde(default = "default_trash_retention", with = "humantime_serde")]
+    pub trash_retention: Duration,
+    /// The interval in which expired resources are purged from the trash, the change log, and the
+    /// history.
+    #[serde(default = "default_trash_purge_interval", with = "humantime_serde")]
+    pub trash_purge_interval: Duration,
+    /// The time changes are kept in the change log, for resuming watches.
+    #[serde(default = "default_watch_retention", with = "humantime_serde")]
+    pub watch_retention: Duration,
+    /// The time changes are kept in the history. Zero keeps them forever.
+    #[serde(default = "default_history_retention", with = "humantime_serde")]
+    pub history_retention: Duration,
+}
+
+fn default_trash_retention() -> Duration {
+    Duration::from_secs(7 * 24 * 60 * 60)
+}
+
+fn default_trash_purge_interval() -> Duration {
+    Duration::from_secs(60 * 60)
+}
+
+fn default_watch_retention() -> Duration {
+    Duration::from_secs(60 * 60)
+}
+
+fn default_history_retention() -> Duration {
+    Duration::from_secs(90 * 24 * 60 * 60)
 }
 
 impl<S> DatabaseService for PostgresManagementService<S>
@@ -66,6 +112,11 @@ where
     pool: Pool,
     sender: S,
     instance: String,
+    listener: watch::OutboxListener,
+    trash_retention: Option<chrono::Duration>,
+    trash_purge_interval: Duration,
+    watch_retention: chrono::Duration,
+    history_retention: Option<chrono::Duration>,
 }
 
 impl<S> PostgresManagementService<S>
@@ -73,9 +124,24 @@ where
     S: EventSender + Clone,
 {
     pub fn new(config: PostgresManagementServiceConfig, sender: S) -> anyhow::Result<Self> {
+        let trash_retention = match config.trash_retention {
+            retention if retention.as_secs() == 0 => None,
+            retention => Some(chrono::Duration::from_std(retention)?),
+        };
+
+        let history_retention = match config.history_retention {
+            retention if retention.as_secs() == 0 => None,
+            retention => Some(chrono::Duration::from_std(retention)?),
+        };
+
         Ok(Self {
+            listener: watch::OutboxListener::new(config.pg.get_pg_config()?),
             pool: config.pg.create_pool(NoTls)?,
             instance: config.instance,
+            trash_retention,
+            trash_purge_interval: config.trash_purge_interval,
+            watch_retention: chrono::Duration::from_std(config.watch_retention)?,
+            history_retention,
             sender,
         })
     }
@@ -188,11 +254,130 @@ where
         Ok((device, aliases))
     }
 
+    fn device_type_to_entity(device_type: DeviceType) -> models::device_type::DeviceType {
+        models::device_type::DeviceType {
+            name: device_type.metadata.name,
+            uid: Uuid::nil(), // will be set internally
+            application: device_type.metadata.application,
+            labels: device_type.metadata.labels,
+            annotations: device_type.metadata.annotations,
+            creation_timestamp: epoch(),   // will be set internally
+            generation: 0,                 // will be set internally
+            resource_version: Uuid::nil(), // will be set internally
+            data: json!({
+                "spec": device_type.spec,
+            }),
+        }
+    }
+
+    /// Ensure that the device type, referenced by a device, exists.
+    ///
+    /// The device type gets locked, so that it cannot be deleted while the transaction is active.
+    async fn check_device_type(
+        t: &Transaction<'_>,
+        app: &str,
+        device_type: Option<&str>,
+    ) -> Result<(), PostgresManagementServiceError<S::Error>> {
+        let device_type = match device_type {
+            Some(device_type) => device_type,
+            None => return Ok(()),
+        };
+
+        match PostgresDeviceTypeAccessor::new(t)
+            .get(app, device_type, Lock::ForShare)
+            .await?
+        {
+            Some(_) => Ok(()),
+            None => Err(ServiceError::ReferenceNotFound.into()),
+        }
+    }
+
+    /// Perform the operation of creating a device
+    ///
+    /// The application of the device must already be checked for existence and access.
+    async fn perform_create_device(
+        &self,
+        t: &Transaction<'_>,
+        identity: &UserInformation,
+        mut device: registry::v1::Device,
+    ) -> Result<Vec<Event>, PostgresManagementServiceError<S::Error>> {
+        // the status can only be set through the status subresource
+        device.status.clear();
+
+        Self::check_device_type(
+            t,
+            &device.metadata.application,
+            device_type_of(&device.spec),
+        )
+        .await?;
+
+        let (mut device, aliases) = Self::device_to_entity(device)?;
+
+        let generation = device.generation;
+        let application = device.application.clone();
+        let name = device.name.clone();
+
+        // assign a new UID
+        let uid = Uuid::new_v4();
+        device.uid = uid;
+
+        let after = device.history_view();
+
+        // create the device
+
+        PostgresDeviceAccessor::new(t)
+            .create(device, aliases)
+            .await
+            .map_err(|err| match err.sql_state() {
+                Some(state) if state == &SqlState::UNIQUE_VIOLATION => {
+                    ServiceError::Conflict("Unique key violation".to_string())
+                }
+                Some(state) if state == &SqlState::FOREIGN_KEY_VIOLATION => {
+                    ServiceError::ReferenceNotFound
+                }
+                _ => err,
+            })?;
+
+        // record the change
+
+        self.record_history(
+            t,
+            identity,
+            Change {
+                kind: ResourceKind::Device,
+                application: &application,
+                name: &name,
+                uid,
+                operation: Operation::Create,
+                generation,
+                before: None,
+                after: Some(after),
+            },
+        )
+        .await?;
+
+        // create events
+
+        Ok(Event::new_device(
+            self.instance.clone(),
+            application,
+            name,
+            uid,
+            generation,
+            vec![],
+        ))
+    }
+
     /// Perform the operation of updating an application
+    ///
+    /// Only the part of the application covered by `subresource` will be changed.
+    #[allow(clippy::too_many_arguments)]
     async fn perform_update_app<S1, S2>(
         &self,
         t: &Transaction<'_>,
+        actor: &UserInformation,
         identity: Option<&UserInformation>,
+        subresource: Subresource,
         mut app: models::app::Application,
         aliases: Option<HashSet<TypedAlias>>,
         expected_uid: S1,
@@ -219,8 +404,35 @@ where
         // we simply copy over the deletion timestamp
         app.deletion_timestamp = current.deletion_timestamp;
 
+        // carry over what the subresource doesn't cover
+        subresource.restrict(&current.data, &mut app.data, APP_DERIVED_STATUS);
+        if subresource == Subresource::Main {
+            secrets::retain_app_secrets(&current.data, &mut app.data);
+        }
+        if subresource == Subresource::Status {
+            app.labels = current.labels.clone();
+            app.annotations = current.annotations.clone();
+            app.finalizers = current.finalizers.clone();
+        }
+
         if app.deletion_timestamp.is_some() && app.finalizers.is_empty() {
             // delete, but don't send any event
+            self.record_history(
+                t,
+                actor,
+                Change {
+                    kind: ResourceKind::Application,
+                    application: &app.name,
+                    name: &app.name,
+                    uid: current.uid,
+                    operation: Operation::Delete,
+                    generation: current.generation,
+                    before: Some(current.history_view()),
+                    after: None,
+                },
+            )
+            .await?;
+            self.archive_app(t, &app.name).await?;
             accessor.delete(&app.name).await?;
 
             Ok(vec![])
@@ -233,11 +445,18 @@ where
                 return Ok(vec![]);
             }
 
-            // next generation
-            let generation = app.set_incremented_generation(&current)?;
+            // next generation, status changes don't make one
+            let generation = match subresource {
+                Subresource::Main => app.set_incremented_generation(&current)?,
+                Subresource::Status => {
+                    app.generation = current.generation;
+                    app.generation
+                }
+            };
 
             let name = app.name.clone();
             let uid = app.uid;
+            let after = app.history_view();
 
             // update
 
@@ -251,6 +470,26 @@ where
                     _ => err,
                 })?;
 
+            // record the change, the status is owned by controllers and not tracked
+
+            if subresource == Subresource::Main {
+                self.record_history(
+                    t,
+                    actor,
+                    Change {
+                        kind: ResourceKind::Application,
+                        application: &name,
+                        name: &name,
+                        uid: current.uid,
+                        operation: Operation::Update,
+                        generation,
+                        before: Some(current.history_view()),
+                        after: Some(after),
+                    },
+                )
+                .await?;
+            }
+
             // send change event
 
             Ok(Event::new_app(
@@ -263,10 +502,147 @@ where
         }
     }
 
+    /// Perform the operation of updating a device
+    ///
+    /// Only the part of the device covered by `subresource` will be changed.
+    #[allow(clippy::too_many_arguments)]
+    async fn perform_update_device<S1, S2>(
+        &self,
+        t: &Transaction<'_>,
+        identity: &UserInformation,
+        subresource: Subresource,
+        mut device: models::device::Device,
+        aliases: Option<HashSet<TypedAlias>>,
+        expected_uid: S1,
+        expected_resource_version: S2,
+    ) -> Result<Vec<Event>, PostgresManagementServiceError<S::Error>>
+    where
+        S1: AsRef<str>,
+        S2: AsRef<str>,
+    {
+        let application = device.application.clone();
+        let name = device.name.clone();
+
+        let accessor = PostgresDeviceAccessor::new(t);
+
+        // get current state for diffing
+        let current = match accessor.get(&application, &name, Lock::ForUpdate).await? {
+            Some(device) => Ok(device),
+            None => Err(ServiceError::NotFound),
+        }?;
+
+        // pre-check versions
+        utils::check_versions(expected_uid, expected_resource_version, &current)?;
+
+        // we simply copy over the deletion timestamp
+        device.deletion_timestamp = current.deletion_timestamp;
+
+        // carry over what the subresource doesn't cover
+        subresource.restrict(&current.data, &mut device.data, &[]);
+        if subresource == Subresource::Status {
+            device.labels = current.labels.clone();
+            device.annotations = current.annotations.clone();
+            device.finalizers = current.finalizers.clone();
+        } else {
+            Self::check_device_type(
+                t,
+                &application,
+                device.data["spec"].as_object().and_then(device_type_of),
+            )
+            .await?;
+        }
+
+        if device.deletion_timestamp.is_some() && device.finalizers.is_empty() {
+            // delete, but don't send any event
+            self.record_history(
+                t,
+                identity,
+                Change {
+                    kind: ResourceKind::Device,
+                    application: &application,
+                    name: &name,
+                    uid: current.uid,
+                    operation: Operation::Delete,
+                    generation: current.generation,
+                    before: Some(current.history_view()),
+                    after: None,
+                },
+            )
+            .await?;
+            self.archive_device(t, &application, &name).await?;
+            accessor.delete(&application, &name).await?;
+
+            // check with the application
+            self.check_clean_app(t, identity, &application).await?;
+
+            Ok(vec![])
+        } else {
+            // check which paths changed
+            let paths = diff_paths(&current, &device);
+            if paths.is_empty() {
+                // there was no change
+                return Ok(vec![]);
+            }
+
+            // next generation, status changes don't make one
+            let generation = match subresource {
+                Subresource::Main => device.set_incremented_generation(&current)?,
+                Subresource::Status => {
+                    device.generation = current.generation;
+                    device.generation
+                }
+            };
+            let uid = current.uid;
+            let after = device.history_view();
+
+            accessor
+                .update(device, aliases)
+                .await
+                .map_err(|err| match err.sql_state() {
+                    Some(state) if state == &SqlState::UNIQUE_VIOLATION => {
+                        ServiceError::Conflict("Unique key violation".to_string())
+                    }
+                    _ => err,
+                })?;
+
+            // record the change, the status is owned by controllers and not tracked
+
+            if subresource == Subresource::Main {
+                self.record_history(
+                    t,
+                    identity,
+                    Change {
+                        kind: ResourceKind::Device,
+                        application: &application,
+                        name: &name,
+                        uid,
+                        operation: Operation::Update,
+                        generation,
+                        before: Some(current.history_view()),
+                        after: Some(after),
+                    },
+                )
+                .await?;
+            }
+
+            // create events
+
+            Ok(Event::new_device(
+                self.instance.clone(),
+                application,
+                name,
+                uid,
+                generation,
+                paths,
+            ))
+        }
+    }
+
     /// Called when a device was deleted, so check if the application can be garbage collected.
     async fn check_clean_app(
         &self,
         t: &Transaction<'_>,
+        identity: &UserInformation,
         app_id: &str,
     ) -> Result<(), PostgresManagementServiceError<S::Error>> {
         let app = PostgresApplicationAccessor::new(t)
@@ -295,7 +671,8 @@ where
 
         // we removed the last of the devices blocking the deletion
         app.finalizers.retain(|f| f != "has-devices");
-        self.perform_update_app(t, None, app, None, "", "").await?;
+        self.perform_update_app(t, identity, None, Subresource::Main, app, None, "", "")
+            .await?;
 
         // done
 
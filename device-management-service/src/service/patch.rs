@@ -0,0 +1,118 @@
+use drogue_cloud_database_common::error::ServiceError;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+
+/// The content type of a JSON patch (RFC 6902).
+pub const CONTENT_TYPE_JSON_PATCH: &str = "application/json-patch+json";
+/// The content type of a JSON merge patch (RFC 7386).
+pub const CONTENT_TYPE_MERGE_PATCH: &str = "application/merge-patch+json";
+
+/// A patch to a resource.
+#[derive(Clone, Debug)]
+pub enum Patch {
+    /// A JSON patch (RFC 6902).
+    Json(json_patch::Patch),
+    /// A JSON merge patch (RFC 7386).
+    Merge(Value),
+}
+
+impl Patch {
+    /// Parse a patch, based on its content type.
+    ///
+    /// Returns `None` if the content type isn't supported. Plain JSON is treated as a merge patch.
+    pub fn parse(content_type: &str, body: &[u8]) -> Option<Result<Self, serde_json::Error>> {
+        let essence = content_type.split(';').next().unwrap_or_default().trim();
+        match essence {
+            CONTENT_TYPE_JSON_PATCH => Some(serde_json::from_slice(body).map(Patch::Json)),
+            CONTENT_TYPE_MERGE_PATCH | "application/json" => {
+                Some(serde_json::from_slice(body).map(Patch::Merge))
+            }
+            _ => None,
+        }
+    }
+
+    /// Apply the patch to a resource.
+    pub fn apply<T>(&self, resource: T) -> Result<T, ServiceError>
+    where
+        T: Serialize + DeserializeOwned,
+    {
+        let mut value = serde_json::to_value(resource)
+            .map_err(|err| ServiceError::Internal(err.to_string()))?;
+
+        match self {
+            Patch::Json(patch) => {
+                json_patch::patch(&mut value, patch).map_err(|err| match err {
+                    json_patch::PatchError::TestFailed => {
+                        ServiceError::Conflict("Patch test operation failed".into())
+                    }
+                    err => ServiceError::BadRequest(err.to_string()),
+                })?
+            }
+            Patch::Merge(patch) => json_patch::merge(&mut value, patch),
+        }
+
+        serde_json::from_value(value).map_err(|err| ServiceError::BadRequest(err.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse() {
+        assert!(matches!(
+            Patch::parse(
+                CONTENT_TYPE_JSON_PATCH,
+                br#"[{"op": "remove", "path": "/a"}]"#
+            ),
+            Some(Ok(Patch::Json(_)))
+        ));
+        assert!(matches!(
+            Patch::parse(
+                "application/merge-patch+json; charset=utf-8",
+                br#"{"a": null}"#
+            ),
+            Some(Ok(Patch::Merge(_)))
+        ));
+        assert!(matches!(
+            Patch::parse(CONTENT_TYPE_JSON_PATCH, br#"{"a": null}"#),
+            Some(Err(_))
+        ));
+        assert!(Patch::parse("text/plain", b"").is_none());
+    }
+
+    #[test]
+    fn test_apply() {
+        let resource = json!({"metadata": {"name": "foo"}, "spec": {"a": 1, "b": 2}});
+
+        let patch = Patch::Merge(json!({"spec": {"a": null, "c": 3}}));
+        assert_eq!(
+            patch.apply(resource.clone()).unwrap(),
+            json!({"metadata": {"name": "foo"}, "spec": {"b": 2, "c": 3}})
+        );
+
+        let patch = Patch::parse(
+            CONTENT_TYPE_JSON_PATCH,
+            br#"[{"op": "replace", "path": "/spec/a", "value": 42}]"#,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            patch.apply(resource.clone()).unwrap(),
+            json!({"metadata": {"name": "foo"}, "spec": {"a": 42, "b": 2}})
+        );
+
+        let patch = Patch::parse(
+            CONTENT_TYPE_JSON_PATCH,
+            br#"[{"op": "test", "path": "/spec/a", "value": 42}]"#,
+        )
+        .unwrap()
+        .unwrap();
+        assert!(matches!(
+            patch.apply(resource),
+            Err(ServiceError::Conflict(_))
+        ));
+    }
+}
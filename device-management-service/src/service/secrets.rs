@@ -0,0 +1,174 @@
+use drogue_client::registry;
+use drogue_cloud_service_api::auth::user::UserInformation;
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+
+/// The value, replacing a secret when reading a resource.
+///
+/// Updating a resource with this value keeps the stored secret.
+pub const REDACTED: &str = "********";
+
+/// Check if the user may read the secrets of an application.
+///
+/// Secrets are only handed out to the controllers using them, e.g. the webhook integration.
+pub fn may_read_secrets(identity: &UserInformation) -> bool {
+    identity.is_admin() || identity.is_controller()
+}
+
+/// A digest of a value, allowing to detect changes without revealing the value.
+pub fn digest(value: &Value) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(value.to_string());
+    format!("sha256:{:x}", hasher.finalize())
+}
+
+/// The webhook targets of an application spec, the secret of each target is stored in `secret`.
+fn webhook_targets(spec: &mut Map<String, Value>) -> impl Iterator<Item = &mut Value> {
+    spec.get_mut("webhooks")
+        .and_then(|webhooks| webhooks.get_mut("targets"))
+        .and_then(Value::as_array_mut)
+        .into_iter()
+        .flatten()
+}
+
+/// Replace each secret of an application spec.
+fn replace_app_secrets<F>(spec: &mut Map<String, Value>, f: F)
+where
+    F: Fn(&Value) -> Value,
+{
+    for target in webhook_targets(spec) {
+        if let Some(secret) = target.get_mut("secret") {
+            *secret = f(secret);
+        }
+    }
+}
+
+/// Replace the secrets of an application with a placeholder, unless the user may read them.
+pub fn redact_app_secrets(
+    identity: &UserInformation,
+    mut app: registry::v1::Application,
+) -> registry::v1::Application {
+    if !may_read_secrets(identity) {
+        replace_app_secrets(&mut app.spec, |_| Value::String(REDACTED.into()));
+    }
+    app
+}
+
+/// Replace the secrets of an application spec with their digest.
+///
+/// This is used for the history, which must not contain any secrets, but should still show
+/// when a secret got changed.
+pub fn digest_app_secrets(spec: &mut Map<String, Value>) {
+    replace_app_secrets(spec, digest);
+}
+
+/// Keep the current secrets of an application, where the update provides the placeholder.
+///
+/// Webhook targets are matched by name. If there is no current secret, the placeholder gets
+/// dropped.
+pub fn retain_app_secrets(current: &Value, app: &mut Value) {
+    let mut current = match current.get("spec") {
+        Some(Value::Object(spec)) => spec.clone(),
+        _ => Map::new(),
+    };
+    let current: Vec<_> = webhook_targets(&mut current).map(|t| t.clone()).collect();
+
+    let spec = match app.get_mut("spec").and_then(Value::as_object_mut) {
+        Some(spec) => spec,
+        None => return,
+    };
+
+    for target in webhook_targets(spec) {
+        if target.get("secret").and_then(Value::as_str) != Some(REDACTED) {
+            continue;
+        }
+
+        let secret = current
+            .iter()
+            .find(|c| c.get("name").is_some() && c.get("name") == target.get("name"))
+            .and_then(|c| c.get("secret"))
+            .cloned();
+
+        if let Some(target) = target.as_object_mut() {
+            match secret {
+                Some(secret) => target.insert("secret".into(), secret),
+                None => target.remove("secret"),
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use drogue_cloud_service_api::auth::user::UserDetails;
+    use serde_json::json;
+
+    fn user(roles: Vec<&str>) -> UserInformation {
+        UserInformation::Authenticated(UserDetails {
+            user_id: "foo".into(),
+            roles: roles.into_iter().map(Into::into).collect(),
+        })
+    }
+
+    fn app(targets: Value) -> registry::v1::Application {
+        let mut app = registry::v1::Application::default();
+        app.spec
+            .insert("webhooks".into(), json!({ "targets": targets }));
+        app
+    }
+
+    #[test]
+    fn test_redact() {
+        let targets = json!([
+            {"name": "t1", "url": "http://localhost", "secret": "s3cr3t"},
+            {"name": "t2", "url": "http://localhost"},
+        ]);
+
+        let redacted = redact_app_secrets(&user(vec![]), app(targets.clone()));
+        assert_eq!(
+            redacted.spec["webhooks"]["targets"],
+            json!([
+                {"name": "t1", "url": "http://localhost", "secret": REDACTED},
+                {"name": "t2", "url": "http://localhost"},
+            ])
+        );
+
+        let controller = redact_app_secrets(&user(vec!["drogue-controller"]), app(targets.clone()));
+        assert_eq!(controller.spec["webhooks"]["targets"], targets);
+    }
+
+    #[test]
+    fn test_digest() {
+        let mut spec = app(json!([{"name": "t1", "secret": "s3cr3t"}])).spec;
+        digest_app_secrets(&mut spec);
+
+        let secret = spec["webhooks"]["targets"][0]["secret"].as_str().unwrap();
+        assert!(secret.starts_with("sha256:"));
+        assert!(!secret.contains("s3cr3t"));
+    }
+
+    #[test]
+    fn test_retain() {
+        let current = json!({"spec": {"webhooks": {"targets": [
+            {"name": "t1", "secret": "s1"},
+            {"name": "t2", "secret": "s2"},
+        ]}}});
+        let mut app = json!({"spec": {"webhooks": {"targets": [
+            {"name": "t1", "secret": REDACTED},
+            {"name": "t2", "secret": "new"},
+            {"name": "t3", "secret": REDACTED},
+        ]}}});
+
+        retain_app_secrets(&current, &mut app);
+
+        assert_eq!(
+            app["spec"]["webhooks"]["targets"],
+            json!([
+                {"name": "t1", "secret": "s1"},
+                {"name": "t2", "secret": "new"},
+                {"name": "t3"},
+            ])
+        );
+    }
+}
@@ -0,0 +1,92 @@
+use serde_json::{Map, Value};
+
+/// The status sections of an application, which are derived from its spec.
+pub const APP_DERIVED_STATUS: &[&str] = &["trustAnchors"];
+
+/// The part of a resource an update is targeting.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Subresource {
+    /// The main resource, consisting of the metadata and the spec.
+    Main,
+    /// The status of the resource.
+    Status,
+}
+
+fn section(data: &Value, key: &str) -> Map<String, Value> {
+    data.get(key)
+        .and_then(Value::as_object)
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Set the `keys` of `target` to the values of `source`, removing keys missing in `source`.
+fn overlay(target: &mut Map<String, Value>, source: &Map<String, Value>, keys: &[&str]) {
+    for key in keys {
+        match source.get(*key) {
+            Some(value) => {
+                target.insert(key.to_string(), value.clone());
+            }
+            None => {
+                target.remove(*key);
+            }
+        }
+    }
+}
+
+impl Subresource {
+    /// Restrict the new data of a resource to what this subresource may change, by carrying over
+    /// everything else from the current data.
+    ///
+    /// Status sections listed in `derived` are computed from the spec, and so they are part of
+    /// the main resource.
+    pub fn restrict(&self, current: &Value, new: &mut Value, derived: &[&str]) {
+        let (spec, status) = match self {
+            Self::Main => {
+                let mut status = section(current, "status");
+                overlay(&mut status, &section(new, "status"), derived);
+                (section(new, "spec"), status)
+            }
+            Self::Status => {
+                let mut status = section(new, "status");
+                overlay(&mut status, &section(current, "status"), derived);
+                (section(current, "spec"), status)
+            }
+        };
+
+        let mut data = new.as_object().cloned().unwrap_or_default();
+        data.insert("spec".into(), Value::Object(spec));
+        data.insert("status".into(), Value::Object(status));
+        *new = Value::Object(data);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_restrict() {
+        let current = json!({
+            "spec": {"a": 1},
+            "status": {"b": 2, "trustAnchors": {"c": 3}},
+        });
+
+        let mut new = json!({
+            "spec": {"a": 2},
+            "status": {"b": 3},
+        });
+        Subresource::Main.restrict(&current, &mut new, APP_DERIVED_STATUS);
+        assert_eq!(new, json!({"spec": {"a": 2}, "status": {"b": 2}}));
+
+        let mut new = json!({
+            "spec": {"a": 2},
+            "status": {"b": 3, "trustAnchors": {"c": 4}},
+        });
+        Subresource::Status.restrict(&current, &mut new, APP_DERIVED_STATUS);
+        assert_eq!(
+            new,
+            json!({"spec": {"a": 1}, "status": {"b": 3, "trustAnchors": {"c": 3}}})
+        );
+    }
+}
@@ -0,0 +1,104 @@
+use super::{error::PostgresManagementServiceError, PostgresManagementService};
+use chrono::Utc;
+use deadpool_postgres::Transaction;
+use drogue_cloud_database_common::models::{
+    change_log::{ChangeLogAccessor, PostgresChangeLogAccessor},
+    trash::{PostgresTrashAccessor, TrashAccessor},
+};
+use drogue_cloud_registry_events::EventSender;
+
+impl<S> PostgresManagementService<S>
+where
+    S: EventSender + Clone,
+{
+    /// Move an application to the trash, if the trash is enabled.
+    pub(crate) async fn archive_app(
+        &self,
+        t: &Transaction<'_>,
+        app: &str,
+    ) -> Result<(), PostgresManagementServiceError<S::Error>> {
+        if let Some(retention) = self.trash_retention {
+            PostgresTrashAccessor::new(t)
+                .archive_app(app, Utc::now() + retention)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Move a device to the trash, if the trash is enabled.
+    pub(crate) async fn archive_device(
+        &self,
+        t: &Transaction<'_>,
+        app: &str,
+        device: &str,
+    ) -> Result<(), PostgresManagementServiceError<S::Error>> {
+        if let Some(retention) = self.trash_retention {
+            PostgresTrashAccessor::new(t)
+                .archive_device(app, device, Utc::now() + retention)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Move all devices of an application, which can be deleted directly, to the trash, if the
+    /// trash is enabled.
+    pub(crate) async fn archive_app_devices(
+        &self,
+        t: &Transaction<'_>,
+        app: &str,
+    ) -> Result<(), PostgresManagementServiceError<S::Error>> {
+        if let Some(retention) = self.trash_retention {
+            let count = PostgresTrashAccessor::new(t)
+                .archive_app_devices(app, Utc::now() + retention)
+                .await?;
+            log::debug!("Moved {} devices to the trash", count);
+        }
+
+        Ok(())
+    }
+
+    /// Purge all expired resources from the trash.
+    pub async fn purge_trash(&self) -> Result<u64, PostgresManagementServiceError<S::Error>> {
+        let c = self.pool.get().await?;
+
+        Ok(PostgresTrashAccessor::new(&c).purge(Utc::now()).await?)
+    }
+
+    /// Purge all expired entries from the change log.
+    pub async fn purge_change_log(&self) -> Result<u64, PostgresManagementServiceError<S::Error>> {
+        let c = self.pool.get().await?;
+
+        Ok(PostgresChangeLogAccessor::new(&c)
+            .purge(Utc::now() - self.watch_retention)
+            .await?)
+    }
+
+    /// Run the job, periodically purging expired resources from the trash, the change log, and the
+    /// history.
+    ///
+    /// This will never return.
+    pub async fn run_purger(self) -> anyhow::Result<()> {
+        let mut interval = tokio::time::interval(self.trash_purge_interval);
+
+        loop {
+            interval.tick().await;
+
+            match self.purge_trash().await {
+                Ok(count) => log::debug!("Purged {} resources from the trash", count),
+                Err(err) => log::warn!("Failed to purge the trash: {}", err),
+            }
+
+            match self.purge_change_log().await {
+                Ok(count) => log::debug!("Purged {} entries from the change log", count),
+                Err(err) => log::warn!("Failed to purge the change log: {}", err),
+            }
+
+            match self.purge_history().await {
+                Ok(count) => log::debug!("Purged {} entries from the history", count),
+                Err(err) => log::warn!("Failed to purge the history: {}", err),
+            }
+        }
+    }
+}
@@ -0,0 +1,775 @@
+use super::secrets::redact_app_secrets;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use deadpool_postgres::Pool;
+use drogue_client::{meta, registry};
+use drogue_cloud_database_common::{
+    auth::ensure,
+    error::ServiceError,
+    models::{
+        app::{ApplicationAccessor, PostgresApplicationAccessor},
+        change_log::{ChangeEntry, ChangeLogAccessor, Position, PostgresChangeLogAccessor},
+        device::{DeviceAccessor, PostgresDeviceAccessor},
+        outbox::OUTBOX_CHANNEL,
+        paging::Sort,
+        trash::{PostgresTrashAccessor, TrashAccessor},
+        Lock,
+    },
+};
+use drogue_cloud_service_api::{
+    auth::user::{authz::Permission, UserInformation},
+    fields::FieldSelector,
+    labels::LabelSelector,
+    watch::WatchEvent,
+};
+use futures::{
+    future,
+    stream::{self, BoxStream},
+    StreamExt, TryStreamExt,
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_postgres::{AsyncMessage, NoTls};
+
+/// The number of changes buffered for each watcher, before it has to re-sync.
+const CAPACITY: usize = 1024;
+/// The time to wait before re-connecting a failed listener.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// A change, reported by the outbox listener.
+#[derive(Clone, Debug)]
+pub enum Change {
+    /// A change log entry was written.
+    Entry(ChangeEntry),
+    /// Changes might have been missed, and watchers need to re-sync.
+    Resync,
+}
+
+/// Listening for notifications of new outbox entries.
+///
+/// The listener connects to the database when the first watcher subscribes.
+#[derive(Clone)]
+pub struct OutboxListener {
+    config: tokio_postgres::Config,
+    sender: Arc<Mutex<Option<broadcast::Sender<Change>>>>,
+}
+
+impl OutboxListener {
+    pub fn new(config: tokio_postgres::Config) -> Self {
+        Self {
+            config,
+            sender: Default::default(),
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Change> {
+        let mut sender = self.sender.lock().unwrap();
+
+        if let Some(sender) = sender.as_ref() {
+            return sender.subscribe();
+        }
+
+        let (tx, rx) = broadcast::channel(CAPACITY);
+        tokio::spawn(Self::run(self.config.clone(), tx.clone()));
+        *sender = Some(tx);
+
+        rx
+    }
+
+    async fn run(config: tokio_postgres::Config, tx: broadcast::Sender<Change>) {
+        loop {
+            if let Err(err) = Self::listen(&config, &tx).await {
+                log::warn!("Outbox listener failed: {}", err);
+            }
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    }
+
+    async fn listen(
+        config: &tokio_postgres::Config,
+        tx: &broadcast::Sender<Change>,
+    ) -> Result<(), tokio_postgres::Error> {
+        let (client, mut connection) = config.connect(NoTls).await?;
+
+        let messages = async {
+            let mut messages = stream::poll_fn(move |cx| connection.poll_message(cx));
+            while let Some(message) = messages.next().await {
+                if let AsyncMessage::Notification(notification) = message? {
+                    match serde_json::from_str(notification.payload()) {
+                        Ok(entry) => {
+                            let _ = tx.send(Change::Entry(entry));
+                        }
+                        Err(err) => log::info!("Invalid outbox notification: {}", err),
+                    }
+                }
+            }
+            Ok::<_, tokio_postgres::Error>(())
+        };
+
+        let listen = async {
+            client
+                .batch_execute(&format!("LISTEN {}", OUTBOX_CHANNEL))
+                .await?;
+            log::info!("Listening for outbox notifications");
+            // we might have missed changes while not listening
+            let _ = tx.send(Change::Resync);
+            Ok::<_, tokio_postgres::Error>(())
+        };
+
+        futures::try_join!(listen, messages)?;
+
+        Ok(())
+    }
+}
+
+/// The object affected by a change.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Affected {
+    /// A single object, by name.
+    Object(String),
+    /// The access to all watched objects might have changed.
+    Access,
+}
+
+/// The state of an affected object.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum State<T> {
+    /// The object exists and is watched.
+    Present(T),
+    /// The object doesn't exist, or is no longer watched.
+    Absent,
+    /// The object must not be reported to the watcher.
+    Hidden,
+}
+
+/// A source of watched objects.
+#[async_trait]
+pub trait WatchSource: Send + Sync + 'static {
+    type Item: Clone + Send + Sync + 'static;
+
+    /// Check which objects are affected by a change.
+    fn affects(&self, entry: &ChangeEntry) -> Option<Affected>;
+
+    /// Check if the watcher (still) has access to the watched objects.
+    async fn has_access(&self) -> Result<bool, ServiceError>;
+
+    /// Get the state of an object.
+    async fn get(&self, name: &str) -> Result<State<Self::Item>, ServiceError>;
+
+    /// List all watched objects.
+    async fn list(&self) -> Result<Vec<Self::Item>, ServiceError>;
+
+    /// Create the object reported for a deleted object, only carrying its identity.
+    fn deleted(&self, name: &str) -> Self::Item;
+
+    fn creation_timestamp(item: &Self::Item) -> DateTime<Utc>;
+}
+
+/// Access to the change log.
+#[async_trait]
+pub trait ChangeLog: Send + Sync + 'static {
+    async fn current(&self) -> Result<Position, ServiceError>;
+
+    async fn position(&self, seq: u64) -> Result<Option<Position>, ServiceError>;
+
+    async fn since(&self, seq: u64) -> Result<Vec<ChangeEntry>, ServiceError>;
+}
+
+/// The change log, stored in the database.
+pub struct PostgresChangeLog {
+    pub pool: Pool,
+}
+
+#[async_trait]
+impl ChangeLog for PostgresChangeLog {
+    async fn current(&self) -> Result<Position, ServiceError> {
+        let c = self.pool.get().await?;
+        PostgresChangeLogAccessor::new(&c).current().await
+    }
+
+    async fn position(&self, seq: u64) -> Result<Option<Position>, ServiceError> {
+        let c = self.pool.get().await?;
+        PostgresChangeLogAccessor::new(&c).position(seq).await
+    }
+
+    async fn since(&self, seq: u64) -> Result<Vec<ChangeEntry>, ServiceError> {
+        let c = self.pool.get().await?;
+        PostgresChangeLogAccessor::new(&c).since(seq).await
+    }
+}
+
+fn gone(seq: u64) -> ServiceError {
+    ServiceError::Gone(format!("Resource version {} is no longer available", seq))
+}
+
+struct Watcher<W: WatchSource, L: ChangeLog> {
+    source: W,
+    log: L,
+    /// The position of the last processed change.
+    position: Position,
+    /// Changes up to this sequence number are already covered by the reported state.
+    start: u64,
+}
+
+impl<W: WatchSource, L: ChangeLog> Watcher<W, L> {
+    /// Evaluate the event of a change.
+    ///
+    /// Objects created after the position `since` are reported as "added".
+    async fn event(
+        &self,
+        entry: &ChangeEntry,
+        since: &Position,
+    ) -> Result<Option<WatchEvent<W::Item>>, ServiceError> {
+        let event = match self.source.affects(entry) {
+            None => return Ok(None),
+            Some(Affected::Access) => match self.source.has_access().await? {
+                true => return Ok(None),
+                // end the watch, the same way as starting a watch without access
+                false => return Err(ServiceError::NotFound),
+            },
+            Some(Affected::Object(name)) => match self.source.get(&name).await? {
+                State::Present(item) if W::creation_timestamp(&item) > since.timestamp => {
+                    WatchEvent::added(item)
+                }
+                State::Present(item) => WatchEvent::modified(item),
+                State::Absent => WatchEvent::deleted(self.source.deleted(&name)),
+                State::Hidden => return Ok(None),
+            },
+        };
+
+        Ok(Some(event.with_resource_version(entry.seq)))
+    }
+
+    /// Report the current state of all objects, at the current position.
+    async fn list(&self) -> Result<Vec<WatchEvent<W::Item>>, ServiceError> {
+        let seq = self.position.seq;
+        Ok(self
+            .source
+            .list()
+            .await?
+            .into_iter()
+            .map(|item| WatchEvent::added(item).with_resource_version(seq))
+            .collect())
+    }
+
+    /// Process a single change.
+    async fn apply(
+        &mut self,
+        entry: ChangeEntry,
+    ) -> Result<Vec<WatchEvent<W::Item>>, ServiceError> {
+        if entry.seq <= self.start {
+            return Ok(vec![]);
+        }
+
+        let since = self.position;
+        if entry.seq > self.position.seq {
+            self.position = entry.position();
+        }
+
+        Ok(self.event(&entry, &since).await?.into_iter().collect())
+    }
+
+    /// Replay all changes after the current position.
+    ///
+    /// Fails with [`ServiceError::Gone`] if the position is no longer part of the change log.
+    async fn replay(&mut self) -> Result<Vec<WatchEvent<W::Item>>, ServiceError> {
+        let since = self.position;
+        if self.log.position(since.seq).await?.is_none() {
+            return Err(gone(since.seq));
+        }
+
+        let entries = self.log.since(since.seq).await?;
+        if let Some(last) = entries.last() {
+            self.position = last.position();
+        }
+        self.start = self.position.seq;
+
+        // only report the latest change of every object
+        let mut latest = HashMap::new();
+        for entry in &entries {
+            latest.insert((&entry.app, &entry.device), entry.seq);
+        }
+
+        let mut events = Vec::new();
+        for entry in entries
+            .iter()
+            .filter(|entry| latest.get(&(&entry.app, &entry.device)) == Some(&entry.seq))
+        {
+            events.extend(self.event(entry, &since).await?);
+        }
+
+        Ok(events)
+    }
+}
+
+fn matches(selector: &LabelSelector, labels: &HashMap<String, String>) -> bool {
+    selector.matches(|label| labels.get(label).map(String::as_str))
+}
+
+/// Watching the applications a user has access to.
+pub struct ApplicationSource {
+    pub pool: Pool,
+    pub identity: UserInformation,
+    pub labels: LabelSelector,
+    pub fields: FieldSelector,
+}
+
+#[async_trait]
+impl WatchSource for ApplicationSource {
+    type Item = registry::v1::Application;
+
+    fn affects(&self, entry: &ChangeEntry) -> Option<Affected> {
+        match entry.device {
+            None => Some(Affected::Object(entry.app.clone())),
+            Some(_) => None,
+        }
+    }
+
+    async fn has_access(&self) -> Result<bool, ServiceError> {
+        // access is checked per application
+        Ok(true)
+    }
+
+    async fn get(&self, name: &str) -> Result<State<Self::Item>, ServiceError> {
+        let c = self.pool.get().await?;
+
+        let app = match PostgresApplicationAccessor::new(&c)
+            .get(name, Lock::None)
+            .await?
+        {
+            Some(app) => app,
+            None => {
+                // only report deleted applications the user had access to, which can't be
+                // proven anymore once the application was purged from the trash
+                return Ok(
+                    match PostgresTrashAccessor::new(&c)
+                        .get_app(name, Lock::None)
+                        .await?
+                    {
+                        Some(app) if ensure(&app, &self.identity, Permission::Read).is_ok() => {
+                            State::Absent
+                        }
+                        _ => State::Hidden,
+                    },
+                );
+            }
+        };
+
+        if ensure(&app, &self.identity, Permission::Read).is_err() {
+            return Ok(State::Hidden);
+        }
+
+        if matches(&self.labels, &app.labels) && self.fields.matches(&app.data) {
+            Ok(State::Present(redact_app_secrets(
+                &self.identity,
+                app.into(),
+            )))
+        } else {
+            Ok(State::Absent)
+        }
+    }
+
+    async fn list(&self) -> Result<Vec<Self::Item>, ServiceError> {
+        let c = self.pool.get().await?;
+
+        PostgresApplicationAccessor::new(&c)
+            .list(
+                None,
+                self.labels.clone(),
+                self.fields.clone(),
+                None,
+                None,
+                Some(&self.identity),
+                Lock::None,
+                &Sort::default(),
+                None,
+            )
+            .await?
+            .try_filter(|app| future::ready(ensure(app, &self.identity, Permission::Read).is_ok()))
+            .map_ok(|app| redact_app_secrets(&self.identity, app.into()))
+            .try_collect()
+            .await
+    }
+
+    fn deleted(&self, name: &str) -> Self::Item {
+        registry::v1::Application {
+            metadata: meta::v1::NonScopedMetadata {
+                name: name.into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn creation_timestamp(item: &Self::Item) -> DateTime<Utc> {
+        item.metadata.creation_timestamp
+    }
+}
+
+/// Watching the devices of an application.
+pub struct DeviceSource {
+    pub pool: Pool,
+    pub identity: UserInformation,
+    pub app: String,
+    pub labels: LabelSelector,
+    pub fields: FieldSelector,
+}
+
+#[async_trait]
+impl WatchSource for DeviceSource {
+    type Item = registry::v1::Device;
+
+    fn affects(&self, entry: &ChangeEntry) -> Option<Affected> {
+        if entry.app != self.app {
+            return None;
+        }
+        match &entry.device {
+            Some(device) => Some(Affected::Object(device.clone())),
+            // changing the application might change the access to all devices
+            None => Some(Affected::Access),
+        }
+    }
+
+    async fn has_access(&self) -> Result<bool, ServiceError> {
+        let c = self.pool.get().await?;
+
+        Ok(PostgresApplicationAccessor::new(&c)
+            .get(&self.app, Lock::None)
+            .await?
+            .map(|app| ensure(&app, &self.identity, Permission::Read).is_ok())
+            .unwrap_or_default())
+    }
+
+    async fn get(&self, name: &str) -> Result<State<Self::Item>, ServiceError> {
+        let c = self.pool.get().await?;
+
+        let device = PostgresDeviceAccessor::new(&c)
+            .get(&self.app, name, Lock::None)
+            .await?
+            .filter(|device| matches(&self.labels, &device.labels))
+            .filter(|device| self.fields.matches(&device.data));
+
+        Ok(match device {
+            Some(device) => State::Present(device.into()),
+            None => State::Absent,
+        })
+    }
+
+    async fn list(&self) -> Result<Vec<Self::Item>, ServiceError> {
+        let c = self.pool.get().await?;
+
+        PostgresDeviceAccessor::new(&c)
+            .list(
+                &self.app,
+                None,
+                self.labels.clone(),
+                self.fields.clone(),
+                None,
+                None,
+                Lock::None,
+                &Sort::default(),
+                None,
+            )
+            .await?
+            .map_ok(Into::into)
+            .try_collect()
+            .await
+    }
+
+    fn deleted(&self, name: &str) -> Self::Item {
+        registry::v1::Device {
+            metadata: meta::v1::ScopedMetadata {
+                application: self.app.clone(),
+                name: name.into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn creation_timestamp(item: &Self::Item) -> DateTime<Utc> {
+        item.metadata.creation_timestamp
+    }
+}
+
+/// Watch the objects of a source.
+///
+/// Without a resource version, the stream starts with the current state of all objects, reported
+/// as "added", followed by the changes. With a resource version, the stream starts with the
+/// changes after that version, and fails with [`ServiceError::Gone`] if the version is no longer
+/// part of the change log.
+///
+/// Every event carries the resource version to resume the watch from. Deleted objects only carry
+/// their identity.
+pub async fn watch<W: WatchSource, L: ChangeLog>(
+    source: W,
+    log: L,
+    changes: broadcast::Receiver<Change>,
+    resource_version: Option<u64>,
+) -> Result<BoxStream<'static, Result<WatchEvent<W::Item>, ServiceError>>, ServiceError> {
+    let position = match resource_version {
+        Some(seq) => log.position(seq).await?.ok_or_else(|| gone(seq))?,
+        None => log.current().await?,
+    };
+
+    let mut watcher = Watcher {
+        source,
+        log,
+        position,
+        start: position.seq,
+    };
+
+    // evaluate the initial state eagerly, reporting errors before the stream starts
+    let initial = match resource_version {
+        Some(_) => watcher.replay().await?,
+        None => watcher.list().await?,
+    };
+
+    let state = (watcher, Some(changes));
+
+    let changes = stream::unfold(state, |(mut watcher, mut changes)| async move {
+        loop {
+            let change = match changes.as_mut() {
+                Some(receiver) => match receiver.recv().await {
+                    Ok(change) => change,
+                    Err(RecvError::Lagged(n)) => {
+                        log::debug!("Watcher lagged by {} changes", n);
+                        Change::Resync
+                    }
+                    Err(RecvError::Closed) => return None,
+                },
+                // the stream failed before
+                None => return None,
+            };
+
+            let result = match change {
+                Change::Resync => watcher.replay().await,
+                Change::Entry(entry) => watcher.apply(entry).await,
+            };
+
+            match result {
+                Ok(events) if events.is_empty() => continue,
+                Ok(events) => return Some((Ok(events), (watcher, changes))),
+                Err(err) => {
+                    log::info!("Failed to process change: {}", err);
+                    // end the stream after reporting the error
+                    return Some((Err(err), (watcher, None)));
+                }
+            }
+        }
+    })
+    .map_ok(|events| stream::iter(events.into_iter().map(Ok)))
+    .try_flatten();
+
+    Ok(stream::iter(initial.into_iter().map(Ok))
+        .chain(changes)
+        .boxed())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    struct Item(String, String, DateTime<Utc>);
+
+    fn item(name: &str, version: &str, created: i64) -> Item {
+        Item(name.into(), version.into(), Utc.timestamp(created, 0))
+    }
+
+    #[derive(Default)]
+    struct Mock {
+        objects: HashMap<String, Item>,
+        hidden: Vec<String>,
+        /// The entries of the change log, starting with the oldest known position.
+        log: Vec<ChangeEntry>,
+    }
+
+    impl Mock {
+        /// Append an entry to the log, using the sequence number as timestamp.
+        fn change(&mut self, name: &str) -> Change {
+            let seq = self.log.last().map(|entry| entry.seq + 1).unwrap_or(1);
+            let entry = ChangeEntry {
+                seq,
+                timestamp: Utc.timestamp(seq as i64, 0),
+                app: name.into(),
+                device: None,
+            };
+            self.log.push(entry.clone());
+            Change::Entry(entry)
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct MockSource(Arc<Mutex<Mock>>);
+
+    #[async_trait]
+    impl WatchSource for MockSource {
+        type Item = Item;
+
+        fn affects(&self, entry: &ChangeEntry) -> Option<Affected> {
+            Some(Affected::Object(entry.app.clone()))
+        }
+
+        async fn has_access(&self) -> Result<bool, ServiceError> {
+            Ok(true)
+        }
+
+        async fn get(&self, name: &str) -> Result<State<Self::Item>, ServiceError> {
+            let mock = self.0.lock().unwrap();
+            if mock.hidden.iter().any(|hidden| hidden == name) {
+                return Ok(State::Hidden);
+            }
+            Ok(match mock.objects.get(name) {
+                Some(item) => State::Present(item.clone()),
+                None => State::Absent,
+            })
+        }
+
+        async fn list(&self) -> Result<Vec<Self::Item>, ServiceError> {
+            Ok(self.0.lock().unwrap().objects.values().cloned().collect())
+        }
+
+        fn deleted(&self, name: &str) -> Self::Item {
+            item(name, "", 0)
+        }
+
+        fn creation_timestamp(item: &Self::Item) -> DateTime<Utc> {
+            item.2
+        }
+    }
+
+    #[async_trait]
+    impl ChangeLog for MockSource {
+        async fn current(&self) -> Result<Position, ServiceError> {
+            Ok(self.0.lock().unwrap().log.last().unwrap().position())
+        }
+
+        async fn position(&self, seq: u64) -> Result<Option<Position>, ServiceError> {
+            Ok(self
+                .0
+                .lock()
+                .unwrap()
+                .log
+                .iter()
+                .find(|entry| entry.seq == seq)
+                .map(ChangeEntry::position))
+        }
+
+        async fn since(&self, seq: u64) -> Result<Vec<ChangeEntry>, ServiceError> {
+            Ok(self
+                .0
+                .lock()
+                .unwrap()
+                .log
+                .iter()
+                .filter(|entry| entry.seq > seq)
+                .cloned()
+                .collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_watch() {
+        let mock = MockSource::default();
+        {
+            let mut m = mock.0.lock().unwrap();
+            m.objects.insert("a".into(), item("a", "1", 0));
+            m.change("a");
+        }
+
+        let (tx, rx) = broadcast::channel(16);
+        let mut events = watch(mock.clone(), mock.clone(), rx, None).await.unwrap();
+
+        // initial state
+
+        let event = events.next().await.unwrap().unwrap();
+        assert_eq!(
+            event,
+            WatchEvent::added(item("a", "1", 0)).with_resource_version(1)
+        );
+
+        // modify, add, hide and delete
+
+        {
+            let mut m = mock.0.lock().unwrap();
+            m.objects.insert("a".into(), item("a", "2", 0));
+            tx.send(m.change("a")).unwrap();
+            m.objects.insert("b".into(), item("b", "1", 3));
+            tx.send(m.change("b")).unwrap();
+            m.hidden.push("c".into());
+            tx.send(m.change("c")).unwrap();
+            m.objects.remove("a");
+            tx.send(m.change("a")).unwrap();
+        }
+
+        let event = events.next().await.unwrap().unwrap();
+        assert_eq!(
+            event,
+            WatchEvent::modified(item("a", "2", 0)).with_resource_version(2)
+        );
+        let event = events.next().await.unwrap().unwrap();
+        assert_eq!(
+            event,
+            WatchEvent::added(item("b", "1", 3)).with_resource_version(3)
+        );
+        let event = events.next().await.unwrap().unwrap();
+        assert_eq!(
+            event,
+            WatchEvent::deleted(item("a", "", 0)).with_resource_version(5)
+        );
+
+        // closing the channel ends the stream
+
+        drop(tx);
+        assert!(events.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_resume() {
+        let mock = MockSource::default();
+        {
+            let mut m = mock.0.lock().unwrap();
+            m.objects.insert("a".into(), item("a", "1", 1));
+            m.change("a");
+            m.objects.insert("b".into(), item("b", "1", 2));
+            m.change("b");
+            m.objects.insert("a".into(), item("a", "2", 1));
+            m.change("a");
+            m.objects.insert("a".into(), item("a", "3", 1));
+            m.change("a");
+        }
+
+        let (_tx, rx) = broadcast::channel(16);
+        let mut events = watch(mock.clone(), mock.clone(), rx, Some(1))
+            .await
+            .unwrap();
+
+        // only the latest change of each object is replayed
+
+        let event = events.next().await.unwrap().unwrap();
+        assert_eq!(
+            event,
+            WatchEvent::added(item("b", "1", 2)).with_resource_version(2)
+        );
+        let event = events.next().await.unwrap().unwrap();
+        assert_eq!(
+            event,
+            WatchEvent::modified(item("a", "3", 1)).with_resource_version(4)
+        );
+
+        // an expired version is gone
+
+        mock.0.lock().unwrap().log.remove(0);
+        let (_tx, rx) = broadcast::channel(16);
+        assert!(matches!(
+            watch(mock.clone(), mock.clone(), rx, Some(1)).await,
+            Err(ServiceError::Gone(_))
+        ));
+    }
+}
@@ -12,6 +12,7 @@ use drogue_cloud_device_management_service::{
     WebData,
 };
 use drogue_cloud_registry_events::{mock::MockEventSender, Event};
+use drogue_cloud_service_api::auth::user::{UserDetails, UserInformation};
 use drogue_cloud_test_common::{client, db};
 use http::{header, HeaderValue};
 use maplit::hashmap;
@@ -838,6 +839,61 @@ async fn test_search_app_limits() -> anyhow::Result<()> {
     })
 }
 
+#[actix_rt::test]
+#[serial]
+async fn test_search_app_continue() -> anyhow::Result<()> {
+    test!((app, _sender, _outbox) => {
+
+        let foo = user("foo");
+
+        for i in 0..5 {
+            create_app(&app, &foo, format!("app-{}", i), HashMap::new()).await?;
+        }
+
+        // first page, with the total count
+
+        let resp = call_http(&app, &foo, test::TestRequest::get().uri("/api/registry/v1alpha1/apps?limit=2&count=true")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers().get("X-Total-Count"), Some(&HeaderValue::from_static("5")));
+        let token = resp.headers().get("X-Continue").expect("Must have a continuation token").to_str()?.to_string();
+        let result: serde_json::Value = test::read_body_json(resp).await;
+        assert_resources(result, &["app-0", "app-1"]);
+
+        // second page
+
+        let resp = call_http(&app, &foo, test::TestRequest::get().uri(&format!("/api/registry/v1alpha1/apps?limit=2&continue={}", token))).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let token = resp.headers().get("X-Continue").expect("Must have a continuation token").to_str()?.to_string();
+        let result: serde_json::Value = test::read_body_json(resp).await;
+        assert_resources(result, &["app-2", "app-3"]);
+
+        // last page
+
+        let resp = call_http(&app, &foo, test::TestRequest::get().uri(&format!("/api/registry/v1alpha1/apps?limit=2&continue={}", token))).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(resp.headers().get("X-Continue").is_none());
+        let result: serde_json::Value = test::read_body_json(resp).await;
+        assert_resources(result, &["app-4"]);
+
+        // the token doesn't work with a different sort order
+
+        let resp = call_http(&app, &foo, test::TestRequest::get().uri(&format!("/api/registry/v1alpha1/apps?limit=2&sort=-name&continue={}", token))).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        // descending
+
+        let resp = call_http(&app, &foo, test::TestRequest::get().uri("/api/registry/v1alpha1/apps?limit=2&sort=-creationTimestamp")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let result: serde_json::Value = test::read_body_json(resp).await;
+        assert_resources(result, &["app-4", "app-3"]);
+
+        // unknown sort field
+
+        let resp = call_http(&app, &foo, test::TestRequest::get().uri("/api/registry/v1alpha1/apps?sort=foo")).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    })
+}
+
 #[actix_rt::test]
 #[serial]
 async fn test_search_app_auth() -> anyhow::Result<()> {
@@ -873,3 +929,51 @@ async fn test_search_app_auth() -> anyhow::Result<()> {
 
     })
 }
+
+#[actix_rt::test]
+#[serial]
+async fn test_webhook_secrets_write_only() -> anyhow::Result<()> {
+    test!((app, sender, outbox) => {
+        let foo = user("foo");
+        let controller = UserInformation::Authenticated(UserDetails {
+            user_id: "controller".into(),
+            roles: vec!["drogue-controller".into()],
+        });
+
+        let resp = call_http(&app, &foo, test::TestRequest::post().uri("/api/registry/v1alpha1/apps").set_json(&json!({
+            "metadata": { "name": "app1" },
+            "spec": { "webhooks": { "targets": [
+                { "name": "t1", "url": "http://localhost", "secret": "s3cr3t" },
+            ]}},
+        }))).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+
+        // users only get the placeholder
+
+        let resp = call_http(&app, &foo, test::TestRequest::get().uri("/api/registry/v1alpha1/apps/app1")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let mut result: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(result["spec"]["webhooks"]["targets"][0]["secret"], "********");
+
+        let resp = call_http(&app, &foo, test::TestRequest::get().uri("/api/registry/v1alpha1/apps")).await;
+        let list: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(list[0]["spec"]["webhooks"]["targets"][0]["secret"], "********");
+
+        // writing back the placeholder keeps the secret
+
+        result["spec"]["webhooks"]["targets"][0]["url"] = json!("http://localhost:8080");
+        let resp = call_http(&app, &foo, test::TestRequest::put().uri("/api/registry/v1alpha1/apps/app1").set_json(&result)).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+        // controllers get the secret
+
+        let resp = call_http(&app, &controller, test::TestRequest::get().uri("/api/registry/v1alpha1/apps/app1")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let result: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(result["spec"]["webhooks"]["targets"][0], json!({
+            "name": "t1",
+            "url": "http://localhost:8080",
+            "secret": "s3cr3t",
+        }));
+    })
+}
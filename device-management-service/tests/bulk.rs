@@ -0,0 +1,175 @@
+mod common;
+
+use crate::common::{
+    assert_events, assert_resources, call_http, create_app, create_device, init, outbox_retrieve,
+    user,
+};
+use actix_cors::Cors;
+use actix_web::{
+    http::{header, StatusCode},
+    middleware::Condition,
+    test, web, App,
+};
+use drogue_cloud_admin_service::apps;
+use drogue_cloud_device_management_service::{
+    app, endpoints,
+    service::{self},
+    WebData,
+};
+use drogue_cloud_registry_events::{mock::MockEventSender, Event};
+use drogue_cloud_test_common::{client, db};
+use serde_json::{json, Value};
+use serial_test::serial;
+
+fn device_event(device: &str) -> Event {
+    Event::Device {
+        instance: "drogue-instance".into(),
+        application: "app1".into(),
+        device: device.into(),
+        uid: "".into(),
+        path: ".".into(),
+        generation: 0,
+    }
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_import_devices() -> anyhow::Result<()> {
+    test!((app, sender, outbox) => {
+        let foo = user("foo");
+
+        create_app(&app, &foo, "app1", Default::default()).await?;
+        create_device(&app, &foo, "app1", "device2", Default::default()).await?;
+
+        sender.reset()?;
+        outbox_retrieve(&outbox).await?;
+
+        let body = r#"{"metadata": {"name": "device1"}}
+{"metadata": {"name": "device2"}}
+{"metadata": {"name": "device3", "application": "app1"}, "spec": {"foo": "bar"}}
+"#;
+
+        // a single transaction rolls back everything
+
+        let resp = call_http(&app, &foo, test::TestRequest::post().uri("/api/registry/v1alpha1/apps/app1/import")
+            .insert_header((header::CONTENT_TYPE, "application/x-ndjson"))
+            .set_payload(body)).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let result: Value = test::read_body_json(resp).await;
+        assert_eq!(result[0]["outcome"], "rolledBack");
+        assert_eq!(result[1]["outcome"], "failed");
+        assert_eq!(result[1]["error"]["error"], "Conflict");
+        assert_eq!(result[2]["outcome"], "rolledBack");
+
+        assert_events(vec![sender.retrieve()?, outbox_retrieve(&outbox).await?], vec![]);
+
+        // chunks skip failed devices
+
+        let resp = call_http(&app, &foo, test::TestRequest::post().uri("/api/registry/v1alpha1/apps/app1/import?chunkSize=2")
+            .insert_header((header::CONTENT_TYPE, "application/x-ndjson"))
+            .set_payload(body)).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let result: Value = test::read_body_json(resp).await;
+        assert_eq!(result, json!([
+            {"name": "device1", "outcome": "created"},
+            {"name": "device2", "outcome": "failed", "error": {"error": "Conflict", "message": "Conflict"}},
+            {"name": "device3", "outcome": "created"},
+        ]));
+
+        assert_events(vec![sender.retrieve()?, outbox_retrieve(&outbox).await?], vec![device_event("device1"), device_event("device3")]);
+
+        let resp = call_http(&app, &foo, test::TestRequest::get().uri("/api/registry/v1alpha1/apps/app1/devices")).await;
+        let result: Value = test::read_body_json(resp).await;
+        assert_resources(result, &["device1", "device2", "device3"]);
+
+        // CSV
+
+        let resp = call_http(&app, &foo, test::TestRequest::post().uri("/api/registry/v1alpha1/apps/app1/import")
+            .insert_header((header::CONTENT_TYPE, "text/csv"))
+            .set_payload("name,labels.floor\ndevice4,1\n")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let result: Value = test::read_body_json(resp).await;
+        assert_eq!(result, json!([{"name": "device4", "outcome": "created"}]));
+
+        let resp = call_http(&app, &foo, test::TestRequest::get().uri("/api/registry/v1alpha1/apps/app1/devices/device4")).await;
+        let result: Value = test::read_body_json(resp).await;
+        assert_eq!(result["metadata"]["labels"], json!({"floor": "1"}));
+
+        // invalid content
+
+        let resp = call_http(&app, &foo, test::TestRequest::post().uri("/api/registry/v1alpha1/apps/app1/import")
+            .insert_header((header::CONTENT_TYPE, "application/x-ndjson"))
+            .set_payload("foo")).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        // chunks report invalid rows, instead of failing the request
+
+        let resp = call_http(&app, &foo, test::TestRequest::post().uri("/api/registry/v1alpha1/apps/app1/import?chunkSize=2")
+            .insert_header((header::CONTENT_TYPE, "text/csv"))
+            .set_payload("name,spec\ndevice5,foo\ndevice6,\n")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let result: Value = test::read_body_json(resp).await;
+        assert_eq!(result[0]["name"], "device5");
+        assert_eq!(result[0]["outcome"], "failed");
+        assert_eq!(result[0]["error"]["error"], "BadRequest");
+        assert_eq!(result[1], json!({"name": "device6", "outcome": "created"}));
+
+        let resp = call_http(&app, &foo, test::TestRequest::post().uri("/api/registry/v1alpha1/apps/app1/import")
+            .insert_header((header::CONTENT_TYPE, "text/plain"))
+            .set_payload("foo")).await;
+        assert_eq!(resp.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        // other users must not import
+
+        let resp = call_http(&app, &user("bar"), test::TestRequest::post().uri("/api/registry/v1alpha1/apps/app1/import")
+            .insert_header((header::CONTENT_TYPE, "application/x-ndjson"))
+            .set_payload(r#"{"metadata": {"name": "device7"}}"#)).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    })
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_export_devices() -> anyhow::Result<()> {
+    test!((app, _sender, _outbox) => {
+        let foo = user("foo");
+
+        create_app(&app, &foo, "app1", Default::default()).await?;
+
+        let resp = call_http(&app, &foo, test::TestRequest::post().uri("/api/registry/v1alpha1/apps/app1/devices").set_json(&json!({
+            "metadata": { "application": "app1", "name": "device1" },
+            "spec": {
+                "credentials": { "credentials": [ { "pass": "foo" } ] },
+                "foo": "bar",
+            },
+        }))).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+
+        let export = |body: actix_web::web::Bytes| -> Vec<Value> {
+            String::from_utf8(body.to_vec())
+                .unwrap()
+                .lines()
+                .map(|line| serde_json::from_str(line).unwrap())
+                .collect()
+        };
+
+        // credentials are redacted by default
+
+        let resp = call_http(&app, &foo, test::TestRequest::get().uri("/api/registry/v1alpha1/apps/app1/export")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let devices = export(test::read_body(resp).await);
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0]["metadata"]["name"], "device1");
+        assert_eq!(devices[0]["spec"], json!({"foo": "bar"}));
+
+        let resp = call_http(&app, &foo, test::TestRequest::get().uri("/api/registry/v1alpha1/apps/app1/export?credentials=true")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let devices = export(test::read_body(resp).await);
+        assert_eq!(devices[0]["spec"]["credentials"], json!({"credentials": [{"pass": "foo"}]}));
+
+        // other users must not export
+
+        let resp = call_http(&app, &user("bar"), test::TestRequest::get().uri("/api/registry/v1alpha1/apps/app1/export")).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    })
+}
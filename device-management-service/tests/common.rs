@@ -33,6 +33,10 @@ macro_rules! test {
         let db = db(&cli, |pg| service::PostgresManagementServiceConfig {
             pg,
             instance: "drogue-instance".to_string(),
+            trash_retention: std::time::Duration::from_secs(60 * 60),
+            trash_purge_interval: std::time::Duration::from_secs(60 * 60),
+            watch_retention: std::time::Duration::from_secs(60 * 60),
+            history_retention: std::time::Duration::from_secs(60 * 60),
         })?;
 
         let sender = MockEventSender::new();
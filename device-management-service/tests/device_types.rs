@@ -0,0 +1,183 @@
+mod common;
+
+use crate::common::{assert_events, call_http, create_app, init, outbox_retrieve, user};
+use actix_cors::Cors;
+use actix_web::{http::StatusCode, middleware::Condition, test, web, App};
+use drogue_cloud_admin_service::apps;
+use drogue_cloud_device_management_service::{
+    app, endpoints,
+    service::{self},
+    WebData,
+};
+use drogue_cloud_registry_events::{mock::MockEventSender, Event};
+use drogue_cloud_test_common::{client, db};
+use http::{header, HeaderValue};
+use serde_json::{json, Value};
+use serial_test::serial;
+
+fn device_type_event() -> Event {
+    Event::Application {
+        instance: "drogue-instance".into(),
+        application: "app1".into(),
+        uid: "".into(),
+        path: ".deviceTypes.sensor".into(),
+        generation: 0,
+    }
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_crud_device_types() -> anyhow::Result<()> {
+    test!((app, sender, outbox) => {
+        let foo = user("foo");
+
+        create_app(&app, &foo, "app1", Default::default()).await?;
+
+        sender.reset()?;
+        outbox_retrieve(&outbox).await?;
+
+        // create
+
+        let resp = call_http(&app, &foo, test::TestRequest::post().uri("/api/registry/v1alpha1/apps/app1/deviceTypes").set_json(&json!({
+            "metadata": { "application": "app1", "name": "sensor" },
+            "spec": { "foo": "bar" },
+        }))).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        assert_eq!(resp.headers().get(header::LOCATION), Some(&HeaderValue::from_static("http://localhost:8080/api/registry/v1alpha1/apps/app1/deviceTypes/sensor")));
+
+        assert_events(vec![sender.retrieve()?, outbox_retrieve(&outbox).await?], vec![device_type_event()]);
+
+        // must not create twice
+
+        let resp = call_http(&app, &foo, test::TestRequest::post().uri("/api/registry/v1alpha1/apps/app1/deviceTypes").set_json(&json!({
+            "metadata": { "application": "app1", "name": "sensor" },
+        }))).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+
+        // read and list
+
+        let resp = call_http(&app, &foo, test::TestRequest::get().uri("/api/registry/v1alpha1/apps/app1/deviceTypes/sensor")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let result: Value = test::read_body_json(resp).await;
+        assert_eq!(result["metadata"]["name"], "sensor");
+        assert_eq!(result["spec"], json!({"foo": "bar"}));
+
+        let resp = call_http(&app, &foo, test::TestRequest::get().uri("/api/registry/v1alpha1/apps/app1/deviceTypes")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let result: Value = test::read_body_json(resp).await;
+        assert_eq!(result.as_array().map(Vec::len), Some(1));
+
+        // update
+
+        let resp = call_http(&app, &foo, test::TestRequest::put().uri("/api/registry/v1alpha1/apps/app1/deviceTypes/sensor").set_json(&json!({
+            "metadata": { "application": "app1", "name": "sensor" },
+            "spec": { "foo": "baz" },
+        }))).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+        assert_events(vec![sender.retrieve()?, outbox_retrieve(&outbox).await?], vec![device_type_event()]);
+
+        let resp = call_http(&app, &foo, test::TestRequest::get().uri("/api/registry/v1alpha1/apps/app1/deviceTypes/sensor")).await;
+        let result: Value = test::read_body_json(resp).await;
+        assert_eq!(result["spec"], json!({"foo": "baz"}));
+        assert_eq!(result["metadata"]["generation"], 1);
+
+        // other users must not see it
+
+        let resp = call_http(&app, &user("bar"), test::TestRequest::get().uri("/api/registry/v1alpha1/apps/app1/deviceTypes/sensor")).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        // delete
+
+        let resp = call_http(&app, &foo, test::TestRequest::delete().uri("/api/registry/v1alpha1/apps/app1/deviceTypes/sensor")).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+        assert_events(vec![sender.retrieve()?, outbox_retrieve(&outbox).await?], vec![device_type_event()]);
+
+        let resp = call_http(&app, &foo, test::TestRequest::get().uri("/api/registry/v1alpha1/apps/app1/deviceTypes/sensor")).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    })
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_device_inherits_type() -> anyhow::Result<()> {
+    test!((app, sender, _outbox) => {
+        let foo = user("foo");
+
+        create_app(&app, &foo, "app1", Default::default()).await?;
+
+        // the type must exist
+
+        let resp = call_http(&app, &foo, test::TestRequest::post().uri("/api/registry/v1alpha1/apps/app1/devices").set_json(&json!({
+            "metadata": { "application": "app1", "name": "device1" },
+            "spec": { "deviceType": "sensor" },
+        }))).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        let resp = call_http(&app, &foo, test::TestRequest::post().uri("/api/registry/v1alpha1/apps/app1/deviceTypes").set_json(&json!({
+            "metadata": { "application": "app1", "name": "sensor" },
+            "spec": {
+                "credentials": { "credentials": [ { "pass": "foo" } ] },
+                "lorawan": { "port": 1, "schema": "sensor" },
+            },
+        }))).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+
+        let resp = call_http(&app, &foo, test::TestRequest::post().uri("/api/registry/v1alpha1/apps/app1/devices").set_json(&json!({
+            "metadata": { "application": "app1", "name": "device1" },
+            "spec": { "deviceType": "sensor", "lorawan": { "port": 2 } },
+        }))).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+
+        // the plain device only has its own spec
+
+        let resp = call_http(&app, &foo, test::TestRequest::get().uri("/api/registry/v1alpha1/apps/app1/devices/device1")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let result: Value = test::read_body_json(resp).await;
+        assert_eq!(result["spec"], json!({"deviceType": "sensor", "lorawan": {"port": 2}}));
+
+        // the effective device has the spec of its type merged in
+
+        let resp = call_http(&app, &foo, test::TestRequest::get().uri("/api/registry/v1alpha1/apps/app1/devices/device1?effective=true")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let result: Value = test::read_body_json(resp).await;
+        assert_eq!(result["spec"], json!({
+            "deviceType": "sensor",
+            "credentials": { "credentials": [ { "pass": "foo" } ] },
+            "lorawan": { "port": 2, "schema": "sensor" },
+        }));
+
+        // changing the type changes the effective spec of the device
+
+        sender.reset()?;
+
+        let resp = call_http(&app, &foo, test::TestRequest::put().uri("/api/registry/v1alpha1/apps/app1/deviceTypes/sensor").set_json(&json!({
+            "metadata": { "application": "app1", "name": "sensor" },
+            "spec": {
+                "lorawan": { "port": 1, "schema": "sensor2" },
+            },
+        }))).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+        assert_events(vec![sender.retrieve()?], vec![device_type_event(), Event::Device {
+            instance: "drogue-instance".into(),
+            application: "app1".into(),
+            device: "device1".into(),
+            uid: "".into(),
+            path: ".spec".into(),
+            generation: 0,
+        }]);
+
+        // the type must not be deleted while in use
+
+        let resp = call_http(&app, &foo, test::TestRequest::delete().uri("/api/registry/v1alpha1/apps/app1/deviceTypes/sensor")).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+
+        let resp = call_http(&app, &foo, test::TestRequest::delete().uri("/api/registry/v1alpha1/apps/app1/devices/device1")).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+        let resp = call_http(&app, &foo, test::TestRequest::delete().uri("/api/registry/v1alpha1/apps/app1/deviceTypes/sensor")).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    })
+}
@@ -0,0 +1,106 @@
+mod common;
+
+use crate::common::{assert_resources, call_http, create_app, init, user};
+use actix_cors::Cors;
+use actix_http::Request;
+use actix_web::{
+    body::MessageBody,
+    dev::{Service, ServiceResponse},
+    http::StatusCode,
+    middleware::Condition,
+    test, web, App,
+};
+use drogue_cloud_admin_service::apps;
+use drogue_cloud_device_management_service::{
+    app, endpoints,
+    service::{self},
+    WebData,
+};
+use drogue_cloud_registry_events::mock::MockEventSender;
+use drogue_cloud_service_api::auth::user::{UserDetails, UserInformation};
+use drogue_cloud_test_common::{client, db};
+use serde_json::{json, Value};
+use serial_test::serial;
+
+fn controller() -> UserInformation {
+    UserInformation::Authenticated(UserDetails {
+        user_id: "controller".into(),
+        roles: vec!["drogue-controller".into()],
+    })
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_search_devices_by_fields() -> anyhow::Result<()> {
+    test!((app, _sender, _outbox) => {
+        let foo = user("foo");
+
+        create_app(&app, &foo, "app1", Default::default()).await?;
+
+        for (name, spec, status) in vec![
+            ("device1", json!({}), json!({})),
+            ("device2", json!({"lorawan": {"devEui": "0123"}}), json!({"firmware": {"version": "1.2"}})),
+            ("device3", json!({"lorawan": {"devEui": "4567"}}), json!({"firmware": {"version": "1.3"}})),
+            ("device4", json!({"lorawan": null, "floor": 1}), json!({"firmware": {"version": 1.2}})),
+        ] {
+            let resp = call_http(&app, &foo, test::TestRequest::post().uri("/api/registry/v1alpha1/apps/app1/devices").set_json(&json!({
+                "metadata": { "application": "app1", "name": name },
+                "spec": spec,
+            }))).await;
+            assert_eq!(resp.status(), StatusCode::CREATED);
+
+            let resp = call_http(&app, &controller(), test::TestRequest::put().uri(&format!("/api/registry/v1alpha1/apps/app1/devices/{}/status", name)).set_json(&json!({
+                "metadata": { "application": "app1", "name": name },
+                "status": status,
+            }))).await;
+            assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        }
+
+        assert_devices(&app, &foo, "spec.lorawan", &["device2", "device3"]).await?;
+        assert_devices(&app, &foo, "!spec.lorawan", &["device1", "device4"]).await?;
+        assert_devices(&app, &foo, "spec.lorawan.devEui=0123", &["device2"]).await?;
+        assert_devices(&app, &foo, "spec.floor=1", &["device4"]).await?;
+        assert_devices(&app, &foo, "status.firmware.version=1.2", &["device2", "device4"]).await?;
+        assert_devices(&app, &foo, "status.firmware.version!=1.2", &["device1", "device3"]).await?;
+        assert_devices(&app, &foo, "status.firmware.version in (1.2, 1.3)", &["device2", "device3", "device4"]).await?;
+        assert_devices(&app, &foo, "status.firmware.version notin (1.2)", &["device1", "device3"]).await?;
+        assert_devices(&app, &foo, "spec.lorawan,status.firmware.version!=1.3", &["device2"]).await?;
+
+        // only the spec and status may be selected
+
+        let resp = call_http(&app, &foo, test::TestRequest::get().uri("/api/registry/v1alpha1/apps/app1/devices?fields=metadata.name")).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    })
+}
+
+async fn assert_devices<S, B, E>(
+    app: &S,
+    user: &UserInformation,
+    fields: &str,
+    outcome: &[&str],
+) -> anyhow::Result<()>
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = E>,
+    B: MessageBody + Unpin,
+    E: std::fmt::Debug,
+{
+    let query = form_urlencoded::Serializer::new(String::new())
+        .append_pair("fields", fields)
+        .finish();
+
+    let resp = call_http(
+        app,
+        user,
+        test::TestRequest::get().uri(&format!(
+            "/api/registry/v1alpha1/apps/app1/devices?{}",
+            query
+        )),
+    )
+    .await;
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    let result: Value = test::read_body_json(resp).await;
+    assert_resources(result, outcome);
+
+    Ok(())
+}
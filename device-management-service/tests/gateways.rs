@@ -0,0 +1,88 @@
+mod common;
+
+use crate::common::{call_http, create_app, init, user};
+use actix_cors::Cors;
+use actix_web::{http::StatusCode, middleware::Condition, test, web, App};
+use drogue_cloud_admin_service::apps;
+use drogue_cloud_device_management_service::{
+    app, endpoints,
+    service::{self},
+    WebData,
+};
+use drogue_cloud_registry_events::mock::MockEventSender;
+use drogue_cloud_test_common::{client, db};
+use serde_json::{json, Value};
+use serial_test::serial;
+
+fn names(result: Value) -> Vec<String> {
+    result
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|device| device["metadata"]["name"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_gateway_chains() -> anyhow::Result<()> {
+    test!((app, _sender, _outbox) => {
+        let foo = user("foo");
+
+        create_app(&app, &foo, "app1", Default::default()).await?;
+
+        let resp = call_http(&app, &foo, test::TestRequest::post().uri("/api/registry/v1alpha1/apps/app1/deviceTypes").set_json(&json!({
+            "metadata": { "application": "app1", "name": "sensor" },
+            "spec": { "gatewaySelector": { "matchNames": [ "sub1" ] } },
+        }))).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+
+        // gw1 <- sub1 (by label) <- device1 (by name), device2 (by type)
+
+        for (name, labels, spec) in vec![
+            ("gw1", json!({"role": "gateway"}), json!({})),
+            ("sub1", json!({}), json!({"gatewaySelector": {"matchLabels": {"role": "gateway"}}})),
+            ("device1", json!({}), json!({"gatewaySelector": {"matchNames": ["sub1"]}})),
+            ("device2", json!({}), json!({"deviceType": "sensor"})),
+            ("device3", json!({}), json!({})),
+        ] {
+            let resp = call_http(&app, &foo, test::TestRequest::post().uri("/api/registry/v1alpha1/apps/app1/devices").set_json(&json!({
+                "metadata": { "application": "app1", "name": name, "labels": labels },
+                "spec": spec,
+            }))).await;
+            assert_eq!(resp.status(), StatusCode::CREATED);
+        }
+
+        for (device, gateways) in vec![
+            ("device1", vec!["sub1", "gw1"]),
+            ("device2", vec!["sub1", "gw1"]),
+            ("device3", vec![]),
+            ("sub1", vec!["gw1"]),
+            ("gw1", vec![]),
+        ] {
+            let resp = call_http(&app, &foo, test::TestRequest::get().uri(&format!("/api/registry/v1alpha1/apps/app1/devices/{}/gateways", device))).await;
+            assert_eq!(resp.status(), StatusCode::OK);
+            let result: Value = test::read_body_json(resp).await;
+            assert_eq!(names(result), gateways, "gateways of {}", device);
+        }
+
+        for (device, children) in vec![
+            ("gw1", vec!["sub1"]),
+            ("sub1", vec!["device1", "device2"]),
+            ("device1", vec![]),
+        ] {
+            let resp = call_http(&app, &foo, test::TestRequest::get().uri(&format!("/api/registry/v1alpha1/apps/app1/devices/{}/children", device))).await;
+            assert_eq!(resp.status(), StatusCode::OK);
+            let result: Value = test::read_body_json(resp).await;
+            assert_eq!(names(result), children, "children of {}", device);
+        }
+
+        // unknown devices, and other users
+
+        let resp = call_http(&app, &foo, test::TestRequest::get().uri("/api/registry/v1alpha1/apps/app1/devices/device4/gateways")).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        let resp = call_http(&app, &user("bar"), test::TestRequest::get().uri("/api/registry/v1alpha1/apps/app1/devices/device1/gateways")).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    })
+}
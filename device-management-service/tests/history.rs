@@ -0,0 +1,182 @@
+mod common;
+
+use crate::common::{call_http, create_app, create_device, init, user};
+use actix_cors::Cors;
+use actix_web::{http::StatusCode, middleware::Condition, test, web, App};
+use drogue_cloud_admin_service::apps;
+use drogue_cloud_device_management_service::{
+    app, endpoints,
+    service::{self},
+    WebData,
+};
+use drogue_cloud_registry_events::mock::MockEventSender;
+use drogue_cloud_service_api::auth::user::{UserDetails, UserInformation};
+use drogue_cloud_test_common::{client, db};
+use serde_json::{json, Value};
+use serial_test::serial;
+
+#[actix_rt::test]
+#[serial]
+async fn test_history() -> anyhow::Result<()> {
+    test!((app, _sender, _outbox) => {
+        let foo = user("foo");
+
+        create_app(&app, &foo, "app1", Default::default()).await?;
+        create_device(&app, &foo, "app1", "device1", Default::default()).await?;
+
+        // update the device
+
+        let resp = call_http(&app, &foo, test::TestRequest::put().uri("/api/registry/v1alpha1/apps/app1/devices/device1").set_json(&json!({
+            "metadata": { "application": "app1", "name": "device1" },
+            "spec": { "foo": "bar" },
+        }))).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+        // update the status, which isn't tracked
+
+        let controller = UserInformation::Authenticated(UserDetails {
+            user_id: "controller".into(),
+            roles: vec!["drogue-controller".into()],
+        });
+        let resp = call_http(&app, &controller, test::TestRequest::put().uri("/api/registry/v1alpha1/apps/app1/devices/device1/status").set_json(&json!({
+            "metadata": { "application": "app1", "name": "device1" },
+            "status": { "connection": { "connected": true, "lastSeen": "2021-01-01T00:00:00Z" } },
+        }))).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+        // delete the device
+
+        let resp = call_http(&app, &foo, test::TestRequest::delete().uri("/api/registry/v1alpha1/apps/app1/devices/device1")).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+        // check the device history, most recent first
+
+        let resp = call_http(&app, &foo, test::TestRequest::get().uri("/api/admin/v1alpha1/apps/app1/devices/device1/history")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let result: Value = test::read_body_json(resp).await;
+        let operations: Vec<_> = result.as_array().unwrap().iter().map(|e| e["operation"].clone()).collect();
+        assert_eq!(operations, vec![json!("delete"), json!("update"), json!("create")]);
+        assert_eq!(result[0]["userId"], "foo");
+        assert_eq!(result[1]["diff"], json!([{"op": "replace", "path": "/spec", "value": {"foo": "bar"}}]));
+
+        // the application history contains the application too
+
+        let resp = call_http(&app, &foo, test::TestRequest::get().uri("/api/admin/v1alpha1/apps/app1/history?limit=3")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let token = resp.headers().get("X-Continue").unwrap().to_str().unwrap().to_string();
+        let result: Value = test::read_body_json(resp).await;
+        assert_eq!(result.as_array().map(Vec::len), Some(3));
+
+        let resp = call_http(&app, &foo, test::TestRequest::get().uri(&format!("/api/admin/v1alpha1/apps/app1/history?limit=3&continue={}", token))).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(resp.headers().get("X-Continue").is_none());
+        let result: Value = test::read_body_json(resp).await;
+        assert_eq!(result.as_array().map(Vec::len), Some(1));
+        assert_eq!(result[0]["kind"], "Application");
+        assert_eq!(result[0]["operation"], "create");
+
+        // other users must not see it
+
+        let resp = call_http(&app, &user("bar"), test::TestRequest::get().uri("/api/admin/v1alpha1/apps/app1/history")).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    })
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_history_redacts_credentials() -> anyhow::Result<()> {
+    test!((app, _sender, _outbox) => {
+        let foo = user("foo");
+
+        create_app(&app, &foo, "app1", Default::default()).await?;
+        create_device(&app, &foo, "app1", "device1", Default::default()).await?;
+
+        let resp = call_http(&app, &foo, test::TestRequest::put().uri("/api/registry/v1alpha1/apps/app1/devices/device1").set_json(&json!({
+            "metadata": { "application": "app1", "name": "device1" },
+            "spec": {
+                "credentials": { "credentials": [ { "pass": "secret1" } ] },
+                "foo": "bar",
+            },
+        }))).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+        let resp = call_http(&app, &foo, test::TestRequest::put().uri("/api/registry/v1alpha1/apps/app1/devices/device1").set_json(&json!({
+            "metadata": { "application": "app1", "name": "device1" },
+            "spec": {
+                "credentials": { "credentials": [ { "pass": "secret2" } ] },
+                "foo": "baz",
+            },
+        }))).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+        // the changes must be recorded, with a digest of the credentials
+
+        let resp = call_http(&app, &foo, test::TestRequest::get().uri("/api/admin/v1alpha1/apps/app1/devices/device1/history")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = test::read_body(resp).await;
+        let result: Value = serde_json::from_slice(&body)?;
+
+        let created = &result[1]["diff"];
+        assert_eq!(created[0]["path"], "/spec");
+        assert_eq!(created[0]["value"]["foo"], "bar");
+        let digest = created[0]["value"]["credentials"].as_str().unwrap();
+        assert!(digest.starts_with("sha256:"));
+
+        // the changed credentials show up as a changed digest
+        let updated = &result[0]["diff"];
+        assert_eq!(updated[0]["op"], "replace");
+        assert_eq!(updated[0]["path"], "/spec/credentials");
+        assert!(updated[0]["value"].as_str().unwrap().starts_with("sha256:"));
+        assert_ne!(updated[0]["value"], digest);
+        assert_eq!(updated[1], json!({"op": "replace", "path": "/spec/foo", "value": "baz"}));
+
+        let body = String::from_utf8(body.to_vec())?;
+        assert!(!body.contains("secret1"));
+        assert!(!body.contains("secret2"));
+    })
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_history_of_deleted_app() -> anyhow::Result<()> {
+    test!((app, _sender, _outbox) => {
+        let foo = user("foo");
+        let bar = user("bar");
+
+        create_app(&app, &foo, "app1", Default::default()).await?;
+
+        // transfer the application
+
+        let resp = call_http(&app, &foo, test::TestRequest::put().uri("/api/admin/v1alpha1/apps/app1/transfer-ownership").set_json(&json!({
+            "newUser": "bar",
+        }))).await;
+        assert_eq!(resp.status(), StatusCode::ACCEPTED);
+
+        let resp = call_http(&app, &bar, test::TestRequest::put().uri("/api/admin/v1alpha1/apps/app1/accept-ownership")).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+        // delete the application
+
+        let resp = call_http(&app, &bar, test::TestRequest::delete().uri("/api/registry/v1alpha1/apps/app1")).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+        // the history must still be available to the owner, including the transfer
+
+        let resp = call_http(&app, &bar, test::TestRequest::get().uri("/api/admin/v1alpha1/apps/app1/history")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let result: Value = test::read_body_json(resp).await;
+        let operations: Vec<_> = result.as_array().unwrap().iter().map(|e| (e["operation"].clone(), e["userId"].clone())).collect();
+        assert_eq!(operations, vec![
+            (json!("delete"), json!("bar")),
+            (json!("update"), json!("bar")),
+            (json!("update"), json!("foo")),
+            (json!("create"), json!("foo")),
+        ]);
+        assert!(result[1]["diff"].as_array().unwrap().contains(&json!({"op": "replace", "path": "/owner", "value": "bar"})));
+
+        // but not to the previous owner
+
+        let resp = call_http(&app, &foo, test::TestRequest::get().uri("/api/admin/v1alpha1/apps/app1/history")).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    })
+}
@@ -0,0 +1,144 @@
+mod common;
+
+use crate::common::{
+    assert_events, call_http, create_app, create_device, init, outbox_retrieve, user,
+};
+use actix_cors::Cors;
+use actix_web::{http::StatusCode, middleware::Condition, test, web, App};
+use drogue_cloud_admin_service::apps;
+use drogue_cloud_device_management_service::{
+    app, endpoints,
+    service::{self},
+    WebData,
+};
+use drogue_cloud_registry_events::{mock::MockEventSender, Event};
+use drogue_cloud_test_common::{client, db};
+use http::header;
+use serde_json::{json, Value};
+use serial_test::serial;
+
+#[actix_rt::test]
+#[serial]
+async fn test_patch_device() -> anyhow::Result<()> {
+    test!((app, sender, outbox) => {
+        let foo = user("foo");
+
+        create_app(&app, &foo, "app1", Default::default()).await?;
+        create_device(&app, &foo, "app1", "device1", Default::default()).await?;
+
+        sender.reset()?;
+        outbox_retrieve(&outbox).await?;
+
+        // merge patch
+
+        let resp = call_http(&app, &foo, test::TestRequest::patch()
+            .uri("/api/registry/v1alpha1/apps/app1/devices/device1")
+            .insert_header((header::CONTENT_TYPE, "application/merge-patch+json"))
+            .set_payload(json!({"spec": {"foo": {"bar": 42}}}).to_string())).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+        // an event must have been fired, for the changed path only
+        assert_events(vec![sender.retrieve()?, outbox_retrieve(&outbox).await?], vec![Event::Device {
+            instance: "drogue-instance".into(),
+            application: "app1".into(),
+            device: "device1".into(),
+            uid: "".into(),
+            path: ".spec.foo".into(),
+            generation: 0,
+        }]);
+
+        // json patch
+
+        let resp = call_http(&app, &foo, test::TestRequest::patch()
+            .uri("/api/registry/v1alpha1/apps/app1/devices/device1")
+            .insert_header((header::CONTENT_TYPE, "application/json-patch+json"))
+            .set_payload(json!([
+                {"op": "test", "path": "/spec/foo/bar", "value": 42},
+                {"op": "replace", "path": "/spec/foo/bar", "value": 43},
+            ]).to_string())).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+        let resp = call_http(&app, &foo, test::TestRequest::get().uri("/api/registry/v1alpha1/apps/app1/devices/device1")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let result: Value = test::read_body_json(resp).await;
+        assert_eq!(result["spec"], json!({"foo": {"bar": 43}}));
+
+        sender.reset()?;
+        outbox_retrieve(&outbox).await?;
+
+        // failing test operation
+
+        let resp = call_http(&app, &foo, test::TestRequest::patch()
+            .uri("/api/registry/v1alpha1/apps/app1/devices/device1")
+            .insert_header((header::CONTENT_TYPE, "application/json-patch+json"))
+            .set_payload(json!([
+                {"op": "test", "path": "/spec/foo/bar", "value": 42},
+            ]).to_string())).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+
+        // renaming is not allowed
+
+        let resp = call_http(&app, &foo, test::TestRequest::patch()
+            .uri("/api/registry/v1alpha1/apps/app1/devices/device1")
+            .insert_header((header::CONTENT_TYPE, "application/merge-patch+json"))
+            .set_payload(json!({"metadata": {"name": "device2"}}).to_string())).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        // unsupported content type
+
+        let resp = call_http(&app, &foo, test::TestRequest::patch()
+            .uri("/api/registry/v1alpha1/apps/app1/devices/device1")
+            .insert_header((header::CONTENT_TYPE, "text/plain"))
+            .set_payload("foo")).await;
+        assert_eq!(resp.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        // nothing must have changed
+        assert_events(vec![sender.retrieve()?, outbox_retrieve(&outbox).await?], vec![]);
+
+        // patching a non-existing device
+
+        let resp = call_http(&app, &foo, test::TestRequest::patch()
+            .uri("/api/registry/v1alpha1/apps/app1/devices/device2")
+            .insert_header((header::CONTENT_TYPE, "application/merge-patch+json"))
+            .set_payload("{}")).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    })
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_patch_app() -> anyhow::Result<()> {
+    test!((app, sender, outbox) => {
+        let foo = user("foo");
+        let bar = user("bar");
+
+        create_app(&app, &foo, "app1", Default::default()).await?;
+
+        sender.reset()?;
+        outbox_retrieve(&outbox).await?;
+
+        let resp = call_http(&app, &foo, test::TestRequest::patch()
+            .uri("/api/registry/v1alpha1/apps/app1")
+            .insert_header((header::CONTENT_TYPE, "application/merge-patch+json"))
+            .set_payload(json!({"metadata": {"labels": {"foo": "bar"}}}).to_string())).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+        assert_events(vec![sender.retrieve()?, outbox_retrieve(&outbox).await?], vec![Event::Application {
+            instance: "drogue-instance".into(),
+            application: "app1".into(),
+            uid: "".into(),
+            path: ".metadata".into(),
+            generation: 0,
+        }]);
+
+        // other users must not patch the application
+
+        let resp = call_http(&app, &bar, test::TestRequest::patch()
+            .uri("/api/registry/v1alpha1/apps/app1")
+            .insert_header((header::CONTENT_TYPE, "application/merge-patch+json"))
+            .set_payload(json!({"metadata": {"labels": {"foo": "baz"}}}).to_string())).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+        assert_events(vec![sender.retrieve()?, outbox_retrieve(&outbox).await?], vec![]);
+    })
+}
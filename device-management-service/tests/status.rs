@@ -0,0 +1,134 @@
+mod common;
+
+use crate::common::{
+    assert_events, call_http, create_app, create_device, init, outbox_retrieve, user,
+};
+use actix_cors::Cors;
+use actix_web::{http::StatusCode, middleware::Condition, test, web, App};
+use drogue_cloud_admin_service::apps;
+use drogue_cloud_device_management_service::{
+    app, endpoints,
+    service::{self},
+    WebData,
+};
+use drogue_cloud_registry_events::{mock::MockEventSender, Event};
+use drogue_cloud_service_api::auth::user::{UserDetails, UserInformation};
+use drogue_cloud_test_common::{client, db};
+use serde_json::{json, Value};
+use serial_test::serial;
+
+fn controller() -> UserInformation {
+    UserInformation::Authenticated(UserDetails {
+        user_id: "controller".into(),
+        roles: vec!["drogue-controller".into()],
+    })
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_device_status() -> anyhow::Result<()> {
+    test!((app, sender, outbox) => {
+        let foo = user("foo");
+
+        create_app(&app, &foo, "app1", Default::default()).await?;
+        create_device(&app, &foo, "app1", "device1", Default::default()).await?;
+
+        let resp = call_http(&app, &foo, test::TestRequest::get().uri("/api/registry/v1alpha1/apps/app1/devices/device1")).await;
+        let device: Value = test::read_body_json(resp).await;
+        let generation = device["metadata"]["generation"].clone();
+
+        sender.reset()?;
+        outbox_retrieve(&outbox).await?;
+
+        // users must not write the status
+
+        let resp = call_http(&app, &foo, test::TestRequest::put().uri("/api/registry/v1alpha1/apps/app1/devices/device1/status").set_json(&json!({
+            "metadata": { "application": "app1", "name": "device1" },
+            "status": { "foo": "bar" },
+        }))).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+        // the main resource ignores status changes
+
+        let resp = call_http(&app, &foo, test::TestRequest::put().uri("/api/registry/v1alpha1/apps/app1/devices/device1").set_json(&json!({
+            "metadata": { "application": "app1", "name": "device1" },
+            "status": { "foo": "bar" },
+        }))).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+        assert_events(vec![sender.retrieve()?, outbox_retrieve(&outbox).await?], vec![]);
+
+        // controllers write the status, but not the spec
+
+        let resp = call_http(&app, &controller(), test::TestRequest::put().uri("/api/registry/v1alpha1/apps/app1/devices/device1/status").set_json(&json!({
+            "metadata": { "application": "app1", "name": "device1" },
+            "spec": { "foo": "baz" },
+            "status": { "foo": "bar" },
+        }))).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+        assert_events(vec![sender.retrieve()?, outbox_retrieve(&outbox).await?], vec![Event::Device {
+            instance: "drogue-instance".into(),
+            application: "app1".into(),
+            device: "device1".into(),
+            uid: "".into(),
+            path: ".status.foo".into(),
+            generation: 0,
+        }]);
+
+        // the generation must not change
+
+        let resp = call_http(&app, &foo, test::TestRequest::get().uri("/api/registry/v1alpha1/apps/app1/devices/device1")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let device: Value = test::read_body_json(resp).await;
+        assert_eq!(device["metadata"]["generation"], generation);
+        assert_eq!(device["spec"], Value::Null);
+        assert_eq!(device["status"], json!({ "foo": "bar" }));
+
+        // a non-existing device
+
+        let resp = call_http(&app, &controller(), test::TestRequest::put().uri("/api/registry/v1alpha1/apps/app1/devices/device2/status").set_json(&json!({
+            "metadata": { "application": "app1", "name": "device2" },
+            "status": { "foo": "bar" },
+        }))).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    })
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_app_status() -> anyhow::Result<()> {
+    test!((app, sender, outbox) => {
+        let foo = user("foo");
+
+        create_app(&app, &foo, "app1", Default::default()).await?;
+
+        sender.reset()?;
+        outbox_retrieve(&outbox).await?;
+
+        let resp = call_http(&app, &foo, test::TestRequest::put().uri("/api/registry/v1alpha1/apps/app1/status").set_json(&json!({
+            "metadata": { "name": "app1" },
+            "status": { "foo": "bar" },
+        }))).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+        let resp = call_http(&app, &controller(), test::TestRequest::put().uri("/api/registry/v1alpha1/apps/app1/status").set_json(&json!({
+            "metadata": { "name": "app1" },
+            "status": { "foo": "bar" },
+        }))).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+        assert_events(vec![sender.retrieve()?, outbox_retrieve(&outbox).await?], vec![Event::Application {
+            instance: "drogue-instance".into(),
+            application: "app1".into(),
+            uid: "".into(),
+            path: ".status.foo".into(),
+            generation: 0,
+        }]);
+
+        let resp = call_http(&app, &foo, test::TestRequest::get().uri("/api/registry/v1alpha1/apps/app1")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let result: Value = test::read_body_json(resp).await;
+        assert_eq!(result["status"], json!({ "foo": "bar" }));
+    })
+}
@@ -0,0 +1,149 @@
+mod common;
+
+use crate::common::{call_http, create_app, create_device, init, user};
+use actix_cors::Cors;
+use actix_web::{http::StatusCode, middleware::Condition, test, web, App};
+use drogue_cloud_admin_service::apps;
+use drogue_cloud_device_management_service::{
+    app, endpoints,
+    service::{self},
+    WebData,
+};
+use drogue_cloud_registry_events::mock::MockEventSender;
+use drogue_cloud_test_common::{client, db};
+use serde_json::{json, Value};
+use serial_test::serial;
+
+#[actix_rt::test]
+#[serial]
+async fn test_restore_device() -> anyhow::Result<()> {
+    test!((app, _sender, _outbox) => {
+        let foo = user("foo");
+
+        create_app(&app, &foo, "app1", Default::default()).await?;
+        create_device(&app, &foo, "app1", "device1", Default::default()).await?;
+
+        let resp = call_http(&app, &foo, test::TestRequest::put().uri("/api/registry/v1alpha1/apps/app1/devices/device1/twin/desired").set_json(&json!({
+            "light": { "on": true },
+        }))).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+        // delete the device
+
+        let resp = call_http(&app, &foo, test::TestRequest::delete().uri("/api/registry/v1alpha1/apps/app1/devices/device1")).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+        let resp = call_http(&app, &foo, test::TestRequest::get().uri("/api/registry/v1alpha1/apps/app1/devices/device1")).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        // it must be in the trash
+
+        let resp = call_http(&app, &foo, test::TestRequest::get().uri("/api/admin/v1alpha1/trash/apps/app1/devices")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let result: Value = test::read_body_json(resp).await;
+        assert_eq!(result[0]["name"], "device1");
+        assert_eq!(result[0]["cascaded"], false);
+
+        // other users must not see it
+
+        let resp = call_http(&app, &user("bar"), test::TestRequest::get().uri("/api/admin/v1alpha1/trash/apps/app1/devices")).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        // restore
+
+        let resp = call_http(&app, &foo, test::TestRequest::put().uri("/api/admin/v1alpha1/trash/apps/app1/devices/device1/restore")).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+        let resp = call_http(&app, &foo, test::TestRequest::get().uri("/api/registry/v1alpha1/apps/app1/devices/device1")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let result: Value = test::read_body_json(resp).await;
+        assert_eq!(result["metadata"]["deletionTimestamp"], Value::Null);
+
+        // including its twin
+
+        let resp = call_http(&app, &foo, test::TestRequest::get().uri("/api/registry/v1alpha1/apps/app1/devices/device1/twin")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let result: Value = test::read_body_json(resp).await;
+        assert_eq!(result["desired"], json!({"light": { "on": true }}));
+
+        // the trash must be empty now
+
+        let resp = call_http(&app, &foo, test::TestRequest::put().uri("/api/admin/v1alpha1/trash/apps/app1/devices/device1/restore")).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        // restoring must not overwrite an existing device
+
+        let resp = call_http(&app, &foo, test::TestRequest::delete().uri("/api/registry/v1alpha1/apps/app1/devices/device1")).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        create_device(&app, &foo, "app1", "device1", Default::default()).await?;
+
+        let resp = call_http(&app, &foo, test::TestRequest::put().uri("/api/admin/v1alpha1/trash/apps/app1/devices/device1/restore")).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+    })
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_restore_app() -> anyhow::Result<()> {
+    test!((app, _sender, _outbox) => {
+        let foo = user("foo");
+
+        create_app(&app, &foo, "app1", Default::default()).await?;
+        create_device(&app, &foo, "app1", "device1", Default::default()).await?;
+
+        let resp = call_http(&app, &foo, test::TestRequest::post().uri("/api/registry/v1alpha1/apps/app1/deviceTypes").set_json(&json!({
+            "metadata": { "application": "app1", "name": "sensor" },
+        }))).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+
+        // delete the application
+
+        let resp = call_http(&app, &foo, test::TestRequest::delete().uri("/api/registry/v1alpha1/apps/app1")).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+        let resp = call_http(&app, &foo, test::TestRequest::get().uri("/api/registry/v1alpha1/apps/app1")).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        // it must be in the trash, for the owner only
+
+        let resp = call_http(&app, &foo, test::TestRequest::get().uri("/api/admin/v1alpha1/trash/apps")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let result: Value = test::read_body_json(resp).await;
+        assert_eq!(result.as_array().map(Vec::len), Some(1));
+        assert_eq!(result[0]["name"], "app1");
+
+        let resp = call_http(&app, &user("bar"), test::TestRequest::get().uri("/api/admin/v1alpha1/trash/apps")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let result: Value = test::read_body_json(resp).await;
+        assert_eq!(result, json!([]));
+
+        let resp = call_http(&app, &user("bar"), test::TestRequest::put().uri("/api/admin/v1alpha1/trash/apps/app1/restore")).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        // restore, including its devices and device types
+
+        let resp = call_http(&app, &foo, test::TestRequest::put().uri("/api/admin/v1alpha1/trash/apps/app1/restore")).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+        let resp = call_http(&app, &foo, test::TestRequest::get().uri("/api/registry/v1alpha1/apps/app1")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let result: Value = test::read_body_json(resp).await;
+        assert_eq!(result["metadata"]["deletionTimestamp"], Value::Null);
+
+        let resp = call_http(&app, &foo, test::TestRequest::get().uri("/api/registry/v1alpha1/apps/app1/devices/device1")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let resp = call_http(&app, &foo, test::TestRequest::get().uri("/api/registry/v1alpha1/apps/app1/deviceTypes/sensor")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        // nothing must remain in the trash
+
+        let resp = call_http(&app, &foo, test::TestRequest::get().uri("/api/admin/v1alpha1/trash/apps")).await;
+        let result: Value = test::read_body_json(resp).await;
+        assert_eq!(result, json!([]));
+
+        let resp = call_http(&app, &foo, test::TestRequest::get().uri("/api/admin/v1alpha1/trash/apps/app1/devices")).await;
+        let result: Value = test::read_body_json(resp).await;
+        assert_eq!(result, json!([]));
+    })
+}
@@ -0,0 +1,104 @@
+mod common;
+
+use crate::common::{
+    assert_events, call_http, create_app, create_device, init, outbox_retrieve, user,
+};
+use actix_cors::Cors;
+use actix_web::{http::StatusCode, middleware::Condition, test, web, App};
+use drogue_cloud_admin_service::apps;
+use drogue_cloud_device_management_service::{
+    app, endpoints,
+    service::{self},
+    WebData,
+};
+use drogue_cloud_registry_events::{mock::MockEventSender, Event};
+use drogue_cloud_test_common::{client, db};
+use serde_json::{json, Value};
+use serial_test::serial;
+
+#[actix_rt::test]
+#[serial]
+async fn test_twin() -> anyhow::Result<()> {
+    test!((app, sender, outbox) => {
+        let foo = user("foo");
+
+        create_app(&app, &foo, "app1", Default::default()).await?;
+        create_device(&app, &foo, "app1", "device1", Default::default()).await?;
+
+        sender.reset()?;
+        outbox_retrieve(&outbox).await?;
+
+        // a new device has an empty twin
+
+        let resp = call_http(&app, &foo, test::TestRequest::get().uri("/api/registry/v1alpha1/apps/app1/devices/device1/twin")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let result: Value = test::read_body_json(resp).await;
+        assert_eq!(result, json!({"reported": {}, "desired": {}}));
+
+        // set the desired state
+
+        let resp = call_http(&app, &foo, test::TestRequest::put().uri("/api/registry/v1alpha1/apps/app1/devices/device1/twin/desired").set_json(&json!({
+            "light": { "on": true },
+        }))).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+        // an event must have been fired
+        assert_events(vec![sender.retrieve()?, outbox_retrieve(&outbox).await?], vec![Event::Device {
+            instance: "drogue-instance".into(),
+            application: "app1".into(),
+            device: "device1".into(),
+            uid: "".into(),
+            path: ".twin".into(),
+            generation: 0,
+        }]);
+
+        // the desired state is pending
+
+        let resp = call_http(&app, &foo, test::TestRequest::get().uri("/api/registry/v1alpha1/apps/app1/devices/device1/twin")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let result: Value = test::read_body_json(resp).await;
+        assert_eq!(result["desired"], json!({"light": { "on": true }}));
+        assert_eq!(result["delta"], json!({"light": { "on": true }}));
+
+        // setting the same state again doesn't fire an event
+
+        let resp = call_http(&app, &foo, test::TestRequest::put().uri("/api/registry/v1alpha1/apps/app1/devices/device1/twin/desired").set_json(&json!({
+            "light": { "on": true },
+        }))).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        assert_events(vec![sender.retrieve()?, outbox_retrieve(&outbox).await?], vec![]);
+
+        // the desired state must be an object
+
+        let resp = call_http(&app, &foo, test::TestRequest::put().uri("/api/registry/v1alpha1/apps/app1/devices/device1/twin/desired").set_json(&json!([1, 2]))).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    })
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_twin_access() -> anyhow::Result<()> {
+    test!((app, _sender, _outbox) => {
+        let foo = user("foo");
+        let bar = user("bar");
+
+        create_app(&app, &foo, "app1", Default::default()).await?;
+        create_device(&app, &foo, "app1", "device1", Default::default()).await?;
+
+        // unknown devices have no twin
+
+        let resp = call_http(&app, &foo, test::TestRequest::get().uri("/api/registry/v1alpha1/apps/app1/devices/device2/twin")).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        let resp = call_http(&app, &foo, test::TestRequest::put().uri("/api/registry/v1alpha1/apps/app1/devices/device2/twin/desired").set_json(&json!({}))).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        // other users don't see the twin
+
+        let resp = call_http(&app, &bar, test::TestRequest::get().uri("/api/registry/v1alpha1/apps/app1/devices/device1/twin")).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        let resp = call_http(&app, &bar, test::TestRequest::put().uri("/api/registry/v1alpha1/apps/app1/devices/device1/twin/desired").set_json(&json!({}))).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    })
+}
@@ -0,0 +1,224 @@
+use super::*;
+
+use async_trait::async_trait;
+use cloudevents::{AttributeValue, AttributesReader};
+use dove::{
+    conn::ConnectionOptions,
+    container::{
+        Connection, Container, ContainerOptions, DeliveryState, Message, MessageBody,
+        MessageProperties, Sender, Session, Value,
+    },
+    error::AmqpError,
+    sasl::SaslMechanism,
+};
+use drogue_cloud_service_common::config::ConfigFromEnv;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// The prefix of application properties used by the CloudEvents AMQP protocol binding.
+const AMQP_CE_PREFIX: &str = "cloudEvents:";
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct AmqpSinkConfig {
+    pub host: String,
+    #[serde(default = "default_amqp_port")]
+    pub port: u16,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    /// The address (queue or topic) to send events to.
+    pub address: String,
+}
+
+#[inline]
+fn default_amqp_port() -> u16 {
+    5672
+}
+
+/// An open link to the broker.
+struct Link {
+    // the connection and session must be kept open as long as the sender is used
+    _connection: Connection,
+    _session: Session,
+    sender: Sender,
+}
+
+/// A sink, sending events to an AMQP 1.0 broker.
+///
+/// Events are encoded using the binary content mode of the CloudEvents AMQP protocol binding.
+///
+/// If sending fails, the link gets dropped, and will be re-opened with the next event.
+#[derive(Clone)]
+pub struct AmqpSink {
+    container: Arc<Container>,
+    config: Arc<AmqpSinkConfig>,
+    link: Arc<Mutex<Option<Arc<Link>>>>,
+}
+
+impl AmqpSink {
+    /// Create a new AMQP sink from a configuration specified by the prefix.
+    pub async fn new(prefix: &str) -> anyhow::Result<Self> {
+        let config = AmqpSinkConfig::from_env_prefix(prefix)
+            .with_context(|| format!("Failed to parse {} config", prefix))?;
+
+        let container = Container::new(ContainerOptions::new())?.start();
+
+        // connect right away, to fail early on a wrong configuration
+
+        let link = Self::connect(&container, &config).await?;
+
+        Ok(Self {
+            container: Arc::new(container),
+            config: Arc::new(config),
+            link: Arc::new(Mutex::new(Some(Arc::new(link)))),
+        })
+    }
+
+    async fn connect(container: &Container, config: &AmqpSinkConfig) -> Result<Link, AmqpError> {
+        let opts = match (&config.username, &config.password) {
+            (Some(username), Some(password)) => ConnectionOptions::new()
+                .sasl_mechanism(SaslMechanism::Plain)
+                .username(username)
+                .password(password),
+            _ => ConnectionOptions::new().sasl_mechanism(SaslMechanism::Anonymous),
+        };
+
+        let connection = container.connect(&config.host, config.port, opts).await?;
+        let session = connection.new_session(None).await?;
+        let sender = session.new_sender(&config.address).await?;
+
+        Ok(Link {
+            _connection: connection,
+            _session: session,
+            sender,
+        })
+    }
+
+    /// Get the current link, re-connecting if necessary.
+    async fn link(&self) -> Result<Arc<Link>, AmqpError> {
+        let mut link = self.link.lock().await;
+
+        if let Some(link) = link.as_ref() {
+            return Ok(link.clone());
+        }
+
+        log::info!(
+            "AMQP sink - connecting to {}:{}",
+            self.config.host,
+            self.config.port
+        );
+
+        let current = Arc::new(Self::connect(&self.container, &self.config).await?);
+        *link = Some(current.clone());
+
+        Ok(current)
+    }
+
+    /// Drop a failed link, unless it was already replaced.
+    async fn reset(&self, failed: &Arc<Link>) {
+        let mut link = self.link.lock().await;
+
+        if let Some(current) = link.as_ref() {
+            if Arc::ptr_eq(current, failed) {
+                *link = None;
+            }
+        }
+    }
+
+    fn to_message(mut event: Event) -> Message {
+        let mut message = Message::new();
+
+        let mut properties = MessageProperties::new();
+        properties.message_id = Some(Value::String(event.id().to_string()));
+        properties.content_type = event.datacontenttype().map(|s| s.to_string());
+
+        let mut application_properties = Vec::new();
+        for (name, value) in event.iter() {
+            if name == "datacontenttype" {
+                continue;
+            }
+            let value = match value {
+                AttributeValue::SpecVersion(v) => v.to_string(),
+                AttributeValue::String(v) => v.to_string(),
+                AttributeValue::URI(v) => v.to_string(),
+                AttributeValue::URIRef(v) => v.to_string(),
+                AttributeValue::Boolean(v) => v.to_string(),
+                AttributeValue::Integer(v) => v.to_string(),
+                AttributeValue::Time(v) => v.to_rfc3339(),
+            };
+            application_properties.push((
+                Value::String(format!("{}{}", AMQP_CE_PREFIX, name)),
+                Value::String(value),
+            ));
+        }
+
+        let (_, _, data) = event.take_data();
+        let body = match data {
+            Some(Data::Binary(data)) => data,
+            Some(Data::String(data)) => data.into_bytes(),
+            Some(Data::Json(data)) => data.to_string().into_bytes(),
+            None => Vec::new(),
+        };
+
+        message.properties = Some(properties);
+        message.application_properties = Some(Value::Map(application_properties));
+        message.body = MessageBody::Data(body);
+
+        message
+    }
+}
+
+#[async_trait]
+impl DownstreamSink for AmqpSink {
+    type Error = AmqpError;
+
+    async fn publish(&self, event: Event) -> Result<PublishOutcome, DownstreamError<Self::Error>> {
+        let message = Self::to_message(event);
+
+        let link = self.link().await.map_err(DownstreamError::Transport)?;
+
+        let disposition = match link.sender.send(message).await {
+            Ok(disposition) => disposition,
+            Err(err) => {
+                log::warn!("AMQP sink - failed to send, re-connecting: {}", err);
+                self.reset(&link).await;
+                return Err(DownstreamError::Transport(err));
+            }
+        };
+
+        match disposition.state {
+            Some(DeliveryState::Accepted) => Ok(PublishOutcome::Accepted),
+            // the broker asks us to try again later
+            Some(DeliveryState::Released) | Some(DeliveryState::Modified(_)) => {
+                Ok(PublishOutcome::QueueFull)
+            }
+            state => {
+                log::debug!("Message not accepted: {:?}", state);
+                Ok(PublishOutcome::Rejected)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serial_test::serial;
+
+    #[test]
+    #[serial]
+    fn test_config() {
+        std::env::set_var("AMQP__HOST", "localhost");
+        std::env::set_var("AMQP__ADDRESS", "iot-events");
+
+        let amqp = AmqpSinkConfig::from_env_prefix("AMQP").unwrap();
+
+        assert_eq!(amqp.host, "localhost");
+        assert_eq!(amqp.port, 5672);
+        assert_eq!(amqp.address, "iot-events");
+
+        std::env::remove_var("AMQP__HOST");
+        std::env::remove_var("AMQP__ADDRESS");
+    }
+}
@@ -0,0 +1,128 @@
+use super::*;
+
+use async_trait::async_trait;
+use drogue_cloud_service_common::config::ConfigFromEnv;
+use std::convert::Infallible;
+
+/// The type of the downstream sink.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SinkType {
+    Kafka,
+    Http,
+    Amqp,
+    Mqtt,
+    Memory,
+}
+
+impl Default for SinkType {
+    fn default() -> Self {
+        Self::Kafka
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct SinkConfig {
+    #[serde(default, rename = "type")]
+    pub r#type: SinkType,
+}
+
+#[derive(Debug, Error)]
+pub enum ConfiguredSinkError {
+    #[error("Kafka sink error")]
+    Kafka(#[source] KafkaSinkError),
+    #[error("HTTP sink error")]
+    Http(#[source] reqwest::Error),
+    #[error("AMQP sink error")]
+    Amqp(#[source] dove::error::AmqpError),
+    #[error("MQTT sink error")]
+    Mqtt(#[source] MqttSinkError),
+    #[error("In-memory sink error")]
+    Memory(#[source] Infallible),
+}
+
+/// A downstream sink, which is selected by configuration.
+///
+/// The type is read from `<prefix>_SINK__TYPE`, defaulting to `kafka`. The actual sink then gets
+/// configured using the prefix `<prefix>_<TYPE>_SINK` (e.g. `DOWNSTREAM_KAFKA_SINK`). The HTTP
+/// sink uses the Knative `K_SINK` variable instead.
+#[derive(Clone)]
+pub enum ConfiguredSink {
+    Kafka(KafkaSink),
+    Http(HttpSink),
+    Amqp(AmqpSink),
+    Mqtt(MqttSink),
+    Memory(MemorySink),
+}
+
+impl ConfiguredSink {
+    /// Create a new sink from a configuration specified by the prefix.
+    pub async fn new(prefix: &str) -> anyhow::Result<Self> {
+        let config = SinkConfig::from_env_prefix(format!("{}_SINK", prefix))
+            .with_context(|| format!("Failed to parse {}_SINK config", prefix))?;
+
+        log::info!("Using downstream sink: {:?}", config.r#type);
+
+        Ok(match config.r#type {
+            SinkType::Kafka => Self::Kafka(KafkaSink::new(&format!("{}_KAFKA_SINK", prefix))?),
+            SinkType::Http => Self::Http(HttpSink::new()?),
+            SinkType::Amqp => Self::Amqp(AmqpSink::new(&format!("{}_AMQP_SINK", prefix)).await?),
+            SinkType::Mqtt => Self::Mqtt(MqttSink::new(&format!("{}_MQTT_SINK", prefix))?),
+            SinkType::Memory => Self::Memory(MemorySink::new(prefix)?),
+        })
+    }
+}
+
+fn map_err<E, F>(
+    result: Result<PublishOutcome, DownstreamError<E>>,
+    f: F,
+) -> Result<PublishOutcome, DownstreamError<ConfiguredSinkError>>
+where
+    E: std::error::Error + 'static,
+    F: FnOnce(E) -> ConfiguredSinkError,
+{
+    result.map_err(|err| match err {
+        DownstreamError::Build(err) => DownstreamError::Build(err),
+        DownstreamError::Event(err) => DownstreamError::Event(err),
+        DownstreamError::Transport(err) => DownstreamError::Transport(f(err)),
+    })
+}
+
+#[async_trait]
+impl DownstreamSink for ConfiguredSink {
+    type Error = ConfiguredSinkError;
+
+    async fn publish(&self, event: Event) -> Result<PublishOutcome, DownstreamError<Self::Error>> {
+        match self {
+            Self::Kafka(sink) => map_err(sink.publish(event).await, ConfiguredSinkError::Kafka),
+            Self::Http(sink) => map_err(sink.publish(event).await, ConfiguredSinkError::Http),
+            Self::Amqp(sink) => map_err(sink.publish(event).await, ConfiguredSinkError::Amqp),
+            Self::Mqtt(sink) => map_err(sink.publish(event).await, ConfiguredSinkError::Mqtt),
+            Self::Memory(sink) => map_err(sink.publish(event).await, ConfiguredSinkError::Memory),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serial_test::serial;
+
+    #[test]
+    #[serial]
+    fn test_default_type() {
+        let config = SinkConfig::from_env_prefix("FOO_SINK").unwrap();
+        assert_eq!(config.r#type, SinkType::Kafka);
+    }
+
+    #[test]
+    #[serial]
+    fn test_type() {
+        std::env::set_var("BAR_SINK__TYPE", "amqp");
+
+        let config = SinkConfig::from_env_prefix("BAR_SINK").unwrap();
+        assert_eq!(config.r#type, SinkType::Amqp);
+
+        std::env::remove_var("BAR_SINK__TYPE");
+    }
+}
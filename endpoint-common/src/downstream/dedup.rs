@@ -0,0 +1,300 @@
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::sync::watch;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct DeduplicationConfig {
+    /// The time window in which duplicates are detected. A window of zero disables the
+    /// deduplication.
+    #[serde(default = "default_window", with = "humantime_serde")]
+    pub window: Duration,
+    /// The maximum number of keys to remember.
+    #[serde(default = "default_max_entries")]
+    pub max_entries: usize,
+}
+
+impl Default for DeduplicationConfig {
+    fn default() -> Self {
+        Self {
+            window: default_window(),
+            max_entries: default_max_entries(),
+        }
+    }
+}
+
+#[inline]
+fn default_window() -> Duration {
+    Duration::from_secs(60)
+}
+
+#[inline]
+fn default_max_entries() -> usize {
+    10_000
+}
+
+#[derive(Debug)]
+enum State {
+    /// The event is being forwarded, the sender gets dropped when the outcome is known.
+    Pending(watch::Receiver<()>),
+    /// The event was forwarded.
+    Accepted,
+}
+
+#[derive(Debug)]
+struct Entry {
+    time: Instant,
+    state: State,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    seen: HashMap<String, Entry>,
+    order: VecDeque<(Instant, String)>,
+}
+
+/// The result of checking a key.
+#[derive(Debug)]
+pub enum Seen {
+    /// The key was not seen in the current time window. The event must be forwarded, and the
+    /// outcome reported using the guard.
+    First(Pending),
+    /// An event with the same key was already forwarded.
+    Duplicate,
+}
+
+/// A key, for which the event is currently being forwarded.
+///
+/// Dropping the guard, without marking it as accepted, forgets the key again. So that the
+/// device can retry.
+#[derive(Debug)]
+pub struct Pending {
+    inner: Option<(Deduplicator, String, Instant)>,
+    _done: Option<watch::Sender<()>>,
+}
+
+impl Pending {
+    /// Mark the event as forwarded, so that all further events with the same key are dropped.
+    pub fn accepted(mut self) {
+        if let Some((dedup, key, time)) = self.inner.take() {
+            let mut inner = dedup.inner.lock().unwrap();
+            if let Some(entry) = inner.seen.get_mut(&key) {
+                if entry.time == time {
+                    entry.state = State::Accepted;
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        if let Some((dedup, key, time)) = self.inner.take() {
+            let mut inner = dedup.inner.lock().unwrap();
+            // only remove the key if it wasn't re-added in the meantime
+            if inner.seen.get(&key).map(|entry| entry.time) == Some(time) {
+                inner.seen.remove(&key);
+            }
+            // the entry in the order queue will be skipped when it expires
+        }
+    }
+}
+
+/// A short-window cache of idempotency keys.
+#[derive(Clone, Debug)]
+pub struct Deduplicator {
+    config: DeduplicationConfig,
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl Deduplicator {
+    pub fn new(config: DeduplicationConfig) -> Self {
+        Self {
+            config,
+            inner: Default::default(),
+        }
+    }
+
+    /// Record a key, checking if it was seen in the current time window.
+    ///
+    /// If an event with the same key is currently being forwarded, this waits for the outcome of
+    /// that attempt. If it failed, the key is handled as if it was seen the first time.
+    pub async fn first_seen(&self, key: &str) -> Seen {
+        if self.config.window.as_nanos() == 0 {
+            return Seen::First(Pending {
+                inner: None,
+                _done: None,
+            });
+        }
+
+        loop {
+            let mut pending = match self.check(key) {
+                Ok(seen) => return seen,
+                Err(pending) => pending,
+            };
+            log::debug!("Waiting for the outcome of the pending event: {}", key);
+            // returns an error once the sender got dropped, which is all we wait for
+            let _ = pending.changed().await;
+        }
+    }
+
+    fn check(&self, key: &str) -> Result<Seen, watch::Receiver<()>> {
+        let now = Instant::now();
+        let mut inner = self.inner.lock().unwrap();
+
+        self.expire(&mut inner, now);
+
+        match inner.seen.get(key).map(|entry| &entry.state) {
+            Some(State::Accepted) => return Ok(Seen::Duplicate),
+            Some(State::Pending(pending)) => return Err(pending.clone()),
+            None => {}
+        }
+
+        // make room for the new entry
+        while !inner.order.is_empty() && inner.order.len() >= self.config.max_entries {
+            Self::evict(&mut inner);
+        }
+
+        let (tx, rx) = watch::channel(());
+
+        inner.seen.insert(
+            key.to_string(),
+            Entry {
+                time: now,
+                state: State::Pending(rx),
+            },
+        );
+        inner.order.push_back((now, key.to_string()));
+
+        Ok(Seen::First(Pending {
+            inner: Some((self.clone(), key.to_string(), now)),
+            _done: Some(tx),
+        }))
+    }
+
+    /// Forget a key, so that it will be accepted again.
+    ///
+    /// This is used when the key gets re-used for a new event.
+    pub fn forget(&self, key: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.seen.remove(key);
+        // the entry in the order queue will be skipped when it expires
+    }
+
+    fn expire(&self, inner: &mut Inner, now: Instant) {
+        while let Some((time, _)) = inner.order.front() {
+            if now.duration_since(*time) < self.config.window {
+                break;
+            }
+            Self::evict(inner);
+        }
+    }
+
+    fn evict(inner: &mut Inner) {
+        if let Some((time, key)) = inner.order.pop_front() {
+            // only remove the key if it wasn't re-added after being forgotten
+            if inner.seen.get(&key).map(|entry| entry.time) == Some(time) {
+                inner.seen.remove(&key);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    async fn first_seen(dedup: &Deduplicator, key: &str) -> Option<Pending> {
+        match dedup.first_seen(key).await {
+            Seen::First(pending) => Some(pending),
+            Seen::Duplicate => None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_duplicate() {
+        let dedup = Deduplicator::new(Default::default());
+
+        first_seen(&dedup, "a").await.unwrap().accepted();
+        assert!(first_seen(&dedup, "a").await.is_none());
+        assert!(first_seen(&dedup, "b").await.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_failed() {
+        let dedup = Deduplicator::new(Default::default());
+
+        // dropping the guard, without accepting, forgets the key
+        drop(first_seen(&dedup, "a").await.unwrap());
+        first_seen(&dedup, "a").await.unwrap().accepted();
+        assert!(first_seen(&dedup, "a").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_forget() {
+        let dedup = Deduplicator::new(Default::default());
+
+        first_seen(&dedup, "a").await.unwrap().accepted();
+        dedup.forget("a");
+        assert!(first_seen(&dedup, "a").await.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_pending() {
+        let dedup = Deduplicator::new(Default::default());
+
+        // the first attempt fails, while the duplicate is waiting for it
+
+        let first = first_seen(&dedup, "a").await.unwrap();
+        let waiting = {
+            let dedup = dedup.clone();
+            tokio::spawn(async move { first_seen(&dedup, "a").await })
+        };
+        tokio::task::yield_now().await;
+        drop(first);
+
+        // so the duplicate must be forwarded
+
+        let second = waiting.await.unwrap().unwrap();
+
+        // the second attempt succeeds, while another duplicate is waiting
+
+        let waiting = {
+            let dedup = dedup.clone();
+            tokio::spawn(async move { first_seen(&dedup, "a").await })
+        };
+        tokio::task::yield_now().await;
+        second.accepted();
+
+        assert!(waiting.await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_max_entries() {
+        let dedup = Deduplicator::new(DeduplicationConfig {
+            max_entries: 2,
+            ..Default::default()
+        });
+
+        first_seen(&dedup, "a").await.unwrap().accepted();
+        first_seen(&dedup, "b").await.unwrap().accepted();
+        first_seen(&dedup, "c").await.unwrap().accepted();
+        // "a" got evicted
+        assert!(first_seen(&dedup, "a").await.is_some());
+        assert!(first_seen(&dedup, "c").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_disabled() {
+        let dedup = Deduplicator::new(DeduplicationConfig {
+            window: Duration::from_secs(0),
+            ..Default::default()
+        });
+
+        first_seen(&dedup, "a").await.unwrap().accepted();
+        assert!(first_seen(&dedup, "a").await.is_some());
+    }
+}
@@ -107,8 +107,10 @@ impl DownstreamSink for KafkaSink {
 #[cfg(test)]
 mod test {
     use super::*;
+    use serial_test::serial;
 
     #[test]
+    #[serial]
     fn test_custom() {
         std::env::set_var("KAFKA__TOPIC", "baz");
         std::env::set_var("KAFKA__CUSTOM__A_B_C", "d.e.f");
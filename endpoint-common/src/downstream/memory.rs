@@ -0,0 +1,145 @@
+use super::*;
+
+use async_trait::async_trait;
+use drogue_cloud_service_common::{config::ConfigFromEnv, defaults};
+use std::{convert::Infallible, sync::Mutex};
+use tokio::sync::broadcast;
+
+/// The default in-process topic for commands.
+pub const MEMORY_COMMANDS_TOPIC: &str = "iot-commands";
+
+lazy_static::lazy_static! {
+    static ref TOPICS: Mutex<HashMap<String, broadcast::Sender<Event>>> = Mutex::new(HashMap::new());
+}
+
+/// Get the in-process channel for a topic, creating it when necessary.
+///
+/// The capacity is only used when the channel gets created.
+pub fn memory_channel(topic: &str, capacity: usize) -> broadcast::Sender<Event> {
+    TOPICS
+        .lock()
+        .unwrap()
+        .entry(topic.to_string())
+        .or_insert_with(|| broadcast::channel(capacity).0)
+        .clone()
+}
+
+/// Subscribe to events of an in-process topic.
+pub fn memory_subscribe(topic: &str) -> broadcast::Receiver<Event> {
+    memory_channel(topic, default_memory_capacity()).subscribe()
+}
+
+/// Get the default in-process topic for a sink prefix.
+///
+/// Command sinks publish to the commands topic, all others to the events topic.
+pub fn default_memory_topic(prefix: &str) -> String {
+    match prefix {
+        "COMMAND" => MEMORY_COMMANDS_TOPIC.into(),
+        _ => defaults::kafka_events_topic(),
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct MemorySinkConfig {
+    /// The topic to publish to, defaults to the topic of the sink prefix.
+    #[serde(default)]
+    pub topic: Option<String>,
+    #[serde(default = "default_memory_capacity")]
+    pub capacity: usize,
+}
+
+#[inline]
+fn default_memory_capacity() -> usize {
+    1024
+}
+
+/// A sink, publishing events to an in-process broadcast channel.
+///
+/// This is intended for development only, when running multiple services in a single process.
+/// Events are only delivered to receivers which are subscribed at the time of publishing,
+/// nothing is persisted.
+#[derive(Clone)]
+pub struct MemorySink {
+    topic: String,
+    sender: broadcast::Sender<Event>,
+}
+
+impl MemorySink {
+    /// Create a new in-memory sink from a configuration specified by the sink prefix
+    /// (e.g. `DOWNSTREAM`).
+    pub fn new(prefix: &str) -> anyhow::Result<Self> {
+        let config = MemorySinkConfig::from_env_prefix(format!("{}_MEMORY_SINK", prefix))
+            .with_context(|| format!("Failed to parse {}_MEMORY_SINK config", prefix))?;
+
+        Ok(Self::from_config(config, prefix))
+    }
+
+    /// Create a new in-memory sink, using the default topic of the prefix if the configuration
+    /// doesn't provide one.
+    pub fn from_config(config: MemorySinkConfig, prefix: &str) -> Self {
+        let topic = config.topic.unwrap_or_else(|| default_memory_topic(prefix));
+
+        log::info!("Publishing to in-memory topic: {}", topic);
+
+        Self {
+            sender: memory_channel(&topic, config.capacity),
+            topic,
+        }
+    }
+
+    /// The topic this sink publishes to.
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+}
+
+#[async_trait]
+impl DownstreamSink for MemorySink {
+    type Error = Infallible;
+
+    async fn publish(&self, event: Event) -> Result<PublishOutcome, DownstreamError<Self::Error>> {
+        // an error only indicates that there are currently no receivers, which is ok
+        if self.sender.send(event).is_err() {
+            log::debug!("No active receivers for event");
+        }
+
+        Ok(PublishOutcome::Accepted)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cloudevents::{EventBuilder, EventBuilderV10};
+
+    #[tokio::test]
+    async fn test_publish_subscribe() {
+        let sink = MemorySink::from_config(
+            MemorySinkConfig {
+                topic: Some("test-publish-subscribe".into()),
+                capacity: 10,
+            },
+            "DOWNSTREAM",
+        );
+
+        let mut receiver = memory_subscribe("test-publish-subscribe");
+
+        let event = EventBuilderV10::new()
+            .id("1")
+            .ty("type")
+            .source("drogue://foo")
+            .build()
+            .unwrap();
+
+        let outcome = sink.publish(event.clone()).await.unwrap();
+        assert!(matches!(outcome, PublishOutcome::Accepted));
+
+        assert_eq!(receiver.recv().await.unwrap(), event);
+    }
+
+    #[test]
+    fn test_default_topic() {
+        assert_eq!(default_memory_topic("DOWNSTREAM"), "iot-events");
+        assert_eq!(default_memory_topic("COMMAND"), "iot-commands");
+    }
+}
@@ -1,8 +1,22 @@
+mod amqp;
+mod configured;
+mod dedup;
 mod http;
 mod kafka;
+mod memory;
+mod mqtt;
+mod quota;
+mod time;
 
 pub use self::http::HttpSink;
+pub use amqp::*;
+pub use configured::*;
+pub use dedup::*;
 pub use kafka::*;
+pub use memory::*;
+pub use mqtt::*;
+pub use quota::*;
+pub use time::*;
 
 use crate::error::HttpEndpointError;
 use actix_web::HttpResponse;
@@ -10,8 +24,11 @@ use anyhow::Context;
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use cloudevents::{event::Data, Event, EventBuilder, EventBuilderV10};
-use drogue_cloud_service_api::EXT_INSTANCE;
-use drogue_cloud_service_common::{Id, IdInjector};
+use drogue_cloud_service_api::{
+    connection::{ConnectionEvent, CHANNEL_CONNECTION, EVENT_TYPE_CONNECTION},
+    EXT_INSTANCE,
+};
+use drogue_cloud_service_common::{config::ConfigFromEnv, Id, IdInjector};
 use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -22,6 +39,8 @@ use thiserror::Error;
 const DEFAULT_TYPE_EVENT: &str = "io.drogue.event.v1";
 
 const EXT_PARTITIONKEY: &str = "partitionkey";
+/// The time the event was received by the endpoint.
+pub const EXT_RECEIVE_TIME: &str = "recvtime";
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Publish {
@@ -38,6 +57,16 @@ pub struct PublishOptions {
     pub data_schema: Option<String>,
     pub content_type: Option<String>,
     pub extensions: HashMap<String, String>,
+    /// A key identifying retries of the same message.
+    pub idempotency_key: Option<String>,
+    /// Whether the transport flagged the message as a re-delivery.
+    ///
+    /// If set, the idempotency key is only unique for the deliveries of a single message (like an
+    /// MQTT packet ID), and gets re-used for a new message when this is `false`.
+    pub redelivery: Option<bool>,
+    /// The quotas of the application, if any.
+    #[serde(skip)]
+    pub quotas: Option<QuotaSpec>,
 }
 
 #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
@@ -48,6 +77,8 @@ pub enum PublishOutcome {
     Rejected,
     /// Input queue full
     QueueFull,
+    /// Quota of the application or device exceeded
+    QuotaExceeded,
 }
 
 #[async_trait]
@@ -75,6 +106,9 @@ where
 {
     sink: S,
     instance: String,
+    dedup: Deduplicator,
+    time: EventTimeConfig,
+    quotas: QuotaEnforcer,
 }
 
 impl<S> DownstreamSender<S>
@@ -83,8 +117,23 @@ where
 {
     pub fn new(sink: S) -> anyhow::Result<Self> {
         let instance = std::env::var("INSTANCE").context("Missing variable 'INSTANCE'")?;
+        let dedup = DeduplicationConfig::from_env_prefix("DEDUPLICATION")
+            .context("Failed to parse DEDUPLICATION config")?;
+        let time = EventTimeConfig::from_env_prefix("EVENT_TIME")
+            .context("Failed to parse EVENT_TIME config")?;
 
-        Ok(Self { sink, instance })
+        Ok(Self {
+            sink,
+            instance,
+            dedup: Deduplicator::new(dedup),
+            time,
+            quotas: QuotaEnforcer::new(),
+        })
+    }
+
+    /// The quota enforcer, shared by all clones of this sender.
+    pub fn quotas(&self) -> &QuotaEnforcer {
+        &self.quotas
     }
 
     pub async fn publish<B>(
@@ -92,6 +141,49 @@ where
         publish: Publish,
         body: B,
     ) -> Result<PublishOutcome, DownstreamError<S::Error>>
+    where
+        B: AsRef<[u8]>,
+    {
+        self.publish_typed(DEFAULT_TYPE_EVENT, publish, body).await
+    }
+
+    /// Publish a change of the connection state of a device.
+    pub async fn publish_connection(
+        &self,
+        app_id: String,
+        device_id: String,
+        protocol: &str,
+        connected: bool,
+    ) -> Result<PublishOutcome, DownstreamError<S::Error>> {
+        let data = ConnectionEvent {
+            connected,
+            protocol: protocol.to_string(),
+        };
+        // serializing the struct can't fail
+        let body = serde_json::to_vec(&data).unwrap_or_default();
+
+        self.publish_typed(
+            EVENT_TYPE_CONNECTION,
+            Publish {
+                app_id,
+                device_id,
+                channel: CHANNEL_CONNECTION.to_string(),
+                options: PublishOptions {
+                    content_type: Some(mime::APPLICATION_JSON.to_string()),
+                    ..Default::default()
+                },
+            },
+            body,
+        )
+        .await
+    }
+
+    async fn publish_typed<B>(
+        &self,
+        ty: &str,
+        publish: Publish,
+        body: B,
+    ) -> Result<PublishOutcome, DownstreamError<S::Error>>
     where
         B: AsRef<[u8]>,
     {
@@ -100,17 +192,74 @@ where
 
         let source = format!("{}/{}", app_enc, device_enc);
 
+        // prefer the time provided by the device, if it is plausible
+
+        let received = Utc::now();
+        let time = match self.time.event_time(received, publish.options.time) {
+            Some(time) => time,
+            None => {
+                log::debug!(
+                    "Rejecting event time outside of permitted clock skew: {:?}",
+                    publish.options.time
+                );
+                return Ok(PublishOutcome::Rejected);
+            }
+        };
+
+        // scope the idempotency key to the device
+        let key = publish
+            .options
+            .idempotency_key
+            .as_ref()
+            .map(|key| format!("{}/{}", source, key));
+
+        let (id, pending) = match &key {
+            Some(key) => {
+                if publish.options.redelivery == Some(false) {
+                    // the key gets re-used for a new event
+                    self.dedup.forget(key);
+                }
+                match self.dedup.first_seen(key).await {
+                    Seen::First(pending) => (
+                        // derive a stable event ID, allowing consumers to detect duplicates as well
+                        uuid::Uuid::new_v5(&uuid::Uuid::NAMESPACE_URL, key.as_bytes()),
+                        Some(pending),
+                    ),
+                    Seen::Duplicate => {
+                        log::debug!("Dropping duplicate event: {}", key);
+                        return Ok(PublishOutcome::Accepted);
+                    }
+                }
+            }
+            None => (uuid::Uuid::new_v4(), None),
+        };
+
+        // check the quota only after dropping duplicates, which must not count against it. When
+        // rejected, dropping the pending key forgets it.
+        if let Some(quotas) = &publish.options.quotas {
+            if !self.quotas.check_message(
+                quotas,
+                &publish.app_id,
+                &publish.device_id,
+                body.as_ref().len(),
+            ) {
+                log::debug!("Rejecting event exceeding the quota of: {}", source);
+                return Ok(PublishOutcome::QuotaExceeded);
+            }
+        }
+
         let mut event = EventBuilderV10::new()
-            .id(uuid::Uuid::new_v4().to_string())
-            .ty(DEFAULT_TYPE_EVENT)
+            .id(id.to_string())
+            .ty(ty)
             // we need an "absolute" URL for the moment: until 0.4 is released
             // see: https://github.com/cloudevents/sdk-rust/issues/106
             .source(format!("drogue://{}", source))
             .inject(Id::new(publish.app_id, publish.device_id))
             .subject(&publish.channel)
-            .time(Utc::now());
+            .time(time);
 
         event = event.extension(EXT_PARTITIONKEY, source);
+        event = event.extension(EXT_RECEIVE_TIME, received.to_rfc3339());
         event = event.extension(EXT_INSTANCE, self.instance.clone());
 
         if let Some(data_schema) = publish.options.data_schema {
@@ -142,7 +291,17 @@ where
 
         // build event
 
-        self.sink.publish(event.build()?).await
+        let result = self.sink.publish(event.build()?).await;
+
+        // if the event was not accepted, the device is expected to retry, dropping the pending
+        // key forgets it
+        if let Some(pending) = pending {
+            if matches!(result, Ok(PublishOutcome::Accepted)) {
+                pending.accepted();
+            }
+        }
+
+        result
     }
 
     pub async fn publish_http<B, H, F>(
@@ -180,8 +339,58 @@ where
                 PublishOutcome::Accepted => Ok(HttpResponse::Accepted().finish()),
                 PublishOutcome::Rejected => Ok(HttpResponse::NotAcceptable().finish()),
                 PublishOutcome::QueueFull => Ok(HttpResponse::ServiceUnavailable().finish()),
+                PublishOutcome::QuotaExceeded => Ok(HttpResponse::TooManyRequests().finish()),
             }
         })
         .await
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn publish(key: &str, quotas: &QuotaSpec) -> Publish {
+        Publish {
+            app_id: "app1".into(),
+            device_id: "device1".into(),
+            channel: "telemetry".into(),
+            options: PublishOptions {
+                idempotency_key: Some(key.into()),
+                quotas: Some(quotas.clone()),
+                ..Default::default()
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn test_duplicates_dont_count_against_quota() {
+        let sender = DownstreamSender {
+            sink: MemorySink::from_config(
+                MemorySinkConfig {
+                    topic: Some("test-duplicates-quota".into()),
+                    capacity: 10,
+                },
+                "DOWNSTREAM",
+            ),
+            instance: "test".into(),
+            dedup: Deduplicator::new(Default::default()),
+            time: Default::default(),
+            quotas: QuotaEnforcer::new(),
+        };
+        let quotas = QuotaSpec {
+            device_messages_per_second: Some(1),
+            ..Default::default()
+        };
+
+        let outcome = sender.publish(publish("1", &quotas), b"{}").await.unwrap();
+        assert!(matches!(outcome, PublishOutcome::Accepted));
+
+        // the retry gets dropped as a duplicate, before checking the quota
+        let outcome = sender.publish(publish("1", &quotas), b"{}").await.unwrap();
+        assert!(matches!(outcome, PublishOutcome::Accepted));
+
+        let outcome = sender.publish(publish("2", &quotas), b"{}").await.unwrap();
+        assert!(matches!(outcome, PublishOutcome::QuotaExceeded));
+    }
+}
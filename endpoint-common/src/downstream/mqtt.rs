@@ -0,0 +1,339 @@
+use super::*;
+
+use async_trait::async_trait;
+use cloudevents::AttributesReader;
+use drogue_cloud_service_common::config::ConfigFromEnv;
+use futures::channel::oneshot;
+use rumqttc::{EventLoop, MqttOptions, Packet, Publish, QoS, Request};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum MqttSinkError {
+    #[error("MQTT event loop stopped")]
+    Closed,
+    #[error("Timeout waiting for acknowledgement")]
+    Timeout,
+    #[error("Transmission canceled")]
+    Canceled,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct MqttSinkConfig {
+    pub host: String,
+    #[serde(default = "default_mqtt_port")]
+    pub port: u16,
+    #[serde(default)]
+    pub client_id: Option<String>,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    /// The topic to publish to. The placeholders `{app}`, `{device}` and `{channel}` get replaced
+    /// with the values from the event.
+    #[serde(default = "default_mqtt_topic")]
+    pub topic: String,
+    #[serde(default = "default_mqtt_queue_size")]
+    pub queue_size: usize,
+    /// The maximum number of messages waiting for their acknowledgement.
+    #[serde(default = "default_mqtt_max_inflight")]
+    pub max_inflight: u16,
+    /// The time to wait for the broker to acknowledge a message.
+    #[serde(default = "default_mqtt_ack_timeout", with = "humantime_serde")]
+    pub ack_timeout: Duration,
+}
+
+#[inline]
+fn default_mqtt_port() -> u16 {
+    1883
+}
+
+#[inline]
+fn default_mqtt_topic() -> String {
+    "drogue/{app}/{device}/{channel}".into()
+}
+
+#[inline]
+fn default_mqtt_queue_size() -> usize {
+    10
+}
+
+#[inline]
+fn default_mqtt_max_inflight() -> u16 {
+    100
+}
+
+#[inline]
+fn default_mqtt_ack_timeout() -> Duration {
+    Duration::from_secs(10)
+}
+
+/// Publish requests, waiting for their acknowledgement.
+///
+/// The sink assigns the packet IDs itself, so that an acknowledgement always belongs to the
+/// request it was sent for, even if the message gets re-sent after a re-connect.
+struct Acks {
+    max_inflight: u16,
+    last: u16,
+    /// Requests which have been sent, by packet ID.
+    ///
+    /// A request which timed out keeps its packet ID until the broker acknowledged it, as the
+    /// event loop may still re-send the message using it.
+    inflight: HashMap<u16, oneshot::Sender<()>>,
+}
+
+impl Acks {
+    fn new(max_inflight: u16) -> Self {
+        Self {
+            max_inflight: max_inflight.max(1),
+            last: 0,
+            inflight: HashMap::new(),
+        }
+    }
+
+    /// Assign a free packet ID to a request, `None` if all packet IDs are in use.
+    fn assign(&mut self, ack: oneshot::Sender<()>) -> Option<u16> {
+        for _ in 0..self.max_inflight {
+            self.last = self.last % self.max_inflight + 1;
+            if !self.inflight.contains_key(&self.last) {
+                self.inflight.insert(self.last, ack);
+                return Some(self.last);
+            }
+        }
+        None
+    }
+
+    /// Release a packet ID, which was not sent.
+    fn release(&mut self, pkid: u16) {
+        self.inflight.remove(&pkid);
+    }
+
+    fn acknowledged(&mut self, pkid: u16) {
+        // a request which timed out is dropped now
+        if let Some(ack) = self.inflight.remove(&pkid) {
+            let _ = ack.send(());
+        }
+    }
+}
+
+/// A sink, publishing events to an MQTT broker.
+///
+/// As MQTT 3.1.1 has no support for message properties, this sink uses the structured content
+/// mode of the CloudEvents MQTT protocol binding.
+///
+/// Messages are published with QoS 1, and are only considered accepted once the broker
+/// acknowledged them.
+#[derive(Clone)]
+pub struct MqttSink {
+    requests: async_channel::Sender<Request>,
+    topic: String,
+    ack_timeout: Duration,
+    acks: Arc<Mutex<Acks>>,
+}
+
+impl MqttSink {
+    /// Create a new MQTT sink from a configuration specified by the prefix.
+    pub fn new(prefix: &str) -> anyhow::Result<Self> {
+        let config = MqttSinkConfig::from_env_prefix(prefix)
+            .with_context(|| format!("Failed to parse {} config", prefix))?;
+
+        let client_id = config
+            .client_id
+            .unwrap_or_else(|| format!("drogue-sink-{}", uuid::Uuid::new_v4()));
+
+        let mut options = MqttOptions::new(client_id, config.host, config.port);
+        options.set_keep_alive(30);
+        options.set_inflight(config.max_inflight);
+        if let (Some(username), Some(password)) = (config.username, config.password) {
+            options.set_credentials(username, password);
+        }
+
+        let mut event_loop = EventLoop::new(options, config.queue_size);
+        let requests = event_loop.handle();
+        let acks = Arc::new(Mutex::new(Acks::new(config.max_inflight)));
+
+        let loop_acks = acks.clone();
+        tokio::spawn(async move {
+            loop {
+                match event_loop.poll().await {
+                    Ok(rumqttc::Event::Incoming(Packet::PubAck(ack))) => {
+                        loop_acks.lock().unwrap().acknowledged(ack.pkid);
+                    }
+                    Ok(rumqttc::Event::Incoming(incoming)) => {
+                        log::debug!("MQTT sink - incoming: {:?}", incoming);
+                    }
+                    Ok(_) => {}
+                    Err(err) => {
+                        log::warn!("MQTT sink - connection error: {}", err);
+                        // the event loop will re-connect on the next poll
+                        tokio::time::sleep(Duration::from_secs(1)).await;
+                    }
+                }
+            }
+        });
+
+        Ok(Self {
+            requests,
+            topic: config.topic,
+            ack_timeout: config.ack_timeout,
+            acks,
+        })
+    }
+
+    /// Get the topic for an event, `None` if the event is missing the device information.
+    fn topic_for(&self, event: &Event) -> Option<String> {
+        let id = Id::from_event(event)?;
+
+        Some(
+            self.topic
+                .replace("{app}", &id.app_id)
+                .replace("{device}", &id.device_id)
+                .replace("{channel}", event.subject().unwrap_or_default()),
+        )
+    }
+}
+
+#[async_trait]
+impl DownstreamSink for MqttSink {
+    type Error = MqttSinkError;
+
+    async fn publish(&self, event: Event) -> Result<PublishOutcome, DownstreamError<Self::Error>> {
+        let topic = match self.topic_for(&event) {
+            Some(topic) => topic,
+            None => {
+                log::debug!(
+                    "Rejecting event without application and device: {}",
+                    event.id()
+                );
+                return Ok(PublishOutcome::Rejected);
+            }
+        };
+
+        log::debug!("Topic: {}", topic);
+
+        let payload = serde_json::to_vec(&event).map_err(|err| {
+            DownstreamError::Event(cloudevents::message::Error::SerdeJsonError { source: err })
+        })?;
+
+        let (tx, rx) = oneshot::channel();
+
+        {
+            let mut acks = self.acks.lock().unwrap();
+
+            let pkid = match acks.assign(tx) {
+                Some(pkid) => pkid,
+                // too many messages waiting for their acknowledgement
+                None => return Ok(PublishOutcome::QueueFull),
+            };
+
+            let mut publish = Publish::new(topic, QoS::AtLeastOnce, payload);
+            publish.pkid = pkid;
+
+            if let Err(err) = self.requests.try_send(Request::Publish(publish)) {
+                acks.release(pkid);
+                return if err.is_full() {
+                    // the request queue of the event loop is full
+                    Ok(PublishOutcome::QueueFull)
+                } else {
+                    log::debug!("Failed to send: {}", err);
+                    Err(DownstreamError::Transport(MqttSinkError::Closed))
+                };
+            }
+        }
+
+        match tokio::time::timeout(self.ack_timeout, rx).await {
+            // acknowledged by the broker
+            Ok(Ok(())) => Ok(PublishOutcome::Accepted),
+            // event loop dropped the request
+            Ok(Err(oneshot::Canceled)) => Err(DownstreamError::Transport(MqttSinkError::Canceled)),
+            Err(_) => {
+                log::debug!("No acknowledgement within {:?}", self.ack_timeout);
+                Err(DownstreamError::Transport(MqttSinkError::Timeout))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serial_test::serial;
+
+    #[test]
+    #[serial]
+    fn test_config() {
+        std::env::set_var("MQTT__HOST", "localhost");
+
+        let mqtt = MqttSinkConfig::from_env_prefix("MQTT").unwrap();
+
+        assert_eq!(mqtt.host, "localhost");
+        assert_eq!(mqtt.port, 1883);
+        assert_eq!(mqtt.topic, "drogue/{app}/{device}/{channel}");
+        assert_eq!(mqtt.ack_timeout, Duration::from_secs(10));
+
+        std::env::remove_var("MQTT__HOST");
+    }
+
+    #[test]
+    fn test_acks() {
+        let mut acks = Acks::new(2);
+
+        let (tx1, mut rx1) = oneshot::channel();
+        let (tx2, mut rx2) = oneshot::channel();
+        assert_eq!(acks.assign(tx1), Some(1));
+        assert_eq!(acks.assign(tx2), Some(2));
+
+        // all packet IDs are in use
+        let (tx3, _) = oneshot::channel();
+        assert_eq!(acks.assign(tx3), None);
+
+        acks.acknowledged(2);
+        assert_eq!(rx2.try_recv(), Ok(Some(())));
+        assert_eq!(rx1.try_recv(), Ok(None));
+
+        // a timed out request keeps its packet ID, until it got acknowledged
+        drop(rx1);
+        let (tx4, mut rx4) = oneshot::channel();
+        assert_eq!(acks.assign(tx4), Some(2));
+
+        let (tx5, _) = oneshot::channel();
+        assert_eq!(acks.assign(tx5), None);
+
+        acks.acknowledged(1);
+        acks.acknowledged(2);
+        assert_eq!(rx4.try_recv(), Ok(Some(())));
+        assert!(acks.inflight.is_empty());
+    }
+
+    #[test]
+    fn test_topic() {
+        let event_loop = EventLoop::new(MqttOptions::new("test", "localhost", 1883), 10);
+        let sink = MqttSink {
+            requests: event_loop.handle(),
+            topic: default_mqtt_topic(),
+            ack_timeout: default_mqtt_ack_timeout(),
+            acks: Arc::new(Mutex::new(Acks::new(default_mqtt_max_inflight()))),
+        };
+
+        let event = EventBuilderV10::new()
+            .id("1")
+            .ty(DEFAULT_TYPE_EVENT)
+            .source("drogue://app1/device1")
+            .subject("temp");
+
+        assert_eq!(
+            sink.topic_for(
+                &event
+                    .clone()
+                    .inject(Id::new("app1", "device1"))
+                    .build()
+                    .unwrap()
+            ),
+            Some("drogue/app1/device1/temp".into())
+        );
+        assert_eq!(sink.topic_for(&event.build().unwrap()), None);
+    }
+}
@@ -0,0 +1,337 @@
+use chrono::{Date, Utc};
+use drogue_client::{dialect, registry, Dialect, Section};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// The interval in which idle rate limiters get cleaned up.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Quotas of an application, stored in the spec section `quotas`.
+///
+/// Quotas are enforced by each endpoint instance individually, using in-memory state only. So all
+/// limits apply per instance, and the effective limit of a deployment grows with the number of
+/// endpoint instances. They protect the system from floods of messages, they are not suited for
+/// accounting.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct QuotaSpec {
+    /// The maximum number of messages per second, for all devices of the application connected to
+    /// the same endpoint instance.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub messages_per_second: Option<u32>,
+    /// The maximum number of messages per second, for a single device.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device_messages_per_second: Option<u32>,
+    /// The maximum number of payload bytes per day (UTC), for all devices of the application
+    /// connected to the same endpoint instance.
+    ///
+    /// The counter starts from zero when the endpoint instance gets restarted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bytes_per_day: Option<u64>,
+    /// The maximum number of devices connected at the same time to the same endpoint instance,
+    /// for connection oriented protocols.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_connected_devices: Option<u32>,
+}
+
+dialect!(QuotaSpec[Section::Spec => "quotas"]);
+
+impl QuotaSpec {
+    /// Get the quotas of an application, ignoring an invalid quota section.
+    pub fn from_application(application: &registry::v1::Application) -> Option<Self> {
+        match application.section::<QuotaSpec>() {
+            Some(Ok(quotas)) => Some(quotas),
+            Some(Err(err)) => {
+                log::info!(
+                    "Invalid quotas of application '{}': {}",
+                    application.metadata.name,
+                    err
+                );
+                None
+            }
+            None => None,
+        }
+    }
+}
+
+/// A token bucket, allowing a burst of one second worth of messages.
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    last: Instant,
+}
+
+impl Bucket {
+    fn new(now: Instant) -> Self {
+        Self {
+            tokens: f64::MAX,
+            last: now,
+        }
+    }
+
+    fn refill(&mut self, rate: u32, now: Instant) -> bool {
+        let rate = rate as f64;
+        let elapsed = now.duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(rate);
+        self.last = now;
+        self.tokens >= 1.0
+    }
+
+    fn is_idle(&self, now: Instant) -> bool {
+        now.duration_since(self.last) > Duration::from_secs(1)
+    }
+}
+
+#[derive(Debug)]
+struct Application {
+    bucket: Bucket,
+    devices: HashMap<String, Bucket>,
+    day: Date<Utc>,
+    bytes: u64,
+    connections: HashMap<String, usize>,
+}
+
+impl Application {
+    fn new(now: Instant) -> Self {
+        Self {
+            bucket: Bucket::new(now),
+            devices: HashMap::new(),
+            day: Utc::today(),
+            bytes: 0,
+            connections: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Inner {
+    applications: HashMap<String, Application>,
+    last_prune: Instant,
+}
+
+impl Default for Inner {
+    fn default() -> Self {
+        Self {
+            applications: HashMap::new(),
+            last_prune: Instant::now(),
+        }
+    }
+}
+
+/// Tracking the usage of applications and devices, and checking it against their quotas.
+#[derive(Clone, Debug, Default)]
+pub struct QuotaEnforcer {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl QuotaEnforcer {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Record a message, returning `false` if this would exceed the quota.
+    ///
+    /// Messages exceeding the quota are not recorded.
+    pub fn check_message(&self, quotas: &QuotaSpec, app: &str, device: &str, size: usize) -> bool {
+        if quotas.messages_per_second.is_none()
+            && quotas.device_messages_per_second.is_none()
+            && quotas.bytes_per_day.is_none()
+        {
+            return true;
+        }
+
+        let now = Instant::now();
+        let mut inner = self.inner.lock().unwrap();
+
+        Self::prune(&mut inner, now);
+
+        let state = inner
+            .applications
+            .entry(app.to_string())
+            .or_insert_with(|| Application::new(now));
+
+        if let Some(rate) = quotas.messages_per_second {
+            if !state.bucket.refill(rate, now) {
+                return false;
+            }
+        }
+
+        if let Some(rate) = quotas.device_messages_per_second {
+            let bucket = state
+                .devices
+                .entry(device.to_string())
+                .or_insert_with(|| Bucket::new(now));
+            if !bucket.refill(rate, now) {
+                return false;
+            }
+        }
+
+        if let Some(max) = quotas.bytes_per_day {
+            let today = Utc::today();
+            if state.day != today {
+                state.day = today;
+                state.bytes = 0;
+            }
+            if state.bytes.saturating_add(size as u64) > max {
+                return false;
+            }
+        }
+
+        // all checks passed, consume
+
+        if quotas.messages_per_second.is_some() {
+            state.bucket.tokens -= 1.0;
+        }
+        if quotas.device_messages_per_second.is_some() {
+            if let Some(bucket) = state.devices.get_mut(device) {
+                bucket.tokens -= 1.0;
+            }
+        }
+        state.bytes = state.bytes.saturating_add(size as u64);
+
+        true
+    }
+
+    /// Record a connection of a device, returning `None` if this would exceed the quota.
+    ///
+    /// Multiple connections of the same device only count once. The connection is released
+    /// when the returned guard is dropped.
+    pub fn connect(&self, quotas: &QuotaSpec, app: &str, device: &str) -> Option<ConnectionGuard> {
+        let mut inner = self.inner.lock().unwrap();
+
+        let state = inner
+            .applications
+            .entry(app.to_string())
+            .or_insert_with(|| Application::new(Instant::now()));
+
+        if let Some(max) = quotas.max_connected_devices {
+            if !state.connections.contains_key(device) && state.connections.len() >= max as usize {
+                return None;
+            }
+        }
+
+        *state.connections.entry(device.to_string()).or_default() += 1;
+
+        Some(ConnectionGuard {
+            inner: self.inner.clone(),
+            app: app.to_string(),
+            device: device.to_string(),
+        })
+    }
+
+    /// Remove rate limiters which are idle, and so would be re-created in the same state.
+    fn prune(inner: &mut Inner, now: Instant) {
+        if now.duration_since(inner.last_prune) < PRUNE_INTERVAL {
+            return;
+        }
+        inner.last_prune = now;
+
+        let today = Utc::today();
+        inner.applications.retain(|_, state| {
+            state.devices.retain(|_, bucket| !bucket.is_idle(now));
+            !state.bucket.is_idle(now)
+                || !state.devices.is_empty()
+                || !state.connections.is_empty()
+                || (state.day == today && state.bytes > 0)
+        });
+    }
+}
+
+/// A connection of a device, counting against the quota of its application as long as it exists.
+#[derive(Debug)]
+pub struct ConnectionGuard {
+    inner: Arc<Mutex<Inner>>,
+    app: String,
+    device: String,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(state) = inner.applications.get_mut(&self.app) {
+            if let Some(count) = state.connections.get_mut(&self.device) {
+                *count -= 1;
+                if *count == 0 {
+                    state.connections.remove(&self.device);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_unlimited() {
+        let quotas = QuotaEnforcer::new();
+
+        for _ in 0..100 {
+            assert!(quotas.check_message(&Default::default(), "app", "device", 1024));
+        }
+    }
+
+    #[test]
+    fn test_messages_per_second() {
+        let quotas = QuotaEnforcer::new();
+        let spec = QuotaSpec {
+            messages_per_second: Some(2),
+            ..Default::default()
+        };
+
+        assert!(quotas.check_message(&spec, "app", "device1", 0));
+        assert!(quotas.check_message(&spec, "app", "device2", 0));
+        assert!(!quotas.check_message(&spec, "app", "device3", 0));
+        // other applications are not affected
+        assert!(quotas.check_message(&spec, "app2", "device1", 0));
+    }
+
+    #[test]
+    fn test_device_messages_per_second() {
+        let quotas = QuotaEnforcer::new();
+        let spec = QuotaSpec {
+            device_messages_per_second: Some(1),
+            ..Default::default()
+        };
+
+        assert!(quotas.check_message(&spec, "app", "device1", 0));
+        assert!(!quotas.check_message(&spec, "app", "device1", 0));
+        assert!(quotas.check_message(&spec, "app", "device2", 0));
+    }
+
+    #[test]
+    fn test_bytes_per_day() {
+        let quotas = QuotaEnforcer::new();
+        let spec = QuotaSpec {
+            bytes_per_day: Some(100),
+            ..Default::default()
+        };
+
+        assert!(quotas.check_message(&spec, "app", "device", 60));
+        assert!(!quotas.check_message(&spec, "app", "device", 60));
+        assert!(quotas.check_message(&spec, "app", "device", 40));
+    }
+
+    #[test]
+    fn test_connections() {
+        let quotas = QuotaEnforcer::new();
+        let spec = QuotaSpec {
+            max_connected_devices: Some(1),
+            ..Default::default()
+        };
+
+        let first = quotas.connect(&spec, "app", "device1");
+        assert!(first.is_some());
+        // the same device may connect again
+        assert!(quotas.connect(&spec, "app", "device1").is_some());
+        assert!(quotas.connect(&spec, "app", "device2").is_none());
+
+        drop(first);
+        assert!(quotas.connect(&spec, "app", "device2").is_some());
+    }
+}
@@ -0,0 +1,102 @@
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::time::Duration;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct EventTimeConfig {
+    /// The maximum time a device supplied timestamp may be ahead of the receive time.
+    #[serde(default = "default_max_future_skew", with = "humantime_serde")]
+    pub max_future_skew: Option<Duration>,
+    /// The maximum time a device supplied timestamp may be behind the receive time.
+    #[serde(default, with = "humantime_serde")]
+    pub max_past_skew: Option<Duration>,
+}
+
+impl Default for EventTimeConfig {
+    fn default() -> Self {
+        Self {
+            max_future_skew: default_max_future_skew(),
+            max_past_skew: None,
+        }
+    }
+}
+
+#[inline]
+fn default_max_future_skew() -> Option<Duration> {
+    Some(Duration::from_secs(5 * 60))
+}
+
+impl EventTimeConfig {
+    /// Evaluate the time of the event, based on the receive time and an optional, device supplied
+    /// timestamp.
+    ///
+    /// Returns `None` if the device supplied timestamp is outside of the permitted limits.
+    pub fn event_time(
+        &self,
+        received: DateTime<Utc>,
+        device: Option<DateTime<Utc>>,
+    ) -> Option<DateTime<Utc>> {
+        let time = match device {
+            Some(time) => time,
+            None => return Some(received),
+        };
+
+        let skew = time.signed_duration_since(received);
+        let limit = match skew.to_std() {
+            // in the future
+            Ok(skew) => self.max_future_skew.map(|max| (skew, max)),
+            // in the past
+            Err(_) => match (-skew).to_std() {
+                Ok(skew) => self.max_past_skew.map(|max| (skew, max)),
+                Err(_) => None,
+            },
+        };
+
+        match limit {
+            Some((skew, max)) if skew > max => None,
+            _ => Some(time),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::Duration as ChronoDuration;
+
+    #[test]
+    fn test_no_device_time() {
+        let config = EventTimeConfig::default();
+        let now = Utc::now();
+
+        assert_eq!(config.event_time(now, None), Some(now));
+    }
+
+    #[test]
+    fn test_limits() {
+        let config = EventTimeConfig {
+            max_future_skew: Some(Duration::from_secs(60)),
+            max_past_skew: Some(Duration::from_secs(3600)),
+        };
+        let now = Utc::now();
+
+        let t = now + ChronoDuration::seconds(30);
+        assert_eq!(config.event_time(now, Some(t)), Some(t));
+        let t = now + ChronoDuration::seconds(90);
+        assert_eq!(config.event_time(now, Some(t)), None);
+
+        let t = now - ChronoDuration::minutes(30);
+        assert_eq!(config.event_time(now, Some(t)), Some(t));
+        let t = now - ChronoDuration::minutes(90);
+        assert_eq!(config.event_time(now, Some(t)), None);
+    }
+
+    #[test]
+    fn test_no_past_limit() {
+        let config = EventTimeConfig::default();
+        let now = Utc::now();
+
+        let t = now - ChronoDuration::days(365);
+        assert_eq!(config.event_time(now, Some(t)), Some(t));
+    }
+}
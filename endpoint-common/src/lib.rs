@@ -3,4 +3,5 @@ pub mod command_endpoint;
 pub mod commands;
 pub mod downstream;
 pub mod error;
+pub mod payload;
 pub mod x509;
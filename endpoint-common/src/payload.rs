@@ -0,0 +1,70 @@
+use serde_json::Value;
+
+/// Decode a payload, embedded in a JSON structure.
+///
+/// The payload is either provided as a JSON value, or as a base64 encoded string, in the fields
+/// `<field>` and `<field>_base64`. A JSON string is used as text, any other JSON value as JSON.
+///
+/// Returns the payload, and the content type to use.
+pub fn decode_payload(
+    field: &str,
+    value: Option<&Value>,
+    base64: Option<&str>,
+    content_type: Option<&str>,
+) -> Result<(Vec<u8>, Option<String>), String> {
+    let content_type = content_type.map(ToString::to_string);
+
+    match (value, base64) {
+        (Some(_), Some(_)) => Err(format!(
+            "Only one of '{0}' and '{0}_base64' must be set",
+            field
+        )),
+        (None, Some(data)) => base64::decode(data)
+            .map(|data| (data, content_type))
+            .map_err(|err| format!("Failed to decode '{}_base64': {}", field, err)),
+        (Some(Value::String(data)), None) => Ok((
+            data.as_bytes().to_vec(),
+            Some(content_type.unwrap_or_else(|| mime::TEXT_PLAIN_UTF_8.to_string())),
+        )),
+        (Some(data), None) => Ok((
+            data.to_string().into_bytes(),
+            Some(content_type.unwrap_or_else(|| mime::APPLICATION_JSON.to_string())),
+        )),
+        (None, None) => Ok((vec![], content_type)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(
+            decode_payload("data", Some(&json!({"temp": 42})), None, None),
+            Ok((
+                br#"{"temp":42}"#.to_vec(),
+                Some("application/json".to_string())
+            ))
+        );
+        assert_eq!(
+            decode_payload("data", Some(&json!("foo")), None, Some("text/csv")),
+            Ok((b"foo".to_vec(), Some("text/csv".to_string())))
+        );
+        assert_eq!(
+            decode_payload("data", None, Some("AQI="), None),
+            Ok((vec![1u8, 2u8], None))
+        );
+        assert_eq!(decode_payload("data", None, None, None), Ok((vec![], None)));
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        assert_eq!(
+            decode_payload("payload", Some(&json!("foo")), Some("AQI="), None),
+            Err("Only one of 'payload' and 'payload_base64' must be set".to_string())
+        );
+        assert!(decode_payload("data", None, Some("%%%"), None).is_err());
+    }
+}
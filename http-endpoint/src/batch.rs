@@ -0,0 +1,292 @@
+use crate::telemetry::PublishCommonOptions;
+use actix_web::{http::header, web, HttpResponse};
+use chrono::{DateTime, Utc};
+use drogue_cloud_endpoint_common::{
+    auth::DeviceAuthenticator,
+    downstream::{self, DownstreamSender, DownstreamSink, PublishOutcome},
+    error::{EndpointError, HttpEndpointError},
+    payload::decode_payload,
+    x509::ClientCertificateChain,
+};
+use drogue_cloud_service_api::auth::device::authn;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+const CONTENT_TYPE_NDJSON: &str = "application/x-ndjson";
+
+/// The limits of batches.
+#[derive(Clone, Debug)]
+pub struct BatchConfig {
+    /// The maximum number of entries of a batch.
+    pub max_entries: usize,
+}
+
+#[derive(Deserialize)]
+pub struct BatchOptions {
+    #[serde(flatten)]
+    pub common: PublishCommonOptions,
+
+    pub r#as: Option<String>,
+}
+
+/// A single entry of a batch.
+///
+/// The field names are aligned with the structured mode of CloudEvents, so that events in that
+/// format can be used as well.
+#[derive(Clone, Debug, Deserialize)]
+pub struct BatchEntry {
+    #[serde(alias = "subject")]
+    pub channel: String,
+    #[serde(default)]
+    pub time: Option<DateTime<Utc>>,
+    #[serde(default, alias = "datacontenttype")]
+    pub content_type: Option<String>,
+    #[serde(default, alias = "dataschema")]
+    pub data_schema: Option<String>,
+    #[serde(default, alias = "id")]
+    pub idempotency_key: Option<String>,
+    #[serde(default)]
+    pub data: Option<Value>,
+    #[serde(default)]
+    pub data_base64: Option<String>,
+}
+
+impl BatchEntry {
+    /// Get the payload, and the content type to use.
+    fn payload(&self) -> Result<(Vec<u8>, Option<String>), String> {
+        decode_payload(
+            "data",
+            self.data.as_ref(),
+            self.data_base64.as_deref(),
+            self.content_type.as_deref(),
+        )
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct BatchEntryResult {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outcome: Option<PublishOutcome>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct BatchResult {
+    pub results: Vec<BatchEntryResult>,
+}
+
+/// Parse the batch, either from a JSON array, or from newline delimited JSON.
+///
+/// Batches with more than `max_entries` entries are rejected.
+fn parse_batch(
+    content_type: Option<&str>,
+    body: &[u8],
+    max_entries: usize,
+) -> Result<Vec<BatchEntry>, EndpointError> {
+    let ndjson = content_type
+        .map(|ct| ct.starts_with(CONTENT_TYPE_NDJSON))
+        .unwrap_or_default();
+
+    let invalid_format = |err: serde_json::Error| EndpointError::InvalidFormat {
+        source: Box::new(err),
+    };
+    let too_many = || EndpointError::InvalidRequest {
+        details: format!("Batch exceeds the maximum of {} entries", max_entries),
+    };
+
+    if ndjson {
+        let mut entries = Vec::new();
+        for entry in serde_json::Deserializer::from_slice(body).into_iter::<BatchEntry>() {
+            // stop parsing as soon as the limit is exceeded
+            if entries.len() >= max_entries {
+                return Err(too_many());
+            }
+            entries.push(entry.map_err(invalid_format)?);
+        }
+        Ok(entries)
+    } else {
+        let entries: Vec<BatchEntry> = serde_json::from_slice(body).map_err(invalid_format)?;
+        if entries.len() > max_entries {
+            return Err(too_many());
+        }
+        Ok(entries)
+    }
+}
+
+pub async fn publish_batch<S>(
+    sender: web::Data<DownstreamSender<S>>,
+    auth: web::Data<DeviceAuthenticator>,
+    config: web::Data<BatchConfig>,
+    web::Query(opts): web::Query<BatchOptions>,
+    req: web::HttpRequest,
+    body: web::Bytes,
+    certs: Option<ClientCertificateChain>,
+) -> Result<HttpResponse, HttpEndpointError>
+where
+    S: DownstreamSink,
+{
+    let (application, device, r#as) = match auth
+        .authenticate_http(
+            opts.common.application,
+            opts.common.device,
+            req.headers().get(http::header::AUTHORIZATION),
+            certs.map(|c| c.0),
+            opts.r#as.clone(),
+        )
+        .await
+        .map_err(|err| HttpEndpointError(err.into()))?
+        .outcome
+    {
+        authn::Outcome::Fail => return Err(HttpEndpointError(EndpointError::AuthenticationError)),
+        authn::Outcome::Pass {
+            application,
+            device,
+            r#as,
+        } => (application, device, r#as),
+    };
+
+    // only parse the batch of authenticated devices
+
+    let entries = parse_batch(
+        req.headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok()),
+        &body,
+        config.max_entries,
+    )?;
+
+    log::debug!("Publish batch of {} entries", entries.len());
+
+    // If we have an "as" parameter, we publish as another device.
+    let device_id = match r#as {
+        Some(device) => device.metadata.name,
+        None => device.metadata.name,
+    };
+
+    let quotas = downstream::QuotaSpec::from_application(&application);
+
+    let mut result = BatchResult::default();
+
+    for entry in entries {
+        let (body, content_type) = match entry.payload() {
+            Ok(payload) => payload,
+            Err(error) => {
+                result.results.push(BatchEntryResult {
+                    error: Some(error),
+                    ..Default::default()
+                });
+                continue;
+            }
+        };
+
+        let publish = downstream::Publish {
+            channel: entry.channel,
+            app_id: application.metadata.name.clone(),
+            device_id: device_id.clone(),
+            options: downstream::PublishOptions {
+                time: entry.time,
+                data_schema: entry
+                    .data_schema
+                    .or_else(|| opts.common.data_schema.clone()),
+                content_type,
+                idempotency_key: entry.idempotency_key,
+                quotas: quotas.clone(),
+                ..Default::default()
+            },
+        };
+
+        result
+            .results
+            .push(match sender.publish(publish, body).await {
+                Ok(outcome) => BatchEntryResult {
+                    outcome: Some(outcome),
+                    ..Default::default()
+                },
+                Err(err) => BatchEntryResult {
+                    error: Some(err.to_string()),
+                    ..Default::default()
+                },
+            });
+    }
+
+    Ok(HttpResponse::Ok().json(result))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_array() {
+        let body = json!([
+            {"channel": "foo", "data": {"temp": 42}},
+            {"subject": "bar", "time": "2021-01-01T00:00:00Z", "data_base64": "AQI="},
+        ])
+        .to_string();
+
+        let entries = parse_batch(Some("application/json"), body.as_bytes(), 10).unwrap();
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].channel, "foo");
+        assert_eq!(
+            entries[0].payload().unwrap(),
+            (
+                br#"{"temp":42}"#.to_vec(),
+                Some("application/json".to_string())
+            )
+        );
+        assert_eq!(entries[1].channel, "bar");
+        assert!(entries[1].time.is_some());
+        assert_eq!(entries[1].payload().unwrap(), (vec![1u8, 2u8], None));
+    }
+
+    #[test]
+    fn test_parse_ndjson() {
+        let body = r#"{"channel": "foo", "data": "bar"}
+{"channel": "baz", "datacontenttype": "text/csv", "data": "1,2,3"}
+"#;
+
+        let entries = parse_batch(Some("application/x-ndjson"), body.as_bytes(), 10).unwrap();
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(
+            entries[0].payload().unwrap(),
+            (
+                b"bar".to_vec(),
+                Some("text/plain; charset=utf-8".to_string())
+            )
+        );
+        assert_eq!(
+            entries[1].payload().unwrap(),
+            (b"1,2,3".to_vec(), Some("text/csv".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(parse_batch(None, b"{}", 10).is_err());
+    }
+
+    #[test]
+    fn test_parse_too_many() {
+        let array = json!([{"channel": "foo"}, {"channel": "bar"}]).to_string();
+        assert!(matches!(
+            parse_batch(None, array.as_bytes(), 1),
+            Err(EndpointError::InvalidRequest { .. })
+        ));
+
+        let ndjson = "{\"channel\": \"foo\"}\n{\"channel\": \"bar\"}\n";
+        assert!(matches!(
+            parse_batch(Some(CONTENT_TYPE_NDJSON), ndjson.as_bytes(), 1),
+            Err(EndpointError::InvalidRequest { .. })
+        ));
+        assert_eq!(
+            parse_batch(Some(CONTENT_TYPE_NDJSON), ndjson.as_bytes(), 2)
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+}
@@ -55,6 +55,11 @@ where
                 Ok(HttpResponse::build(http::StatusCode::SERVICE_UNAVAILABLE).finish())
             }
 
+            // ok, but quota exceeded
+            Ok(PublishOutcome::QuotaExceeded) => {
+                Ok(HttpResponse::build(http::StatusCode::TOO_MANY_REQUESTS).finish())
+            }
+
             // internal error
             Err(err) => Ok(HttpResponse::InternalServerError().json(ErrorInformation {
                 error: "InternalError".into(),
@@ -1,5 +1,6 @@
 use crate::downstream::HttpCommandSender;
 use actix_web::{http::header, web, HttpResponse};
+use chrono::{DateTime, Utc};
 use drogue_cloud_endpoint_common::{
     auth::DeviceAuthenticator,
     commands::Commands,
@@ -10,6 +11,11 @@ use drogue_cloud_endpoint_common::{
 use drogue_cloud_service_api::auth::device::authn;
 use serde::Deserialize;
 
+/// The header, carrying a device supplied key, used to detect retries.
+pub const HEADER_IDEMPOTENCY_KEY: &str = "idempotency-key";
+/// The header, carrying a device supplied event time, as defined by the CloudEvents HTTP binding.
+pub const HEADER_TIME: &str = "ce-time";
+
 #[derive(Deserialize)]
 pub struct PublishCommonOptions {
     pub application: Option<String>,
@@ -27,6 +33,33 @@ pub struct PublishOptions {
 
     #[serde(alias = "commandTimeout")]
     pub ct: Option<u64>,
+
+    /// The time of the event, as provided by the device.
+    pub time: Option<DateTime<Utc>>,
+}
+
+/// Evaluate the device supplied event time, preferring the query parameter over the header.
+fn eval_time(
+    req: &web::HttpRequest,
+    time: Option<DateTime<Utc>>,
+) -> Result<Option<DateTime<Utc>>, EndpointError> {
+    if time.is_some() {
+        return Ok(time);
+    }
+
+    req.headers()
+        .get(HEADER_TIME)
+        .map(|value| {
+            value
+                .to_str()
+                .ok()
+                .and_then(|value| DateTime::parse_from_rfc3339(value).ok())
+                .map(|time| time.with_timezone(&Utc))
+                .ok_or_else(|| EndpointError::InvalidRequest {
+                    details: format!("Invalid value of '{}' header", HEADER_TIME),
+                })
+        })
+        .transpose()
 }
 
 pub async fn publish_plain<S>(
@@ -129,6 +162,8 @@ where
         None => device.metadata.name,
     };
 
+    let time = eval_time(&req, opts.time)?;
+
     // publish
 
     let publish = downstream::Publish {
@@ -136,6 +171,7 @@ where
         app_id: application.metadata.name.clone(),
         device_id: device_id.clone(),
         options: downstream::PublishOptions {
+            time,
             data_schema: opts.common.data_schema,
             topic: suffix,
             content_type: req
@@ -143,6 +179,12 @@ where
                 .get(header::CONTENT_TYPE)
                 .and_then(|v| v.to_str().ok())
                 .map(|s| s.to_string()),
+            idempotency_key: req
+                .headers()
+                .get(HEADER_IDEMPOTENCY_KEY)
+                .and_then(|v| v.to_str().ok())
+                .map(|s| s.to_string()),
+            quotas: downstream::QuotaSpec::from_application(&application),
             ..Default::default()
         },
     };
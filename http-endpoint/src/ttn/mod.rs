@@ -46,6 +46,7 @@ pub struct Uplink {
     pub time: DateTime<Utc>,
     pub is_retry: Option<bool>,
     pub hardware_address: String,
+    pub frame_counter: Option<u32>,
 
     pub payload_raw: Vec<u8>,
     pub payload_fields: Value,
@@ -105,6 +106,12 @@ where
     if let Some(is_retry) = uplink.is_retry {
         extensions.insert("lorawanretry".into(), is_retry.to_string());
     }
+
+    // the frame counter, together with the hardware address, identifies retries of an uplink
+    let idempotency_key = uplink
+        .frame_counter
+        .map(|counter| format!("ttn/{}/{}", uplink.hardware_address, counter));
+
     extensions.insert("hwaddr".into(), uplink.hardware_address);
 
     log::info!("Device ID: {}, Data Schema: {:?}", device_id, data_schema);
@@ -133,12 +140,14 @@ where
     send_uplink(
         sender,
         application.metadata.name.clone(),
+        downstream::QuotaSpec::from_application(&application),
         device_id,
         port,
         time,
         content_type,
         data_schema,
         extensions,
+        idempotency_key,
         body,
     )
     .await
@@ -147,12 +156,14 @@ where
 async fn send_uplink<B, S>(
     sender: web::Data<DownstreamSender<S>>,
     app_id: String,
+    quotas: Option<downstream::QuotaSpec>,
     device_id: String,
     port: String,
     time: DateTime<Utc>,
     content_type: Option<String>,
     data_schema: Option<String>,
     extensions: HashMap<String, String>,
+    idempotency_key: Option<String>,
     body: B,
 ) -> Result<HttpResponse, HttpEndpointError>
 where
@@ -170,6 +181,8 @@ where
                     content_type,
                     data_schema,
                     extensions,
+                    idempotency_key,
+                    quotas,
                     ..Default::default()
                 },
             },
@@ -42,6 +42,7 @@ where
             time: uplink.metadata.time,
             is_retry: Some(uplink.is_retry),
             hardware_address: uplink.hardware_serial,
+            frame_counter: Some(uplink.counter),
             payload_raw: uplink.payload_raw,
             payload_fields: uplink.payload_fields,
         },
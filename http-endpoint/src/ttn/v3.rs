@@ -49,6 +49,7 @@ where
             time: uplink.received_at,
             is_retry: None,
             hardware_address: msg.end_device_ids.dev_addr,
+            frame_counter: Some(uplink.frame_counter),
             payload_raw: uplink.frame_payload,
             payload_fields: uplink.decoded_payload.unwrap_or_default(),
         },
@@ -1,5 +1,7 @@
 mod sender;
 
+pub use sender::{to_builder, Error as SenderError, HttpError};
+
 use actix_web::HttpResponse;
 use drogue_client::{registry, Translator};
 use drogue_cloud_endpoint_common::{
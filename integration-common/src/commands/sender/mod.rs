@@ -165,7 +165,7 @@ fn to_method(name: &str) -> Result<Option<reqwest::Method>, Error> {
 }
 
 /// Takes an external endpoint and creates an HTTP request builder from it.
-pub(crate) fn to_builder<F>(
+pub fn to_builder<F>(
     client: reqwest::Client,
     default_method: reqwest::Method,
     endpoint: &registry::v1::ExternalEndpoint,
@@ -0,0 +1,332 @@
+use cloudevents::{event::ExtensionValue, AttributesReader, Event};
+use drogue_client::{error::ClientError, registry, Context};
+use drogue_cloud_service_api::{labels::LabelSelector, EXT_APPLICATION, EXT_DEVICE};
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use uuid::Uuid;
+
+/// How long the labels of a device are cached.
+const LABELS_TTL: Duration = Duration::from_secs(60);
+/// The number of cached devices, after which expired entries get removed.
+const LABELS_MAX_ENTRIES: usize = 10_000;
+
+/// A filter on the device and channel part of an event.
+///
+/// The filter uses MQTT topic filter semantics, and is evaluated against the "virtual" topic of
+/// an event: `<device>/<channel>`. As the channel may contain slashes itself, it may span
+/// multiple segments.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TopicFilter(Vec<String>);
+
+impl TopicFilter {
+    /// Create a new filter from the topic segments following the application.
+    ///
+    /// Returns `None` if the filter is invalid, e.g. when `#` is not the last segment.
+    pub fn new<S: AsRef<str>>(segments: &[S]) -> Option<Self> {
+        let segments: Vec<String> = segments.iter().map(|s| s.as_ref().to_string()).collect();
+
+        for (i, seg) in segments.iter().enumerate() {
+            if seg == "#" && i != segments.len() - 1 {
+                return None;
+            }
+            if seg != "+" && seg != "#" && (seg.contains('+') || seg.contains('#')) {
+                return None;
+            }
+        }
+
+        Some(Self(segments))
+    }
+
+    /// Check if the filter matches everything.
+    pub fn is_all(&self) -> bool {
+        self.0.is_empty() || self.0 == ["#"]
+    }
+
+    /// Check if the filter has a literal (non-wildcard) segment at the index.
+    pub fn is_literal(&self, index: usize) -> bool {
+        matches!(self.0.get(index).map(String::as_str), Some(seg) if seg != "+" && seg != "#")
+    }
+
+    /// Test if the filter matches the topic segments.
+    pub fn matches<S: AsRef<str>>(&self, topic: &[S]) -> bool {
+        if self.0.is_empty() {
+            return true;
+        }
+
+        let mut topic = topic.iter();
+
+        for seg in &self.0 {
+            match (seg.as_str(), topic.next()) {
+                ("#", _) => return true,
+                ("+", Some(_)) => {}
+                (seg, Some(t)) if seg == t.as_ref() => {}
+                _ => return false,
+            }
+        }
+
+        topic.next().is_none()
+    }
+}
+
+impl fmt::Display for TopicFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0.join("/"))
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum FilterError {
+    #[error("Failed to look up device labels: {0}")]
+    Registry(#[from] ClientError<reqwest::Error>),
+}
+
+/// A subscription filter, evaluated against events before they get forwarded.
+#[derive(Clone, Debug, Default)]
+pub struct EventFilter {
+    pub topic: Option<TopicFilter>,
+    /// A label selector, evaluated against the labels of the device of the event.
+    pub selector: Option<LabelSelector>,
+}
+
+impl EventFilter {
+    /// Create a filter, matching the device and channel exactly, if present.
+    pub fn new(
+        device: Option<&str>,
+        channel: Option<&str>,
+        selector: Option<&str>,
+    ) -> Result<Self, String> {
+        let topic = match (device, channel) {
+            (None, None) => None,
+            (device, channel) => {
+                let mut segments = vec![device.unwrap_or("+")];
+                match channel {
+                    Some(channel) => segments.extend(channel.split('/')),
+                    None => segments.push("#"),
+                }
+                Some(
+                    TopicFilter::new(&segments)
+                        .ok_or_else(|| "Invalid device or channel".to_string())?,
+                )
+            }
+        };
+
+        let selector = selector
+            .map(|selector| {
+                LabelSelector::try_from(selector)
+                    .map_err(|err| format!("Invalid selector: {}", err))
+            })
+            .transpose()?;
+
+        Ok(Self { topic, selector })
+    }
+
+    /// Get the device and channel segments of an event.
+    pub fn event_topic(event: &Event) -> Vec<&str> {
+        let device = match event.extension(EXT_DEVICE) {
+            Some(ExtensionValue::String(device)) => device.as_str(),
+            _ => "",
+        };
+
+        let mut topic = vec![device];
+        topic.extend(event.subject().unwrap_or_default().split('/'));
+        topic
+    }
+
+    /// Evaluate the topic filter only, which doesn't require looking up the device.
+    ///
+    /// Like MQTT topics starting with `$`, system channels (e.g. `$connection`) are not matched
+    /// by wildcards, but must be subscribed to explicitly.
+    pub fn matches_topic(&self, event: &Event) -> bool {
+        let topic = Self::event_topic(event);
+
+        if topic
+            .get(1)
+            .map_or(false, |channel| channel.starts_with('$'))
+            && !self
+                .topic
+                .as_ref()
+                .map_or(false, |filter| filter.is_literal(1))
+        {
+            return false;
+        }
+
+        match &self.topic {
+            Some(filter) => filter.matches(&topic),
+            None => true,
+        }
+    }
+
+    /// Evaluate the filter, looking up the labels of the device when necessary.
+    pub async fn matches(&self, event: &Event, labels: &DeviceLabels) -> Result<bool, FilterError> {
+        if !self.matches_topic(event) {
+            return Ok(false);
+        }
+
+        let selector = match &self.selector {
+            Some(selector) => selector,
+            None => return Ok(true),
+        };
+
+        let (app, device) = match (
+            event.extension(EXT_APPLICATION),
+            event.extension(EXT_DEVICE),
+        ) {
+            (Some(ExtensionValue::String(app)), Some(ExtensionValue::String(device))) => {
+                (app, device)
+            }
+            // not an event of a device
+            _ => return Ok(false),
+        };
+
+        Ok(match labels.get(app, device).await? {
+            Some(labels) => selector.matches(|name| labels.get(name).map(String::as_str)),
+            // the device is gone
+            None => false,
+        })
+    }
+}
+
+/// Derive a stable ID from the definition of a subscription.
+///
+/// This is used to scope consumer groups, so that subscriptions with different filters never
+/// share a group. Otherwise, they would split the partitions among them, and drop the events the
+/// others are interested in.
+pub fn subscription_id(definition: &[Option<&str>]) -> String {
+    let definition = definition
+        .iter()
+        .map(|s| s.unwrap_or_default())
+        .collect::<Vec<_>>()
+        .join("\n");
+    Uuid::new_v5(&Uuid::NAMESPACE_OID, definition.as_bytes())
+        .to_simple()
+        .to_string()
+}
+
+type Labels = Option<Arc<HashMap<String, String>>>;
+
+/// Looks up the labels of devices, caching them for a short time.
+#[derive(Clone)]
+pub struct DeviceLabels {
+    registry: registry::v1::Client,
+    cache: Arc<Mutex<HashMap<(String, String), (Instant, Labels)>>>,
+}
+
+impl DeviceLabels {
+    pub fn new(registry: registry::v1::Client) -> Self {
+        Self {
+            registry,
+            cache: Default::default(),
+        }
+    }
+
+    /// Get the labels of a device, `None` if the device doesn't exist.
+    pub async fn get(&self, app: &str, device: &str) -> Result<Labels, FilterError> {
+        let key = (app.to_string(), device.to_string());
+        let now = Instant::now();
+
+        if let Some((time, labels)) = self.cache.lock().unwrap().get(&key) {
+            if now.duration_since(*time) < LABELS_TTL {
+                return Ok(labels.clone());
+            }
+        }
+
+        let labels = self
+            .registry
+            .get_device(app, device, Context::default())
+            .await?
+            .map(|device| Arc::new(device.metadata.labels));
+
+        let mut cache = self.cache.lock().unwrap();
+        if cache.len() >= LABELS_MAX_ENTRIES {
+            cache.retain(|_, (time, _)| now.duration_since(*time) < LABELS_TTL);
+        }
+        cache.insert(key, (now, labels.clone()));
+
+        Ok(labels)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cloudevents::{EventBuilder, EventBuilderV10};
+
+    fn filter(filter: &[&str]) -> TopicFilter {
+        TopicFilter::new(filter).unwrap()
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert_eq!(TopicFilter::new(&["#", "foo"]), None);
+        assert_eq!(TopicFilter::new(&["foo+"]), None);
+    }
+
+    #[test]
+    fn test_matches() {
+        assert!(filter(&[]).matches(&["device", "channel"]));
+        assert!(filter(&["#"]).matches(&["device", "channel"]));
+        assert!(filter(&["device", "#"]).matches(&["device", "channel", "sub"]));
+        assert!(filter(&["+", "channel"]).matches(&["device", "channel"]));
+        assert!(filter(&["device", "+"]).matches(&["device", "channel"]));
+        assert!(filter(&["device", "channel"]).matches(&["device", "channel"]));
+
+        assert!(!filter(&["device"]).matches(&["device", "channel"]));
+        assert!(!filter(&["other", "#"]).matches(&["device", "channel"]));
+        assert!(!filter(&["+", "other"]).matches(&["device", "channel"]));
+        assert!(!filter(&["device", "channel", "+"]).matches(&["device", "channel"]));
+    }
+
+    #[test]
+    fn test_new() {
+        let filter = EventFilter::new(None, None, None).unwrap();
+        assert!(filter.topic.is_none());
+
+        let filter = EventFilter::new(Some("device"), None, None).unwrap();
+        assert_eq!(filter.topic, Some(self::filter(&["device", "#"])));
+
+        let filter = EventFilter::new(None, Some("sensor/temp"), Some("foo=bar")).unwrap();
+        assert_eq!(filter.topic, Some(self::filter(&["+", "sensor", "temp"])));
+        assert!(filter.selector.is_some());
+
+        assert!(EventFilter::new(Some("dev#ice"), None, None).is_err());
+        assert!(EventFilter::new(None, None, Some("foo/bar/baz")).is_err());
+    }
+
+    fn event(device: &str, channel: &str) -> Event {
+        EventBuilderV10::new()
+            .id("1")
+            .ty("io.drogue.event.v1")
+            .source("drogue://app/device")
+            .subject(channel)
+            .extension(EXT_DEVICE, device)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_system_channels() {
+        let all = EventFilter::default();
+        assert!(all.matches_topic(&event("device", "temp")));
+        assert!(!all.matches_topic(&event("device", "$connection")));
+
+        let wildcard = EventFilter::new(Some("device"), None, None).unwrap();
+        assert!(!wildcard.matches_topic(&event("device", "$connection")));
+
+        let explicit = EventFilter::new(None, Some("$connection"), None).unwrap();
+        assert!(explicit.matches_topic(&event("device", "$connection")));
+        assert!(!explicit.matches_topic(&event("device", "temp")));
+    }
+
+    #[test]
+    fn test_subscription_id() {
+        let id = subscription_id(&[Some("device/#"), None]);
+        assert_eq!(id, subscription_id(&[Some("device/#"), None]));
+        assert_ne!(id, subscription_id(&[Some("device/temp"), None]));
+        assert_ne!(id, subscription_id(&[Some("device/#"), Some("foo=bar")]));
+    }
+}
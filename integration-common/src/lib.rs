@@ -1,3 +1,4 @@
 #[cfg(feature = "with_actix")]
 pub mod commands;
+pub mod filter;
 pub mod stream;
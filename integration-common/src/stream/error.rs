@@ -7,6 +7,8 @@ pub enum EventStreamError {
     Kafka(#[from] KafkaError),
     #[error("Missing metadata")]
     MissingMetadata,
+    #[error("Invalid configuration: {0}")]
+    Config(&'static str),
     #[error("Cloud event error: {0}")]
     CloudEvent(#[from] cloudevents::message::Error),
 }
@@ -6,10 +6,12 @@ mod error;
 pub use self::actix::*;
 pub use error::*;
 
+use chrono::{DateTime, Utc};
 use cloudevents::{
     binding::rdkafka::MessageExt, event::ExtensionValue, AttributesReader, AttributesWriter, Data,
     Event,
 };
+use drogue_cloud_endpoint_common::downstream::memory_subscribe;
 use drogue_cloud_service_api::EXT_APPLICATION;
 use futures::{
     task::{Context, Poll},
@@ -20,33 +22,94 @@ use rdkafka::error::KafkaResult;
 use rdkafka::{
     config::{ClientConfig, RDKafkaLogLevel},
     consumer::{stream_consumer::StreamConsumer, CommitMode, Consumer, DefaultConsumerContext},
-    message::BorrowedMessage,
+    message::{BorrowedMessage, Message},
     util::Timeout,
-    TopicPartitionList,
+    Offset, TopicPartitionList,
 };
+use serde::Deserialize;
 use std::{
     collections::HashMap,
     fmt::{Debug, Formatter},
     pin::Pin,
     time::Duration,
 };
+use tokio_stream::wrappers::{errors::BroadcastStreamRecvError, BroadcastStream};
 use uuid::Uuid;
 
+/// The source of events.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EventSource {
+    /// Consume events from a Kafka topic.
+    Kafka,
+    /// Consume events from an in-process topic, fed by the `MemorySink`.
+    Memory,
+}
+
+impl Default for EventSource {
+    fn default() -> Self {
+        Self::Kafka
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct EventStreamConfig {
+    pub source: EventSource,
     pub bootstrap_servers: String,
     pub properties: HashMap<String, String>,
     pub topic: String,
-    pub app: String,
+    /// The application to receive events for, or all applications if `None`.
+    pub app: Option<String>,
     pub consumer_group: Option<String>,
+    /// Don't commit offsets automatically, but require the consumer to call
+    /// [`EventStream::commit`] after processing an event.
+    pub manual_commit: bool,
+    /// Start with the events received at, or after, this time, instead of the latest events.
+    ///
+    /// This can't be combined with a consumer group, as it requires assigning the partitions
+    /// manually.
+    pub since: Option<DateTime<Utc>>,
+}
+
+/// Parse the start time of a stream.
+///
+/// This can either be an RFC 3339 timestamp, or a duration (e.g. `15m`), relative to now.
+pub fn parse_since(value: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.with_timezone(&Utc));
+    }
+
+    let duration = humantime::parse_duration(value)
+        .map_err(|err| format!("Invalid start time '{}': {}", value, err))?;
+    let duration = chrono::Duration::from_std(duration)
+        .map_err(|err| format!("Invalid start time '{}': {}", value, err))?;
+
+    Ok(Utc::now() - duration)
+}
+
+/// The position of an event in the upstream topic.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EventOffset {
+    topic: String,
+    partition: i32,
+    offset: i64,
+}
+
+enum Upstream {
+    Kafka(
+        OwningHandle<
+            Box<StreamConsumer>,
+            Box<rdkafka::consumer::MessageStream<'static, DefaultConsumerContext>>,
+        >,
+    ),
+    Memory(BroadcastStream<Event>),
 }
 
 pub struct EventStream {
-    upstream: OwningHandle<
-        Box<StreamConsumer>,
-        Box<rdkafka::consumer::MessageStream<'static, DefaultConsumerContext>>,
-    >,
-    app: String,
+    upstream: Upstream,
+    app: Option<String>,
+    manual_commit: bool,
+    offset: Option<EventOffset>,
 }
 
 impl Debug for EventStream {
@@ -59,7 +122,14 @@ impl Debug for EventStream {
 
 impl EventStream {
     pub fn new(cfg: EventStreamConfig) -> Result<Self, EventStreamError> {
+        if let EventSource::Memory = cfg.source {
+            return Ok(Self::new_memory(&cfg));
+        }
+
         match &cfg.consumer_group {
+            Some(_) if cfg.since.is_some() => Err(EventStreamError::Config(
+                "A start time can't be combined with a consumer group",
+            )),
             Some(consumer_group) => Self::new_with_group(&cfg, consumer_group.clone()),
             None => {
                 // create a random subscriber ID until we can use `new_without_group`.
@@ -131,47 +201,141 @@ impl EventStream {
 
         log::debug!("Subscribed");
 
-        Ok(Self::wrap(cfg.app.clone(), consumer))
+        Ok(Self::wrap(cfg, consumer))
     }
 
     fn new_with_group(cfg: &EventStreamConfig, group_id: String) -> Result<Self, EventStreamError> {
         let mut consumer = Self::new_config(cfg);
         consumer
-            .set("enable.auto.commit", "true")
+            .set(
+                "enable.auto.commit",
+                if cfg.manual_commit { "false" } else { "true" },
+            )
             .set("group.id", &group_id);
 
         let consumer: StreamConsumer<DefaultConsumerContext> = consumer.create()?;
 
         log::debug!("Created consumer");
 
-        consumer.subscribe(&[&cfg.topic])?;
+        match cfg.since {
+            Some(since) => Self::assign_since(&consumer, &cfg.topic, since)?,
+            None => consumer.subscribe(&[&cfg.topic])?,
+        }
 
         log::debug!("Subscribed");
 
-        Ok(Self::wrap(cfg.app.clone(), consumer))
+        Ok(Self::wrap(cfg, consumer))
+    }
+
+    /// Assign all partitions of the topic, starting with the first offset at, or after, the
+    /// provided time.
+    ///
+    /// This doesn't use group management, so all partitions will be consumed. Therefore, it must
+    /// only be used with the random group of an anonymous stream.
+    fn assign_since(
+        consumer: &StreamConsumer,
+        topic: &str,
+        since: DateTime<Utc>,
+    ) -> Result<(), EventStreamError> {
+        let timeout = Timeout::After(Duration::from_secs(10));
+
+        let metadata = consumer.fetch_metadata(Some(topic), timeout)?;
+
+        let partitions = metadata
+            .topics()
+            .iter()
+            .find(|t| t.name() == topic)
+            .map(|topic| topic.partitions())
+            .ok_or(EventStreamError::MissingMetadata)?;
+
+        // the offset is used to provide the timestamp when looking up the offsets
+
+        let mut times = TopicPartitionList::with_capacity(partitions.len());
+        for part in partitions {
+            times.add_partition_offset(
+                topic,
+                part.id(),
+                Offset::Offset(since.timestamp_millis()),
+            )?;
+        }
+
+        let offsets = consumer.offsets_for_times(times, timeout)?;
+
+        log::debug!("Starting from {} with: {:?}", since, offsets);
+
+        consumer.assign(&offsets)?;
+
+        Ok(())
     }
 
-    fn wrap(app: String, consumer: StreamConsumer) -> Self {
+    /// Create a new stream, consuming from an in-process topic.
+    ///
+    /// As the in-process topic doesn't persist events, this will only receive events published
+    /// after the stream was created. Consumer groups are not supported.
+    fn new_memory(cfg: &EventStreamConfig) -> Self {
+        if cfg.since.is_some() {
+            log::warn!("In-memory topics don't support starting from a point in time");
+        }
+
+        log::debug!("Subscribing to in-memory topic: {}", cfg.topic);
+
         Self {
-            upstream: OwningHandle::new_with_fn(Box::new(consumer), |c| {
+            upstream: Upstream::Memory(BroadcastStream::new(memory_subscribe(&cfg.topic))),
+            app: cfg.app.clone(),
+            manual_commit: cfg.manual_commit,
+            offset: None,
+        }
+    }
+
+    fn wrap(cfg: &EventStreamConfig, consumer: StreamConsumer) -> Self {
+        Self {
+            upstream: Upstream::Kafka(OwningHandle::new_with_fn(Box::new(consumer), |c| {
                 Box::new(unsafe { &*c }.stream())
-            }),
-            app,
+            })),
+            app: cfg.app.clone(),
+            manual_commit: cfg.manual_commit,
+            offset: None,
+        }
+    }
+
+    /// Get the offset of the last event received, when using manual commits.
+    ///
+    /// Events from an in-process topic don't have an offset.
+    pub fn offset(&self) -> Option<EventOffset> {
+        self.offset.clone()
+    }
+
+    /// Commit an offset, marking the event, and all events before it, as processed.
+    pub fn commit(&self, offset: &EventOffset) -> Result<(), EventStreamError> {
+        if let Upstream::Kafka(upstream) = &self.upstream {
+            let mut list = TopicPartitionList::with_capacity(1);
+            // the committed offset is the offset of the next event to consume
+            list.add_partition_offset(
+                &offset.topic,
+                offset.partition,
+                Offset::Offset(offset.offset + 1),
+            )?;
+            upstream.as_owner().commit(&list, CommitMode::Async)?;
         }
+        Ok(())
     }
 
     /// Test if the message/event matches an optional filter.
     fn matches(&self, event: &Event) -> bool {
-        match event.extension(EXT_APPLICATION) {
-            Some(ExtensionValue::String(other_app)) => &self.app == other_app,
+        match (&self.app, event.extension(EXT_APPLICATION)) {
+            (None, _) => true,
+            (Some(app), Some(ExtensionValue::String(other_app))) => app == other_app,
             _ => false,
         }
     }
 
     fn ack(&self, msg: &BorrowedMessage) -> KafkaResult<()> {
-        self.upstream
-            .as_owner()
-            .commit_message(&msg, CommitMode::Async)
+        match &self.upstream {
+            Upstream::Kafka(upstream) => {
+                upstream.as_owner().commit_message(&msg, CommitMode::Async)
+            }
+            Upstream::Memory(_) => Ok(()),
+        }
     }
 
     /// Check if the content type indicates a JSON payload
@@ -247,28 +411,44 @@ impl Stream for EventStream {
     type Item = Result<Event, EventStreamError>;
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        let next = self.upstream.poll_next_unpin(cx);
-
-        match next {
-            Poll::Pending => Poll::Pending,
-            Poll::Ready(next) => match next {
-                None => Poll::Ready(None),
-                Some(Err(e)) => Poll::Ready(Some(Err(e.into()))),
-                Some(Ok(msg)) => {
-                    self.ack(&msg)?;
-
-                    let event = msg.to_event()?;
-                    let event = Self::fixup_data_type(event);
-
-                    match self.matches(&event) {
-                        true => Poll::Ready(Some(Ok(event))),
-                        false => {
-                            cx.waker().wake_by_ref();
-                            Poll::Pending
-                        }
+        let event = match &mut self.upstream {
+            Upstream::Kafka(upstream) => match upstream.poll_next_unpin(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e.into()))),
+                Poll::Ready(Some(Ok(msg))) => {
+                    if self.manual_commit {
+                        self.offset = Some(EventOffset {
+                            topic: msg.topic().to_string(),
+                            partition: msg.partition(),
+                            offset: msg.offset(),
+                        });
+                    } else {
+                        self.ack(&msg)?;
                     }
+                    msg.to_event()?
+                }
+            },
+            Upstream::Memory(upstream) => match upstream.poll_next_unpin(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Ready(Some(Err(BroadcastStreamRecvError::Lagged(n)))) => {
+                    log::info!("Stream lagging behind, skipped {} events", n);
+                    cx.waker().wake_by_ref();
+                    return Poll::Pending;
                 }
+                Poll::Ready(Some(Ok(event))) => event,
             },
+        };
+
+        let event = Self::fixup_data_type(event);
+
+        match self.matches(&event) {
+            true => Poll::Ready(Some(Ok(event))),
+            false => {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
         }
     }
 }
@@ -276,6 +456,7 @@ impl Stream for EventStream {
 #[cfg(test)]
 mod test {
     use super::*;
+    use chrono::TimeZone;
     use cloudevents::{EventBuilder, EventBuilderV10};
     use serde_json::json;
     use url::Url;
@@ -298,6 +479,36 @@ mod test {
         event
     }
 
+    #[test]
+    fn test_parse_since() {
+        assert_eq!(
+            parse_since("2021-01-01T00:00:00Z").unwrap(),
+            Utc.ymd(2021, 1, 1).and_hms(0, 0, 0)
+        );
+
+        let since = parse_since("15m").unwrap();
+        let expected = Utc::now() - chrono::Duration::minutes(15);
+        assert!((expected - since).num_seconds().abs() < 5);
+
+        assert!(parse_since("foo").is_err());
+    }
+
+    #[test]
+    fn test_since_with_group() {
+        let result = EventStream::new(EventStreamConfig {
+            source: EventSource::Kafka,
+            bootstrap_servers: "localhost:9092".into(),
+            properties: Default::default(),
+            topic: "events".into(),
+            app: None,
+            consumer_group: Some("group".into()),
+            manual_commit: false,
+            since: Some(Utc::now()),
+        });
+
+        assert!(matches!(result, Err(EventStreamError::Config(_))));
+    }
+
     #[test]
     fn test_fixup_json() {
         for (content_type, input, output) in [
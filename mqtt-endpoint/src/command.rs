@@ -1,23 +1,20 @@
-use crate::{cloudevents_sdk_ntex::request_to_event, App};
-use drogue_cloud_endpoint_common::{commands::Command, downstream::DownstreamSink};
+use crate::cloudevents_sdk_ntex::request_to_event;
+use drogue_cloud_endpoint_common::commands::{Command, Commands};
 use ntex::{http, web};
 use std::convert::TryFrom;
 
-pub async fn command_service<S>(
+pub async fn command_service(
     req: web::HttpRequest,
     payload: web::types::Payload,
-    app: web::types::Data<App<S>>,
-) -> http::Response
-where
-    S: DownstreamSink,
-{
+    commands: web::types::Data<Commands>,
+) -> http::Response {
     log::debug!("Command request: {:?}", req);
 
     let request_event = request_to_event(&req, payload).await.unwrap();
 
     match Command::try_from(request_event.clone()) {
         Ok(command) => {
-            if let Err(e) = app.commands.send(command).await {
+            if let Err(e) = commands.send(command).await {
                 log::error!("Failed to route command: {}", e);
                 web::HttpResponse::BadRequest().finish()
             } else {
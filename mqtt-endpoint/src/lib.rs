@@ -0,0 +1,122 @@
+#![type_length_limit = "6000000"]
+
+mod auth;
+mod cloudevents_sdk_ntex;
+mod command;
+mod error;
+mod mqtt;
+mod server;
+mod will;
+mod x509;
+
+pub use command::command_service;
+
+use crate::{
+    auth::DeviceAuthenticator,
+    server::{build, build_tls},
+};
+use bytes::Bytes;
+use bytestring::ByteString;
+use drogue_cloud_endpoint_common::downstream::DownstreamSink;
+use drogue_cloud_endpoint_common::{
+    commands::Commands,
+    downstream::{ConfiguredSink, DownstreamSender},
+    error::EndpointError,
+    x509::ClientCertificateChain,
+};
+use drogue_cloud_service_api::auth::device::authn::Outcome as AuthOutcome;
+use drogue_cloud_service_common::{defaults, health::HealthServerConfig};
+use serde::Deserialize;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub disable_tls: bool,
+    #[serde(default)]
+    pub cert_bundle_file: Option<String>,
+    #[serde(default)]
+    pub key_file: Option<String>,
+    #[serde(default)]
+    pub bind_addr_mqtt: Option<String>,
+    #[serde(default = "defaults::bind_addr")]
+    pub bind_addr_http: String,
+
+    #[serde(default)]
+    pub health: HealthServerConfig,
+}
+
+#[derive(Clone, Debug)]
+pub struct App<S>
+where
+    S: DownstreamSink,
+{
+    pub downstream: DownstreamSender<S>,
+    pub authenticator: DeviceAuthenticator,
+    pub commands: Commands,
+    pub wills: will::PendingWills,
+}
+
+impl<S> App<S>
+where
+    S: DownstreamSink,
+{
+    /// authenticate a client
+    async fn authenticate(
+        &self,
+        username: &Option<ByteString>,
+        password: &Option<Bytes>,
+        client_id: &ByteString,
+        certs: Option<ClientCertificateChain>,
+    ) -> Result<AuthOutcome, EndpointError> {
+        let password = password
+            .as_ref()
+            .map(|p| String::from_utf8(p.to_vec()))
+            .transpose()
+            .map_err(|err| {
+                log::debug!("Failed to convert password: {}", err);
+                EndpointError::AuthenticationError
+            })?;
+
+        Ok(self
+            .authenticator
+            .authenticate_mqtt(username.as_ref(), password, &client_id, certs)
+            .await
+            .map_err(|err| {
+                log::debug!("Failed to call authentication service: {}", err);
+                EndpointError::AuthenticationServiceError {
+                    source: Box::new(err),
+                }
+            })?
+            .outcome)
+    }
+}
+
+/// Run the MQTT endpoint, publishing events to the sender and delivering commands from the
+/// command router.
+pub async fn run(
+    config: Config,
+    sender: DownstreamSender<ConfiguredSink>,
+    commands: Commands,
+) -> anyhow::Result<()> {
+    let app = App {
+        downstream: sender,
+        authenticator: DeviceAuthenticator(
+            drogue_cloud_endpoint_common::auth::DeviceAuthenticator::new().await?,
+        ),
+        commands,
+        wills: Default::default(),
+    };
+
+    let builder = ntex::server::Server::build();
+    let addr = config.bind_addr_mqtt.as_deref();
+
+    let builder = if !config.disable_tls {
+        build_tls(addr, builder, app, &config)?
+    } else {
+        build(addr, builder, app)?
+    };
+
+    builder.run().await?;
+
+    Ok(())
+}
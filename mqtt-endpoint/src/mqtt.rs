@@ -1,7 +1,12 @@
-use crate::{error::ServerError, server::Session, x509::ClientCertificateRetriever, App};
+use crate::{
+    error::ServerError, server::Session, will::Will, x509::ClientCertificateRetriever, App,
+};
 use bytes::Bytes;
 use bytestring::ByteString;
-use drogue_cloud_endpoint_common::downstream::{DownstreamSink, Publish, PublishOutcome};
+use chrono::{DateTime, Utc};
+use drogue_cloud_endpoint_common::downstream::{
+    DownstreamSender, DownstreamSink, Publish, PublishOptions, PublishOutcome, QuotaSpec,
+};
 use drogue_cloud_service_api::auth::device::authn::Outcome as AuthOutcome;
 use drogue_cloud_service_common::Id;
 use ntex_mqtt::{
@@ -18,8 +23,101 @@ const TOPIC_COMMAND_INBOX: &str = "command/inbox";
 const TOPIC_COMMAND_INBOX_PATTERN: &str = "command/inbox/#";
 // const TOPIC_COMMAND_OUTBOX: &str = "command/outbox";
 
+/// The v5 user property, carrying a device supplied key, used to detect retries.
+const PROPERTY_IDEMPOTENCY_KEY: &str = "idempotency-key";
+/// The prefix of the idempotency key, derived from the packet ID of a v3 publish.
+const PACKET_ID_KEY_PREFIX: &str = "mqtt-packet-id:";
+/// The v5 user property, carrying the time of the event (RFC 3339), as provided by the device.
+const PROPERTY_TIME: &str = "time";
+/// The protocol, reported in connection events.
+const PROTOCOL: &str = "mqtt";
+
+/// Publish a change of the connection state of the device of the session.
+async fn publish_connection<S>(session: &Session<S>, connected: bool)
+where
+    S: DownstreamSink,
+{
+    let id = session.device_id.clone();
+    match session
+        .sender
+        .publish_connection(id.app_id, id.device_id, PROTOCOL, connected)
+        .await
+    {
+        Ok(PublishOutcome::Accepted) => {}
+        Ok(outcome) => log::info!("Connection event not accepted: {:?}", outcome),
+        Err(err) => log::info!("Failed to publish connection event: {}", err),
+    }
+}
+
+/// Publish the will of the device, if it has one, after the will delay expired.
+///
+/// The will is not published if the device connects again before that.
+async fn publish_will<S>(session: &Session<S>)
+where
+    S: DownstreamSink,
+{
+    let will = match session.will.lock().unwrap().take() {
+        Some(will) => will,
+        None => return,
+    };
+
+    let id = session.device_id.clone();
+    let sender = session.sender.clone();
+    let quotas = session.quotas.clone();
+
+    if will.delay.as_secs() == 0 {
+        send_will(sender, id, quotas, will).await;
+        return;
+    }
+
+    log::debug!("Scheduling will of {:?} in {:?}", id, will.delay);
+
+    let wills = session.wills.clone();
+    let token = wills.schedule(id.clone());
+    ntex::rt::spawn(async move {
+        tokio::time::sleep(will.delay).await;
+        if wills.claim(&id, token) {
+            send_will(sender, id, quotas, will).await;
+        }
+    });
+}
+
+async fn send_will<S>(sender: DownstreamSender<S>, id: Id, quotas: Option<QuotaSpec>, will: Will)
+where
+    S: DownstreamSink,
+{
+    log::debug!("Publishing will of {:?} to '{}'", id, will.channel);
+
+    let result = sender
+        .publish(
+            Publish {
+                channel: will.channel,
+                app_id: id.app_id,
+                device_id: id.device_id,
+                options: PublishOptions {
+                    content_type: will.content_type,
+                    quotas,
+                    ..Default::default()
+                },
+            },
+            will.payload,
+        )
+        .await;
+
+    match result {
+        Ok(PublishOutcome::Accepted) => {}
+        Ok(outcome) => log::info!("Will not accepted: {:?}", outcome),
+        Err(err) => log::info!("Failed to publish will: {}", err),
+    }
+}
+
+enum ConnectError {
+    Failed,
+    QuotaExceeded,
+}
+
 macro_rules! connect {
-    ($connect:expr, $app:expr, $certs:expr) => {{
+    ($connect:expr, $app:expr, $certs:expr, $will:expr) => {{
         log::info!("new connection: {:?}", $connect);
         match $app
             .authenticate(
@@ -38,15 +136,33 @@ macro_rules! connect {
                 let app_id = application.metadata.name.clone();
                 let device_id = device.metadata.name.clone();
 
-                let session = Session::new(
-                    $app.downstream,
-                    Id::new(app_id.clone(), device_id.clone()),
-                    $app.commands.clone(),
+                let quotas = QuotaSpec::from_application(&application);
+                let connection = $app.downstream.quotas().connect(
+                    &quotas.clone().unwrap_or_default(),
+                    &app_id,
+                    &device_id,
                 );
 
-                Ok(session)
+                let id = Id::new(app_id.clone(), device_id.clone());
+
+                match connection {
+                    Some(connection) => {
+                        // the device is back, before its previous will got published
+                        $app.wills.cancel(&id);
+                        Ok(Session::new(
+                            $app.downstream,
+                            id,
+                            $app.commands.clone(),
+                            quotas,
+                            connection,
+                            $will,
+                            $app.wills.clone(),
+                        ))
+                    }
+                    None => Err(ConnectError::QuotaExceeded),
+                }
             }
-            AuthOutcome::Fail => Err("Failed"),
+            AuthOutcome::Fail => Err(ConnectError::Failed),
         }
     }};
 }
@@ -62,11 +178,18 @@ where
     let certs = connect.io().client_certs();
     log::debug!("Certs: {:?}", certs);
 
+    let will = connect.packet().last_will.as_ref().map(Will::from_v3);
+
     // handle connect
 
-    match connect!(connect, app, certs) {
-        Ok(session) => Ok(connect.ack(session, false)),
-        Err(_) => Ok(connect.bad_username_or_pwd()),
+    match connect!(connect, app, certs, will) {
+        Ok(session) => {
+            publish_connection(&session, true).await;
+            Ok(connect.ack(session, false))
+        }
+        // MQTTv3 has no dedicated return code for exceeding a quota
+        Err(ConnectError::QuotaExceeded) => Ok(connect.service_unavailable()),
+        Err(ConnectError::Failed) => Ok(connect.bad_username_or_pwd()),
     }
 }
 
@@ -81,16 +204,22 @@ where
     let certs = connect.io().client_certs();
     log::debug!("Certs: {:?}", certs);
 
-    match connect!(connect, app, certs) {
-        Ok(session) => Ok(connect.ack(session).with(|ack| {
-            ack.wildcard_subscription_available = Some(false);
-        })),
-        Err(_) => Ok(connect.failed(ConnectAckReason::BadUserNameOrPassword)),
+    let will = connect.packet().last_will.as_ref().map(Will::from_v5);
+
+    match connect!(connect, app, certs, will) {
+        Ok(session) => {
+            publish_connection(&session, true).await;
+            Ok(connect.ack(session).with(|ack| {
+                ack.wildcard_subscription_available = Some(false);
+            }))
+        }
+        Err(ConnectError::QuotaExceeded) => Ok(connect.failed(ConnectAckReason::QuotaExceeded)),
+        Err(ConnectError::Failed) => Ok(connect.failed(ConnectAckReason::BadUserNameOrPassword)),
     }
 }
 
 macro_rules! publish {
-    ($session: expr, $publish:expr) => {{
+    ($session: expr, $publish:expr, $options:expr) => {{
         log::debug!(
             "incoming publish: {:?} -> {:?} / {:?}",
             $publish.id(),
@@ -106,7 +235,10 @@ macro_rules! publish {
                 channel: channel.into(),
                 app_id: id.app_id,
                 device_id: id.device_id,
-                options: Default::default(),
+                options: PublishOptions {
+                    quotas: $session.quotas.clone(),
+                    ..$options
+                },
             },
             $publish.payload(),
         )
@@ -120,7 +252,17 @@ pub async fn publish_v3<S>(
 where
     S: DownstreamSink,
 {
-    match publish!(session, publish).await {
+    // MQTT v3 has no properties, but QoS 1 messages may get re-delivered, using the same packet ID
+    let options = match publish.packet().packet_id {
+        Some(id) => PublishOptions {
+            idempotency_key: Some(format!("{}{}", PACKET_ID_KEY_PREFIX, id)),
+            redelivery: Some(publish.packet().dup),
+            ..Default::default()
+        },
+        None => Default::default(),
+    };
+
+    match publish!(session, publish, options).await {
         Ok(PublishOutcome::Accepted) => Ok(()),
 
         Ok(PublishOutcome::Rejected) => Err(ServerError {
@@ -133,6 +275,11 @@ where
             msg: "QueueFull".into(),
         }),
 
+        Ok(PublishOutcome::QuotaExceeded) => Err(ServerError {
+            // with MQTTv3, we can only close the connection
+            msg: "QuotaExceeded".into(),
+        }),
+
         Err(e) => Err(ServerError { msg: e.to_string() }),
     }
 }
@@ -144,12 +291,38 @@ pub async fn publish_v5<S>(
 where
     S: DownstreamSink,
 {
-    match publish!(session, publish).await {
+    let property = |name: &str| {
+        publish
+            .packet()
+            .properties
+            .user_properties
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.to_string())
+    };
+
+    let time = match property(PROPERTY_TIME).map(|time| DateTime::parse_from_rfc3339(&time)) {
+        Some(Ok(time)) => Some(time.with_timezone(&Utc)),
+        Some(Err(_)) => {
+            return Ok(publish
+                .ack()
+                .reason_code(PublishAckReason::PayloadFormatInvalid))
+        }
+        None => None,
+    };
+
+    let options = PublishOptions {
+        time,
+        idempotency_key: property(PROPERTY_IDEMPOTENCY_KEY),
+        ..Default::default()
+    };
+
+    match publish!(session, publish, options).await {
         Ok(PublishOutcome::Accepted) => Ok(publish.ack()),
         Ok(PublishOutcome::Rejected) => Ok(publish
             .ack()
             .reason_code(PublishAckReason::UnspecifiedError)),
-        Ok(PublishOutcome::QueueFull) => {
+        Ok(PublishOutcome::QueueFull) | Ok(PublishOutcome::QuotaExceeded) => {
             Ok(publish.ack().reason_code(PublishAckReason::QuotaExceeded))
         }
         Err(e) => Err(ServerError { msg: e.to_string() }),
@@ -226,12 +399,20 @@ where
 {
     match control {
         v3::ControlMessage::Ping(p) => Ok(p.ack()),
-        v3::ControlMessage::Disconnect(d) => unsubscribe!(d, session, "Disconnecting device {:?}"),
+        v3::ControlMessage::Disconnect(d) => {
+            // clean disconnect, discard the will
+            session.will.lock().unwrap().take();
+            unsubscribe!(d, session, "Disconnecting device {:?}")
+        }
         v3::ControlMessage::Subscribe(mut s) => {
             subscribe!(s, session, |mut sub: v3::control::Subscription| sub.fail())
         }
         v3::ControlMessage::Unsubscribe(u) => unsubscribe!(u, session, "Unsubscribing device {:?}"),
-        v3::ControlMessage::Closed(c) => unsubscribe!(c, session, "Closing device connection {:?}"),
+        v3::ControlMessage::Closed(c) => {
+            publish_will(session.state()).await;
+            publish_connection(session.state(), false).await;
+            unsubscribe!(c, session, "Closing device connection {:?}")
+        }
     }
 }
 
@@ -247,12 +428,22 @@ where
         v5::ControlMessage::Error(e) => Ok(e.ack(DisconnectReasonCode::UnspecifiedError)),
         v5::ControlMessage::ProtocolError(pe) => Ok(pe.ack()),
         v5::ControlMessage::Ping(p) => Ok(p.ack()),
-        v5::ControlMessage::Disconnect(d) => unsubscribe!(d, session, "Disconnecting device {:?}"),
+        v5::ControlMessage::Disconnect(d) => {
+            // clean disconnect, discard the will, unless the device requested it
+            if d.packet().reason_code != DisconnectReasonCode::DisconnectWithWillMessage {
+                session.will.lock().unwrap().take();
+            }
+            unsubscribe!(d, session, "Disconnecting device {:?}")
+        }
         v5::ControlMessage::Subscribe(mut s) => {
             subscribe!(s, session, |mut sub: v5::control::Subscription| sub
                 .fail(v5::codec::SubscribeAckReason::NotAuthorized))
         }
         v5::ControlMessage::Unsubscribe(u) => unsubscribe!(u, session, "Unsubscribing device {:?}"),
-        v5::ControlMessage::Closed(c) => unsubscribe!(c, session, "Closing device connection {:?}"),
+        v5::ControlMessage::Closed(c) => {
+            publish_will(session.state()).await;
+            publish_connection(session.state(), false).await;
+            unsubscribe!(c, session, "Closing device connection {:?}")
+        }
     }
 }
@@ -2,12 +2,13 @@ use crate::{
     auth::AcceptAllClientCertVerifier,
     error::ServerError,
     mqtt::{connect_v3, connect_v5, control_v3, control_v5, publish_v3, publish_v5},
+    will::{PendingWills, Will},
     App, Config,
 };
 use anyhow::Context;
 use drogue_cloud_endpoint_common::{
     commands::Commands,
-    downstream::{DownstreamSender, DownstreamSink},
+    downstream::{ConnectionGuard, DownstreamSender, DownstreamSink, QuotaSpec},
 };
 use drogue_cloud_service_common::Id;
 use futures::future::ok;
@@ -19,7 +20,11 @@ use ntex_mqtt::{v3, v5, MqttError, MqttServer};
 use ntex_service::pipeline_factory;
 use pem::parse_many;
 use rust_tls::{internal::pemfile::certs, PrivateKey, ServerConfig};
-use std::{fs::File, io::BufReader, sync::Arc};
+use std::{
+    fs::File,
+    io::BufReader,
+    sync::{Arc, Mutex},
+};
 
 #[derive(Clone)]
 pub struct Session<S>
@@ -29,17 +34,35 @@ where
     pub sender: DownstreamSender<S>,
     pub device_id: Id,
     pub commands: Commands,
+    pub quotas: Option<QuotaSpec>,
+    /// The last will of the device, cleared on a clean disconnect.
+    pub will: Arc<Mutex<Option<Will>>>,
+    pub wills: PendingWills,
+    /// Counts the connection against the quota, as long as the session exists.
+    _connection: Arc<ConnectionGuard>,
 }
 
 impl<S> Session<S>
 where
     S: DownstreamSink,
 {
-    pub fn new(sender: DownstreamSender<S>, device_id: Id, commands: Commands) -> Self {
+    pub fn new(
+        sender: DownstreamSender<S>,
+        device_id: Id,
+        commands: Commands,
+        quotas: Option<QuotaSpec>,
+        connection: ConnectionGuard,
+        will: Option<Will>,
+        wills: PendingWills,
+    ) -> Self {
         Session {
             sender,
             device_id,
             commands,
+            quotas,
+            will: Arc::new(Mutex::new(will)),
+            wills,
+            _connection: Arc::new(connection),
         }
     }
 }
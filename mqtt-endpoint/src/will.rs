@@ -0,0 +1,111 @@
+use bytes::Bytes;
+use drogue_cloud_service_common::Id;
+use ntex_mqtt::{v3, v5};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// The last will of a device, published when the connection drops without a clean disconnect.
+#[derive(Clone, Debug)]
+pub struct Will {
+    pub channel: String,
+    pub payload: Bytes,
+    pub content_type: Option<String>,
+    /// The time to wait before publishing the will.
+    pub delay: Duration,
+}
+
+impl Will {
+    pub fn from_v3(will: &v3::codec::LastWill) -> Self {
+        Self {
+            channel: will.topic.to_string(),
+            payload: will.message.clone(),
+            content_type: None,
+            delay: Duration::from_secs(0),
+        }
+    }
+
+    pub fn from_v5(will: &v5::codec::LastWill) -> Self {
+        Self {
+            channel: will.topic.to_string(),
+            payload: will.message.clone(),
+            content_type: will.content_type.as_ref().map(|t| t.to_string()),
+            delay: Duration::from_secs(will.will_delay_interval_sec.unwrap_or_default() as u64),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    next: u64,
+    pending: HashMap<Id, u64>,
+}
+
+/// The wills waiting for their delay to expire.
+///
+/// A will is cancelled when the device connects again before the delay expired.
+#[derive(Clone, Debug, Default)]
+pub struct PendingWills {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl PendingWills {
+    /// Schedule the will of a device, returning the token to claim it with.
+    pub fn schedule(&self, id: Id) -> u64 {
+        let mut inner = self.inner.lock().unwrap();
+        inner.next += 1;
+        let token = inner.next;
+        inner.pending.insert(id, token);
+        token
+    }
+
+    /// Claim a scheduled will, returning `true` if it wasn't cancelled or replaced.
+    pub fn claim(&self, id: &Id, token: u64) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        match inner.pending.get(id) {
+            Some(current) if *current == token => {
+                inner.pending.remove(id);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Cancel the scheduled will of a device.
+    pub fn cancel(&self, id: &Id) {
+        self.inner.lock().unwrap().pending.remove(id);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_claim() {
+        let wills = PendingWills::default();
+        let id = Id::new("app", "device");
+
+        let token = wills.schedule(id.clone());
+        assert!(wills.claim(&id, token));
+        assert!(!wills.claim(&id, token));
+    }
+
+    #[test]
+    fn test_cancel() {
+        let wills = PendingWills::default();
+        let id = Id::new("app", "device");
+
+        let token = wills.schedule(id.clone());
+        wills.cancel(&id);
+        assert!(!wills.claim(&id, token));
+
+        // a newer will replaces the older one
+        let first = wills.schedule(id.clone());
+        let second = wills.schedule(id.clone());
+        assert!(!wills.claim(&id, first));
+        assert!(wills.claim(&id, second));
+    }
+}
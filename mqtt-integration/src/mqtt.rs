@@ -41,7 +41,7 @@ where
             ack.retain_available = Some(false);
             ack.shared_subscription_available = Some(true);
             ack.subscription_identifiers_available = Some(true);
-            ack.wildcard_subscription_available = Some(false);
+            ack.wildcard_subscription_available = Some(true);
         })),
         Err(_) => Ok(connect.failed(ConnectAckReason::BadUserNameOrPassword)),
     }
@@ -271,7 +271,6 @@ impl<'a> Subscription<'a> {
         }
     }
 
-    #[allow(dead_code)]
     pub fn qos(&self) -> QoS {
         match self {
             Subscription::V3(sub) => sub.qos(),
@@ -1,19 +1,24 @@
-use crate::{error::ServerError, mqtt::*};
+use crate::{error::ServerError, mqtt::*, transform::ContentMode};
+use chrono::{DateTime, Utc};
 use cloudevents::Data;
-use drogue_client::{registry, Context};
+use drogue_client::Context;
 use drogue_cloud_endpoint_common::downstream::{DownstreamSender, DownstreamSink};
 use drogue_cloud_integration_common::{
     self,
     commands::CommandOptions,
-    stream::{EventStream, EventStreamConfig},
+    filter::{subscription_id, DeviceLabels, EventFilter, TopicFilter},
+    stream::{parse_since, EventSource, EventStream, EventStreamConfig},
 };
-use drogue_cloud_service_api::auth::user::{
-    authn::{AuthenticationRequest, Outcome},
-    authz::{self, AuthorizationRequest, Permission},
-    UserInformation,
+use drogue_cloud_service_api::{
+    auth::user::{
+        authn::{AuthenticationRequest, Outcome},
+        authz::{self, AuthorizationRequest, Permission},
+        UserInformation,
+    },
+    labels::LabelSelector,
 };
 use drogue_cloud_service_common::{
-    client::UserAuthClient,
+    client::{RegistryGatewayClient, UserAuthClient},
     defaults,
     openid::{Authenticator, AuthenticatorError},
 };
@@ -23,6 +28,7 @@ use ntex_mqtt::{types::QoS, v5};
 use serde::Deserialize;
 use std::{
     collections::HashMap,
+    convert::TryFrom,
     num::NonZeroU32,
     sync::{Arc, Mutex},
 };
@@ -30,6 +36,8 @@ use tokio::task::JoinHandle;
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct ServiceConfig {
+    #[serde(default)]
+    pub event_source: EventSource,
     #[serde(default = "defaults::kafka_bootstrap_servers")]
     pub kafka_bootstrap_servers: String,
     #[serde(default = "defaults::kafka_events_topic")]
@@ -45,6 +53,7 @@ pub struct ServiceConfig {
 impl Default for ServiceConfig {
     fn default() -> Self {
         Self {
+            event_source: Default::default(),
             kafka_bootstrap_servers: defaults::kafka_bootstrap_servers(),
             kafka_topic: defaults::kafka_events_topic(),
             kafka_properties: Default::default(),
@@ -61,7 +70,8 @@ pub struct App<S: DownstreamSink> {
     pub config: ServiceConfig,
     pub sender: DownstreamSender<S>,
     pub client: reqwest::Client,
-    pub registry: registry::v1::Client,
+    pub gateways: RegistryGatewayClient,
+    pub labels: DeviceLabels,
 }
 
 pub struct Session<S: DownstreamSink> {
@@ -71,21 +81,30 @@ pub struct Session<S: DownstreamSink> {
     pub sink: Sink,
     pub client_id: String,
     pub user: UserInformation,
+    /// A persistent session keeps its consumer group and offsets across connections.
+    pub persistent: bool,
 
     streams: Arc<Mutex<HashMap<String, JoinHandle<()>>>>,
 
     pub sender: DownstreamSender<S>,
     pub client: reqwest::Client,
-    pub registry: registry::v1::Client,
+    pub gateways: RegistryGatewayClient,
+    pub labels: DeviceLabels,
 
     pub token: Option<String>,
 }
 
 struct Stream {
     pub topic: ByteString,
+    /// The topic prefix (e.g. `$mode/binary/app/<app>`), used to build the topic of events when
+    /// subscribing using wildcards.
+    pub topic_prefix: Option<String>,
     pub id: Option<NonZeroU32>,
     pub event_stream: EventStream,
     pub content_mode: ContentMode,
+    pub filter: EventFilter,
+    pub labels: DeviceLabels,
+    pub qos: QoS,
 }
 
 impl Drop for Stream {
@@ -94,12 +113,6 @@ impl Drop for Stream {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
-enum ContentMode {
-    Binary,
-    Structured,
-}
-
 impl<S> App<S>
 where
     S: DownstreamSink,
@@ -165,10 +178,6 @@ where
     pub async fn connect<Io>(&self, connect: Connect<'_, Io>) -> Result<Session<S>, ServerError> {
         log::debug!("Processing connect request");
 
-        if !connect.clean_session() {
-            return Err(ServerError::UnsupportedOperation);
-        }
-
         let user = if let Some(auth) = &self.authenticator {
             // authenticate
             self.authenticate(&connect, auth)
@@ -180,6 +189,12 @@ where
         };
 
         let client_id = connect.client_id().to_string();
+        let persistent = !connect.clean_session();
+
+        if persistent && client_id.is_empty() {
+            // a persistent session requires a client id to identify it
+            return Err(ServerError::UnsupportedOperation);
+        }
 
         let token = match connect.credentials().1 {
             Some(token) => match String::from_utf8(token.to_vec()) {
@@ -195,9 +210,11 @@ where
             connect.sink(),
             user,
             client_id,
+            persistent,
             self.sender.clone(),
             self.client.clone(),
-            self.registry.clone(),
+            self.gateways.clone(),
+            self.labels.clone(),
             token,
         ))
     }
@@ -207,15 +224,18 @@ impl<S> Session<S>
 where
     S: DownstreamSink,
 {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         config: ServiceConfig,
         user_auth: Option<Arc<UserAuthClient>>,
         sink: Sink,
         user: UserInformation,
         client_id: String,
+        persistent: bool,
         sender: DownstreamSender<S>,
         client: reqwest::Client,
-        registry: registry::v1::Client,
+        gateways: RegistryGatewayClient,
+        labels: DeviceLabels,
         token: Option<String>,
     ) -> Self {
         Session {
@@ -224,10 +244,12 @@ where
             user,
             sink,
             client_id,
+            persistent,
             streams: Arc::new(Mutex::new(HashMap::new())),
             sender,
             client,
-            registry,
+            gateways,
+            labels,
             token,
         }
     }
@@ -270,40 +292,95 @@ where
         &self,
         id: Option<NonZeroU32>,
         original_topic: String,
-        content_mode: ContentMode,
+        content_mode: Option<ContentMode>,
+        selector: Option<(String, LabelSelector)>,
+        since: Option<DateTime<Utc>>,
+        qos: QoS,
     ) -> Result<QoS, v5::codec::SubscribeAckReason> {
         // split topic into path segments
         let topic = original_topic.split('/').collect::<Vec<_>>();
 
+        // extract the content mode, which v3 clients can't provide using properties
+        let (content_mode, topic) = match topic.as_slice() {
+            ["$mode", mode, topic @ ..] => (
+                ContentMode::parse(mode, None)
+                    .ok_or(v5::codec::SubscribeAckReason::TopicFilterInvalid)?,
+                topic,
+            ),
+            other => (content_mode.unwrap_or(ContentMode::Structured), other),
+        };
+
         // extract the shared named, which we use as kafka consumer group id
-        let (group_id, topic) = match topic.as_slice() {
+        let (group_id, topic) = match topic {
             ["$shared", group_id, topic @ ..] => (Some(group_id), topic),
             other => (None, other),
         };
 
-        // check for wildcard subscriptions
-        if topic.iter().any(|seg| *seg == "+" || *seg == "#") {
-            return Err(v5::codec::SubscribeAckReason::WildcardSubscriptionsNotSupported);
-        }
-
-        let app = match topic {
+        let (app, filter) = match topic {
             [] => Err(v5::codec::SubscribeAckReason::NotAuthorized),
-            ["a", application] => Ok(application),
-            ["app", application] => Ok(application),
-            ["application", application] => Ok(application),
+            ["a", application, filter @ ..]
+            | ["app", application, filter @ ..]
+            | ["application", application, filter @ ..] => Ok((application, filter)),
             _ => Err(v5::codec::SubscribeAckReason::TopicFilterInvalid),
         }?;
 
+        // wildcards are only supported for the device and channel segments
+
+        if *app == "+" || *app == "#" {
+            return Err(v5::codec::SubscribeAckReason::WildcardSubscriptionsNotSupported);
+        }
+
+        let filter_len = filter.len();
+        let filter =
+            TopicFilter::new(filter).ok_or(v5::codec::SubscribeAckReason::TopicFilterInvalid)?;
+
+        // when using wildcards, we need to build the topic for each event, keeping the leading
+        // segments (like `$mode/<mode>`), so that the topic still matches the subscription
+
+        let topic_prefix = match filter.is_all() && !filter_has_wildcards(&original_topic) {
+            true => None,
+            false => Some(subscription_prefix(&original_topic, filter_len)),
+        };
+
         // scope the group id, as we currently only have a single kafka topic
 
-        let group_id = group_id.map(|g| format!("{}:{}", app, g));
+        let subscription = subscription_id(&[
+            Some(&filter.to_string()),
+            selector.as_ref().map(|(s, _)| s.as_str()),
+        ]);
+        let group_id = match (group_id, self.persistent) {
+            (Some(g), _) => Some(shared_group_id(app, g, &subscription)),
+            // persistent sessions use a durable group, so that they continue where they left off
+            (None, true) => Some(session_group_id(
+                app,
+                self.user.user_id().unwrap_or("anonymous"),
+                &self.client_id,
+                &subscription,
+            )),
+            (None, false) => None,
+        };
+
+        // starting from a point in time requires assigning the partitions manually
+
+        if since.is_some() && group_id.is_some() {
+            log::info!("A start time can't be combined with a shared or persistent subscription");
+            return Err(v5::codec::SubscribeAckReason::ImplementationSpecificError);
+        }
+
+        // we support "at least once" for durable groups, committing after delivery
+
+        let qos = match (&group_id, qos) {
+            (Some(_), QoS::AtLeastOnce) | (Some(_), QoS::ExactlyOnce) => QoS::AtLeastOnce,
+            _ => QoS::AtMostOnce,
+        };
 
         // log the request
 
         log::debug!(
-            "Request to subscribe to app: {} (group: {:?})",
+            "Request to subscribe to app: {} (group: {:?}, qos: {:?})",
             app,
-            group_id
+            group_id,
+            qos
         );
 
         // authorize topic for user
@@ -323,11 +400,14 @@ where
         // create stream
 
         let stream = EventStream::new(EventStreamConfig {
+            source: self.config.event_source,
             bootstrap_servers: self.config.kafka_bootstrap_servers.clone(),
             properties: self.config.kafka_properties.clone(),
             topic: self.config.kafka_topic.clone(),
-            app: app.to_string(),
+            app: Some(app.to_string()),
             consumer_group: group_id,
+            manual_commit: qos != QoS::AtMostOnce,
+            since,
         })
         .map_err(|err| {
             log::info!("Failed to subscribe to Kafka topic: {}", err);
@@ -338,16 +418,26 @@ where
 
         let stream = Stream {
             topic: original_topic.into(),
+            topic_prefix,
             id,
             event_stream: stream,
             content_mode,
+            filter: EventFilter {
+                topic: match filter.is_all() {
+                    true => None,
+                    false => Some(filter),
+                },
+                selector: selector.map(|(_, selector)| selector),
+            },
+            labels: self.labels.clone(),
+            qos,
         };
 
         self.attach_app(stream);
 
         // done
 
-        Ok(QoS::AtMostOnce)
+        Ok(qos)
     }
 
     pub async fn subscribe(&self, subscribe: Subscribe<'_>) -> Result<(), ServerError> {
@@ -359,27 +449,75 @@ where
         // evaluate the content mode
 
         let content_mode = {
-            let value = user_properties.and_then(|props| {
-                props
-                    .iter()
-                    .find(|(k, _)| k == "content-mode")
-                    .map(|(_, v)| v.to_string())
-            });
-            match value.as_deref() {
-                None | Some("structured") => ContentMode::Structured,
-                Some("binary") => ContentMode::Binary,
-                Some(other) => {
-                    log::info!("Unknown content mode: {}", other);
-                    return Err(ServerError::UnsupportedOperation);
-                }
+            let property = |name: &str| {
+                user_properties.and_then(|props| {
+                    props
+                        .iter()
+                        .find(|(k, _)| k == name)
+                        .map(|(_, v)| v.to_string())
+                })
+            };
+            match property("content-mode") {
+                None => None,
+                Some(mode) => match ContentMode::parse(&mode, property("attributes").as_deref()) {
+                    Some(mode) => Some(mode),
+                    None => {
+                        log::info!("Unknown content mode: {}", mode);
+                        return Err(ServerError::UnsupportedOperation);
+                    }
+                },
             }
         };
 
         log::debug!("Content mode: {:?}", content_mode);
 
+        // evaluate the selector, filtering on the labels of the device
+
+        let selector = user_properties
+            .and_then(|props| {
+                props
+                    .iter()
+                    .find(|(k, _)| k == "selector")
+                    .map(|(_, v)| v.to_string())
+            })
+            .map(
+                |selector| match LabelSelector::try_from(selector.as_str()) {
+                    Ok(parsed) => Ok((selector, parsed)),
+                    Err(err) => {
+                        log::info!("Invalid selector: {}", err);
+                        Err(ServerError::UnsupportedOperation)
+                    }
+                },
+            )
+            .transpose()?;
+
+        // evaluate the start time, for replaying events
+
+        let since = user_properties
+            .and_then(|props| {
+                props
+                    .iter()
+                    .find(|(k, _)| k == "since")
+                    .map(|(_, v)| v.to_string())
+            })
+            .map(|since| {
+                parse_since(&since).map_err(|err| {
+                    log::info!("{}", err);
+                    ServerError::UnsupportedOperation
+                })
+            })
+            .transpose()?;
+
         for mut sub in subscribe {
             let res = self
-                .subscribe_to(id, sub.topic().to_string(), content_mode)
+                .subscribe_to(
+                    id,
+                    sub.topic().to_string(),
+                    content_mode.clone(),
+                    selector.clone(),
+                    since,
+                    sub.qos(),
+                )
                 .await;
             log::debug!("Subscribing to: {:?} -> {:?}", sub.topic(), res);
             match res {
@@ -438,7 +576,7 @@ where
             }
 
             let response = self
-                .registry
+                .gateways
                 .get_device_and_gateways(&app, &device, Context::default())
                 .await;
 
@@ -488,39 +626,79 @@ where
     }
 
     async fn run_stream(mut stream: Stream, sink: &mut Sink) -> Result<(), anyhow::Error> {
-        let content_mode = stream.content_mode;
+        let content_mode = stream.content_mode.clone();
+        let qos = stream.qos;
 
-        log::debug!("Running stream - content-mode: {:?}", content_mode);
+        log::debug!(
+            "Running stream - content-mode: {:?}, qos: {:?}",
+            content_mode,
+            qos
+        );
+
+        macro_rules! send {
+            ($builder:expr) => {
+                match qos {
+                    QoS::AtMostOnce => $builder
+                        .send_at_most_once()
+                        .map_err(|err| anyhow::anyhow!("Failed to send event: {}", err)),
+                    _ => $builder
+                        .send_at_least_once()
+                        .await
+                        .map(|_| ())
+                        .map_err(|err| anyhow::anyhow!("Failed to send event: {:?}", err)),
+                }
+            };
+        }
 
         // run event stream
         while let Some(event) = stream.event_stream.next().await {
             log::debug!("Event: {:?}", event);
 
             let mut event = event?;
-            let topic = stream.topic.clone();
+            // only set when committing manually
+            let offset = stream.event_stream.offset();
+
+            if !stream.filter.matches(&event, &stream.labels).await? {
+                if let Some(offset) = offset {
+                    stream.event_stream.commit(&offset)?;
+                }
+                continue;
+            }
 
-            match (&mut *sink, content_mode) {
+            let topic = match &stream.topic_prefix {
+                Some(prefix) => ByteString::from(format!(
+                    "{}/{}",
+                    prefix,
+                    EventFilter::event_topic(&event).join("/")
+                )),
+                None => stream.topic.clone(),
+            };
+
+            // MQTT v3.1 has no properties, so we fall back to structured mode
+            let payload = match (&*sink, &content_mode) {
+                (Sink::V3(_), ContentMode::Binary) => ContentMode::Structured.encode(&event)?,
+                (_, content_mode) => content_mode.encode(&event)?,
+            };
+
+            match (&mut *sink, payload) {
                 // MQTT v3.1
-                (Sink::V3(sink), _) => {
-                    let event = serde_json::to_vec(&event)?;
-                    sink.publish(topic.clone(), event.into())
-                        .send_at_most_once()
+                (Sink::V3(sink), Some((payload, _))) => {
+                    send!(sink.publish(topic.clone(), payload.into()))
+                }
+                (Sink::V3(_), None) => {
+                    Err(anyhow::anyhow!("Missing encoded payload for MQTT v3.1"))
                 }
 
-                // MQTT v5 in structured mode
-                (Sink::V5(sink), ContentMode::Structured) => {
-                    let event = serde_json::to_vec(&event)?;
-                    sink.publish(topic.clone(), event.into())
-                        .properties(|p| {
-                            p.content_type =
-                                Some("application/cloudevents+json; charset=utf-8".into());
-                            p.is_utf8_payload = Some(true);
-                        })
-                        .send_at_most_once()
+                // MQTT v5 in any mode, except binary
+                (Sink::V5(sink), Some((payload, content_type))) => {
+                    send!(sink.publish(topic.clone(), payload.into()).properties(|p| {
+                        p.content_type = Some(content_type.into());
+                        p.is_utf8_payload = Some(true);
+                    }))
                 }
 
                 // MQTT v5 in binary mode
-                (Sink::V5(sink), ContentMode::Binary) => {
+                (Sink::V5(sink), None) => {
                     let (content_type, _, data) = event.take_data();
                     let builder = match data {
                         Some(Data::Binary(data)) => sink.publish(topic.clone(), data.into()),
@@ -533,18 +711,23 @@ where
 
                     // convert attributes and extensions ...
 
-                    builder
-                        .properties(|p| {
-                            for (k, v) in event.iter() {
-                                p.user_properties.push((k.into(), v.to_string().into()));
-                            }
-                            p.content_type = content_type.map(Into::into);
-                        })
-                        // ... and send
-                        .send_at_most_once()
+                    let builder = builder.properties(|p| {
+                        for (k, v) in event.iter() {
+                            p.user_properties.push((k.into(), v.to_string().into()));
+                        }
+                        p.content_type = content_type.map(Into::into);
+                    });
+
+                    // ... and send
+                    send!(builder)
                 }
+            }?;
+
+            // the event was delivered (or acknowledged), mark it as processed
+
+            if let Some(offset) = offset {
+                stream.event_stream.commit(&offset)?;
             }
-            .map_err(|err| anyhow::anyhow!("Failed to send event: {}", err))?;
 
             log::debug!("Sent message - go back to sleep");
         }
@@ -587,6 +770,33 @@ where
     }
 }
 
+/// The consumer group of a shared subscription.
+///
+/// Subscriptions only share a group when they also use the same filter. Otherwise they would
+/// split the partitions among them, and drop the events the other ones are interested in.
+fn shared_group_id(app: &str, group: &str, subscription: &str) -> String {
+    format!("{}:{}:{}", app, group, subscription)
+}
+
+/// The durable consumer group of a subscription of a persistent session.
+///
+/// A client may hold several subscriptions on the same application, each of them needs its own
+/// group, tracking its own offsets.
+fn session_group_id(app: &str, user: &str, client_id: &str, subscription: &str) -> String {
+    format!("{}:session:{}:{}:{}", app, user, client_id, subscription)
+}
+
+/// Check if a topic filter contains wildcards.
+fn filter_has_wildcards(topic: &str) -> bool {
+    topic.split('/').any(|seg| seg == "+" || seg == "#")
+}
+
+/// The leading segments of a subscription topic, up to and including the application.
+fn subscription_prefix(topic: &str, filter_len: usize) -> String {
+    let segments = topic.split('/').collect::<Vec<_>>();
+    segments[..segments.len() - filter_len].join("/")
+}
+
 impl<S> Drop for Session<S>
 where
     S: DownstreamSink,
@@ -601,3 +811,41 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn group_id(filter: &[&str], selector: Option<&str>) -> String {
+        let filter = TopicFilter::new(filter).unwrap();
+        let subscription = subscription_id(&[Some(&filter.to_string()), selector]);
+        session_group_id("app", "user", "client", &subscription)
+    }
+
+    #[test]
+    fn test_subscription_prefix() {
+        assert_eq!(subscription_prefix("app/foo/+/temp", 2), "app/foo");
+        assert_eq!(subscription_prefix("app/foo/#", 1), "app/foo");
+        assert_eq!(
+            subscription_prefix("$mode/binary/app/foo/#", 1),
+            "$mode/binary/app/foo"
+        );
+        assert_eq!(
+            subscription_prefix("$mode/binary/$shared/group/a/foo/dev/+", 2),
+            "$mode/binary/$shared/group/a/foo"
+        );
+    }
+
+    #[test]
+    fn test_session_group_id() {
+        let temp = group_id(&["device", "temp"], None);
+        let humidity = group_id(&["device", "humidity"], None);
+
+        // two subscriptions of the same client, on the same app
+        assert_ne!(temp, humidity);
+        assert_ne!(temp, group_id(&["device", "temp"], Some("foo=bar")));
+
+        // re-subscribing continues with the same group
+        assert_eq!(temp, group_id(&["device", "temp"], None));
+    }
+}
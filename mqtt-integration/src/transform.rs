@@ -0,0 +1,229 @@
+use cloudevents::{AttributesReader, Data, Event};
+use serde_json::{json, Map, Value};
+
+/// The attributes merged into the payload by default, when using the data content mode.
+const DEFAULT_ATTRIBUTES: &[&str] = &["device", "subject", "time"];
+
+/// The format of the events sent to the client.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ContentMode {
+    /// The data of the event, with the attributes mapped to v5 properties.
+    Binary,
+    /// The full event, using the structured mode of CloudEvents.
+    Structured,
+    /// The data of the event as JSON object, with the selected attributes merged in.
+    Data(Vec<String>),
+    /// A flat list of metrics, similar to Sparkplug.
+    Flat,
+}
+
+impl ContentMode {
+    /// Parse the content mode.
+    ///
+    /// The attributes are only used for the data mode. If they are not provided, a default
+    /// set of attributes is used.
+    pub fn parse(mode: &str, attributes: Option<&str>) -> Option<Self> {
+        match mode {
+            "structured" => Some(Self::Structured),
+            "binary" => Some(Self::Binary),
+            "data" => Some(Self::Data(match attributes {
+                Some(attributes) => attributes
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty())
+                    .map(ToString::to_string)
+                    .collect(),
+                None => DEFAULT_ATTRIBUTES.iter().map(ToString::to_string).collect(),
+            })),
+            "flat" => Some(Self::Flat),
+            _ => None,
+        }
+    }
+
+    /// Encode the event as payload, along with its content type.
+    ///
+    /// Returns `None` when using binary mode, as this is handled by the caller.
+    pub fn encode(&self, event: &Event) -> Result<Option<(Vec<u8>, &'static str)>, anyhow::Error> {
+        Ok(match self {
+            Self::Binary => None,
+            Self::Structured => Some((
+                serde_json::to_vec(event)?,
+                "application/cloudevents+json; charset=utf-8",
+            )),
+            Self::Data(attributes) => Some((
+                serde_json::to_vec(&to_data(event, attributes))?,
+                "application/json",
+            )),
+            Self::Flat => Some((serde_json::to_vec(&to_flat(event))?, "application/json")),
+        })
+    }
+}
+
+/// Get the value of an attribute or extension, as string.
+fn attribute(event: &Event, name: &str) -> Option<String> {
+    event
+        .iter()
+        .find(|(k, _)| *k == name)
+        .map(|(_, v)| v.to_string())
+}
+
+/// Get the data of the event as JSON value.
+fn data_value(event: &Event) -> Value {
+    match event.data() {
+        Some(Data::Json(value)) => value.clone(),
+        Some(Data::String(value)) => Value::String(value.clone()),
+        Some(Data::Binary(value)) => serde_json::from_slice(value)
+            .unwrap_or_else(|_| json!({ "value_base64": base64::encode(value) })),
+        None => Value::Null,
+    }
+}
+
+/// Convert the event into its data, as JSON object, merging in the requested attributes.
+///
+/// Non-object values are wrapped in an object, using the field `value`. Fields of the data take
+/// precedence over attributes of the same name.
+pub fn to_data(event: &Event, attributes: &[String]) -> Value {
+    let mut result = match data_value(event) {
+        Value::Object(map) => map,
+        Value::Null => Map::new(),
+        other => {
+            let mut map = Map::new();
+            map.insert("value".into(), other);
+            map
+        }
+    };
+
+    for name in attributes {
+        if result.contains_key(name) {
+            continue;
+        }
+        if let Some(value) = attribute(event, name) {
+            result.insert(name.clone(), Value::String(value));
+        }
+    }
+
+    Value::Object(result)
+}
+
+fn data_type(value: &Value) -> &'static str {
+    match value {
+        Value::Bool(_) => "Boolean",
+        Value::Number(n) if n.is_f64() => "Double",
+        Value::Number(_) => "Int64",
+        _ => "String",
+    }
+}
+
+fn flatten(prefix: &str, value: &Value, timestamp: i64, metrics: &mut Vec<Value>) {
+    let name = |key: &str| match prefix.is_empty() {
+        true => key.to_string(),
+        false => format!("{}/{}", prefix, key),
+    };
+
+    match value {
+        Value::Object(map) => {
+            for (k, v) in map {
+                flatten(&name(k), v, timestamp, metrics);
+            }
+        }
+        Value::Array(values) => {
+            for (i, v) in values.iter().enumerate() {
+                flatten(&name(&i.to_string()), v, timestamp, metrics);
+            }
+        }
+        Value::Null => {}
+        value => metrics.push(json!({
+            "name": match prefix.is_empty() {
+                true => "value",
+                false => prefix,
+            },
+            "timestamp": timestamp,
+            "dataType": data_type(value),
+            "value": value,
+        })),
+    }
+}
+
+/// Convert the event into a flat list of metrics, similar to a Sparkplug payload.
+///
+/// Nested structures are flattened, using `/` as separator for the metric names.
+pub fn to_flat(event: &Event) -> Value {
+    let timestamp = event
+        .time()
+        .map(|time| time.timestamp_millis())
+        .unwrap_or_default();
+
+    let mut metrics = Vec::new();
+    flatten("", &data_value(event), timestamp, &mut metrics);
+
+    json!({
+        "timestamp": timestamp,
+        "device": attribute(event, "device"),
+        "channel": event.subject(),
+        "metrics": metrics,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+    use cloudevents::{EventBuilder, EventBuilderV10};
+
+    fn event(data: Value) -> Event {
+        EventBuilderV10::new()
+            .id("1")
+            .ty("io.drogue.event.v1")
+            .source("drogue://app/device")
+            .subject("telemetry")
+            .time(Utc.ymd(2021, 1, 1).and_hms(0, 0, 0))
+            .extension("device", "device")
+            .data("application/json", data)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            ContentMode::parse("data", Some("device, time")),
+            Some(ContentMode::Data(vec!["device".into(), "time".into()]))
+        );
+        assert_eq!(ContentMode::parse("flat", None), Some(ContentMode::Flat));
+        assert_eq!(ContentMode::parse("foo", None), None);
+    }
+
+    #[test]
+    fn test_data() {
+        let event = event(json!({"temp": 42, "device": "other"}));
+
+        assert_eq!(
+            to_data(&event, &["device".into(), "subject".into()]),
+            json!({"temp": 42, "device": "other", "subject": "telemetry"})
+        );
+
+        let event = self::event(json!(42));
+        assert_eq!(
+            to_data(&event, &["device".into()]),
+            json!({"value": 42, "device": "device"})
+        );
+    }
+
+    #[test]
+    fn test_flat() {
+        let event = event(json!({"state": {"on": true}, "temp": 42.5}));
+
+        assert_eq!(
+            to_flat(&event),
+            json!({
+                "timestamp": 1609459200000i64,
+                "device": "device",
+                "channel": "telemetry",
+                "metrics": [
+                    {"name": "state/on", "timestamp": 1609459200000i64, "dataType": "Boolean", "value": true},
+                    {"name": "temp", "timestamp": 1609459200000i64, "dataType": "Double", "value": 42.5},
+                ]
+            })
+        );
+    }
+}
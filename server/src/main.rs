@@ -0,0 +1,119 @@
+//! Run the HTTP endpoint, the MQTT endpoint, and the MQTT integration in a single process.
+//!
+//! Events and commands are exchanged using in-process topics, instead of Kafka. This is intended
+//! for development only.
+
+use cloudevents::Event;
+use dotenv::dotenv;
+use drogue_cloud_endpoint_common::{
+    commands::{Command, Commands},
+    downstream::{memory_subscribe, ConfiguredSink, DownstreamSender, MemorySink},
+};
+use drogue_cloud_integration_common::stream::EventSource;
+use drogue_cloud_service_common::{
+    config::ConfigFromEnv,
+    health::{HealthServer, HealthServerConfig},
+};
+use serde::Deserialize;
+use std::{convert::TryFrom, sync::mpsc, thread};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+#[derive(Clone, Debug, Deserialize)]
+struct Config {
+    #[serde(default)]
+    pub health: HealthServerConfig,
+}
+
+/// Route commands from the in-process commands topic to the devices connected to the endpoints.
+async fn route_commands(
+    mut receiver: broadcast::Receiver<Event>,
+    endpoints: Vec<Commands>,
+) -> anyhow::Result<()> {
+    loop {
+        match receiver.recv().await {
+            Ok(event) => match Command::try_from(event) {
+                Ok(command) => {
+                    for commands in &endpoints {
+                        if let Err(err) = commands.send(command.clone()).await {
+                            log::info!("Failed to route command: {}", err);
+                        }
+                    }
+                }
+                Err(_) => log::info!("Dropping command without a device id"),
+            },
+            Err(RecvError::Lagged(n)) => log::warn!("Dropped {} commands", n),
+            Err(RecvError::Closed) => break,
+        }
+    }
+
+    Ok(())
+}
+
+fn main() -> anyhow::Result<()> {
+    env_logger::init();
+    dotenv().ok();
+
+    let config = Config::from_env()?;
+
+    let http_config = drogue_cloud_http_endpoint::Config::from_env_prefix("HTTP_ENDPOINT")?;
+    let mqtt_config = drogue_cloud_mqtt_endpoint::Config::from_env_prefix("MQTT_ENDPOINT")?;
+    let mut integration_config =
+        drogue_cloud_mqtt_integration::Config::from_env_prefix("MQTT_INTEGRATION")?;
+
+    // wire up the in-process topics
+
+    let events = MemorySink::new("DOWNSTREAM")?;
+    let commands = MemorySink::new("COMMAND")?;
+
+    integration_config.service.event_source = EventSource::Memory;
+    integration_config.service.kafka_topic = events.topic().to_string();
+    if integration_config.bind_addr_mqtt.is_none() {
+        // the defaults of the MQTT endpoint and integration would clash
+        integration_config.bind_addr_mqtt = Some(if integration_config.disable_tls {
+            "127.0.0.1:1884".into()
+        } else {
+            "127.0.0.1:8884".into()
+        });
+    }
+
+    let command_receiver = memory_subscribe(commands.topic());
+    let http_commands = Commands::new();
+    let mqtt_commands = Commands::new();
+
+    let http_sender = DownstreamSender::new(ConfiguredSink::Memory(events.clone()))?;
+    let mqtt_sender = DownstreamSender::new(ConfiguredSink::Memory(events))?;
+    let integration_sender = DownstreamSender::new(ConfiguredSink::Memory(commands))?;
+
+    let health = HealthServer::new(config.health, vec![]);
+
+    // the HTTP endpoint requires actix, the MQTT services require ntex, so each gets its own thread
+
+    let (tx, rx) = mpsc::channel();
+
+    let result = tx.clone();
+    let router = vec![http_commands.clone(), mqtt_commands.clone()];
+    thread::Builder::new().name("http".into()).spawn(move || {
+        let _ = result.send(actix_rt::System::new().block_on(async move {
+            futures::try_join!(
+                health.run(),
+                route_commands(command_receiver, router),
+                drogue_cloud_http_endpoint::run(http_config, http_sender, http_commands),
+            )
+            .map(|_| ())
+        }));
+    })?;
+
+    thread::Builder::new().name("mqtt".into()).spawn(move || {
+        let _ = tx.send(ntex::rt::System::new("mqtt").block_on(async move {
+            futures::try_join!(
+                drogue_cloud_mqtt_endpoint::run(mqtt_config, mqtt_sender, mqtt_commands),
+                drogue_cloud_mqtt_integration::run(integration_config, integration_sender),
+            )
+            .map(|_| ())
+        }));
+    })?;
+
+    // exit as soon as one of the services stops
+
+    rx.recv()?
+}
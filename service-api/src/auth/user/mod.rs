@@ -13,6 +13,10 @@ impl UserDetails {
     pub fn is_admin(&self) -> bool {
         self.roles.iter().any(|s| s == "drogue-admin")
     }
+
+    pub fn is_controller(&self) -> bool {
+        self.roles.iter().any(|s| s == "drogue-controller")
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -42,6 +46,12 @@ impl UserInformation {
             Self::Anonymous => false,
         }
     }
+    pub fn is_controller(&self) -> bool {
+        match self {
+            Self::Authenticated(details) => details.is_controller(),
+            Self::Anonymous => false,
+        }
+    }
 }
 
 #[cfg(feature = "with_actix")]
@@ -0,0 +1,37 @@
+use chrono::{DateTime, Utc};
+use drogue_client::{dialect, Dialect, Section};
+use serde::{Deserialize, Serialize};
+
+/// The type of events, reporting a change of the connection state of a device.
+pub const EVENT_TYPE_CONNECTION: &str = "io.drogue.connection.v1";
+/// The channel of connection events.
+pub const CHANNEL_CONNECTION: &str = "$connection";
+
+/// The data of a connection event.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectionEvent {
+    pub connected: bool,
+    /// The protocol the device connected with, e.g. `mqtt`.
+    pub protocol: String,
+}
+
+/// The connection state of a device, stored in the status section `connection`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceConnectionStatus {
+    /// If the device is currently connected, using a connection oriented protocol.
+    #[serde(default)]
+    pub connected: bool,
+    /// The protocol of the current, or last, connection.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub protocol: Option<String>,
+    /// The time the connection state last changed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub since: Option<DateTime<Utc>>,
+    /// The last time an event of the device was received.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_seen: Option<DateTime<Utc>>,
+}
+
+dialect!(DeviceConnectionStatus[Section::Status => "connection"]);
@@ -0,0 +1,98 @@
+use crate::twin::merge;
+use drogue_client::{meta, registry};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// The field of the device spec, referencing the device type by name.
+pub const SPEC_DEVICE_TYPE: &str = "deviceType";
+
+/// A type of devices in an application.
+///
+/// Devices referencing a type inherit its spec sections, and may override them.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DeviceType {
+    pub metadata: meta::v1::ScopedMetadata,
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub spec: Map<String, Value>,
+}
+
+/// Get the name of the device type a device spec references, if any.
+pub fn device_type_of(spec: &Map<String, Value>) -> Option<&str> {
+    spec.get(SPEC_DEVICE_TYPE)
+        .and_then(Value::as_str)
+        .filter(|name| !name.is_empty())
+}
+
+/// Evaluate the effective spec of a device.
+///
+/// The spec of the device is merged over the spec of its type, following the rules of a JSON
+/// merge patch. So a device may override a field of its type, or remove it using `null`.
+pub fn effective_spec(
+    type_spec: &Map<String, Value>,
+    device_spec: Map<String, Value>,
+) -> Map<String, Value> {
+    let mut spec = Value::Object(type_spec.clone());
+    merge(&mut spec, Value::Object(device_spec));
+
+    match spec {
+        Value::Object(spec) => spec,
+        _ => Map::new(),
+    }
+}
+
+/// Turn a device into its effective device, by merging in the spec of its type.
+pub fn apply(device_type: &DeviceType, mut device: registry::v1::Device) -> registry::v1::Device {
+    device.spec = effective_spec(&device_type.spec, device.spec);
+    device
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn map(value: Value) -> Map<String, Value> {
+        match value {
+            Value::Object(map) => map,
+            _ => panic!("Not an object"),
+        }
+    }
+
+    #[test]
+    fn test_device_type_of() {
+        assert_eq!(
+            device_type_of(&map(json!({"deviceType": "sensor"}))),
+            Some("sensor")
+        );
+        assert_eq!(device_type_of(&map(json!({"deviceType": ""}))), None);
+        assert_eq!(device_type_of(&map(json!({"deviceType": 1}))), None);
+        assert_eq!(device_type_of(&map(json!({}))), None);
+    }
+
+    #[test]
+    fn test_effective_spec() {
+        let type_spec = map(json!({
+            "credentials": {"credentials": [{"pass": "foo"}]},
+            "lorawan": {"port": 1, "schema": "sensor"},
+            "gatewaySelector": {"matchNames": ["gw1"]},
+        }));
+
+        let spec = effective_spec(
+            &type_spec,
+            map(json!({
+                "deviceType": "sensor",
+                "lorawan": {"port": 2},
+                "gatewaySelector": null,
+            })),
+        );
+
+        assert_eq!(
+            Value::Object(spec),
+            json!({
+                "deviceType": "sensor",
+                "credentials": {"credentials": [{"pass": "foo"}]},
+                "lorawan": {"port": 2, "schema": "sensor"},
+            })
+        );
+    }
+}
@@ -0,0 +1,149 @@
+//! Selecting resources by the content of their spec or status.
+
+use crate::labels::Operation;
+use serde_json::Value;
+use std::collections::HashMap;
+
+#[cfg(feature = "nom")]
+use crate::labels::{parse_from, ParserError};
+#[cfg(feature = "nom")]
+use std::convert::TryFrom;
+
+/// The sections of a resource, which a field selector may refer to.
+pub const SECTIONS: &[&str] = &["spec", "status"];
+
+/// A selector on the spec and status of a resource.
+///
+/// It uses the same syntax as the [`LabelSelector`](crate::labels::LabelSelector), with the label
+/// being a dotted path to a field, like `spec.lorawan` or `status.firmware.version!=1.2`. The
+/// segments of the path may only consist of ASCII letters, digits, `_` and `-`.
+///
+/// Fields are compared by their text representation. A field with a `null` value is considered
+/// to be missing.
+#[derive(Clone, Debug, Default)]
+pub struct FieldSelector(pub Vec<Operation>);
+
+/// Lookup the text representation of a field, by its dotted path.
+fn lookup(data: &Value, field: &str) -> Option<String> {
+    let value = field
+        .split('.')
+        .try_fold(data, |value, segment| value.get(segment))?;
+
+    match value {
+        Value::Null => None,
+        Value::String(value) => Some(value.clone()),
+        value => Some(value.to_string()),
+    }
+}
+
+impl FieldSelector {
+    /// Evaluate the selector against the data of a resource, consisting of its spec and status.
+    pub fn matches(&self, data: &Value) -> bool {
+        let values: HashMap<&str, String> = self
+            .0
+            .iter()
+            .filter_map(|op| lookup(data, op.label()).map(|value| (op.label(), value)))
+            .collect();
+
+        self.0
+            .iter()
+            .all(|op| op.matches(|field| values.get(field).map(String::as_str)))
+    }
+}
+
+/// Check if a segment of a field path is valid.
+///
+/// The path gets embedded into SQL/JSON path expressions, so only allow characters which need
+/// no escaping.
+#[cfg(feature = "nom")]
+fn valid_segment(segment: &str) -> bool {
+    !segment.is_empty()
+        && segment
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+#[cfg(feature = "nom")]
+fn validate(ops: Vec<Operation>) -> Result<Vec<Operation>, ParserError> {
+    for op in &ops {
+        let field = op.label();
+        let mut segments = field.split('.');
+        let valid = segments
+            .next()
+            .map(|section| SECTIONS.contains(&section))
+            .unwrap_or_default()
+            && segments.all(valid_segment)
+            && field.contains('.');
+
+        if !valid {
+            return Err(ParserError::new(format!(
+                "Invalid field: '{}', must be a field of the spec or status, like 'spec.foo'",
+                field
+            )));
+        }
+    }
+
+    Ok(ops)
+}
+
+#[cfg(feature = "nom")]
+impl TryFrom<&str> for FieldSelector {
+    type Error = ParserError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        Ok(FieldSelector(validate(parse_from(value)?)?))
+    }
+}
+
+#[cfg(feature = "nom")]
+impl TryFrom<String> for FieldSelector {
+    type Error = ParserError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Ok(FieldSelector(validate(parse_from(&value)?)?))
+    }
+}
+
+#[cfg(all(test, feature = "nom"))]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn matches(selector: &str, data: Value) -> bool {
+        FieldSelector::try_from(selector).unwrap().matches(&data)
+    }
+
+    #[test]
+    fn test_parse() {
+        assert!(FieldSelector::try_from("").is_ok());
+        assert!(FieldSelector::try_from("spec.lorawan,status.firmware.version!=1.2").is_ok());
+        assert!(FieldSelector::try_from("spec").is_err());
+        assert!(FieldSelector::try_from("metadata.name").is_err());
+        assert!(FieldSelector::try_from("spec..foo").is_err());
+        assert!(FieldSelector::try_from("spec.foo/bar").is_err());
+        assert!(FieldSelector::try_from("spec.dev_eui-2").is_ok());
+        assert!(FieldSelector::try_from(r#"spec.foo" || @ == "bar"#).is_err());
+        assert!(FieldSelector::try_from(r#"spec.foo\bar"#).is_err());
+        assert!(FieldSelector::try_from("spec.foo'bar").is_err());
+    }
+
+    #[test]
+    fn test_matches() {
+        let data = json!({
+            "spec": {"lorawan": {"devEui": "0123"}, "enabled": true, "nothing": null},
+            "status": {"firmware": {"version": "1.2"}, "retries": 3},
+        });
+
+        assert!(matches("", data.clone()));
+        assert!(matches("spec.lorawan", data.clone()));
+        assert!(matches("spec.lorawan.devEui=0123", data.clone()));
+        assert!(!matches("!spec.lorawan", data.clone()));
+        assert!(!matches("spec.nothing", data.clone()));
+        assert!(matches("spec.enabled=true", data.clone()));
+        assert!(matches("status.retries in (2, 3)", data.clone()));
+        assert!(!matches("status.firmware.version!=1.2", data.clone()));
+        assert!(matches("status.firmware.version!=1.3", data.clone()));
+        assert!(matches("status.foo!=1.2", data.clone()));
+        assert!(!matches("status.foo=1.2", data));
+    }
+}
@@ -0,0 +1,73 @@
+use drogue_client::{dialect, Dialect, Section};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// The maximum number of levels of a chain of gateways.
+pub const MAX_GATEWAY_DEPTH: usize = 8;
+
+/// Select the gateways, which may act on behalf of a device.
+///
+/// This is compatible with the `gatewaySelector` section of the device spec, and extends it by
+/// selecting gateways using their labels. A gateway may select gateways of its own, forming a
+/// chain of gateways.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct GatewaySelector {
+    /// Select gateways by their name.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub match_names: Vec<String>,
+    /// Select gateways having all of these labels.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub match_labels: HashMap<String, String>,
+}
+
+dialect!(GatewaySelector[Section::Spec => "gatewaySelector"]);
+
+impl GatewaySelector {
+    /// Check if the selector doesn't select any gateway.
+    pub fn is_empty(&self) -> bool {
+        self.match_names.is_empty() && self.match_labels.is_empty()
+    }
+
+    /// Check if a device, with its name and labels, is selected as gateway.
+    pub fn matches(&self, name: &str, labels: &HashMap<String, String>) -> bool {
+        if self.match_names.iter().any(|n| n == name) {
+            return true;
+        }
+
+        !self.match_labels.is_empty()
+            && self
+                .match_labels
+                .iter()
+                .all(|(key, value)| labels.get(key) == Some(value))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_matches() {
+        let labels: HashMap<String, String> = vec![("floor".to_string(), "1".to_string())]
+            .into_iter()
+            .collect();
+
+        let selector: GatewaySelector = serde_json::from_value(json!({
+            "matchNames": ["gw1"],
+        }))
+        .unwrap();
+        assert!(selector.matches("gw1", &Default::default()));
+        assert!(!selector.matches("gw2", &labels));
+
+        let selector: GatewaySelector = serde_json::from_value(json!({
+            "matchLabels": {"floor": "1"},
+        }))
+        .unwrap();
+        assert!(selector.matches("gw2", &labels));
+        assert!(!selector.matches("gw2", &Default::default()));
+
+        assert!(!GatewaySelector::default().matches("gw1", &labels));
+    }
+}
@@ -0,0 +1,127 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{fmt, str::FromStr};
+
+/// The kind of a registry resource, tracked by the history.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ResourceKind {
+    Application,
+    Device,
+    DeviceType,
+}
+
+/// The operation performed on a resource.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum Operation {
+    Create,
+    Update,
+    Delete,
+}
+
+/// A change of a registry resource.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryEntry {
+    pub kind: ResourceKind,
+    pub application: String,
+    pub name: String,
+    pub uid: String,
+    pub operation: Operation,
+    pub timestamp: DateTime<Utc>,
+    /// The generation of the resource, after the change.
+    pub generation: u64,
+    /// The user performing the change, `None` for anonymous users.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<String>,
+    /// The change, as JSON patch, from the previous to the new state of the resource.
+    ///
+    /// A non-existing resource is represented as `null`.
+    pub diff: Value,
+}
+
+impl AsRef<str> for ResourceKind {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::Application => "Application",
+            Self::Device => "Device",
+            Self::DeviceType => "DeviceType",
+        }
+    }
+}
+
+impl FromStr for ResourceKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Application" => Ok(Self::Application),
+            "Device" => Ok(Self::Device),
+            "DeviceType" => Ok(Self::DeviceType),
+            _ => Err(format!("Unknown resource kind: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for ResourceKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_ref())
+    }
+}
+
+impl AsRef<str> for Operation {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::Create => "create",
+            Self::Update => "update",
+            Self::Delete => "delete",
+        }
+    }
+}
+
+impl FromStr for Operation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "create" => Ok(Self::Create),
+            "update" => Ok(Self::Update),
+            "delete" => Ok(Self::Delete),
+            _ => Err(format!("Unknown operation: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        for kind in &[
+            ResourceKind::Application,
+            ResourceKind::Device,
+            ResourceKind::DeviceType,
+        ] {
+            assert_eq!(kind.to_string().parse(), Ok(*kind));
+            assert_eq!(
+                serde_json::to_value(kind).unwrap(),
+                Value::String(kind.to_string())
+            );
+        }
+        for operation in &[Operation::Create, Operation::Update, Operation::Delete] {
+            assert_eq!(operation.to_string().parse(), Ok(*operation));
+            assert_eq!(
+                serde_json::to_value(operation).unwrap(),
+                Value::String(operation.to_string())
+            );
+        }
+    }
+}
@@ -6,6 +6,7 @@ pub use parser::*;
 
 use std::convert::TryFrom;
 
+#[derive(Clone, Debug)]
 pub struct LabelSelector(pub Vec<Operation>);
 
 impl Default for LabelSelector {
@@ -24,6 +25,52 @@ pub enum Operation {
     NotExists(String),
 }
 
+impl LabelSelector {
+    /// Evaluate the selector against a set of labels.
+    ///
+    /// The labels are provided by a lookup function, allowing to evaluate the selector against
+    /// things other than a map.
+    pub fn matches<'a, F>(&self, lookup: F) -> bool
+    where
+        F: Fn(&str) -> Option<&'a str>,
+    {
+        self.0.iter().all(|op| op.matches(&lookup))
+    }
+}
+
+impl Operation {
+    /// The label, or field, this operation refers to.
+    pub fn label(&self) -> &str {
+        match self {
+            Self::Eq(label, _)
+            | Self::NotEq(label, _)
+            | Self::In(label, _)
+            | Self::NotIn(label, _)
+            | Self::Exists(label)
+            | Self::NotExists(label) => label,
+        }
+    }
+
+    /// Evaluate the operation against a set of labels.
+    pub fn matches<'a, F>(&self, lookup: F) -> bool
+    where
+        F: Fn(&str) -> Option<&'a str>,
+    {
+        match self {
+            Self::Eq(label, value) => lookup(label) == Some(value.as_str()),
+            Self::NotEq(label, value) => lookup(label) != Some(value.as_str()),
+            Self::In(label, values) => lookup(label)
+                .map(|v| values.iter().any(|value| value == v))
+                .unwrap_or_default(),
+            Self::NotIn(label, values) => lookup(label)
+                .map(|v| !values.iter().any(|value| value == v))
+                .unwrap_or(true),
+            Self::Exists(label) => lookup(label).is_some(),
+            Self::NotExists(label) => lookup(label).is_none(),
+        }
+    }
+}
+
 #[cfg(feature = "nom")]
 impl TryFrom<&str> for LabelSelector {
     type Error = parser::ParserError;
@@ -41,3 +88,29 @@ impl TryFrom<String> for LabelSelector {
         Ok(LabelSelector(parser::parse_from(&value)?))
     }
 }
+
+#[cfg(all(test, feature = "nom"))]
+mod test {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn matches(selector: &str, labels: &[(&str, &str)]) -> bool {
+        let labels: HashMap<_, _> = labels.iter().cloned().collect();
+        LabelSelector::try_from(selector)
+            .unwrap()
+            .matches(|label| labels.get(label).cloned())
+    }
+
+    #[test]
+    fn test_matches() {
+        assert!(matches("", &[]));
+        assert!(matches("foo", &[("foo", "bar")]));
+        assert!(!matches("!foo", &[("foo", "bar")]));
+        assert!(matches("foo=bar", &[("foo", "bar")]));
+        assert!(!matches("foo!=bar", &[("foo", "bar")]));
+        assert!(matches("foo in (bar, baz)", &[("foo", "baz")]));
+        assert!(!matches("foo in (bar, baz)", &[]));
+        assert!(matches("foo notin (bar, baz)", &[]));
+        assert!(!matches("foo=bar,baz", &[("foo", "bar")]));
+    }
+}
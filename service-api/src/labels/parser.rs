@@ -10,6 +10,14 @@ pub struct ParserError {
     details: String,
 }
 
+impl ParserError {
+    pub(crate) fn new<S: Into<String>>(details: S) -> Self {
+        Self {
+            details: details.into(),
+        }
+    }
+}
+
 impl std::error::Error for ParserError {}
 
 impl core::fmt::Display for ParserError {
@@ -1,10 +1,17 @@
 pub mod api;
 pub mod auth;
+pub mod connection;
+pub mod device_type;
 pub mod endpoints;
+pub mod fields;
+pub mod gateway;
 pub mod health;
+pub mod history;
 mod id;
 pub mod labels;
 mod serde;
+pub mod twin;
 pub mod version;
+pub mod watch;
 
 pub use id::*;
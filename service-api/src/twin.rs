@@ -0,0 +1,118 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// The command sent to a device, when its desired and reported state diverge.
+pub const COMMAND_DELTA: &str = "$delta";
+/// The channel a device publishes (parts of) its reported state on.
+pub const CHANNEL_TWIN: &str = "$twin";
+
+/// The twin of a device, holding the state reported by the device and the state desired by the
+/// application.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceTwin {
+    #[serde(default)]
+    pub reported: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reported_timestamp: Option<DateTime<Utc>>,
+
+    #[serde(default)]
+    pub desired: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub desired_timestamp: Option<DateTime<Utc>>,
+
+    /// The part of the desired state, which doesn't match the reported state.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delta: Option<Value>,
+}
+
+/// Merge a JSON document into another one, following the rules of a JSON merge patch
+/// (RFC 7386): objects get merged recursively, `null` values remove a field, and all other
+/// values replace the existing value.
+pub fn merge(target: &mut Value, patch: Value) {
+    match patch {
+        Value::Object(patch) => {
+            if !target.is_object() {
+                *target = Value::Object(Map::new());
+            }
+            if let Value::Object(target) = target {
+                for (key, value) in patch {
+                    if value.is_null() {
+                        target.remove(&key);
+                    } else {
+                        merge(target.entry(key).or_insert(Value::Null), value);
+                    }
+                }
+            }
+        }
+        patch => *target = patch,
+    }
+}
+
+/// Evaluate the part of the desired state, which doesn't match the reported state.
+///
+/// Returns `None` if the reported state matches the desired state.
+pub fn delta(desired: &Value, reported: &Value) -> Option<Value> {
+    match (desired, reported) {
+        (Value::Object(desired), Value::Object(reported)) => {
+            let delta: Map<_, _> = desired
+                .iter()
+                .filter_map(|(key, value)| {
+                    let delta = match reported.get(key) {
+                        Some(reported) => delta(value, reported),
+                        None => Some(value.clone()),
+                    };
+                    delta.map(|delta| (key.clone(), delta))
+                })
+                .collect();
+            if delta.is_empty() {
+                None
+            } else {
+                Some(Value::Object(delta))
+            }
+        }
+        // an empty desired state matches everything
+        (Value::Object(desired), _) if desired.is_empty() => None,
+        (Value::Null, _) => None,
+        (desired, reported) if desired == reported => None,
+        (desired, _) => Some(desired.clone()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_merge() {
+        let mut state = json!({"a": 1, "b": {"c": 2, "d": 3}});
+
+        merge(
+            &mut state,
+            json!({"a": 2, "b": {"c": null, "e": 4}, "f": [1]}),
+        );
+        assert_eq!(state, json!({"a": 2, "b": {"d": 3, "e": 4}, "f": [1]}));
+
+        merge(&mut state, json!({"b": 1}));
+        assert_eq!(state, json!({"a": 2, "b": 1, "f": [1]}));
+
+        let mut state = Value::Null;
+        merge(&mut state, json!({"a": {"b": 1}}));
+        assert_eq!(state, json!({"a": {"b": 1}}));
+    }
+
+    #[test]
+    fn test_delta() {
+        let desired = json!({"a": 1, "b": {"c": 2, "d": 3}, "e": [1, 2]});
+
+        assert_eq!(delta(&desired, &desired), None);
+        assert_eq!(delta(&json!({}), &json!({"a": 1})), None);
+        assert_eq!(
+            delta(&desired, &json!({"a": 1, "b": {"c": 2}, "e": [1], "f": 1})),
+            Some(json!({"b": {"d": 3}, "e": [1, 2]}))
+        );
+        assert_eq!(delta(&desired, &Value::Null), Some(desired.clone()));
+    }
+}
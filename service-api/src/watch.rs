@@ -0,0 +1,79 @@
+use serde::{Deserialize, Serialize};
+
+/// The type of change, reported by a watch.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum WatchEventType {
+    Added,
+    Modified,
+    Deleted,
+}
+
+/// A change of a watched resource.
+///
+/// For deleted resources, the object only holds the identifying metadata.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct WatchEvent<T> {
+    #[serde(rename = "type")]
+    pub r#type: WatchEventType,
+    pub object: T,
+    /// The version of the watch, which can be used to resume the watch after this event.
+    #[serde(
+        default,
+        rename = "resourceVersion",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub resource_version: Option<String>,
+}
+
+impl<T> WatchEvent<T> {
+    pub fn added(object: T) -> Self {
+        Self {
+            r#type: WatchEventType::Added,
+            object,
+            resource_version: None,
+        }
+    }
+
+    pub fn modified(object: T) -> Self {
+        Self {
+            r#type: WatchEventType::Modified,
+            object,
+            resource_version: None,
+        }
+    }
+
+    pub fn deleted(object: T) -> Self {
+        Self {
+            r#type: WatchEventType::Deleted,
+            object,
+            resource_version: None,
+        }
+    }
+
+    pub fn with_resource_version<V: ToString>(mut self, resource_version: V) -> Self {
+        self.resource_version = Some(resource_version.to_string());
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_serialize() {
+        assert_eq!(
+            serde_json::to_value(WatchEvent::added(json!({"metadata": {"name": "foo"}}))).unwrap(),
+            json!({"type": "ADDED", "object": {"metadata": {"name": "foo"}}})
+        );
+        assert_eq!(
+            serde_json::to_value(
+                WatchEvent::deleted(json!({"metadata": {"name": "foo"}})).with_resource_version(42)
+            )
+            .unwrap(),
+            json!({"type": "DELETED", "object": {"metadata": {"name": "foo"}}, "resourceVersion": "42"})
+        );
+    }
+}
@@ -1,7 +1,13 @@
 //! Clients for services.
 
 mod device_auth;
+mod registry_gateways;
+mod registry_list;
+mod registry_status;
 mod user_auth;
 
 pub use device_auth::*;
+pub use registry_gateways::*;
+pub use registry_list::*;
+pub use registry_status::*;
 pub use user_auth::*;
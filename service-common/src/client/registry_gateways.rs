@@ -0,0 +1,108 @@
+use drogue_client::{
+    error::{ClientError, ErrorInformation},
+    openid::{OpenIdTokenProvider, TokenInjector},
+    registry, Context,
+};
+use reqwest::{Response, StatusCode};
+use serde::de::DeserializeOwned;
+use url::Url;
+
+/// A client for resolving the gateways of a device, using the device registry.
+///
+/// Other than the plain registry client, this follows chains of gateways, selects gateways by
+/// labels, and evaluates the effective device, including its device type.
+#[derive(Clone, Debug)]
+pub struct RegistryGatewayClient {
+    client: reqwest::Client,
+    api_url: Url,
+    token_provider: Option<OpenIdTokenProvider>,
+}
+
+impl RegistryGatewayClient {
+    /// Create a new client instance.
+    pub fn new(
+        client: reqwest::Client,
+        url: Url,
+        token_provider: Option<OpenIdTokenProvider>,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            api_url: url.join("/api/registry/v1alpha1/")?,
+            client,
+            token_provider,
+        })
+    }
+
+    /// Get the effective device, and its effective gateways, nearest first.
+    ///
+    /// Returns `None` if the device was not found.
+    pub async fn get_device_and_gateways(
+        &self,
+        app: &str,
+        device: &str,
+        context: Context,
+    ) -> Result<
+        Option<(registry::v1::Device, Vec<registry::v1::Device>)>,
+        ClientError<reqwest::Error>,
+    > {
+        let mut url = self.url(&["apps", app, "devices", device])?;
+        url.query_pairs_mut().append_pair("effective", "true");
+
+        let effective = match self
+            .get(
+                url,
+                Context {
+                    provided_token: context.provided_token.clone(),
+                },
+            )
+            .await?
+        {
+            Some(effective) => effective,
+            None => return Ok(None),
+        };
+
+        let url = self.url(&["apps", app, "devices", device, "gateways"])?;
+
+        Ok(self
+            .get(url, context)
+            .await?
+            .map(|gateways| (effective, gateways)))
+    }
+
+    fn url(&self, segments: &[&str]) -> Result<Url, ClientError<reqwest::Error>> {
+        let mut url = self.api_url.clone();
+        url.path_segments_mut()
+            .map_err(|_| ClientError::Request("Failed to get path for URL".into()))?
+            .pop_if_empty()
+            .extend(segments);
+        Ok(url)
+    }
+
+    async fn get<T: DeserializeOwned>(
+        &self,
+        url: Url,
+        context: Context,
+    ) -> Result<Option<T>, ClientError<reqwest::Error>> {
+        let req = self
+            .client
+            .get(url)
+            .inject_token(&self.token_provider, context)
+            .await?;
+
+        let response: Response = req.send().await.map_err(Box::new)?;
+
+        match response.status() {
+            StatusCode::OK => Ok(Some(response.json().await.map_err(Box::new)?)),
+            StatusCode::NOT_FOUND => Ok(None),
+            code => match response.json::<ErrorInformation>().await {
+                Ok(result) => {
+                    log::debug!("Service reported error ({}): {}", code, result);
+                    Err(ClientError::Service(result))
+                }
+                Err(err) => Err(ClientError::Request(format!(
+                    "Failed to decode service error response: {}",
+                    err
+                ))),
+            },
+        }
+    }
+}
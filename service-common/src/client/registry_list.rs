@@ -0,0 +1,124 @@
+use drogue_client::{
+    error::{ClientError, ErrorInformation},
+    openid::{OpenIdTokenProvider, TokenInjector},
+    registry, Context,
+};
+use reqwest::{Response, StatusCode};
+use url::Url;
+
+/// The header carrying the token for fetching the next page.
+const HEADER_CONTINUE: &str = "X-Continue";
+
+/// A client for listing resources of the device registry.
+///
+/// Lists are fetched page by page, following the continuation token of the registry.
+#[derive(Clone, Debug)]
+pub struct RegistryListClient {
+    client: reqwest::Client,
+    api_url: Url,
+    token_provider: Option<OpenIdTokenProvider>,
+    page_size: usize,
+}
+
+impl RegistryListClient {
+    /// Create a new client instance.
+    pub fn new(
+        client: reqwest::Client,
+        url: Url,
+        token_provider: Option<OpenIdTokenProvider>,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            api_url: url.join("/api/registry/v1alpha1/")?,
+            client,
+            token_provider,
+            page_size: 100,
+        })
+    }
+
+    /// List all applications, matching the field selector.
+    ///
+    /// Only returns the applications the caller has access to.
+    pub async fn list_apps(
+        &self,
+        fields: Option<&str>,
+        context: Context,
+    ) -> Result<Vec<registry::v1::Application>, ClientError<reqwest::Error>> {
+        let mut result = Vec::new();
+        let mut continue_token: Option<String> = None;
+
+        loop {
+            let mut url = self.url(&["apps"])?;
+            {
+                let mut query = url.query_pairs_mut();
+                query.append_pair("limit", &self.page_size.to_string());
+                if let Some(fields) = fields {
+                    query.append_pair("fields", fields);
+                }
+                if let Some(token) = &continue_token {
+                    query.append_pair("continue", token);
+                }
+            }
+
+            let (mut items, next) = self
+                .get(
+                    url,
+                    Context {
+                        provided_token: context.provided_token.clone(),
+                    },
+                )
+                .await?;
+            result.append(&mut items);
+
+            match next {
+                Some(next) => continue_token = Some(next),
+                None => break,
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn url(&self, segments: &[&str]) -> Result<Url, ClientError<reqwest::Error>> {
+        let mut url = self.api_url.clone();
+        url.path_segments_mut()
+            .map_err(|_| ClientError::Request("Failed to get path for URL".into()))?
+            .pop_if_empty()
+            .extend(segments);
+        Ok(url)
+    }
+
+    async fn get(
+        &self,
+        url: Url,
+        context: Context,
+    ) -> Result<(Vec<registry::v1::Application>, Option<String>), ClientError<reqwest::Error>> {
+        let req = self
+            .client
+            .get(url)
+            .inject_token(&self.token_provider, context)
+            .await?;
+
+        let response: Response = req.send().await.map_err(Box::new)?;
+
+        match response.status() {
+            StatusCode::OK => {
+                let next = response
+                    .headers()
+                    .get(HEADER_CONTINUE)
+                    .and_then(|value| value.to_str().ok())
+                    .map(ToString::to_string);
+                Ok((response.json().await.map_err(Box::new)?, next))
+            }
+            code => match response.json::<ErrorInformation>().await {
+                Ok(result) => {
+                    log::debug!("Service reported error ({}): {}", code, result);
+                    Err(ClientError::Service(result))
+                }
+                Err(err) => Err(ClientError::Request(format!(
+                    "Failed to decode service error response: {}",
+                    err
+                ))),
+            },
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use drogue_client::{
+    error::{ClientError, ErrorInformation},
+    openid::{OpenIdTokenProvider, TokenInjector},
+    registry, Context,
+};
+use reqwest::{Response, StatusCode};
+use serde::Serialize;
+use url::Url;
+
+/// A client for the status subresources of the device registry.
+///
+/// Updating the status doesn't increment the generation of a resource, and the status cannot be
+/// changed through the main resource.
+#[derive(Clone, Debug)]
+pub struct RegistryStatusClient {
+    client: reqwest::Client,
+    api_url: Url,
+    token_provider: Option<OpenIdTokenProvider>,
+}
+
+impl RegistryStatusClient {
+    /// Create a new client instance.
+    pub fn new(
+        client: reqwest::Client,
+        url: Url,
+        token_provider: Option<OpenIdTokenProvider>,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            api_url: url.join("/api/registry/v1alpha1/")?,
+            client,
+            token_provider,
+        })
+    }
+
+    /// Update the status of an application.
+    ///
+    /// Returns `false` if the application was not found.
+    pub async fn update_app_status(
+        &self,
+        app: &registry::v1::Application,
+        context: Context,
+    ) -> Result<bool, ClientError<reqwest::Error>> {
+        let url = self.url(&["apps", &app.metadata.name, "status"])?;
+        self.update(url, app, context).await
+    }
+
+    /// Update the status of a device.
+    ///
+    /// Returns `false` if the device was not found.
+    pub async fn update_device_status(
+        &self,
+        device: &registry::v1::Device,
+        context: Context,
+    ) -> Result<bool, ClientError<reqwest::Error>> {
+        let url = self.url(&[
+            "apps",
+            &device.metadata.application,
+            "devices",
+            &device.metadata.name,
+            "status",
+        ])?;
+        self.update(url, device, context).await
+    }
+
+    fn url(&self, segments: &[&str]) -> Result<Url, ClientError<reqwest::Error>> {
+        let mut url = self.api_url.clone();
+        url.path_segments_mut()
+            .map_err(|_| ClientError::Request("Failed to get path for URL".into()))?
+            .pop_if_empty()
+            .extend(segments);
+        Ok(url)
+    }
+
+    async fn update<T: Serialize>(
+        &self,
+        url: Url,
+        resource: &T,
+        context: Context,
+    ) -> Result<bool, ClientError<reqwest::Error>> {
+        let req = self
+            .client
+            .put(url)
+            .inject_token(&self.token_provider, context)
+            .await?;
+
+        let response: Response = req.json(resource).send().await.map_err(Box::new)?;
+
+        match response.status() {
+            StatusCode::OK | StatusCode::NO_CONTENT => Ok(true),
+            StatusCode::NOT_FOUND => Ok(false),
+            code => match response.json::<ErrorInformation>().await {
+                Ok(result) => {
+                    log::debug!("Service reported error ({}): {}", code, result);
+                    Err(ClientError::Service(result))
+                }
+                Err(err) => Err(ClientError::Request(format!(
+                    "Failed to decode service error response: {}",
+                    err
+                ))),
+            },
+        }
+    }
+}
@@ -11,22 +11,40 @@ use crate::{
     error::ReconcileError,
     ttn,
 };
+use anyhow::anyhow;
 use drogue_client::{
+    error::ClientError,
     meta::{self, v1::CommonMetadataMut},
     registry, Translator,
 };
+use drogue_cloud_service_common::client::RegistryStatusClient;
 use url::Url;
 
+/// The number of attempts to store a status, when it conflicts with concurrent changes.
+const STATUS_ATTEMPTS: usize = 3;
+
+/// Check if an error reports a conflicting change of the resource.
+fn is_conflict(err: &ClientError<reqwest::Error>) -> bool {
+    matches!(err, ClientError::Service(info) if info.error == "OptimisticLockFailed")
+}
+
 pub struct Controller {
     registry: registry::v1::Client,
+    status: RegistryStatusClient,
     ttn: ttn::Client,
     endpoint_url: Url,
 }
 
 impl Controller {
-    pub fn new(registry: registry::v1::Client, ttn: ttn::Client, endpoint_url: Url) -> Self {
+    pub fn new(
+        registry: registry::v1::Client,
+        status: RegistryStatusClient,
+        ttn: ttn::Client,
+        endpoint_url: Url,
+    ) -> Self {
         Self {
             registry,
+            status,
             ttn,
             endpoint_url,
         }
@@ -63,8 +81,9 @@ impl Controller {
 
             log::debug!("Storing: {:#?}", device);
             self.registry
-                .update_device(device, Default::default())
+                .update_device(device.clone(), Default::default())
                 .await?;
+            self.store_device_status(&device).await?;
         } else {
             // If application and/or device are missing, we have nothing to do. As we have
             // finalizers to guard against this.
@@ -99,7 +118,10 @@ impl Controller {
                 Ok(app)
             })?;
             log::debug!("Storing: {:#?}", app);
-            self.registry.update_app(app, Default::default()).await?;
+            self.registry
+                .update_app(app.clone(), Default::default())
+                .await?;
+            self.store_app_status(&app).await?;
         } else {
             // If the application is just gone, we can ignore this, as we have finalizers
             // to guard against this.
@@ -108,6 +130,92 @@ impl Controller {
         Ok(())
     }
 
+    /// Store the TTN status section of a device.
+    ///
+    /// Storing the device changed its resource version, so this re-reads the device, and retries
+    /// if the status conflicts with a concurrent change.
+    async fn store_device_status(&self, device: &registry::v1::Device) -> anyhow::Result<()> {
+        let status = match device.section::<TtnDeviceStatus>() {
+            Some(status) => status?,
+            None => return Ok(()),
+        };
+
+        for _ in 0..STATUS_ATTEMPTS {
+            let mut current = match self
+                .registry
+                .get_device(
+                    &device.metadata.application,
+                    &device.metadata.name,
+                    Default::default(),
+                )
+                .await?
+            {
+                Some(current) => current,
+                None => return Ok(()),
+            };
+            current.set_section(status.clone())?;
+
+            match self
+                .status
+                .update_device_status(&current, Default::default())
+                .await
+            {
+                Err(err) if is_conflict(&err) => {
+                    log::debug!("Conflict when storing the device status, retrying");
+                }
+                result => {
+                    result?;
+                    return Ok(());
+                }
+            }
+        }
+
+        Err(anyhow!(
+            "Failed to store the device status due to conflicts"
+        ))
+    }
+
+    /// Store the TTN status section of an application.
+    ///
+    /// Storing the application changed its resource version, so this re-reads the application,
+    /// and retries if the status conflicts with a concurrent change.
+    async fn store_app_status(&self, app: &registry::v1::Application) -> anyhow::Result<()> {
+        let status = match app.section::<TtnAppStatus>() {
+            Some(status) => status?,
+            None => return Ok(()),
+        };
+
+        for _ in 0..STATUS_ATTEMPTS {
+            let mut current = match self
+                .registry
+                .get_app(&app.metadata.name, Default::default())
+                .await?
+            {
+                Some(current) => current,
+                None => return Ok(()),
+            };
+            current.set_section(status.clone())?;
+
+            match self
+                .status
+                .update_app_status(&current, Default::default())
+                .await
+            {
+                Err(err) if is_conflict(&err) => {
+                    log::debug!("Conflict when storing the application status, retrying");
+                }
+                result => {
+                    result?;
+                    return Ok(());
+                }
+            }
+        }
+
+        Err(anyhow!(
+            "Failed to store the application status due to conflicts"
+        ))
+    }
+
     /// ensures that the finalizer is set
     ///
     /// Returns `true` if the finalizer was added and the resource must be stored
@@ -14,6 +14,7 @@ use anyhow::anyhow;
 use dotenv::dotenv;
 use drogue_client::registry;
 use drogue_cloud_service_common::{
+    client::RegistryStatusClient,
     config::ConfigFromEnv,
     defaults,
     endpoints::create_endpoint_source,
@@ -82,17 +83,17 @@ async fn main() -> anyhow::Result<()> {
         .join("/ttn/v3")?;
 
     let client = reqwest::Client::new();
+    let token_provider = TokenConfig::from_env_prefix("REGISTRY")?
+        .amend_with_env()
+        .discover_from(client.clone())
+        .await?;
     let controller = controller::Controller::new(
         registry::v1::Client::new(
             client.clone(),
-            config.registry.url,
-            Some(
-                TokenConfig::from_env_prefix("REGISTRY")?
-                    .amend_with_env()
-                    .discover_from(client.clone())
-                    .await?,
-            ),
+            config.registry.url.clone(),
+            Some(token_provider.clone()),
         ),
+        RegistryStatusClient::new(client.clone(), config.registry.url, Some(token_provider))?,
         ttn::Client::new(client),
         endpoint_url,
     );
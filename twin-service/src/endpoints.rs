@@ -0,0 +1,56 @@
+use crate::WebData;
+use actix_web::{post, web, HttpResponse};
+use drogue_cloud_registry_events::Event;
+use serde_json::json;
+use std::convert::TryInto;
+
+#[post("/")]
+pub async fn events(
+    event: cloudevents::Event,
+    data: web::Data<WebData>,
+) -> Result<HttpResponse, actix_web::error::Error> {
+    log::debug!("Received event: {:?}", event);
+
+    let event = match event.try_into() {
+        Ok(event) => event,
+        Err(err) => {
+            return Ok(HttpResponse::BadRequest().json(json!({ "details": format!("{}", err) })))
+        }
+    };
+
+    log::debug!("Registry event: {:?}", event);
+
+    let (app, device) = match is_relevant(event) {
+        Some(id) => id,
+        None => return Ok(HttpResponse::Ok().finish()),
+    };
+
+    // the desired state changed, send the delta, if there is one
+
+    let result = async {
+        if let Some(delta) = data.service.delta(&app, &device).await? {
+            data.service.send_delta(&app, &device, delta).await?;
+        }
+        Ok::<_, anyhow::Error>(())
+    }
+    .await;
+
+    Ok(match result {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(err) => HttpResponse::InternalServerError().json(json!({
+            "details": err.to_string(),
+        })),
+    })
+}
+
+fn is_relevant(event: Event) -> Option<(String, String)> {
+    match event {
+        Event::Device {
+            path,
+            application,
+            device,
+            ..
+        } if path == ".twin" => Some((application, device)),
+        _ => None,
+    }
+}
@@ -0,0 +1,142 @@
+mod endpoints;
+mod processor;
+mod service;
+
+use crate::{
+    processor::Processor,
+    service::{TwinService, TwinServiceConfig},
+};
+use actix_web::{get, middleware, web, App, HttpResponse, HttpServer, Responder};
+use dotenv::dotenv;
+use drogue_cloud_endpoint_common::downstream::{ConfiguredSink, DownstreamSender};
+use drogue_cloud_integration_common::stream::{EventSource, EventStreamConfig};
+use drogue_cloud_service_common::{
+    client::RegistryGatewayClient,
+    config::ConfigFromEnv,
+    defaults,
+    health::{HealthServer, HealthServerConfig},
+    openid::TokenConfig,
+};
+use futures::TryFutureExt;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashMap;
+use url::Url;
+
+#[derive(Clone, Debug, Deserialize)]
+struct Config {
+    #[serde(default = "defaults::max_json_payload_size")]
+    pub max_json_payload_size: usize,
+
+    #[serde(default = "defaults::bind_addr")]
+    pub bind_addr: String,
+
+    #[serde(default)]
+    pub event_source: EventSource,
+    #[serde(default = "defaults::kafka_bootstrap_servers")]
+    pub kafka_bootstrap_servers: String,
+    #[serde(default = "defaults::kafka_events_topic")]
+    pub kafka_topic: String,
+    #[serde(default)]
+    pub kafka_properties: HashMap<String, String>,
+
+    #[serde(default)]
+    pub registry: RegistryConfig,
+
+    #[serde(default)]
+    pub health: HealthServerConfig,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct RegistryConfig {
+    #[serde(default = "defaults::registry_url")]
+    pub url: Url,
+}
+
+impl Default for RegistryConfig {
+    fn default() -> Self {
+        Self {
+            url: defaults::registry_url(),
+        }
+    }
+}
+
+pub struct WebData {
+    pub service: TwinService,
+}
+
+#[get("/")]
+async fn index() -> impl Responder {
+    HttpResponse::Ok().json(json!({"success": true}))
+}
+
+#[actix_web::main]
+async fn main() -> anyhow::Result<()> {
+    env_logger::init();
+    dotenv().ok();
+
+    let config = Config::from_env()?;
+    let max_json_payload_size = config.max_json_payload_size;
+
+    log::info!("Event source: {:?}", config.event_source);
+    log::info!("Kafka servers: {}", config.kafka_bootstrap_servers);
+    log::info!("Kafka topic: {}", config.kafka_topic);
+
+    let client = reqwest::Client::new();
+    let registry = RegistryGatewayClient::new(
+        client.clone(),
+        config.registry.url,
+        Some(
+            TokenConfig::from_env_prefix("REGISTRY")?
+                .amend_with_env()
+                .discover_from(client.clone())
+                .await?,
+        ),
+    )?;
+
+    let sender = DownstreamSender::new(ConfiguredSink::new("COMMAND").await?)?;
+
+    let service = TwinService::new(TwinServiceConfig::from_env()?, registry, sender, client)?;
+
+    // process the events of all applications
+
+    let processor = Processor::new(service.clone());
+
+    let stream = EventStreamConfig {
+        source: config.event_source,
+        bootstrap_servers: config.kafka_bootstrap_servers,
+        properties: config.kafka_properties,
+        topic: config.kafka_topic,
+        app: None,
+        consumer_group: Some("twin-service".into()),
+        manual_commit: false,
+        since: None,
+    };
+
+    let data = web::Data::new(WebData { service });
+
+    // health server
+
+    let health = HealthServer::new(config.health, vec![Box::new(data.service.clone())]);
+
+    // main
+
+    let main = HttpServer::new(move || {
+        App::new()
+            .wrap(middleware::Logger::default())
+            .data(web::JsonConfig::default().limit(max_json_payload_size))
+            .app_data(data.clone())
+            .service(index)
+            .service(endpoints::events)
+    })
+    .bind(config.bind_addr)?
+    .run();
+
+    // run
+
+    futures::try_join!(health.run(), main.err_into(), processor.run(stream))?;
+
+    // exiting
+
+    Ok(())
+}
@@ -0,0 +1,182 @@
+use crate::service::TwinService;
+use chrono::{DateTime, Utc};
+use cloudevents::{event::ExtensionValue, AttributesReader, Data, Event};
+use drogue_cloud_endpoint_common::downstream::EXT_RECEIVE_TIME;
+use drogue_cloud_integration_common::stream::{EventStream, EventStreamConfig};
+use drogue_cloud_service_api::{
+    connection::{ConnectionEvent, EVENT_TYPE_CONNECTION},
+    twin::CHANNEL_TWIN,
+    EXT_APPLICATION, EXT_DEVICE,
+};
+use futures::StreamExt;
+use serde_json::Value;
+
+const EVENT_TYPE_EVENT: &str = "io.drogue.event.v1";
+
+/// What an event means for the twin of a device.
+#[derive(Clone, Debug, PartialEq)]
+enum Update {
+    /// The device reported (parts of) its state.
+    Report(Value, DateTime<Utc>),
+    /// The device connected, and might have missed a delta.
+    Connected,
+}
+
+/// Get a string extension of the event.
+fn extension<'e>(event: &'e Event, name: &str) -> Option<&'e str> {
+    match event.extension(name) {
+        Some(ExtensionValue::String(value)) => Some(value.as_str()),
+        _ => None,
+    }
+}
+
+/// The time the event was received by the endpoint.
+fn received(event: &Event) -> DateTime<Utc> {
+    extension(event, EXT_RECEIVE_TIME)
+        .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+        .map(|time| time.with_timezone(&Utc))
+        .or_else(|| event.time().cloned())
+        .unwrap_or_else(Utc::now)
+}
+
+fn json(event: &Event) -> Option<Value> {
+    match event.data() {
+        Some(Data::Json(value)) => Some(value.clone()),
+        Some(Data::Binary(value)) => serde_json::from_slice(value).ok(),
+        Some(Data::String(value)) => serde_json::from_str(value).ok(),
+        None => None,
+    }
+}
+
+/// Evaluate the update of an event.
+///
+/// Only events on the twin channel, with a JSON object as payload, are considered a report of the
+/// device state. Regular telemetry doesn't change the twin.
+fn update(event: &Event) -> Option<Update> {
+    match event.ty() {
+        EVENT_TYPE_EVENT if event.subject() == Some(CHANNEL_TWIN) => match json(event) {
+            Some(value) if value.is_object() => Some(Update::Report(value, received(event))),
+            _ => None,
+        },
+        EVENT_TYPE_CONNECTION => json(event)
+            .and_then(|value| serde_json::from_value::<ConnectionEvent>(value).ok())
+            .filter(|connection| connection.connected)
+            .map(|_| Update::Connected),
+        _ => None,
+    }
+}
+
+/// Processing the events of devices, storing their reported state.
+pub struct Processor {
+    service: TwinService,
+}
+
+impl Processor {
+    pub fn new(service: TwinService) -> Self {
+        Self { service }
+    }
+
+    pub async fn run(self, config: EventStreamConfig) -> anyhow::Result<()> {
+        let mut stream = EventStream::new(config)?;
+
+        while let Some(event) = stream.next().await {
+            let event = match event {
+                Ok(event) => event,
+                Err(err) => anyhow::bail!("Failed to receive event: {}", err),
+            };
+
+            let (app, device) = match (
+                extension(&event, EXT_APPLICATION),
+                extension(&event, EXT_DEVICE),
+            ) {
+                (Some(app), Some(device)) => (app, device),
+                _ => continue,
+            };
+
+            if let Err(err) = self.handle(app, device, &event).await {
+                log::info!("Failed to update twin of '{}/{}': {}", app, device, err);
+            }
+        }
+
+        anyhow::bail!("Event stream closed")
+    }
+
+    async fn handle(&self, app: &str, device: &str, event: &Event) -> anyhow::Result<()> {
+        let delta = match update(event) {
+            Some(Update::Report(report, timestamp)) => {
+                self.service
+                    .update_reported(app, device, report, timestamp)
+                    .await?
+            }
+            Some(Update::Connected) => self.service.delta(app, device).await?,
+            None => None,
+        };
+
+        if let Some(delta) = delta {
+            self.service.send_delta(app, device, delta).await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+    use cloudevents::{EventBuilder, EventBuilderV10};
+    use serde_json::json;
+
+    fn event(ty: &str, channel: &str, data: Value) -> Event {
+        EventBuilderV10::new()
+            .id("1")
+            .ty(ty)
+            .source("drogue://app/device")
+            .subject(channel)
+            .extension(
+                EXT_RECEIVE_TIME,
+                Utc.ymd(2021, 1, 1).and_hms(0, 0, 0).to_rfc3339(),
+            )
+            .data("application/json", data)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_update() {
+        assert_eq!(
+            update(&event(EVENT_TYPE_EVENT, CHANNEL_TWIN, json!({"temp": 42}))),
+            Some(Update::Report(
+                json!({"temp": 42}),
+                Utc.ymd(2021, 1, 1).and_hms(0, 0, 0)
+            ))
+        );
+        // only objects are reports
+        assert_eq!(
+            update(&event(EVENT_TYPE_EVENT, CHANNEL_TWIN, json!([42]))),
+            None
+        );
+        // regular telemetry isn't a report
+        assert_eq!(
+            update(&event(EVENT_TYPE_EVENT, "temperature", json!({"temp": 42}))),
+            None
+        );
+
+        assert_eq!(
+            update(&event(
+                EVENT_TYPE_CONNECTION,
+                "$connection",
+                json!({"connected": true, "protocol": "mqtt"})
+            )),
+            Some(Update::Connected)
+        );
+        assert_eq!(
+            update(&event(
+                EVENT_TYPE_CONNECTION,
+                "$connection",
+                json!({"connected": false, "protocol": "mqtt"})
+            )),
+            None
+        );
+    }
+}
@@ -0,0 +1,152 @@
+use chrono::{DateTime, Utc};
+use deadpool_postgres::Pool;
+use drogue_cloud_database_common::{
+    models::{
+        twin::{PostgresTwinAccessor, Twin, TwinAccessor},
+        Lock,
+    },
+    DatabaseService,
+};
+use drogue_cloud_endpoint_common::downstream::{ConfiguredSink, DownstreamSender};
+use drogue_cloud_integration_common::commands::{process_command, CommandOptions};
+use drogue_cloud_service_api::{
+    health::{HealthCheckError, HealthChecked},
+    twin::{self, COMMAND_DELTA},
+};
+use drogue_cloud_service_common::client::RegistryGatewayClient;
+use serde::Deserialize;
+use serde_json::Value;
+use tokio_postgres::NoTls;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct TwinServiceConfig {
+    pub pg: deadpool_postgres::Config,
+}
+
+/// Storing the reported state of devices, and sending the delta to the desired state.
+#[derive(Clone)]
+pub struct TwinService {
+    pool: Pool,
+    registry: RegistryGatewayClient,
+    sender: DownstreamSender<ConfiguredSink>,
+    client: reqwest::Client,
+}
+
+impl DatabaseService for TwinService {
+    fn pool(&self) -> &Pool {
+        &self.pool
+    }
+}
+
+#[async_trait::async_trait]
+impl HealthChecked for TwinService {
+    async fn is_ready(&self) -> Result<(), HealthCheckError> {
+        Ok(DatabaseService::is_ready(self)
+            .await
+            .map_err(HealthCheckError::from)?)
+    }
+}
+
+impl TwinService {
+    pub fn new(
+        config: TwinServiceConfig,
+        registry: RegistryGatewayClient,
+        sender: DownstreamSender<ConfiguredSink>,
+        client: reqwest::Client,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            pool: config.pg.create_pool(NoTls)?,
+            registry,
+            sender,
+            client,
+        })
+    }
+
+    /// Merge a report into the reported state of a device.
+    ///
+    /// Returns the delta to the desired state, if the report changed it.
+    pub async fn update_reported(
+        &self,
+        app: &str,
+        device: &str,
+        report: Value,
+        timestamp: DateTime<Utc>,
+    ) -> anyhow::Result<Option<Value>> {
+        let mut c = self.pool.get().await?;
+        let t = c.build_transaction().start().await?;
+
+        let accessor = PostgresTwinAccessor::new(&t);
+
+        let mut twin = accessor
+            .get(app, device, Lock::ForUpdate)
+            .await?
+            .unwrap_or_else(|| Twin::new(app, device));
+
+        if twin.reported_timestamp.map_or(false, |ts| ts > timestamp) {
+            // don't let outdated reports override newer ones
+            log::debug!("Ignoring outdated report of '{}/{}'", app, device);
+            return Ok(None);
+        }
+
+        let before = twin.delta();
+        twin::merge(&mut twin.reported, report);
+        let after = twin.delta();
+
+        accessor
+            .set_reported(app, device, twin.reported, timestamp)
+            .await?;
+
+        t.commit().await?;
+
+        Ok(if before != after { after } else { None })
+    }
+
+    /// Get the current delta between the desired and reported state of a device.
+    pub async fn delta(&self, app: &str, device: &str) -> anyhow::Result<Option<Value>> {
+        let c = self.pool.get().await?;
+
+        Ok(PostgresTwinAccessor::new(&c)
+            .get(app, device, Lock::None)
+            .await?
+            .and_then(|twin| twin.delta()))
+    }
+
+    /// Send the delta command to a device.
+    pub async fn send_delta(&self, app: &str, device: &str, delta: Value) -> anyhow::Result<()> {
+        log::debug!("Sending delta to '{}/{}': {}", app, device, delta);
+
+        let (device, gateways) = match self
+            .registry
+            .get_device_and_gateways(app, device, Default::default())
+            .await?
+        {
+            Some(result) => result,
+            // the device is gone
+            None => return Ok(()),
+        };
+
+        let opts = CommandOptions {
+            application: app.to_string(),
+            device: device.metadata.name.clone(),
+            command: COMMAND_DELTA.to_string(),
+        };
+
+        let response = process_command(
+            device,
+            gateways,
+            &self.sender,
+            self.client.clone(),
+            Some("application/json".into()),
+            opts,
+            serde_json::to_vec(&delta)?.into(),
+        )
+        .await
+        .map_err(|err| anyhow::anyhow!("Failed to send command: {}", err))?;
+
+        if !response.status().is_success() {
+            anyhow::bail!("Failed to send command: {}", response.status());
+        }
+
+        Ok(())
+    }
+}
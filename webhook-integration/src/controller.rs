@@ -0,0 +1,149 @@
+use crate::{
+    data::WebhookSpec,
+    dispatcher::{Dispatcher, DispatcherContext},
+};
+use drogue_client::{registry, Translator};
+use drogue_cloud_service_common::client::RegistryListClient;
+use std::{collections::HashMap, sync::Mutex};
+
+pub struct Controller {
+    ctx: DispatcherContext,
+    list: RegistryListClient,
+    dispatchers: Mutex<HashMap<String, Dispatcher>>,
+}
+
+/// Evaluate the webhook targets of an application, a deleted application has none.
+fn webhook_spec(
+    app: Option<registry::v1::Application>,
+) -> Result<Option<(u64, WebhookSpec)>, anyhow::Error> {
+    Ok(match app {
+        Some(app) if app.metadata.deletion_timestamp.is_none() => app
+            .section::<WebhookSpec>()
+            .transpose()?
+            .filter(|spec| !spec.targets.is_empty())
+            .map(|spec| (app.metadata.generation, spec)),
+        _ => None,
+    })
+}
+
+impl Controller {
+    pub fn new(ctx: DispatcherContext, list: RegistryListClient) -> Self {
+        Self {
+            ctx,
+            list,
+            dispatchers: Default::default(),
+        }
+    }
+
+    /// Start the dispatchers of all applications having webhooks.
+    ///
+    /// This picks up the applications which changed while the service wasn't running. Dispatchers
+    /// which were already started from a registry event are kept, as they are more recent.
+    pub async fn reconcile(&self) -> Result<(), anyhow::Error> {
+        let apps = self
+            .list
+            .list_apps(Some("spec.webhooks"), Default::default())
+            .await?;
+
+        log::info!("Reconciling {} application(s) with webhooks", apps.len());
+
+        for app in apps {
+            let app_id = app.metadata.name.clone();
+            if self.dispatchers.lock().unwrap().contains_key(&app_id) {
+                continue;
+            }
+            self.apply(app_id, webhook_spec(Some(app))?)?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn handle_app_event(&self, app_id: String) -> Result<(), anyhow::Error> {
+        log::info!("Application changed: {}", app_id);
+
+        let app = self
+            .ctx
+            .registry
+            .get_app(&app_id, Default::default())
+            .await?;
+
+        self.apply(app_id, webhook_spec(app)?)
+    }
+
+    /// Start, replace or stop the dispatcher of an application.
+    fn apply(&self, app_id: String, spec: Option<(u64, WebhookSpec)>) -> Result<(), anyhow::Error> {
+        let mut dispatchers = self.dispatchers.lock().unwrap();
+
+        match spec {
+            Some((generation, spec)) => {
+                let value = serde_json::to_value(&spec)?;
+                let changed = dispatchers
+                    .get(&app_id)
+                    .map(|dispatcher| dispatcher.spec != value)
+                    .unwrap_or(true);
+
+                if changed {
+                    log::info!("Starting webhook dispatcher for: {}", app_id);
+                    // replacing the dispatcher will stop the existing one
+                    dispatchers.insert(
+                        app_id.clone(),
+                        Dispatcher::start(app_id, generation, spec, self.ctx.clone()),
+                    );
+                }
+            }
+            None => {
+                if dispatchers.remove(&app_id).is_some() {
+                    log::info!("Stopped webhook dispatcher for: {}", app_id);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::Utc;
+    use drogue_client::meta;
+    use serde_json::json;
+
+    fn app(generation: u64, targets: serde_json::Value) -> registry::v1::Application {
+        let mut app = registry::v1::Application {
+            metadata: meta::v1::NonScopedMetadata {
+                name: "app1".into(),
+                generation,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        app.set_section::<WebhookSpec>(
+            serde_json::from_value(json!({ "targets": targets })).unwrap(),
+        )
+        .unwrap();
+        app
+    }
+
+    #[test]
+    fn test_webhook_spec() {
+        let targets = json!([{"name": "foo", "url": "https://example.com", "method": ""}]);
+
+        let (generation, spec) = webhook_spec(Some(app(2, targets.clone())))
+            .unwrap()
+            .unwrap();
+        assert_eq!(generation, 2);
+        assert_eq!(spec.targets.len(), 1);
+
+        // no targets
+        assert!(webhook_spec(Some(app(1, json!([])))).unwrap().is_none());
+
+        // being deleted
+        let mut deleted = app(1, targets);
+        deleted.metadata.deletion_timestamp = Some(Utc::now());
+        assert!(webhook_spec(Some(deleted)).unwrap().is_none());
+
+        // gone
+        assert!(webhook_spec(None).unwrap().is_none());
+    }
+}
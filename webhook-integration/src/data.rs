@@ -0,0 +1,159 @@
+use chrono::{DateTime, Utc};
+use drogue_client::{dialect, registry, Dialect, Section};
+use drogue_cloud_integration_common::filter::EventFilter;
+use serde::{Deserialize, Serialize};
+
+/// The placeholder, which the registry returns instead of a secret.
+pub const REDACTED_SECRET: &str = "********";
+
+/// The content mode, used for sending events.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ContentMode {
+    Binary,
+    Structured,
+}
+
+impl Default for ContentMode {
+    fn default() -> Self {
+        Self::Binary
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookSpec {
+    #[serde(default)]
+    pub targets: Vec<WebhookTarget>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookTarget {
+    /// The name of the target, unique for the application.
+    pub name: String,
+    #[serde(flatten)]
+    pub endpoint: registry::v1::ExternalEndpoint,
+    #[serde(default)]
+    pub mode: ContentMode,
+    /// A secret, used for signing the request body using HMAC-SHA256.
+    ///
+    /// The secret is write-only, the registry only hands it out to controllers. Other users read
+    /// the placeholder [`REDACTED_SECRET`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    /// Only forward events of this channel.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel: Option<String>,
+    /// Only forward events matching this label selector, evaluated against the device labels.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub selector: Option<String>,
+}
+
+impl WebhookTarget {
+    /// Check the target for configuration errors.
+    pub fn validate(&self) -> Result<(), String> {
+        match self.secret.as_deref() {
+            Some(REDACTED_SECRET) => Err(
+                "Secret is redacted, the integration requires the 'drogue-controller' role".into(),
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    /// Create the filter for this target.
+    pub fn filter(&self) -> Result<EventFilter, String> {
+        EventFilter::new(None, self.channel.as_deref(), self.selector.as_deref())
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookStatus {
+    pub observed_generation: u64,
+    #[serde(default)]
+    pub targets: Vec<WebhookTargetStatus>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookTargetStatus {
+    pub name: String,
+    pub state: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_delivery: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_failure: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub delivered: u64,
+    #[serde(default)]
+    pub failed: u64,
+}
+
+impl WebhookTargetStatus {
+    pub fn new<S: Into<String>>(name: S) -> Self {
+        Self {
+            name: name.into(),
+            state: "Pending".into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn invalid<S: Into<String>>(name: S, reason: String) -> Self {
+        Self {
+            name: name.into(),
+            state: "Invalid".into(),
+            reason: Some(reason),
+            ..Default::default()
+        }
+    }
+
+    pub fn delivered(&mut self) {
+        self.state = "Ok".into();
+        self.reason = None;
+        self.last_delivery = Some(Utc::now());
+        self.delivered += 1;
+    }
+
+    pub fn failed(&mut self, reason: String) {
+        self.state = "Failed".into();
+        self.reason = Some(reason);
+        self.last_failure = Some(Utc::now());
+        self.failed += 1;
+    }
+}
+
+dialect!(WebhookSpec[Section::Spec => "webhooks"]);
+dialect!(WebhookStatus[Section::Status => "webhooks"]);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_spec() {
+        let spec: WebhookSpec = serde_json::from_value(json!({
+            "targets": [{
+                "name": "foo",
+                "url": "https://example.com/events",
+                "method": "",
+                "headers": {"x-foo": "bar"},
+                "mode": "structured",
+                "secret": "my-secret",
+                "channel": "temperature",
+            }]
+        }))
+        .unwrap();
+
+        assert_eq!(spec.targets.len(), 1);
+        let target = &spec.targets[0];
+        assert_eq!(target.name, "foo");
+        assert_eq!(target.endpoint.url, "https://example.com/events");
+        assert_eq!(target.mode, ContentMode::Structured);
+        assert_eq!(target.secret.as_deref(), Some("my-secret"));
+        assert!(target.filter().is_ok());
+    }
+}
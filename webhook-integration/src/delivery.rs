@@ -0,0 +1,214 @@
+use crate::data::{ContentMode, WebhookTarget};
+use cloudevents::{binding::reqwest::RequestBuilderExt, AttributesReader, Data, Event};
+use drogue_cloud_integration_common::commands::{to_builder, HttpError, SenderError};
+use reqwest::{header, Method};
+use ring::hmac;
+use serde::Deserialize;
+use std::time::Duration;
+use thiserror::Error;
+
+/// The header carrying the HMAC-SHA256 signature of the request body.
+pub const HEADER_SIGNATURE: &str = "x-drogue-signature-256";
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct RetryConfig {
+    /// The maximum number of attempts for delivering an event to a single target.
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    /// The delay before the first retry, doubled with each subsequent attempt.
+    #[serde(default = "default_initial_delay", with = "humantime_serde")]
+    pub initial_delay: Duration,
+    /// The upper limit of the delay between two attempts.
+    #[serde(default = "default_max_delay", with = "humantime_serde")]
+    pub max_delay: Duration,
+    /// The timeout of a single request.
+    #[serde(default = "default_timeout", with = "humantime_serde")]
+    pub timeout: Duration,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: default_max_attempts(),
+            initial_delay: default_initial_delay(),
+            max_delay: default_max_delay(),
+            timeout: default_timeout(),
+        }
+    }
+}
+
+#[inline]
+fn default_max_attempts() -> u32 {
+    5
+}
+
+#[inline]
+fn default_initial_delay() -> Duration {
+    Duration::from_secs(1)
+}
+
+#[inline]
+fn default_max_delay() -> Duration {
+    Duration::from_secs(60)
+}
+
+#[inline]
+fn default_timeout() -> Duration {
+    Duration::from_secs(10)
+}
+
+impl RetryConfig {
+    /// The delay before the next attempt, after the provided number of failed attempts.
+    pub fn delay(&self, failed_attempts: u32) -> Duration {
+        let factor = 2u32.saturating_pow(failed_attempts.saturating_sub(1));
+        self.initial_delay
+            .checked_mul(factor)
+            .map_or(self.max_delay, |delay| delay.min(self.max_delay))
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum DeliveryError {
+    #[error("Failed to build request: {0}")]
+    Request(#[from] SenderError),
+    #[error("Failed to encode event: {0}")]
+    Encoding(String),
+    #[error("Failed to send request: {0}")]
+    Transport(#[from] reqwest::Error),
+    #[error("{0}")]
+    Http(HttpError),
+}
+
+impl DeliveryError {
+    /// Check if it makes sense to retry the operation.
+    pub fn is_temporary(&self) -> bool {
+        match self {
+            Self::Request(_) | Self::Encoding(_) => false,
+            Self::Transport(_) => true,
+            // retry server errors, and requests that were throttled
+            Self::Http(HttpError(code, _)) => {
+                code.is_server_error() || code.as_u16() == 408 || code.as_u16() == 429
+            }
+        }
+    }
+}
+
+/// Sign the body with the secret of the target.
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    format!("sha256={}", hex::encode(hmac::sign(&key, body).as_ref()))
+}
+
+/// Get the body, as it will be sent in the request.
+fn body(event: &Event, mode: ContentMode) -> Result<Vec<u8>, DeliveryError> {
+    match mode {
+        ContentMode::Structured => {
+            serde_json::to_vec(event).map_err(|err| DeliveryError::Encoding(err.to_string()))
+        }
+        ContentMode::Binary => match event.data() {
+            Some(Data::Binary(data)) => Ok(data.clone()),
+            Some(Data::String(data)) => Ok(data.as_bytes().to_vec()),
+            Some(Data::Json(data)) => {
+                serde_json::to_vec(data).map_err(|err| DeliveryError::Encoding(err.to_string()))
+            }
+            None => Ok(vec![]),
+        },
+    }
+}
+
+/// Deliver an event to a target, once.
+async fn deliver_once(
+    client: &reqwest::Client,
+    target: &WebhookTarget,
+    event: &Event,
+    timeout: Duration,
+) -> Result<(), DeliveryError> {
+    let builder = to_builder(client.clone(), Method::POST, &target.endpoint, Ok)?.timeout(timeout);
+    let body = body(event, target.mode)?;
+
+    let builder = match &target.secret {
+        Some(secret) => builder.header(HEADER_SIGNATURE, sign(secret, &body)),
+        None => builder,
+    };
+
+    let builder = match target.mode {
+        ContentMode::Structured => builder
+            .header(
+                header::CONTENT_TYPE,
+                "application/cloudevents+json; charset=utf-8",
+            )
+            .body(body),
+        ContentMode::Binary => builder
+            .event(event.clone())
+            .map_err(|err| DeliveryError::Encoding(err.to_string()))?,
+    };
+
+    let response = builder.send().await?;
+
+    match response.status() {
+        code if code.is_success() => Ok(()),
+        code => {
+            let text = response.text().await.unwrap_or_default();
+            Err(DeliveryError::Http(HttpError(code, text)))
+        }
+    }
+}
+
+/// Deliver an event to a target, retrying temporary failures.
+pub async fn deliver(
+    client: &reqwest::Client,
+    target: &WebhookTarget,
+    event: &Event,
+    retry: &RetryConfig,
+) -> Result<(), DeliveryError> {
+    let mut attempts = 0;
+
+    loop {
+        attempts += 1;
+
+        match deliver_once(client, target, event, retry.timeout).await {
+            Ok(()) => return Ok(()),
+            Err(err) if err.is_temporary() && attempts < retry.max_attempts => {
+                let delay = retry.delay(attempts);
+                log::debug!(
+                    "Failed to deliver event {} to '{}' (attempt {}), retrying in {:?}: {}",
+                    event.id(),
+                    target.name,
+                    attempts,
+                    delay,
+                    err
+                );
+                tokio::time::sleep(delay).await;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sign() {
+        assert_eq!(
+            sign("secret", b"payload"),
+            "sha256=b82fcb791acec57859b989b430a826488ce2e479fdf92326bd0a2e8375a42ba4"
+        );
+    }
+
+    #[test]
+    fn test_delay() {
+        let retry = RetryConfig {
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(10),
+            ..Default::default()
+        };
+
+        assert_eq!(retry.delay(1), Duration::from_secs(1));
+        assert_eq!(retry.delay(2), Duration::from_secs(2));
+        assert_eq!(retry.delay(3), Duration::from_secs(4));
+        assert_eq!(retry.delay(5), Duration::from_secs(10));
+        assert_eq!(retry.delay(100), Duration::from_secs(10));
+    }
+}
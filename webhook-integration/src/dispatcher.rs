@@ -0,0 +1,232 @@
+use crate::{
+    data::{WebhookSpec, WebhookStatus, WebhookTarget, WebhookTargetStatus},
+    delivery::{deliver, RetryConfig},
+};
+use cloudevents::Event;
+use drogue_client::{registry, Translator};
+use drogue_cloud_integration_common::{
+    filter::{DeviceLabels, EventFilter, FilterError},
+    stream::{EventStream, EventStreamConfig},
+};
+use drogue_cloud_service_common::client::RegistryStatusClient;
+use futures::StreamExt;
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::task::JoinHandle;
+
+/// The delay before re-creating a failed event stream.
+const RESTART_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Clone)]
+pub struct DispatcherContext {
+    pub stream: EventStreamConfig,
+    pub registry: registry::v1::Client,
+    pub labels: DeviceLabels,
+    pub status: RegistryStatusClient,
+    pub client: reqwest::Client,
+    pub retry: RetryConfig,
+    pub status_interval: Duration,
+}
+
+/// Dispatching the events of a single application to its webhook targets.
+pub struct Dispatcher {
+    /// The spec the dispatcher was created from, used to detect changes.
+    pub spec: serde_json::Value,
+    handle: JoinHandle<()>,
+}
+
+impl Drop for Dispatcher {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+struct Target {
+    target: WebhookTarget,
+    filter: EventFilter,
+    index: usize,
+}
+
+struct State {
+    status: Vec<WebhookTargetStatus>,
+    dirty: bool,
+}
+
+impl Dispatcher {
+    pub fn start(
+        application: String,
+        generation: u64,
+        spec: WebhookSpec,
+        ctx: DispatcherContext,
+    ) -> Self {
+        let spec_value = serde_json::to_value(&spec).unwrap_or_default();
+
+        let mut targets = Vec::with_capacity(spec.targets.len());
+        let mut status = Vec::with_capacity(spec.targets.len());
+
+        for (index, target) in spec.targets.into_iter().enumerate() {
+            match target.validate().and_then(|_| target.filter()) {
+                Ok(filter) => {
+                    status.push(WebhookTargetStatus::new(&target.name));
+                    targets.push(Target {
+                        target,
+                        filter,
+                        index,
+                    });
+                }
+                Err(reason) => {
+                    log::info!("Invalid webhook target '{}': {}", target.name, reason);
+                    status.push(WebhookTargetStatus::invalid(&target.name, reason));
+                }
+            }
+        }
+
+        let state = Arc::new(Mutex::new(State {
+            status,
+            dirty: true,
+        }));
+
+        let handle = actix_web::rt::spawn(Self::run(application, generation, targets, state, ctx));
+
+        Self {
+            spec: spec_value,
+            handle,
+        }
+    }
+
+    async fn run(
+        application: String,
+        generation: u64,
+        targets: Vec<Target>,
+        state: Arc<Mutex<State>>,
+        ctx: DispatcherContext,
+    ) {
+        let mut interval = tokio::time::interval(ctx.status_interval);
+
+        loop {
+            let mut stream = match EventStream::new(EventStreamConfig {
+                app: Some(application.clone()),
+                // continue where we left off, when restarting
+                consumer_group: Some(format!("{}:webhooks", application)),
+                manual_commit: true,
+                ..ctx.stream.clone()
+            }) {
+                Ok(stream) => stream,
+                Err(err) => {
+                    log::info!(
+                        "Failed to create event stream for '{}': {}",
+                        application,
+                        err
+                    );
+                    tokio::time::sleep(RESTART_DELAY).await;
+                    continue;
+                }
+            };
+
+            loop {
+                tokio::select! {
+                    event = stream.next() => match event {
+                        Some(Ok(event)) => {
+                            let offset = stream.offset();
+                            let matching = match Self::matching(&targets, &event, &ctx.labels).await {
+                                Ok(matching) => matching,
+                                Err(err) => {
+                                    // re-process the event, after restarting the stream
+                                    log::info!("Failed to evaluate filters for '{}': {}", application, err);
+                                    break;
+                                }
+                            };
+                            for target in matching {
+                                let result = deliver(&ctx.client, &target.target, &event, &ctx.retry).await;
+                                let mut state = state.lock().unwrap();
+                                let status = &mut state.status[target.index];
+                                match result {
+                                    Ok(()) => status.delivered(),
+                                    Err(err) => {
+                                        log::info!("Failed to deliver event to '{}': {}", target.target.name, err);
+                                        status.failed(err.to_string());
+                                    }
+                                }
+                                state.dirty = true;
+                            }
+                            // all targets are processed (or gave up), move on
+                            if let Some(offset) = offset {
+                                if let Err(err) = stream.commit(&offset) {
+                                    log::info!("Failed to commit offset: {}", err);
+                                }
+                            }
+                        }
+                        Some(Err(err)) => {
+                            log::info!("Failed to receive event for '{}': {}", application, err);
+                            break;
+                        }
+                        None => break,
+                    },
+                    _ = interval.tick() => {
+                        Self::update_status(&application, generation, &state, &ctx).await;
+                    }
+                }
+            }
+
+            tokio::time::sleep(RESTART_DELAY).await;
+        }
+    }
+
+    /// Evaluate the filters of the targets for an event.
+    async fn matching<'t>(
+        targets: &'t [Target],
+        event: &Event,
+        labels: &DeviceLabels,
+    ) -> Result<Vec<&'t Target>, FilterError> {
+        let mut result = Vec::with_capacity(targets.len());
+        for target in targets {
+            if target.filter.matches(event, labels).await? {
+                result.push(target);
+            }
+        }
+        Ok(result)
+    }
+
+    /// Store the delivery status in the status section of the application, if it changed.
+    async fn update_status(
+        application: &str,
+        generation: u64,
+        state: &Mutex<State>,
+        ctx: &DispatcherContext,
+    ) {
+        let status = {
+            let mut state = state.lock().unwrap();
+            if !state.dirty {
+                return;
+            }
+            state.dirty = false;
+            state.status.clone()
+        };
+
+        let result = async {
+            if let Some(mut app) = ctx
+                .registry
+                .get_app(application, Default::default())
+                .await?
+            {
+                app.set_section(WebhookStatus {
+                    observed_generation: generation,
+                    targets: status,
+                })?;
+                ctx.status
+                    .update_app_status(&app, Default::default())
+                    .await?;
+            }
+            Ok::<_, anyhow::Error>(())
+        }
+        .await;
+
+        if let Err(err) = result {
+            log::info!("Failed to update status of '{}': {}", application, err);
+            // try again next time
+            state.lock().unwrap().dirty = true;
+        }
+    }
+}
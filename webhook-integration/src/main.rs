@@ -0,0 +1,180 @@
+mod controller;
+mod data;
+mod delivery;
+mod dispatcher;
+mod endpoints;
+
+use crate::{delivery::RetryConfig, dispatcher::DispatcherContext};
+use actix_web::{get, middleware, web, App, HttpResponse, HttpServer, Responder};
+use dotenv::dotenv;
+use drogue_client::registry;
+use drogue_cloud_integration_common::{
+    filter::DeviceLabels,
+    stream::{EventSource, EventStreamConfig},
+};
+use drogue_cloud_service_common::{
+    client::{RegistryListClient, RegistryStatusClient},
+    config::ConfigFromEnv,
+    defaults,
+    health::{HealthServer, HealthServerConfig},
+    openid::TokenConfig,
+};
+use futures::TryFutureExt;
+use serde::Deserialize;
+use serde_json::json;
+use std::{collections::HashMap, time::Duration};
+use url::Url;
+
+/// The delay before trying to reconcile again, e.g. when the registry isn't available yet.
+const RECONCILE_RETRY_DELAY: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Debug, Deserialize)]
+struct Config {
+    #[serde(default = "defaults::max_json_payload_size")]
+    pub max_json_payload_size: usize,
+
+    #[serde(default = "defaults::bind_addr")]
+    pub bind_addr: String,
+
+    #[serde(default)]
+    pub event_source: EventSource,
+    #[serde(default = "defaults::kafka_bootstrap_servers")]
+    pub kafka_bootstrap_servers: String,
+    #[serde(default = "defaults::kafka_events_topic")]
+    pub kafka_topic: String,
+    #[serde(default)]
+    pub kafka_properties: HashMap<String, String>,
+
+    #[serde(default)]
+    pub retry: RetryConfig,
+    /// The minimum time between two updates of the delivery status.
+    #[serde(default = "default_status_interval", with = "humantime_serde")]
+    pub status_interval: Duration,
+
+    #[serde(default)]
+    pub registry: RegistryConfig,
+
+    #[serde(default)]
+    pub health: HealthServerConfig,
+}
+
+#[inline]
+fn default_status_interval() -> Duration {
+    Duration::from_secs(30)
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct RegistryConfig {
+    #[serde(default = "defaults::registry_url")]
+    pub url: Url,
+}
+
+impl Default for RegistryConfig {
+    fn default() -> Self {
+        Self {
+            url: defaults::registry_url(),
+        }
+    }
+}
+
+pub struct WebData {
+    pub controller: controller::Controller,
+}
+
+#[get("/")]
+async fn index() -> impl Responder {
+    HttpResponse::Ok().json(json!({"success": true}))
+}
+
+#[actix_web::main]
+async fn main() -> anyhow::Result<()> {
+    env_logger::init();
+    dotenv().ok();
+
+    let config = Config::from_env()?;
+    let max_json_payload_size = config.max_json_payload_size;
+
+    log::info!("Event source: {:?}", config.event_source);
+    log::info!("Kafka servers: {}", config.kafka_bootstrap_servers);
+    log::info!("Kafka topic: {}", config.kafka_topic);
+
+    let client = reqwest::Client::new();
+    let token_provider = TokenConfig::from_env_prefix("REGISTRY")?
+        .amend_with_env()
+        .discover_from(client.clone())
+        .await?;
+    let registry = registry::v1::Client::new(
+        client.clone(),
+        config.registry.url.clone(),
+        Some(token_provider.clone()),
+    );
+    let status = RegistryStatusClient::new(
+        client.clone(),
+        config.registry.url.clone(),
+        Some(token_provider.clone()),
+    )?;
+    let list = RegistryListClient::new(client.clone(), config.registry.url, Some(token_provider))?;
+
+    // dispatchers get started when receiving the registry events of their application, and for
+    // all existing applications when starting up
+
+    let controller = controller::Controller::new(
+        DispatcherContext {
+            stream: EventStreamConfig {
+                source: config.event_source,
+                bootstrap_servers: config.kafka_bootstrap_servers,
+                properties: config.kafka_properties,
+                topic: config.kafka_topic,
+                app: None,
+                consumer_group: None,
+                manual_commit: true,
+                since: None,
+            },
+            labels: DeviceLabels::new(registry.clone()),
+            registry,
+            status,
+            client,
+            retry: config.retry,
+            status_interval: config.status_interval,
+        },
+        list,
+    );
+
+    let data = web::Data::new(WebData { controller });
+
+    let reconcile = {
+        let data = data.clone();
+        async move {
+            while let Err(err) = data.controller.reconcile().await {
+                log::info!("Failed to reconcile webhook dispatchers: {}", err);
+                tokio::time::sleep(RECONCILE_RETRY_DELAY).await;
+            }
+            Ok::<_, anyhow::Error>(())
+        }
+    };
+
+    // health server
+
+    let health = HealthServer::new(config.health, vec![]);
+
+    // main
+
+    let main = HttpServer::new(move || {
+        App::new()
+            .wrap(middleware::Logger::default())
+            .data(web::JsonConfig::default().limit(max_json_payload_size))
+            .app_data(data.clone())
+            .service(index)
+            .service(endpoints::events)
+    })
+    .bind(config.bind_addr)?
+    .run();
+
+    // run
+
+    futures::try_join!(health.run(), main.err_into(), reconcile)?;
+
+    // exiting
+
+    Ok(())
+}
@@ -0,0 +1,197 @@
+use drogue_client::Context;
+use drogue_cloud_service_api::auth::user::{
+    authn::{AuthenticationRequest, Outcome},
+    authz::{self, AuthorizationRequest, Permission},
+    UserInformation,
+};
+use drogue_cloud_service_common::{client::UserAuthClient, openid::Authenticator};
+
+/// The websocket sub-protocol, which the server selects during the handshake.
+pub const PROTOCOL: &str = "drogue";
+/// The prefix of the sub-protocol carrying an access token.
+///
+/// Browsers can't set headers on websocket requests, but they can offer sub-protocols. So a
+/// browser may connect using the protocols `drogue` and `bearer.<token>`, keeping the token out
+/// of the URL (and access logs).
+const TOKEN_PROTOCOL_PREFIX: &str = "bearer.";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Credentials {
+    Token(String),
+    ApiKey { username: String, api_key: String },
+    Anonymous,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuthError {
+    #[error("Invalid credentials")]
+    InvalidCredentials,
+    #[error("Authentication failed")]
+    Failed,
+    #[error("Authentication error: {0}")]
+    Internal(String),
+}
+
+/// Extract the credentials from the authorization header, or the websocket protocol header.
+///
+/// The authorization header takes precedence over the protocol header.
+pub fn credentials(
+    header: Option<&str>,
+    protocols: Option<&str>,
+) -> Result<Credentials, AuthError> {
+    if let Some(header) = header {
+        return match header.splitn(2, ' ').collect::<Vec<_>>().as_slice() {
+            ["Bearer", token] => Ok(Credentials::Token(token.to_string())),
+            ["Basic", value] => {
+                let value = base64::decode(value)
+                    .ok()
+                    .and_then(|v| String::from_utf8(v).ok())
+                    .ok_or(AuthError::InvalidCredentials)?;
+                match value.splitn(2, ':').collect::<Vec<_>>().as_slice() {
+                    [username, api_key] => Ok(Credentials::ApiKey {
+                        username: username.to_string(),
+                        api_key: api_key.to_string(),
+                    }),
+                    _ => Err(AuthError::InvalidCredentials),
+                }
+            }
+            _ => Err(AuthError::InvalidCredentials),
+        };
+    }
+
+    let tokens = protocols
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|protocol| protocol.trim().strip_prefix(TOKEN_PROTOCOL_PREFIX))
+        .collect::<Vec<_>>();
+
+    match tokens.as_slice() {
+        [] => Ok(Credentials::Anonymous),
+        [token] if !token.is_empty() => Ok(Credentials::Token(token.to_string())),
+        _ => Err(AuthError::InvalidCredentials),
+    }
+}
+
+/// Authenticate a user from the provided credentials.
+pub async fn authenticate(
+    authenticator: Option<&Authenticator>,
+    user_auth: Option<&UserAuthClient>,
+    disable_api_keys: bool,
+    credentials: Credentials,
+) -> Result<UserInformation, AuthError> {
+    let authenticator = match authenticator {
+        Some(authenticator) => authenticator,
+        // we are running without authentication
+        None => return Ok(UserInformation::Anonymous),
+    };
+
+    match (credentials, user_auth) {
+        (Credentials::Token(token), _) => {
+            log::debug!("Authenticate with token");
+            let token = authenticator
+                .validate_token(&token)
+                .await
+                .map_err(|_| AuthError::Failed)?;
+            Ok(UserInformation::Authenticated(token.into()))
+        }
+        (Credentials::ApiKey { username, api_key }, Some(user_auth)) if !disable_api_keys => {
+            log::debug!("Authenticate with API key");
+            match user_auth
+                .authenticate_api_key(
+                    AuthenticationRequest {
+                        user_id: username,
+                        api_key,
+                    },
+                    Context::default(),
+                )
+                .await
+                .map_err(|err| AuthError::Internal(err.to_string()))?
+                .outcome
+            {
+                Outcome::Known(details) => Ok(UserInformation::Authenticated(details)),
+                Outcome::Unknown => {
+                    log::debug!("Unknown API key");
+                    Err(AuthError::Failed)
+                }
+            }
+        }
+        (Credentials::ApiKey { .. }, _) => {
+            log::debug!("API key authentication is not available");
+            Err(AuthError::Failed)
+        }
+        (Credentials::Anonymous, _) => Ok(UserInformation::Anonymous),
+    }
+}
+
+/// Authorize a user for an operation on an application.
+pub async fn authorize(
+    user_auth: &UserAuthClient,
+    user: &UserInformation,
+    application: String,
+    permission: Permission,
+) -> Result<(), AuthError> {
+    log::debug!(
+        "Authorizing - user: {:?}, app: {}, permission: {:?}",
+        user,
+        application,
+        permission
+    );
+
+    let response = user_auth
+        .authorize(
+            AuthorizationRequest {
+                application,
+                permission,
+                user_id: user.user_id().map(ToString::to_string),
+                roles: user.roles().clone(),
+            },
+            Default::default(),
+        )
+        .await
+        .map_err(|err| AuthError::Internal(err.to_string()))?;
+
+    log::debug!("Outcome: {:?}", response);
+
+    match response.outcome {
+        authz::Outcome::Allow => Ok(()),
+        authz::Outcome::Deny => Err(AuthError::Failed),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_credentials_header() {
+        let protocols = Some("drogue, bearer.other");
+
+        assert_eq!(
+            credentials(Some("Bearer foo"), protocols).unwrap(),
+            Credentials::Token("foo".into())
+        );
+        assert_eq!(
+            credentials(Some("Basic Zm9vOmJhcg=="), protocols).unwrap(),
+            Credentials::ApiKey {
+                username: "foo".into(),
+                api_key: "bar".into()
+            }
+        );
+        assert!(credentials(Some("Digest foo"), protocols).is_err());
+    }
+
+    #[test]
+    fn test_credentials_protocol() {
+        assert_eq!(credentials(None, None).unwrap(), Credentials::Anonymous);
+        assert_eq!(
+            credentials(None, Some("drogue")).unwrap(),
+            Credentials::Anonymous
+        );
+        assert_eq!(
+            credentials(None, Some("drogue, bearer.foo.bar")).unwrap(),
+            Credentials::Token("foo.bar".into())
+        );
+        assert!(credentials(None, Some("drogue, bearer.")).is_err());
+        assert!(credentials(None, Some("bearer.foo, bearer.bar")).is_err());
+    }
+}
@@ -0,0 +1,149 @@
+mod auth;
+mod messages;
+mod session;
+
+use crate::session::{Service, ServiceConfig};
+use actix_cors::Cors;
+use actix_web::{get, middleware, web, App, HttpResponse, HttpServer, Responder};
+use dotenv::dotenv;
+use drogue_client::registry;
+use drogue_cloud_endpoint_common::downstream::{ConfiguredSink, DownstreamSender};
+use drogue_cloud_integration_common::filter::DeviceLabels;
+use drogue_cloud_service_common::{
+    client::{RegistryGatewayClient, UserAuthClient, UserAuthClientConfig},
+    config::ConfigFromEnv,
+    defaults,
+    health::{HealthServer, HealthServerConfig},
+    openid::{Authenticator, TokenConfig},
+};
+use futures::TryFutureExt;
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::Arc;
+use url::Url;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Config {
+    #[serde(default = "defaults::bind_addr")]
+    pub bind_addr: String,
+    #[serde(default = "defaults::enable_auth")]
+    pub enable_auth: bool,
+
+    #[serde(default)]
+    pub registry: RegistryConfig,
+
+    #[serde(default)]
+    pub service: ServiceConfig,
+    #[serde(default)]
+    pub user_auth: UserAuthClientConfig,
+
+    #[serde(default)]
+    pub health: HealthServerConfig,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct RegistryConfig {
+    #[serde(default = "defaults::registry_url")]
+    pub url: Url,
+}
+
+impl Default for RegistryConfig {
+    fn default() -> Self {
+        Self {
+            url: defaults::registry_url(),
+        }
+    }
+}
+
+#[get("/")]
+async fn index() -> impl Responder {
+    HttpResponse::Ok().json(json!({"success": true}))
+}
+
+#[actix_web::main]
+async fn main() -> anyhow::Result<()> {
+    env_logger::init();
+    dotenv().ok();
+
+    let config = Config::from_env()?;
+
+    let enable_auth = config.enable_auth;
+
+    log::info!("Authentication enabled: {}", enable_auth);
+    log::info!("Event source: {:?}", config.service.event_source);
+    log::info!("Kafka servers: {}", config.service.kafka_bootstrap_servers);
+    log::info!("Kafka topic: {}", config.service.kafka_topic);
+
+    // set up security
+
+    let (authenticator, user_auth) = if enable_auth {
+        let client = reqwest::Client::new();
+        let authenticator = Authenticator::new().await?;
+        let user_auth = Arc::new(
+            UserAuthClient::from_config(
+                client,
+                config.user_auth,
+                TokenConfig::from_env_prefix("USER_AUTH")?.amend_with_env(),
+            )
+            .await?,
+        );
+        (Some(authenticator), Some(user_auth))
+    } else {
+        (None, None)
+    };
+
+    let client = reqwest::Client::new();
+
+    let registry_token = TokenConfig::from_env_prefix("REGISTRY")?
+        .amend_with_env()
+        .discover_from(client.clone())
+        .await?;
+    let registry = registry::v1::Client::new(
+        client.clone(),
+        config.registry.url.clone(),
+        Some(registry_token.clone()),
+    );
+    let gateways =
+        RegistryGatewayClient::new(client.clone(), config.registry.url, Some(registry_token))?;
+    let sender = DownstreamSender::new(ConfiguredSink::new("COMMAND").await?)?;
+
+    let service = web::Data::new(Service {
+        config: config.service,
+        authenticator,
+        user_auth,
+        sender,
+        client,
+        labels: DeviceLabels::new(registry),
+        gateways,
+    });
+
+    // health server
+
+    let health = HealthServer::new(config.health, vec![]);
+
+    // main server
+
+    let main = HttpServer::new(move || {
+        App::new()
+            .wrap(middleware::Logger::default())
+            .app_data(service.clone())
+            .service(index)
+            .service(
+                web::scope("/api/ws/v1alpha1")
+                    .wrap(Cors::permissive())
+                    .service(
+                        web::resource("").route(web::get().to(session::connect::<ConfiguredSink>)),
+                    ),
+            )
+    })
+    .bind(config.bind_addr)?
+    .run();
+
+    // run
+
+    futures::try_join!(health.run(), main.err_into())?;
+
+    // exiting
+
+    Ok(())
+}
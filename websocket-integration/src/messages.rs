@@ -0,0 +1,145 @@
+use cloudevents::Event;
+use drogue_cloud_endpoint_common::payload::decode_payload;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// A request, sent by the client.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Request {
+    Subscribe(SubscribeRequest),
+    Unsubscribe(UnsubscribeRequest),
+    Command(CommandRequest),
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct SubscribeRequest {
+    /// The client assigned ID of the subscription.
+    pub id: String,
+    pub application: String,
+    /// An optional consumer group, shared with other subscriptions using the same group.
+    #[serde(default)]
+    pub group: Option<String>,
+    #[serde(default)]
+    pub device: Option<String>,
+    #[serde(default)]
+    pub channel: Option<String>,
+    /// A label selector, evaluated against the labels of the device.
+    #[serde(default)]
+    pub selector: Option<String>,
+    /// Replay events, starting at this time. Either an RFC 3339 timestamp, or a duration
+    /// relative to now. Can't be combined with a consumer group.
+    #[serde(default)]
+    pub since: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct UnsubscribeRequest {
+    pub id: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct CommandRequest {
+    /// An optional, client assigned ID, used for correlating the response.
+    #[serde(default)]
+    pub id: Option<String>,
+    pub application: String,
+    pub device: String,
+    pub command: String,
+    #[serde(default)]
+    pub content_type: Option<String>,
+    #[serde(default)]
+    pub payload: Option<Value>,
+    #[serde(default)]
+    pub payload_base64: Option<String>,
+}
+
+impl CommandRequest {
+    /// Get the payload, and the content type to use.
+    pub fn payload(&self) -> Result<(Vec<u8>, Option<String>), String> {
+        decode_payload(
+            "payload",
+            self.payload.as_ref(),
+            self.payload_base64.as_deref(),
+            self.content_type.as_deref(),
+        )
+    }
+}
+
+/// A message, sent by the server.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Response {
+    Subscribed {
+        id: String,
+    },
+    Unsubscribed {
+        id: String,
+    },
+    Event {
+        subscription: String,
+        event: Event,
+    },
+    #[serde(rename = "commandResult")]
+    CommandResult {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+        /// The HTTP status code, as if the command was sent to the command endpoint.
+        status: u16,
+    },
+    Error {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+        message: String,
+    },
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_subscribe() {
+        let request: Request = serde_json::from_value(json!({
+            "type": "subscribe",
+            "id": "1",
+            "application": "app",
+            "device": "device",
+        }))
+        .unwrap();
+
+        match request {
+            Request::Subscribe(request) => {
+                assert_eq!(request.id, "1");
+                assert_eq!(request.application, "app");
+                assert_eq!(request.device.as_deref(), Some("device"));
+                assert_eq!(request.channel, None);
+            }
+            _ => panic!("Unexpected request: {:?}", request),
+        }
+    }
+
+    #[test]
+    fn test_parse_command() {
+        let request: Request = serde_json::from_value(json!({
+            "type": "command",
+            "application": "app",
+            "device": "device",
+            "command": "set",
+            "payload": {"temp": 42},
+        }))
+        .unwrap();
+
+        match request {
+            Request::Command(request) => assert_eq!(
+                request.payload().unwrap(),
+                (
+                    br#"{"temp":42}"#.to_vec(),
+                    Some("application/json".to_string())
+                )
+            ),
+            _ => panic!("Unexpected request: {:?}", request),
+        }
+    }
+}
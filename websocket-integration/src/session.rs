@@ -0,0 +1,491 @@
+use crate::{
+    auth,
+    messages::{CommandRequest, Request, Response, SubscribeRequest},
+};
+use actix::prelude::*;
+use actix_web::{http::header, web, HttpRequest, HttpResponse};
+use actix_web_actors::ws;
+use chrono::{DateTime, Utc};
+use cloudevents::Event;
+use drogue_client::Context;
+use drogue_cloud_endpoint_common::downstream::{DownstreamSender, DownstreamSink};
+use drogue_cloud_integration_common::{
+    commands::{process_command, CommandOptions},
+    filter::{subscription_id, DeviceLabels, EventFilter, FilterError},
+    stream::{parse_since, EventSource, EventStream, EventStreamConfig, EventStreamError},
+};
+use drogue_cloud_service_api::auth::user::{authz::Permission, UserInformation};
+use drogue_cloud_service_common::{
+    client::{RegistryGatewayClient, UserAuthClient},
+    defaults,
+    error::ServiceError,
+    openid::Authenticator,
+};
+use futures::StreamExt;
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// How often heartbeat pings are sent.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+/// How long before lack of client response causes a timeout.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ServiceConfig {
+    #[serde(default)]
+    pub event_source: EventSource,
+    #[serde(default = "defaults::kafka_bootstrap_servers")]
+    pub kafka_bootstrap_servers: String,
+    #[serde(default = "defaults::kafka_events_topic")]
+    pub kafka_topic: String,
+    #[serde(default)]
+    pub kafka_properties: HashMap<String, String>,
+    #[serde(default)]
+    pub disable_api_keys: bool,
+}
+
+impl Default for ServiceConfig {
+    fn default() -> Self {
+        Self {
+            event_source: Default::default(),
+            kafka_bootstrap_servers: defaults::kafka_bootstrap_servers(),
+            kafka_topic: defaults::kafka_events_topic(),
+            kafka_properties: Default::default(),
+            disable_api_keys: false,
+        }
+    }
+}
+
+pub struct Service<S: DownstreamSink> {
+    pub config: ServiceConfig,
+    pub authenticator: Option<Authenticator>,
+    pub user_auth: Option<Arc<UserAuthClient>>,
+    pub sender: DownstreamSender<S>,
+    pub client: reqwest::Client,
+    pub gateways: RegistryGatewayClient,
+    pub labels: DeviceLabels,
+}
+
+/// Authenticate the request, and upgrade it to a websocket session.
+pub async fn connect<S>(
+    req: HttpRequest,
+    stream: web::Payload,
+    service: web::Data<Service<S>>,
+) -> Result<HttpResponse, actix_web::Error>
+where
+    S: DownstreamSink,
+{
+    let credentials = auth::credentials(
+        req.headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok()),
+        req.headers()
+            .get(header::SEC_WEBSOCKET_PROTOCOL)
+            .and_then(|v| v.to_str().ok()),
+    )
+    .map_err(|_| ServiceError::AuthenticationError)?;
+
+    let user = auth::authenticate(
+        service.authenticator.as_ref(),
+        service.user_auth.as_deref(),
+        service.config.disable_api_keys,
+        credentials,
+    )
+    .await
+    .map_err(|err| {
+        log::debug!("Failed to authenticate: {}", err);
+        ServiceError::AuthenticationError
+    })?;
+
+    ws::start_with_protocols(
+        WsSession::new(service, user),
+        &[auth::PROTOCOL],
+        &req,
+        stream,
+    )
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SubscriptionError {
+    #[error(transparent)]
+    Stream(#[from] EventStreamError),
+    #[error(transparent)]
+    Filter(#[from] FilterError),
+}
+
+/// An event, received for a subscription.
+pub struct SubscriptionEvent {
+    subscription: String,
+    event: Result<Event, SubscriptionError>,
+}
+
+pub struct WsSession<S: DownstreamSink> {
+    service: web::Data<Service<S>>,
+    user: UserInformation,
+    subscriptions: HashMap<String, SpawnHandle>,
+    /// Subscriptions waiting for their authorization.
+    pending: HashSet<String>,
+    heartbeat: Instant,
+}
+
+impl<S> WsSession<S>
+where
+    S: DownstreamSink,
+{
+    pub fn new(service: web::Data<Service<S>>, user: UserInformation) -> Self {
+        Self {
+            service,
+            user,
+            subscriptions: HashMap::new(),
+            pending: HashSet::new(),
+            heartbeat: Instant::now(),
+        }
+    }
+
+    fn send(&self, ctx: &mut ws::WebsocketContext<Self>, response: &Response) {
+        match serde_json::to_string(response) {
+            Ok(text) => ctx.text(text),
+            Err(err) => log::warn!("Failed to serialize response: {}", err),
+        }
+    }
+
+    fn send_error<M: Into<String>>(
+        &self,
+        ctx: &mut ws::WebsocketContext<Self>,
+        id: Option<String>,
+        message: M,
+    ) {
+        self.send(
+            ctx,
+            &Response::Error {
+                id,
+                message: message.into(),
+            },
+        );
+    }
+
+    /// Ping the client, and check if it still responds.
+    fn heartbeat(&self, ctx: &mut ws::WebsocketContext<Self>) {
+        ctx.run_interval(HEARTBEAT_INTERVAL, |act, ctx| {
+            if Instant::now().duration_since(act.heartbeat) > CLIENT_TIMEOUT {
+                log::info!("Websocket client heartbeat failed, disconnecting");
+                ctx.stop();
+                return;
+            }
+            ctx.ping(b"");
+        });
+    }
+
+    fn handle_request(&mut self, request: Request, ctx: &mut ws::WebsocketContext<Self>) {
+        log::debug!("Request: {:?}", request);
+
+        match request {
+            Request::Subscribe(request) => self.subscribe(request, ctx),
+            Request::Unsubscribe(request) => match self.subscriptions.remove(&request.id) {
+                Some(handle) => {
+                    ctx.cancel_future(handle);
+                    self.send(ctx, &Response::Unsubscribed { id: request.id });
+                }
+                // still authorizing, the stream won't get started
+                None if self.pending.remove(&request.id) => {
+                    self.send(ctx, &Response::Unsubscribed { id: request.id });
+                }
+                None => self.send_error(ctx, Some(request.id), "No such subscription"),
+            },
+            Request::Command(request) => self.command(request, ctx),
+        }
+    }
+
+    fn subscribe(&mut self, request: SubscribeRequest, ctx: &mut ws::WebsocketContext<Self>) {
+        if self.subscriptions.contains_key(&request.id) || self.pending.contains(&request.id) {
+            self.send_error(ctx, Some(request.id), "Subscription already exists");
+            return;
+        }
+
+        let filter = match EventFilter::new(
+            request.device.as_deref(),
+            request.channel.as_deref(),
+            request.selector.as_deref(),
+        ) {
+            Ok(filter) => filter,
+            Err(err) => {
+                self.send_error(ctx, Some(request.id), err);
+                return;
+            }
+        };
+
+        let since = match request.since.as_deref().map(parse_since).transpose() {
+            Ok(since) => since,
+            Err(err) => {
+                self.send_error(ctx, Some(request.id), err);
+                return;
+            }
+        };
+
+        if since.is_some() && request.group.is_some() {
+            self.send_error(
+                ctx,
+                Some(request.id),
+                "A start time can't be combined with a consumer group",
+            );
+            return;
+        }
+
+        let service = self.service.clone();
+        let user = self.user.clone();
+        let application = request.application.clone();
+
+        self.pending.insert(request.id.clone());
+
+        let authorized = async move {
+            match &service.user_auth {
+                Some(user_auth) => {
+                    auth::authorize(user_auth, &user, application, Permission::Read).await
+                }
+                // authorization disabled ... nothing to do
+                None => Ok(()),
+            }
+        };
+
+        ctx.spawn(
+            authorized
+                .into_actor(self)
+                .map(move |result, act, ctx| match result {
+                    // unsubscribed in the meantime
+                    _ if !act.pending.remove(&request.id) => {}
+                    Ok(()) => act.start_stream(request, filter, since, ctx),
+                    Err(err) => {
+                        log::debug!("Failed to authorize subscription: {}", err);
+                        act.send_error(ctx, Some(request.id), "Not authorized");
+                    }
+                }),
+        );
+    }
+
+    fn start_stream(
+        &mut self,
+        request: SubscribeRequest,
+        filter: EventFilter,
+        since: Option<DateTime<Utc>>,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) {
+        // check again, as the authorization is processed asynchronously
+        if self.subscriptions.contains_key(&request.id) {
+            self.send_error(ctx, Some(request.id), "Subscription already exists");
+            return;
+        }
+
+        // scope the group id, as we currently only have a single kafka topic, and subscriptions
+        // with different filters must not share a group
+
+        let group_id = request.group.as_ref().map(|g| {
+            let subscription = subscription_id(&[
+                request.device.as_deref(),
+                request.channel.as_deref(),
+                request.selector.as_deref(),
+            ]);
+            format!("{}:{}:{}", request.application, g, subscription)
+        });
+
+        log::debug!(
+            "Subscribing to app: {} (group: {:?})",
+            request.application,
+            group_id
+        );
+
+        let config = &self.service.config;
+        let stream = match EventStream::new(EventStreamConfig {
+            source: config.event_source,
+            bootstrap_servers: config.kafka_bootstrap_servers.clone(),
+            properties: config.kafka_properties.clone(),
+            topic: config.kafka_topic.clone(),
+            app: Some(request.application.clone()),
+            consumer_group: group_id,
+            manual_commit: false,
+            since,
+        }) {
+            Ok(stream) => stream,
+            Err(err) => {
+                log::info!("Failed to subscribe to Kafka topic: {}", err);
+                self.send_error(ctx, Some(request.id), "Failed to subscribe");
+                return;
+            }
+        };
+
+        let id = request.id.clone();
+        let labels = self.service.labels.clone();
+        let stream = stream
+            .filter_map(move |event| {
+                let filter = filter.clone();
+                let labels = labels.clone();
+                async move {
+                    match event {
+                        Ok(event) => match filter.matches(&event, &labels).await {
+                            Ok(true) => Some(Ok(event)),
+                            Ok(false) => None,
+                            Err(err) => Some(Err(err.into())),
+                        },
+                        // forward errors
+                        Err(err) => Some(Err(err.into())),
+                    }
+                }
+            })
+            .map(move |event| SubscriptionEvent {
+                subscription: id.clone(),
+                event,
+            });
+
+        let handle = ctx.add_stream(stream);
+        self.subscriptions.insert(request.id.clone(), handle);
+
+        self.send(ctx, &Response::Subscribed { id: request.id });
+    }
+
+    fn command(&mut self, request: CommandRequest, ctx: &mut ws::WebsocketContext<Self>) {
+        let (body, content_type) = match request.payload() {
+            Ok(payload) => payload,
+            Err(err) => {
+                self.send_error(ctx, request.id, err);
+                return;
+            }
+        };
+
+        let service = self.service.clone();
+        let user = self.user.clone();
+        let id = request.id.clone();
+
+        let result = async move {
+            if let Some(user_auth) = &service.user_auth {
+                auth::authorize(
+                    user_auth,
+                    &user,
+                    request.application.clone(),
+                    Permission::Write,
+                )
+                .await
+                .map_err(|_| "Not authorized".to_string())?;
+            }
+
+            let (device, gateways) = service
+                .gateways
+                .get_device_and_gateways(&request.application, &request.device, Context::default())
+                .await
+                .map_err(|err| format!("Failed to look up device: {}", err))?
+                .ok_or_else(|| "Not authorized".to_string())?;
+
+            let opts = CommandOptions {
+                application: request.application,
+                device: request.device,
+                command: request.command,
+            };
+
+            process_command(
+                device,
+                gateways,
+                &service.sender,
+                service.client.clone(),
+                content_type,
+                opts,
+                body.into(),
+            )
+            .await
+            .map(|response| response.status().as_u16())
+            .map_err(|err| format!("Failed to send command: {}", err))
+        };
+
+        ctx.spawn(
+            result
+                .into_actor(self)
+                .map(move |result, act, ctx| match result {
+                    Ok(status) => act.send(ctx, &Response::CommandResult { id, status }),
+                    Err(message) => act.send_error(ctx, id, message),
+                }),
+        );
+    }
+}
+
+impl<S> Actor for WsSession<S>
+where
+    S: DownstreamSink,
+{
+    type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        log::debug!("Websocket session started - user: {:?}", self.user);
+        self.heartbeat(ctx);
+    }
+
+    fn stopped(&mut self, _: &mut Self::Context) {
+        log::debug!("Websocket session stopped");
+    }
+}
+
+impl<S> StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsSession<S>
+where
+    S: DownstreamSink,
+{
+    fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        match msg {
+            Ok(ws::Message::Ping(msg)) => {
+                self.heartbeat = Instant::now();
+                ctx.pong(&msg);
+            }
+            Ok(ws::Message::Pong(_)) => {
+                self.heartbeat = Instant::now();
+            }
+            Ok(ws::Message::Text(text)) => match serde_json::from_str(&text) {
+                Ok(request) => self.handle_request(request, ctx),
+                Err(err) => self.send_error(ctx, None, format!("Invalid request: {}", err)),
+            },
+            Ok(ws::Message::Binary(_)) => {
+                self.send_error(ctx, None, "Binary messages are not supported");
+            }
+            Ok(ws::Message::Close(reason)) => {
+                ctx.close(reason);
+                ctx.stop();
+            }
+            Ok(ws::Message::Continuation(_)) | Ok(ws::Message::Nop) => {}
+            Err(err) => {
+                log::info!("Websocket protocol error: {}", err);
+                ctx.stop();
+            }
+        }
+    }
+}
+
+impl<S> StreamHandler<SubscriptionEvent> for WsSession<S>
+where
+    S: DownstreamSink,
+{
+    fn handle(&mut self, msg: SubscriptionEvent, ctx: &mut Self::Context) {
+        match msg.event {
+            Ok(event) => self.send(
+                ctx,
+                &Response::Event {
+                    subscription: msg.subscription,
+                    event,
+                },
+            ),
+            Err(err) => {
+                log::info!("Failed to receive event: {}", err);
+                if let Some(handle) = self.subscriptions.remove(&msg.subscription) {
+                    ctx.cancel_future(handle);
+                }
+                self.send_error(
+                    ctx,
+                    Some(msg.subscription),
+                    format!("Failed to receive event: {}", err),
+                );
+            }
+        }
+    }
+
+    fn finished(&mut self, _: &mut Self::Context) {
+        // the event stream ended, but the session continues
+        log::debug!("Subscription stream finished");
+    }
+}